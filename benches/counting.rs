@@ -0,0 +1,85 @@
+//! Short-message counting benchmark: `cargo bench --bench counting`.
+//!
+//! Compares the metric counting path against the previous SipHash
+//! `HashMap` / `HashSet` implementation on typical chat-sized inputs and
+//! fails if the speedup drops below `MIN_SPEEDUP`.
+
+use std::collections::{HashMap, HashSet};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use word_math_guard::{repetition_density_of, text, topic_drift_of, EmojiMode};
+
+const MIN_SPEEDUP: f64 = 2.0;
+const ITERATIONS: usize = 200_000;
+
+fn baseline_repetition(tokens: &[String]) -> f64 {
+    if tokens.is_empty() {
+        return 0.0;
+    }
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for w in tokens {
+        *counts.entry(w.as_str()).or_insert(0) += 1;
+    }
+    counts.values().copied().max().unwrap_or(0) as f64 / tokens.len() as f64
+}
+
+fn baseline_drift(message: &[String], topic: &[String]) -> f64 {
+    let a: HashSet<&str> = message.iter().map(String::as_str).collect();
+    let b: HashSet<&str> = topic.iter().map(String::as_str).collect();
+    if a.is_empty() && b.is_empty() {
+        return 0.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 1.0;
+    }
+    1.0 - a.intersection(&b).count() as f64 / a.union(&b).count() as f64
+}
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let messages = [
+        "How do I configure the axum router to share state between handlers?",
+        "buy now buy now buy now limited offer buy now while stocks last buy now",
+        "The deployment failed again because the readiness probe timed out after thirty seconds",
+    ];
+    let topic = text::tokenize("axum router state handlers deployment", EmojiMode::Strip);
+    let inputs: Vec<Vec<String>> = messages
+        .iter()
+        .map(|m| text::tokenize(m, EmojiMode::Strip))
+        .collect();
+
+    let baseline = time(|| {
+        for tokens in &inputs {
+            black_box(baseline_repetition(black_box(tokens)));
+            black_box(baseline_drift(black_box(tokens), black_box(&topic)));
+        }
+    });
+    let current = time(|| {
+        for tokens in &inputs {
+            black_box(repetition_density_of(black_box(tokens)));
+            black_box(topic_drift_of(black_box(tokens), black_box(&topic)));
+        }
+    });
+
+    let per_call = |d: Duration| d.as_nanos() as f64 / (ITERATIONS * inputs.len()) as f64;
+    let speedup = baseline.as_secs_f64() / current.as_secs_f64();
+    println!(
+        "short-message counting: baseline {:.0} ns, current {:.0} ns, speedup {:.2}x",
+        per_call(baseline),
+        per_call(current),
+        speedup
+    );
+    assert!(
+        speedup >= MIN_SPEEDUP,
+        "short-message speedup {:.2}x is below the {:.1}x floor",
+        speedup,
+        MIN_SPEEDUP
+    );
+}
@@ -0,0 +1,111 @@
+//! Shared analyzer handle and the process-wide default instance.
+//!
+//! An `Analyzer` bundles a config with a `TopicCache`, so repeated topics
+//! are tokenized once. Small applications can skip threading one through
+//! and use `global()` (or the crate-level `analyze` / `evaluate`), which is
+//! created from `WordMathConfig::from_env` on first use unless
+//! `init_global` installed one earlier.
+
+use crate::topic::{CacheStats, TopicCache};
+use crate::verdict::{self, VerdictExplanation};
+use crate::{analyze_with_topic, WordMathAnalysis, WordMathConfig, WordMathError, WordMathTrace};
+use std::sync::OnceLock;
+
+static GLOBAL: OnceLock<Analyzer> = OnceLock::new();
+
+/// A config plus a compiled-topic cache; cheap to share across threads.
+#[derive(Debug, Default)]
+pub struct Analyzer {
+    cfg: WordMathConfig,
+    topics: TopicCache,
+}
+
+impl Analyzer {
+    pub fn new(cfg: WordMathConfig) -> Self {
+        Self {
+            cfg,
+            topics: TopicCache::default(),
+        }
+    }
+
+    /// Use a topic cache holding at most `capacity` topics.
+    pub fn with_topic_cache_capacity(mut self, capacity: usize) -> Self {
+        self.topics = TopicCache::new(capacity);
+        self
+    }
+
+    pub fn config(&self) -> &WordMathConfig {
+        &self.cfg
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.topics.stats()
+    }
+
+    /// Score a message against a topic, compiling the topic at most once.
+    pub fn analyze(&self, message: &str, topic: &str) -> (WordMathAnalysis, WordMathTrace) {
+        let compiled = self.topics.get_or_compile(topic, self.cfg.emoji_mode);
+        let result = analyze_with_topic(message, &compiled, self.cfg);
+        #[cfg(feature = "metrics")]
+        {
+            crate::telemetry::counter(crate::telemetry::ANALYSES_TOTAL, &[], 1);
+            crate::telemetry::histogram(crate::telemetry::SCORE, &[], result.0.score);
+        }
+        result
+    }
+
+    /// Score a message and decide its verdict.
+    pub fn evaluate(&self, message: &str, topic: &str) -> VerdictExplanation {
+        let (analysis, _) = self.analyze(message, topic);
+        let explanation = verdict::evaluate(&analysis, &self.cfg);
+        #[cfg(feature = "metrics")]
+        crate::telemetry::counter(
+            crate::telemetry::VERDICTS_TOTAL,
+            &[("verdict", explanation.verdict.as_str())],
+            1,
+        );
+        explanation
+    }
+}
+
+/// The process-wide analyzer, created from the environment on first use.
+pub fn global() -> &'static Analyzer {
+    GLOBAL.get_or_init(|| Analyzer::new(WordMathConfig::from_env()))
+}
+
+/// Install the process-wide analyzer. Fails if `global()` was already
+/// initialized, explicitly or by first use.
+pub fn init_global(analyzer: Analyzer) -> Result<(), WordMathError> {
+    GLOBAL
+        .set(analyzer)
+        .map_err(|_| WordMathError::Config("global analyzer already initialized".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Verdict;
+
+    #[test]
+    fn test_analyzer_caches_topics() {
+        let analyzer = Analyzer::new(WordMathConfig::default()).with_topic_cache_capacity(8);
+        let (first, _) = analyzer.analyze("reset my password", "password reset");
+        let (second, _) = analyzer.analyze("reset my password", "password reset");
+        assert_eq!(first.score, second.score);
+        let stats = analyzer.cache_stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!(
+            analyzer
+                .evaluate("spam spam spam spam", "password reset")
+                .verdict,
+            Verdict::Block
+        );
+    }
+
+    #[test]
+    fn test_global_is_initialized_once() {
+        let first = global() as *const Analyzer;
+        assert!(init_global(Analyzer::default()).is_err());
+        assert_eq!(first, global() as *const Analyzer);
+    }
+}
@@ -0,0 +1,171 @@
+//! Scoring of speech-recognition transcripts with per-word confidences.
+//!
+//! ASR systems attach a confidence in [0, 1] to every recognized word, and
+//! low-confidence words are often garbage that repeats or drifts without
+//! the speaker having said anything of the kind. Here every token counts
+//! with its word's confidence instead of 1:
+//!
+//! - repetition y = max_w C(w) / C, where C(w) sums the confidences of
+//!   token w and C those of all tokens;
+//! - drift z = 1 - weighted Jaccard similarity, where a message key weighs
+//!   its highest confidence and topic keys weigh 1, i.e.
+//!   z = 1 - sum of m(k) over shared keys / (|T| + sum of m(k) over
+//!   off-topic keys).
+//!
+//! With every confidence at 1 both equal the unweighted metrics.
+
+use crate::matching::MatchForm;
+use crate::{
+    analyze_with_topic, evasion, score_linear, text, CompiledTopic, WordMathAnalysis,
+    WordMathConfig, WordMathTrace,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// One recognized word and the recognizer's confidence in it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeightedWord {
+    pub text: String,
+    pub confidence: f64,
+}
+
+/// The transcript's text, words separated by spaces.
+pub fn transcript_text(words: &[WeightedWord]) -> String {
+    words
+        .iter()
+        .map(|w| w.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Mean confidence of `words`, or 1 when there are none.
+pub fn mean_confidence(words: &[WeightedWord]) -> f64 {
+    if words.is_empty() {
+        return 1.0;
+    }
+    words.iter().map(|w| clamp(w.confidence)).sum::<f64>() / words.len() as f64
+}
+
+/// Confidences outside [0, 1] are clamped; NaN counts as 0.
+fn clamp(confidence: f64) -> f64 {
+    if confidence.is_nan() {
+        0.0
+    } else {
+        confidence.clamp(0.0, 1.0)
+    }
+}
+
+/// Like `analyze_with_topic` on the transcript text, with y and z weighted
+/// by confidence. The other metrics and the trace cover the whole text.
+pub fn analyze_transcript(
+    words: &[WeightedWord],
+    topic: &CompiledTopic,
+    cfg: WordMathConfig,
+) -> (WordMathAnalysis, WordMathTrace) {
+    let (mut analysis, mut trace) = analyze_with_topic(&transcript_text(words), topic, cfg);
+
+    let form = MatchForm::of(&cfg);
+    let mut total = 0.0;
+    let mut mass: HashMap<String, f64> = HashMap::new();
+    let mut keys: HashMap<String, f64> = HashMap::new();
+    for word in words {
+        let confidence = clamp(word.confidence);
+        let normalized = if cfg.normalize {
+            text::normalize(&word.text).text
+        } else {
+            word.text.clone()
+        };
+        for mut token in text::tokenize(&normalized, cfg.emoji_mode) {
+            if cfg.normalize {
+                token = evasion::fold_homoglyphs(&token).unwrap_or(token);
+            }
+            total += confidence;
+            let key = form.key(&token);
+            let best = keys.entry(key).or_insert(0.0);
+            *best = best.max(confidence);
+            *mass.entry(token).or_insert(0.0) += confidence;
+        }
+    }
+
+    let topic_keys = if topic.emoji_mode() == cfg.emoji_mode {
+        topic.tokens_for(form).to_vec()
+    } else {
+        CompiledTopic::compile(topic.text(), cfg.emoji_mode)
+            .tokens_for(form)
+            .to_vec()
+    };
+    let y = if total > 0.0 {
+        mass.values().fold(0.0, |max: f64, &m| max.max(m)) / total
+    } else {
+        0.0
+    };
+    let z = match (total > 0.0, topic_keys.is_empty()) {
+        (false, true) => 0.0,
+        (false, false) | (true, true) => 1.0,
+        (true, false) => {
+            let shared: f64 = topic_keys.iter().filter_map(|k| keys.get(k)).sum();
+            let off_topic: f64 = keys
+                .iter()
+                .filter(|(k, _)| topic_keys.binary_search(k).is_err())
+                .map(|(_, m)| m)
+                .sum();
+            1.0 - shared / (topic_keys.len() as f64 + off_topic)
+        }
+    };
+
+    let raw_score = score_linear(y, z, cfg);
+    analysis.y_repetition = y;
+    analysis.z_drift = z;
+    analysis.score = cfg.transform.apply(raw_score);
+    trace.raw_score = raw_score;
+    trace.adjusted_score = analysis.score;
+    (analysis, trace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(pairs: &[(&str, f64)]) -> Vec<WeightedWord> {
+        pairs
+            .iter()
+            .map(|&(text, confidence)| WeightedWord {
+                text: text.to_string(),
+                confidence,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_full_confidence_matches_plain_analysis() {
+        let cfg = WordMathConfig::default();
+        let topic = CompiledTopic::compile("rust web server", cfg.emoji_mode);
+        let transcript = words(&[("the", 1.0), ("rust", 1.0), ("server,", 1.0), ("the", 1.0)]);
+        let (weighted, _) = analyze_transcript(&transcript, &topic, cfg);
+        let (plain, _) = analyze_with_topic(&transcript_text(&transcript), &topic, cfg);
+        assert_eq!(weighted.y_repetition, plain.y_repetition);
+        assert!((weighted.z_drift - plain.z_drift).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_low_confidence_garbage_barely_counts() {
+        let cfg = WordMathConfig::default();
+        let topic = CompiledTopic::compile("rust web server", cfg.emoji_mode);
+        let transcript = words(&[
+            ("rust", 0.95),
+            ("web", 0.9),
+            ("server", 0.92),
+            ("uh", 0.05),
+            ("uh", 0.05),
+            ("uh", 0.05),
+            ("uh", 0.05),
+        ]);
+        let (weighted, _) = analyze_transcript(&transcript, &topic, cfg);
+        let (plain, _) = analyze_with_topic(&transcript_text(&transcript), &topic, cfg);
+        assert!(plain.y_repetition > 0.5 && plain.z_drift > 0.2);
+        assert!(weighted.y_repetition < 0.35, "{}", weighted.y_repetition);
+        assert!(weighted.z_drift < 0.1, "{}", weighted.z_drift);
+        assert!(weighted.score > plain.score);
+        assert!((mean_confidence(&transcript) - 0.4243).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::verdict::{self, Verdict};
+use crate::{analyze_with_topic, CompiledTopic, WordMathAnalysis, WordMathConfig, WordMathTrace};
+use serde::Serialize;
+
+/// Default number of worst items reported in a batch summary.
+pub const DEFAULT_WORST_K: usize = 10;
+
+/// Scored result for one batch item.
+#[derive(Debug, Clone)]
+pub struct BatchItemResult {
+    pub analysis: WordMathAnalysis,
+    pub trace: WordMathTrace,
+    pub verdict: Verdict,
+}
+
+/// Verdict tallies for a batch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct VerdictCounts {
+    pub allow: usize,
+    pub warn: usize,
+    pub block: usize,
+}
+
+impl VerdictCounts {
+    pub fn record(&mut self, verdict: Verdict) {
+        match verdict {
+            Verdict::Allow => self.allow += 1,
+            Verdict::Warn => self.warn += 1,
+            Verdict::Block => self.block += 1,
+        }
+    }
+}
+
+/// Aggregate view of a batch so callers don't post-process every row.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BatchSummary {
+    pub count: usize,
+    pub mean_score: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub verdicts: VerdictCounts,
+    /// Indices of the lowest-scoring items, worst first.
+    pub worst_indices: Vec<usize>,
+}
+
+/// Results in input order plus their summary.
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    pub items: Vec<BatchItemResult>,
+    pub summary: BatchSummary,
+}
+
+/// Nearest-rank percentile of an ascending slice; 0.0 when empty.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Build the summary for already scored items.
+pub fn summarize(items: &[BatchItemResult], worst_k: usize) -> BatchSummary {
+    let mut scores: Vec<f64> = items.iter().map(|item| item.analysis.score).collect();
+    scores.sort_by(f64::total_cmp);
+
+    let mut verdicts = VerdictCounts::default();
+    for item in items {
+        verdicts.record(item.verdict);
+    }
+
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    order.sort_by(|&a, &b| {
+        items[a]
+            .analysis
+            .score
+            .total_cmp(&items[b].analysis.score)
+            .then(a.cmp(&b))
+    });
+    order.truncate(worst_k);
+
+    let mean_score = if scores.is_empty() {
+        0.0
+    } else {
+        scores.iter().sum::<f64>() / scores.len() as f64
+    };
+
+    BatchSummary {
+        count: items.len(),
+        mean_score,
+        p50: percentile(&scores, 50.0),
+        p90: percentile(&scores, 90.0),
+        p99: percentile(&scores, 99.0),
+        verdicts,
+        worst_indices: order,
+    }
+}
+
+/// Analyze `(message, topic)` pairs with one config and summarize them.
+pub fn analyze_batch<'a, I>(items: I, cfg: WordMathConfig, worst_k: usize) -> BatchResult
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let compiled: Vec<(&str, CompiledTopic)> = items
+        .into_iter()
+        .map(|(message, topic)| (message, CompiledTopic::compile(topic, cfg.emoji_mode)))
+        .collect();
+    analyze_batch_with_topics(
+        compiled.iter().map(|(message, topic)| (*message, topic)),
+        cfg,
+        worst_k,
+    )
+}
+
+/// Like `analyze_batch`, with precompiled topics.
+pub fn analyze_batch_with_topics<'a, I>(
+    items: I,
+    cfg: WordMathConfig,
+    worst_k: usize,
+) -> BatchResult
+where
+    I: IntoIterator<Item = (&'a str, &'a CompiledTopic)>,
+{
+    analyze_batch_with_configs(
+        items
+            .into_iter()
+            .map(|(message, topic)| (message, topic, cfg)),
+        worst_k,
+    )
+}
+
+/// Like `analyze_batch_with_topics`, with a config per item, for batches
+/// that mix profiles.
+pub fn analyze_batch_with_configs<'a, I>(items: I, worst_k: usize) -> BatchResult
+where
+    I: IntoIterator<Item = (&'a str, &'a CompiledTopic, WordMathConfig)>,
+{
+    let items: Vec<BatchItemResult> = items
+        .into_iter()
+        .map(|(message, topic, cfg)| {
+            let (analysis, trace) = analyze_with_topic(message, topic, cfg);
+            let verdict = verdict::evaluate(&analysis, &cfg).verdict;
+            BatchItemResult {
+                analysis,
+                trace,
+                verdict,
+            }
+        })
+        .collect();
+
+    let summary = summarize(&items, worst_k);
+    BatchResult { items, summary }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmojiMode;
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let sorted: Vec<f64> = (1..=100).map(|i| i as f64).collect();
+        assert_eq!(percentile(&sorted, 50.0), 50.0);
+        assert_eq!(percentile(&sorted, 90.0), 90.0);
+        assert_eq!(percentile(&sorted, 99.0), 99.0);
+        assert_eq!(percentile(&[], 50.0), 0.0);
+        assert_eq!(percentile(&[0.4], 99.0), 0.4);
+    }
+
+    #[test]
+    fn test_batch_summary_counts_and_worst() {
+        let cfg = WordMathConfig::default();
+        let result = analyze_batch(
+            [
+                ("rust web server", "rust web server"),
+                ("spam spam spam spam", "rust web server"),
+                ("rust axum server", "rust web server"),
+            ],
+            cfg,
+            2,
+        );
+
+        assert_eq!(result.items.len(), 3);
+        assert_eq!(result.summary.count, 3);
+        assert_eq!(result.summary.worst_indices, vec![1, 2]);
+        assert_eq!(result.summary.verdicts.block, 1);
+        assert_eq!(
+            result.summary.verdicts.allow + result.summary.verdicts.warn,
+            2
+        );
+        assert!(result.summary.p50 <= result.summary.p90);
+    }
+
+    #[test]
+    fn test_batch_with_configs_scores_each_item_with_its_own() {
+        let topic = CompiledTopic::compile("rust web server", EmojiMode::Strip);
+        let lenient = WordMathConfig {
+            alpha: 0.1,
+            beta: 0.1,
+            ..WordMathConfig::default()
+        };
+        let message = "spam spam spam spam";
+        let result = analyze_batch_with_configs(
+            [
+                (message, &topic, WordMathConfig::default()),
+                (message, &topic, lenient),
+            ],
+            DEFAULT_WORST_K,
+        );
+        assert_eq!(result.items[0].verdict, Verdict::Block);
+        // Only the repetition cap still fires under the lenient weights.
+        assert_eq!(result.items[1].verdict, Verdict::Warn);
+        assert_eq!(result.summary.worst_indices, vec![0, 1]);
+    }
+}
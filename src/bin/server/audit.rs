@@ -0,0 +1,515 @@
+//! Merkle-root digests over the audit (trace) records.
+//!
+//! Every scored record is appended, as its leaf hash, to an append-only
+//! audit log that outlives the bounded trace store: records trimmed from
+//! `/traces` stay in the tree. With an embedded `KvStore` the log is
+//! persisted under `audit/leaf/<index>`; without one it starts afresh on
+//! every restart. The log keeps each leaf and every complete subtree hash,
+//! about 64 bytes plus the hex ID per record ever scored.
+//!
+//! Every WORD_MATH_MERKLE_INTERVAL_SECS (default 60) the root over the
+//! whole log is published at `/audit/merkle`, and optionally POSTed as
+//! JSON to WORD_MATH_MERKLE_WEBHOOK (`http://host[:port]/path`, or its
+//! `_FILE` variant). Anyone holding a published root can later check, with
+//! an inclusion proof from `/audit/merkle/proof/:hex_id`, that a record was
+//! part of it unmodified, and with a consistency proof from
+//! `/audit/merkle/consistency?first=M&second=N` that the tree of N leaves
+//! extends the tree of M leaves, so no record was removed or rewritten
+//! between two published roots.
+//!
+//! Hashing and tree shape follow RFC 6962 (see `word_math_guard::merkle`):
+//! leaves are `SHA-256(0x00 || record JSON)`, interior nodes
+//! `SHA-256(0x01 || left || right)`, and a tree of n leaves splits at the
+//! largest power of two below n.
+
+use crate::kv::KvStore;
+use crate::labels::Labels;
+use crate::traces::{TraceRecord, TraceStore};
+use serde::Serialize;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use word_math_guard::merkle::{self, empty_root, from_hex, node_hash, split, to_hex, Hash};
+use word_math_guard::{generate_hex_id, secret};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+const KV_PREFIX: &str = "audit/leaf/";
+
+fn leaf_hash(record: &TraceRecord) -> Hash {
+    merkle::leaf_hash(&serde_json::to_vec(record).unwrap_or_default())
+}
+
+/// One step of an inclusion proof: the sibling hash and which side it is on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProofStep {
+    pub sibling: String,
+    pub side: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InclusionProof {
+    pub hex_id: String,
+    pub leaf_index: u64,
+    pub tree_size: u64,
+    pub leaf: String,
+    pub root: String,
+    pub path: Vec<ProofStep>,
+}
+
+/// Proof that the tree of `second` leaves extends the tree of `first`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsistencyProof {
+    pub first: u64,
+    pub second: u64,
+    pub first_root: String,
+    pub second_root: String,
+    pub proof: Vec<String>,
+}
+
+#[derive(Default)]
+struct Tree {
+    /// `levels[h][i]` is the root of the complete subtree of `2^h` leaves
+    /// starting at leaf `i * 2^h`; `levels[0]` holds the leaves.
+    levels: Vec<Vec<Hash>>,
+    hex_ids: Vec<String>,
+}
+
+impl Tree {
+    fn size(&self) -> u64 {
+        self.hex_ids.len() as u64
+    }
+
+    fn push(&mut self, hex_id: String, leaf: Hash) {
+        self.hex_ids.push(hex_id);
+        let mut node = leaf;
+        let mut height = 0;
+        loop {
+            if self.levels.len() == height {
+                self.levels.push(Vec::new());
+            }
+            let level = &mut self.levels[height];
+            level.push(node);
+            if level.len() % 2 == 1 {
+                break;
+            }
+            node = node_hash(&level[level.len() - 2], &level[level.len() - 1]);
+            height += 1;
+        }
+    }
+
+    /// RFC 6962 MTH of leaves `start..end`. Left subtrees produced by the
+    /// split rule are complete and aligned, so they come from `levels`.
+    fn root(&self, start: u64, end: u64) -> Hash {
+        let n = end - start;
+        if n == 0 {
+            return empty_root();
+        }
+        if n.is_power_of_two() && start.is_multiple_of(n) {
+            let height = n.trailing_zeros() as usize;
+            return self.levels[height][(start >> height) as usize];
+        }
+        let k = split(n);
+        node_hash(&self.root(start, start + k), &self.root(start + k, end))
+    }
+
+    /// RFC 6962 PATH(m, D[start:end]), leaf side first.
+    fn path(&self, m: u64, start: u64, end: u64, out: &mut Vec<ProofStep>) {
+        let n = end - start;
+        if n <= 1 {
+            return;
+        }
+        let k = split(n);
+        if m < start + k {
+            self.path(m, start, start + k, out);
+            out.push(ProofStep {
+                sibling: to_hex(&self.root(start + k, end)),
+                side: "right",
+            });
+        } else {
+            self.path(m, start + k, end, out);
+            out.push(ProofStep {
+                sibling: to_hex(&self.root(start, start + k)),
+                side: "left",
+            });
+        }
+    }
+
+    /// RFC 6962 SUBPROOF(m, D[start:end], complete).
+    fn subproof(&self, m: u64, start: u64, end: u64, complete: bool, out: &mut Vec<Hash>) {
+        let n = end - start;
+        if m == n {
+            if !complete {
+                out.push(self.root(start, end));
+            }
+            return;
+        }
+        let k = split(n);
+        if m <= k {
+            self.subproof(m, start, start + k, complete, out);
+            out.push(self.root(start + k, end));
+        } else {
+            self.subproof(m - k, start + k, end, false, out);
+            out.push(self.root(start, start + k));
+        }
+    }
+}
+
+/// Append-only log of every record's leaf hash.
+#[derive(Default)]
+pub struct AuditLog {
+    kv: Option<Arc<KvStore>>,
+    tree: Mutex<Tree>,
+}
+
+impl AuditLog {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Tree> {
+        self.tree.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Persist leaves in `kv`, first loading the ones stored there.
+    pub fn set_persistence(&mut self, kv: Arc<KvStore>) {
+        self.kv = Some(kv);
+        self.reload();
+    }
+
+    /// Replace the in-memory log with the persisted one. Loading stops at
+    /// the first missing or unreadable leaf, so the tree stays a prefix.
+    pub fn reload(&self) -> u64 {
+        let Some(kv) = &self.kv else { return 0 };
+        let mut tree = Tree::default();
+        for (key, value) in kv.scan_prefix(KV_PREFIX) {
+            let index = u64::from_str_radix(&key[KV_PREFIX.len()..], 16).ok();
+            let leaf = value
+                .split_once(' ')
+                .and_then(|(hex_id, leaf)| Some((hex_id, from_hex(leaf)?)));
+            match (index, leaf) {
+                (Some(index), Some((hex_id, leaf))) if index == tree.size() => {
+                    tree.push(hex_id.to_string(), leaf);
+                }
+                _ => {
+                    warn!(
+                        "audit log ends early at {}: unexpected leaf {}",
+                        tree.size(),
+                        key
+                    );
+                    break;
+                }
+            }
+        }
+        let size = tree.size();
+        *self.lock() = tree;
+        size
+    }
+
+    /// Append `record`'s leaf.
+    pub fn append(&self, record: &TraceRecord) {
+        let leaf = leaf_hash(record);
+        let mut tree = self.lock();
+        if let Some(kv) = &self.kv {
+            let key = format!("{}{:016x}", KV_PREFIX, tree.size());
+            if let Err(e) = kv.put(&key, format!("{} {}", record.hex_id, to_hex(&leaf))) {
+                warn!("persisting audit leaf {} failed: {}", record.hex_id, e);
+            }
+        }
+        tree.push(record.hex_id.clone(), leaf);
+    }
+
+    pub fn size(&self) -> u64 {
+        self.lock().size()
+    }
+
+    /// Inclusion proof of `hex_id` in the tree of the first `size` leaves.
+    pub fn proof(&self, hex_id: &str, size: u64) -> Option<InclusionProof> {
+        let tree = self.lock();
+        let size = size.min(tree.size());
+        let index = tree.hex_ids[..size as usize]
+            .iter()
+            .rposition(|id| id == hex_id)? as u64;
+        let mut path = Vec::new();
+        tree.path(index, 0, size, &mut path);
+        Some(InclusionProof {
+            hex_id: hex_id.to_string(),
+            leaf_index: index,
+            tree_size: size,
+            leaf: to_hex(&tree.levels[0][index as usize]),
+            root: to_hex(&tree.root(0, size)),
+            path,
+        })
+    }
+
+    /// Consistency proof between the trees of `first` and `second` leaves;
+    /// None unless `first <= second <= size()`.
+    pub fn consistency(&self, first: u64, second: u64) -> Option<ConsistencyProof> {
+        let tree = self.lock();
+        if first > second || second > tree.size() {
+            return None;
+        }
+        let mut proof = Vec::new();
+        if first > 0 {
+            tree.subproof(first, 0, second, true, &mut proof);
+        }
+        Some(ConsistencyProof {
+            first,
+            second,
+            first_root: to_hex(&tree.root(0, first)),
+            second_root: to_hex(&tree.root(0, second)),
+            proof: proof.iter().map(to_hex).collect(),
+        })
+    }
+}
+
+/// Published root over the audit log.
+#[derive(Debug, Clone, Serialize)]
+pub struct MerkleDigest {
+    /// Hex trace ID of this digest computation.
+    pub digest_id: String,
+    pub root: String,
+    /// Tree size: records in the log when the root was taken.
+    pub leaves: u64,
+    pub first_hex_id: Option<String>,
+    pub last_hex_id: Option<String>,
+    /// Deployment labels of the instance that published the digest.
+    #[serde(skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
+}
+
+impl MerkleDigest {
+    /// Root over every leaf currently in `log`.
+    pub fn compute(log: &AuditLog) -> Self {
+        let tree = log.lock();
+        let size = tree.size();
+        Self {
+            digest_id: generate_hex_id(),
+            root: to_hex(&tree.root(0, size)),
+            leaves: size,
+            first_hex_id: tree.hex_ids.first().cloned(),
+            last_hex_id: tree.hex_ids.last().cloned(),
+            labels: Labels::default(),
+        }
+    }
+}
+
+/// Latest digest, refreshed by a background thread.
+#[derive(Default)]
+pub struct MerkleAuditor {
+    latest: Mutex<Option<Arc<MerkleDigest>>>,
+}
+
+impl MerkleAuditor {
+    pub fn latest(&self) -> Option<Arc<MerkleDigest>> {
+        self.latest
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn refresh(&self, traces: &TraceStore) -> Arc<MerkleDigest> {
+        let digest = Arc::new(MerkleDigest {
+            labels: traces.labels().clone(),
+            ..MerkleDigest::compute(traces.audit())
+        });
+        info!(
+            "HEX[{}]: audit merkle root {} over {} record(s)",
+            digest.digest_id, digest.root, digest.leaves
+        );
+        *self.latest.lock().unwrap_or_else(|e| e.into_inner()) = Some(Arc::clone(&digest));
+        digest
+    }
+}
+
+/// POST `body` as JSON to `http://host[:port]/path`.
+pub fn post_webhook(url: &str, body: &str) -> std::io::Result<()> {
+    let invalid =
+        |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, msg.to_string());
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| invalid("webhook must be http://"))?;
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let mut stream = TcpStream::connect(address)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    write!(
+        stream,
+        "POST /{} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        authority,
+        body.len(),
+        body
+    )?;
+    let mut status = [0u8; 12];
+    stream.read_exact(&mut status)?;
+    match &status[9..10] {
+        b"2" => Ok(()),
+        _ => Err(std::io::Error::other(format!(
+            "webhook returned {}",
+            String::from_utf8_lossy(&status[9..12])
+        ))),
+    }
+}
+
+/// Start the periodic digest thread.
+pub fn spawn(auditor: Arc<MerkleAuditor>, traces: Arc<TraceStore>) {
+    let interval = std::env::var("WORD_MATH_MERKLE_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|&secs| secs > 0)
+        .map_or(DEFAULT_INTERVAL, Duration::from_secs);
+    // The URL may embed a token, so it is read as a secret.
+    let webhook = match secret::from_env("WORD_MATH_MERKLE_WEBHOOK") {
+        Ok(webhook) => webhook,
+        Err(e) => {
+            warn!("not publishing merkle roots: {}", e);
+            None
+        }
+    };
+
+    std::thread::Builder::new()
+        .name("wordmath-merkle".to_string())
+        .spawn(move || loop {
+            let digest = auditor.refresh(&traces);
+            if let Some(url) = &webhook {
+                let body = serde_json::to_string(&*digest).unwrap_or_default();
+                if let Err(e) = post_webhook(url.expose(), &body) {
+                    warn!("publishing merkle root failed: {}", e);
+                }
+            }
+            std::thread::sleep(interval);
+        })
+        .expect("spawning the merkle thread failed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use word_math_guard::Verdict;
+
+    fn record(hex_id: &str) -> TraceRecord {
+        TraceRecord {
+            hex_id: hex_id.to_string(),
+            y_repetition: 0.1,
+            z_drift: 0.2,
+            raw_score: 0.85,
+            score: 0.85,
+            verdict: Verdict::Allow,
+            profile: None,
+            session_id: None,
+            topic_id: None,
+            rule_hits: Vec::new(),
+            experiment: None,
+            variant: None,
+            labels: Labels::default(),
+        }
+    }
+
+    fn verify(proof: &InclusionProof) -> bool {
+        let path = proof.path.iter().map(|s| (s.sibling.as_str(), s.side));
+        merkle::fold_path(&proof.leaf, path).as_deref() == Some(proof.root.as_str())
+    }
+
+    fn log_of(records: &[TraceRecord]) -> AuditLog {
+        let log = AuditLog::default();
+        for record in records {
+            log.append(record);
+        }
+        log
+    }
+
+    #[test]
+    fn test_proofs_verify_and_root_detects_changes() {
+        let records: Vec<TraceRecord> = (0..5).map(|i| record(&format!("{:04}", i))).collect();
+        let log = log_of(&records);
+        let digest = MerkleDigest::compute(&log);
+        for r in &records {
+            let proof = log.proof(&r.hex_id, digest.leaves).unwrap();
+            assert_eq!(proof.root, digest.root);
+            assert!(verify(&proof));
+        }
+        assert!(log.proof("missing", digest.leaves).is_none());
+        // Not yet covered by a tree of the first two leaves.
+        assert!(log.proof("0003", 2).is_none());
+        assert!(verify(&log.proof("0001", 2).unwrap()));
+
+        // RFC 6962 shape: 5 leaves split as 4 + 1, not (2 + 2) + promoted.
+        let leaves: Vec<Hash> = records.iter().map(leaf_hash).collect();
+        let four = node_hash(
+            &node_hash(&leaves[0], &leaves[1]),
+            &node_hash(&leaves[2], &leaves[3]),
+        );
+        assert_eq!(digest.root, to_hex(&node_hash(&four, &leaves[4])));
+
+        let mut tampered = records.clone();
+        tampered[2].score = 0.99;
+        assert_ne!(MerkleDigest::compute(&log_of(&tampered)).root, digest.root);
+        assert_ne!(
+            MerkleDigest::compute(&log_of(&records[1..])).root,
+            digest.root
+        );
+    }
+
+    #[test]
+    fn test_successive_roots_are_provably_consistent() {
+        let records: Vec<TraceRecord> = (0..11).map(|i| record(&format!("{:04}", i))).collect();
+        let log = log_of(&records[..6]);
+        let earlier = MerkleDigest::compute(&log);
+        for record in &records[6..] {
+            log.append(record);
+        }
+        let later = MerkleDigest::compute(&log);
+        let proof = log.consistency(earlier.leaves, later.leaves).unwrap();
+        assert_eq!(
+            (proof.first_root.as_str(), proof.second_root.as_str()),
+            (earlier.root.as_str(), later.root.as_str())
+        );
+        let hashes: Vec<Hash> = proof.proof.iter().map(|h| from_hex(h).unwrap()).collect();
+        let root = |hex: &str| from_hex(hex).unwrap();
+        assert!(merkle::verify_consistency(
+            earlier.leaves,
+            later.leaves,
+            &root(&earlier.root),
+            &root(&later.root),
+            &hashes
+        ));
+
+        // A log that dropped an early record cannot prove consistency with
+        // the root published before.
+        let mut pruned = records.clone();
+        pruned.remove(1);
+        let pruned = log_of(&pruned);
+        let forged = MerkleDigest::compute(&pruned);
+        let proof = pruned.consistency(earlier.leaves, forged.leaves).unwrap();
+        let hashes: Vec<Hash> = proof.proof.iter().map(|h| from_hex(h).unwrap()).collect();
+        assert!(!merkle::verify_consistency(
+            earlier.leaves,
+            forged.leaves,
+            &root(&earlier.root),
+            &root(&forged.root),
+            &hashes
+        ));
+        assert!(log.consistency(3, 12).is_none());
+    }
+
+    #[test]
+    fn test_persisted_log_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("wordmath-audit-{}", generate_hex_id()));
+        let kv = Arc::new(KvStore::open(&dir).unwrap());
+        let mut log = AuditLog::default();
+        log.set_persistence(Arc::clone(&kv));
+        for i in 0..7 {
+            log.append(&record(&format!("{:04}", i)));
+        }
+        let before = MerkleDigest::compute(&log);
+
+        let mut reopened = AuditLog::default();
+        reopened.set_persistence(kv);
+        let after = MerkleDigest::compute(&reopened);
+        assert_eq!((after.root, after.leaves), (before.root, before.leaves));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
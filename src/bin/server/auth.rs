@@ -0,0 +1,208 @@
+//! Role-based access control for the trace and admin endpoints.
+//!
+//! WORD_MATH_API_KEYS maps API keys to roles as `key:role` pairs separated
+//! by commas, e.g. `k1:reader,k2:admin`. Roles are ordered, each one
+//! including the ones below it:
+//!
+//! - `reader` may query traces, rollups, audit digests and session exports,
+//!   and post reviewer feedback;
+//! - `operator` may also use the operational `/admin` endpoints and fetch
+//!   tuning proposals;
+//! - `admin` may also import sessions, hand state off between instances
+//!   and back up or restore (purge) data.
+//!
+//! The key is read from `x-api-key` or an `Authorization: Bearer` header.
+//! Scoring endpoints, `/metrics` and `/version` stay open. With no keys
+//! configured the reader routes are open, as before, but the operator and
+//! admin routes (restore, re-encryption, backups, ...) are not mounted at
+//! all unless WORD_MATH_AUTH_DISABLED=1 explicitly opens them too, e.g. for
+//! local development. The setting accepts the `_FILE` and `${ENV}` forms
+//! described in `word_math_guard::secret`.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use word_math_guard::secret;
+
+use crate::replay::API_KEY_HEADER;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Reader,
+    Operator,
+    Admin,
+}
+
+impl Role {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "reader" => Some(Role::Reader),
+            "operator" => Some(Role::Operator),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ApiKeys {
+    roles: HashMap<String, Role>,
+    /// Serve operator and admin routes without keys.
+    disabled: bool,
+}
+
+impl ApiKeys {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut roles = HashMap::new();
+        for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, role) = pair
+                .rsplit_once(':')
+                .ok_or_else(|| format!("expected key:role, got {:?}", pair))?;
+            let role = Role::parse(role).ok_or_else(|| format!("unknown role {:?}", role))?;
+            if key.is_empty() {
+                return Err("empty API key".to_string());
+            }
+            roles.insert(key.to_string(), role);
+        }
+        Ok(Self {
+            roles,
+            disabled: false,
+        })
+    }
+
+    /// Keys from WORD_MATH_API_KEYS (or the file in WORD_MATH_API_KEYS_FILE);
+    /// WORD_MATH_AUTH_DISABLED=1 opens every route when there are none.
+    pub fn from_env() -> Result<Self, String> {
+        let keys = match secret::from_env("WORD_MATH_API_KEYS").map_err(|e| e.to_string())? {
+            Some(spec) => Self::parse(spec.expose())?,
+            None => Self::default(),
+        };
+        let disabled = matches!(
+            std::env::var("WORD_MATH_AUTH_DISABLED").as_deref(),
+            Ok("1" | "true")
+        );
+        Ok(keys.with_auth_disabled(disabled))
+    }
+
+    /// Serve operator and admin routes even without keys.
+    pub fn with_auth_disabled(mut self, disabled: bool) -> Self {
+        self.disabled = disabled;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.roles.is_empty()
+    }
+
+    /// Whether routes requiring `role` are mounted: always with keys,
+    /// otherwise only reader routes unless auth was explicitly disabled.
+    pub fn serves(&self, role: Role) -> bool {
+        self.is_enabled() || self.disabled || role == Role::Reader
+    }
+
+    pub fn len(&self) -> usize {
+        self.roles.len()
+    }
+
+    /// Check that the caller's key grants at least `required`.
+    pub fn authorize(
+        &self,
+        headers: &HeaderMap,
+        required: Role,
+    ) -> Result<(), (StatusCode, String)> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let key = headers
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .or_else(|| {
+                headers
+                    .get(header::AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.strip_prefix("Bearer "))
+            })
+            .ok_or_else(|| (StatusCode::UNAUTHORIZED, "missing API key".to_string()))?;
+        let role = self
+            .roles
+            .get(key)
+            .ok_or_else(|| (StatusCode::UNAUTHORIZED, "unknown API key".to_string()))?;
+        if *role < required {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!("{:?} role required", required).to_lowercase(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Middleware rejecting requests whose key does not grant `role`.
+pub async fn require(
+    State((keys, role)): State<(Arc<ApiKeys>, Role)>,
+    request: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, String)> {
+    keys.authorize(request.headers(), role)?;
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_roles_are_hierarchical() {
+        let keys = ApiKeys::parse("r:reader, o:operator, a:Admin").unwrap();
+        let key = |k: &str| headers(header::HeaderName::from_static(API_KEY_HEADER), k);
+
+        assert!(keys.authorize(&key("r"), Role::Reader).is_ok());
+        assert_eq!(
+            keys.authorize(&key("r"), Role::Operator).unwrap_err().0,
+            StatusCode::FORBIDDEN
+        );
+        assert!(keys.authorize(&key("o"), Role::Operator).is_ok());
+        assert!(keys.authorize(&key("a"), Role::Admin).is_ok());
+        assert_eq!(
+            keys.authorize(&key("x"), Role::Reader).unwrap_err().0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            keys.authorize(&HeaderMap::new(), Role::Reader)
+                .unwrap_err()
+                .0,
+            StatusCode::UNAUTHORIZED
+        );
+        let bearer = headers(header::AUTHORIZATION, "Bearer a");
+        assert!(keys.authorize(&bearer, Role::Admin).is_ok());
+    }
+
+    #[test]
+    fn test_parse_and_disabled() {
+        assert!(ApiKeys::parse("k:superuser").is_err());
+        assert!(ApiKeys::parse("nokey").is_err());
+        let open = ApiKeys::parse("").unwrap();
+        assert!(!open.is_enabled());
+        assert!(open.authorize(&HeaderMap::new(), Role::Reader).is_ok());
+        // Without keys, operator and admin routes stay unmounted unless
+        // auth is disabled on purpose.
+        assert!(open.serves(Role::Reader));
+        assert!(!open.serves(Role::Operator));
+        assert!(!open.serves(Role::Admin));
+        let dev = ApiKeys::parse("").unwrap().with_auth_disabled(true);
+        assert!(dev.serves(Role::Admin));
+        assert!(dev.authorize(&HeaderMap::new(), Role::Admin).is_ok());
+        assert!(ApiKeys::parse("a:admin").unwrap().serves(Role::Admin));
+    }
+}
@@ -0,0 +1,223 @@
+//! AES-256-GCM encryption of persisted values.
+//!
+//! WORD_MATH_AUDIT_KEYS (a secret, so `_FILE` and `${ENV}` forms work)
+//! lists `key_id:64-hex-digit-key` pairs separated by commas. The first key
+//! encrypts new values; the others are kept only to decrypt values written
+//! before a rotation, until `wordmath admin reencrypt` rewrites them.
+//!
+//! A sealed value is `enc:<key_id>:<nonce>:<ciphertext+tag>` (hex), with
+//! the store key as additional data so values cannot be swapped between
+//! keys. Once a keyring is configured, values without the `enc:` prefix are
+//! rejected: a plaintext value planted in the file would otherwise be read
+//! as if it had been sealed. The only exception is `open_or_plaintext`,
+//! used by the explicit re-encryption that migrates a store written before
+//! encryption was turned on.
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use std::io::{self, Read};
+use word_math_guard::secret;
+
+const PREFIX: &str = "enc:";
+const NONCE_LEN: usize = 12;
+
+/// Whether `stored` is a sealed value rather than plaintext.
+pub fn is_sealed(stored: &str) -> bool {
+    stored.starts_with(PREFIX)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn random_nonce() -> io::Result<[u8; NONCE_LEN]> {
+    let mut nonce = [0u8; NONCE_LEN];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut nonce)?;
+    Ok(nonce)
+}
+
+struct Key {
+    id: String,
+    /// Zeroes its round keys on drop.
+    aead: Aes256Gcm,
+}
+
+/// Keyring for sealing and opening stored values.
+pub struct Cipher {
+    /// The first key is the active one.
+    keys: Vec<Key>,
+}
+
+impl Cipher {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut keys = Vec::new();
+        for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (id, hex) = pair
+                .split_once(':')
+                .ok_or_else(|| "expected key_id:hex_key".to_string())?;
+            if id.is_empty() || id.contains(':') || keys.iter().any(|k: &Key| k.id == id) {
+                return Err(format!("bad or duplicate key id {:?}", id));
+            }
+            let mut bytes = from_hex(hex)
+                .filter(|b| b.len() == 32)
+                .ok_or_else(|| format!("key {:?} must be 64 hex digits", id))?;
+            let aead = Aes256Gcm::new_from_slice(&bytes).map_err(|e| e.to_string())?;
+            for b in bytes.iter_mut() {
+                // SAFETY: `b` is a valid, aligned reference.
+                unsafe { std::ptr::write_volatile(b, 0) };
+            }
+            keys.push(Key {
+                id: id.to_string(),
+                aead,
+            });
+        }
+        if keys.is_empty() {
+            return Err("no keys given".to_string());
+        }
+        Ok(Self { keys })
+    }
+
+    /// Keyring from WORD_MATH_AUDIT_KEYS, if configured.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        match secret::from_env("WORD_MATH_AUDIT_KEYS").map_err(|e| e.to_string())? {
+            Some(spec) => Self::parse(spec.expose())
+                .map(Some)
+                .map_err(|e| format!("WORD_MATH_AUDIT_KEYS: {}", e)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn active_key_id(&self) -> &str {
+        &self.keys[0].id
+    }
+
+    /// Encrypt `value` under the active key, bound to the store key `aad`.
+    pub fn seal(&self, aad: &str, value: &str) -> io::Result<String> {
+        let key = &self.keys[0];
+        let nonce = random_nonce()?;
+        let payload = Payload {
+            msg: value.as_bytes(),
+            aad: aad.as_bytes(),
+        };
+        let sealed = key
+            .aead
+            .encrypt(&Nonce::from(nonce), payload)
+            .map_err(|e| io::Error::other(format!("{}: {}", aad, e)))?;
+        Ok(format!(
+            "{}{}:{}:{}",
+            PREFIX,
+            key.id,
+            to_hex(&nonce),
+            to_hex(&sealed)
+        ))
+    }
+
+    /// Decrypt a sealed value; plaintext values are an error.
+    pub fn open(&self, aad: &str, stored: &str) -> io::Result<String> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let Some(rest) = stored.strip_prefix(PREFIX) else {
+            return Err(invalid(format!(
+                "{}: value is not encrypted (run `wordmath admin reencrypt` to migrate it)",
+                aad
+            )));
+        };
+        let mut parts = rest.splitn(3, ':');
+        let (Some(id), Some(nonce), Some(sealed)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid(format!("{}: malformed sealed value", aad)));
+        };
+        let key = self
+            .keys
+            .iter()
+            .find(|k| k.id == id)
+            .ok_or_else(|| invalid(format!("{}: unknown key id {:?}", aad, id)))?;
+        let nonce: [u8; NONCE_LEN] = from_hex(nonce)
+            .and_then(|n| n.try_into().ok())
+            .ok_or_else(|| invalid(format!("{}: bad nonce", aad)))?;
+        let sealed = from_hex(sealed).ok_or_else(|| invalid(format!("{}: bad ciphertext", aad)))?;
+        let payload = Payload {
+            msg: &sealed,
+            aad: aad.as_bytes(),
+        };
+        let plain = key
+            .aead
+            .decrypt(&Nonce::from(nonce), payload)
+            .map_err(|_| invalid(format!("{}: authentication failed", aad)))?;
+        String::from_utf8(plain).map_err(|e| invalid(format!("{}: {}", aad, e)))
+    }
+
+    /// Like `open`, but plaintext values are returned as they are. Only for
+    /// re-encryption, which seals them.
+    pub fn open_or_plaintext(&self, aad: &str, stored: &str) -> io::Result<String> {
+        if is_sealed(stored) {
+            self.open(aad, stored)
+        } else {
+            Ok(stored.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The ciphertext+tag of `plaintext` under `key` with a zero nonce.
+    fn seal_zero_nonce(key: &[u8; 32], plaintext: &[u8]) -> String {
+        let aead = Aes256Gcm::new_from_slice(key).unwrap();
+        to_hex(
+            &aead
+                .encrypt(&Nonce::from([0u8; NONCE_LEN]), plaintext)
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_known_answer_vectors() {
+        // NIST GCM test cases 13 and 14.
+        assert_eq!(
+            seal_zero_nonce(&[0u8; 32], &[]),
+            "530f8afbc74536b9a963b4f1c4cb738b"
+        );
+        assert_eq!(
+            seal_zero_nonce(&[0u8; 32], &[0u8; 16]),
+            "cea7403d4d606b6e074ec5d3baf39d18d0d1c8a799996bf0265b98b5d48ab919"
+        );
+    }
+
+    #[test]
+    fn test_seal_open_and_rotation() {
+        let old = Cipher::parse(&format!("k1:{}", "11".repeat(32))).unwrap();
+        let sealed = old.seal("trace/a", "{\"score\":0.5}").unwrap();
+        assert!(sealed.starts_with("enc:k1:"));
+        assert_eq!(old.open("trace/a", &sealed).unwrap(), "{\"score\":0.5}");
+        assert!(old.open("trace/b", &sealed).is_err());
+        assert!(old.open("trace/a", "plain").is_err());
+        assert_eq!(old.open_or_plaintext("trace/a", "plain").unwrap(), "plain");
+        assert_eq!(
+            old.open_or_plaintext("trace/a", &sealed).unwrap(),
+            "{\"score\":0.5}"
+        );
+
+        let rotated =
+            Cipher::parse(&format!("k2:{},k1:{}", "22".repeat(32), "11".repeat(32))).unwrap();
+        assert_eq!(rotated.active_key_id(), "k2");
+        assert_eq!(rotated.open("trace/a", &sealed).unwrap(), "{\"score\":0.5}");
+        let mut tampered = sealed.clone();
+        let last = tampered.pop().unwrap();
+        tampered.push(if last == '0' { '1' } else { '0' });
+        assert!(rotated.open("trace/a", &tampered).is_err());
+
+        assert!(Cipher::parse("k1:abcd").is_err());
+        assert!(Cipher::parse("").is_err());
+    }
+}
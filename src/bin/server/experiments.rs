@@ -0,0 +1,430 @@
+//! A/B experiments over scoring profiles, metrics and policy rules.
+//!
+//! Experiments come from the JSON file in WORD_MATH_EXPERIMENTS:
+//!
+//! ```json
+//! [{ "id": "exp-42", "traffic": 0.2, "variants": [
+//!     { "id": "control" },
+//!     { "id": "strict", "profile": "strict", "disable_rules": ["loop"] }
+//! ] }]
+//! ```
+//!
+//! Each experiment takes a disjoint `traffic` share, split between its
+//! variants by `weight` (default 1). The unit of assignment (session, else
+//! API key, else message) is hashed, so a unit always lands in the same
+//! variant. The first variant is the baseline the others are compared to.
+//! Traces carry the experiment and variant IDs; per-variant tallies are
+//! rebuilt from the trace store on startup.
+
+use crate::switches;
+use crate::traces::TraceRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::Mutex;
+use word_math_guard::batch::VerdictCounts;
+use word_math_guard::hash::FxHasher;
+use word_math_guard::{ProfileSet, RuleSet};
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+/// One arm of an experiment; an empty variant scores like the request.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Variant {
+    pub id: String,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    /// Scoring profile used instead of the requested one.
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub disable_metrics: Vec<String>,
+    #[serde(default)]
+    pub disable_rules: Vec<String>,
+}
+
+impl Variant {
+    pub fn metric_disabled(&self, name: &str) -> bool {
+        self.disable_metrics.iter().any(|m| m == name)
+    }
+
+    pub fn rule_disabled(&self, id: &str) -> bool {
+        self.disable_rules.iter().any(|r| r == id)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Experiment {
+    pub id: String,
+    /// Share of all traffic enrolled in this experiment, in [0, 1].
+    pub traffic: f64,
+    pub variants: Vec<Variant>,
+}
+
+/// The experiment arm a request was enrolled in.
+#[derive(Debug, Clone, Copy)]
+pub struct Assignment<'a> {
+    pub experiment: &'a Experiment,
+    pub variant: &'a Variant,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Tally {
+    count: usize,
+    sum: f64,
+    sum_sq: f64,
+    verdicts: VerdictCounts,
+}
+
+impl Tally {
+    fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+
+    /// Sample variance of the scores.
+    fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        let n = self.count as f64;
+        ((self.sum_sq - self.sum * self.sum / n) / (n - 1.0)).max(0.0)
+    }
+
+    fn block_rate(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.verdicts.block as f64 / self.count as f64
+        }
+    }
+}
+
+/// A variant against the experiment's baseline.
+#[derive(Debug, Clone, Serialize)]
+pub struct BaselineComparison {
+    pub mean_delta: f64,
+    /// Welch z-statistic of the mean score difference.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_z: Option<f64>,
+    pub block_rate_delta: f64,
+    /// Pooled two-proportion z-statistic of the block-rate difference.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_rate_z: Option<f64>,
+}
+
+impl BaselineComparison {
+    fn new(variant: &Tally, baseline: &Tally) -> Self {
+        let (n1, n0) = (variant.count as f64, baseline.count as f64);
+        let se = (variant.variance() / n1 + baseline.variance() / n0).sqrt();
+        let mean_delta = variant.mean() - baseline.mean();
+        let pooled = (variant.verdicts.block + baseline.verdicts.block) as f64 / (n1 + n0);
+        let se_rate = (pooled * (1.0 - pooled) * (1.0 / n1 + 1.0 / n0)).sqrt();
+        let block_rate_delta = variant.block_rate() - baseline.block_rate();
+        let z = |delta: f64, se: f64| (se.is_finite() && se > 0.0).then(|| delta / se);
+        Self {
+            mean_delta,
+            mean_z: z(mean_delta, se),
+            block_rate_delta,
+            block_rate_z: z(block_rate_delta, se_rate),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantReport {
+    pub id: String,
+    pub count: usize,
+    pub mean_score: f64,
+    pub stddev: f64,
+    pub verdicts: VerdictCounts,
+    pub block_rate: f64,
+    /// Absent for the baseline and while either side has no samples.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vs_baseline: Option<BaselineComparison>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExperimentReport {
+    pub id: String,
+    pub traffic: f64,
+    pub variants: Vec<VariantReport>,
+}
+
+#[derive(Debug, Default)]
+pub struct Experiments {
+    experiments: Vec<Experiment>,
+    tallies: Mutex<HashMap<(String, String), Tally>>,
+}
+
+impl Experiments {
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let experiments: Vec<Experiment> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let mut ids = HashSet::new();
+        let mut total = 0.0;
+        for experiment in &experiments {
+            if !ids.insert(experiment.id.as_str()) {
+                return Err(format!("duplicate experiment id: {}", experiment.id));
+            }
+            if !(0.0..=1.0).contains(&experiment.traffic) {
+                return Err(format!("{}: traffic must be in [0, 1]", experiment.id));
+            }
+            total += experiment.traffic;
+            if experiment.variants.len() < 2 {
+                return Err(format!("{}: needs at least two variants", experiment.id));
+            }
+            let mut variant_ids = HashSet::new();
+            for variant in &experiment.variants {
+                if !variant_ids.insert(variant.id.as_str()) {
+                    return Err(format!(
+                        "{}: duplicate variant {}",
+                        experiment.id, variant.id
+                    ));
+                }
+                if !(variant.weight > 0.0 && variant.weight.is_finite()) {
+                    return Err(format!(
+                        "{}/{}: weight must be positive",
+                        experiment.id, variant.id
+                    ));
+                }
+            }
+        }
+        if total > 1.0 + 1e-9 {
+            return Err(format!("experiment traffic sums to {} (> 1)", total));
+        }
+        Ok(Self {
+            experiments,
+            tallies: Mutex::default(),
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let json =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::from_json(&json)
+    }
+
+    /// Load the file named by WORD_MATH_EXPERIMENTS, or none if unset.
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("WORD_MATH_EXPERIMENTS") {
+            Ok(path) => Self::load(path),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Check that variants only name known profiles, metrics and rules.
+    pub fn validate(&self, profiles: &ProfileSet, rules: &RuleSet) -> Result<(), String> {
+        for experiment in &self.experiments {
+            for variant in &experiment.variants {
+                let context = format!("{}/{}", experiment.id, variant.id);
+                if let Some(profile) = variant.profile.as_deref() {
+                    if profiles.get(profile).is_none() {
+                        return Err(format!("{}: unknown profile {}", context, profile));
+                    }
+                }
+                if let Some(metric) = variant
+                    .disable_metrics
+                    .iter()
+                    .find(|m| !switches::METRICS.contains(&m.as_str()))
+                {
+                    return Err(format!("{}: unknown metric {}", context, metric));
+                }
+                if let Some(rule) = variant
+                    .disable_rules
+                    .iter()
+                    .find(|id| !rules.rules().iter().any(|r| &r.id == *id))
+                {
+                    return Err(format!("{}: unknown rule {}", context, rule));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.experiments.len()
+    }
+
+    /// The arm `unit` is enrolled in, if any.
+    pub fn assign(&self, unit: &str) -> Option<Assignment<'_>> {
+        let mut hasher = FxHasher::default();
+        unit.hash(&mut hasher);
+        // The top 53 bits are the best mixed; map them to [0, 1).
+        let mut position = (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64;
+        for experiment in &self.experiments {
+            if position >= experiment.traffic {
+                position -= experiment.traffic;
+                continue;
+            }
+            let total: f64 = experiment.variants.iter().map(|v| v.weight).sum();
+            let mut point = position / experiment.traffic * total;
+            let variant = experiment
+                .variants
+                .iter()
+                .find(|v| {
+                    point -= v.weight;
+                    point < 0.0
+                })
+                .unwrap_or(&experiment.variants[experiment.variants.len() - 1]);
+            return Some(Assignment {
+                experiment,
+                variant,
+            });
+        }
+        None
+    }
+
+    /// Count a scored message towards its variant's tally.
+    pub fn observe(&self, record: &TraceRecord) {
+        let (Some(experiment), Some(variant)) = (&record.experiment, &record.variant) else {
+            return;
+        };
+        let mut tallies = self.tallies.lock().unwrap_or_else(|e| e.into_inner());
+        let tally = tallies
+            .entry((experiment.clone(), variant.clone()))
+            .or_default();
+        tally.count += 1;
+        tally.sum += record.score;
+        tally.sum_sq += record.score * record.score;
+        tally.verdicts.record(record.verdict);
+    }
+
+    /// Per-variant aggregates of every configured experiment.
+    pub fn report(&self) -> Vec<ExperimentReport> {
+        let tallies = self.tallies.lock().unwrap_or_else(|e| e.into_inner());
+        self.experiments
+            .iter()
+            .map(|experiment| {
+                let tally = |variant: &Variant| {
+                    tallies
+                        .get(&(experiment.id.clone(), variant.id.clone()))
+                        .copied()
+                        .unwrap_or_default()
+                };
+                let baseline = tally(&experiment.variants[0]);
+                let variants = experiment
+                    .variants
+                    .iter()
+                    .enumerate()
+                    .map(|(i, variant)| {
+                        let t = tally(variant);
+                        VariantReport {
+                            id: variant.id.clone(),
+                            count: t.count,
+                            mean_score: t.mean(),
+                            stddev: t.variance().sqrt(),
+                            verdicts: t.verdicts,
+                            block_rate: t.block_rate(),
+                            vs_baseline: (i > 0 && t.count > 0 && baseline.count > 0)
+                                .then(|| BaselineComparison::new(&t, &baseline)),
+                        }
+                    })
+                    .collect();
+                ExperimentReport {
+                    id: experiment.id.clone(),
+                    traffic: experiment.traffic,
+                    variants,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labels::Labels;
+    use word_math_guard::Verdict;
+
+    const JSON: &str = r#"[{ "id": "exp-42", "traffic": 0.5, "variants": [
+        { "id": "control" },
+        { "id": "lenient", "weight": 3, "disable_metrics": ["drift"] }
+    ] }]"#;
+
+    fn record(variant: &str, score: f64, verdict: Verdict) -> TraceRecord {
+        TraceRecord {
+            hex_id: String::new(),
+            y_repetition: 0.0,
+            z_drift: 0.0,
+            raw_score: score,
+            score,
+            verdict,
+            profile: None,
+            session_id: None,
+            topic_id: None,
+            rule_hits: Vec::new(),
+            experiment: Some("exp-42".to_string()),
+            variant: Some(variant.to_string()),
+            labels: Labels::default(),
+        }
+    }
+
+    #[test]
+    fn test_assignment_is_sticky_and_weighted() {
+        let experiments = Experiments::from_json(JSON).unwrap();
+        let mut counts = HashMap::new();
+        for i in 0..10_000 {
+            let unit = format!("session-{}", i);
+            let first = experiments.assign(&unit).map(|a| a.variant.id.clone());
+            let again = experiments.assign(&unit).map(|a| a.variant.id.clone());
+            assert_eq!(first, again);
+            *counts.entry(first).or_insert(0usize) += 1;
+        }
+        let share = |id: Option<&str>| counts[&id.map(str::to_string)] as f64 / 10_000.0;
+        assert!((share(None) - 0.5).abs() < 0.03);
+        assert!((share(Some("control")) - 0.125).abs() < 0.03);
+        assert!((share(Some("lenient")) - 0.375).abs() < 0.03);
+    }
+
+    #[test]
+    fn test_report_compares_against_baseline() {
+        let experiments = Experiments::from_json(JSON).unwrap();
+        for score in [0.2, 0.4, 0.2, 0.4] {
+            let verdict = if score < 0.3 {
+                Verdict::Block
+            } else {
+                Verdict::Warn
+            };
+            experiments.observe(&record("control", score, verdict));
+            experiments.observe(&record("lenient", score + 0.4, Verdict::Allow));
+        }
+        let report = experiments.report();
+        let [control, lenient] = &report[0].variants[..] else {
+            panic!("expected two variants");
+        };
+        assert_eq!(control.count, 4);
+        assert!((control.mean_score - 0.3).abs() < 1e-9);
+        assert_eq!(control.block_rate, 0.5);
+        assert!(control.vs_baseline.is_none());
+        let delta = lenient.vs_baseline.as_ref().unwrap();
+        assert!((delta.mean_delta - 0.4).abs() < 1e-9);
+        assert!(delta.mean_z.unwrap() > 3.0);
+        assert_eq!(delta.block_rate_delta, -0.5);
+    }
+
+    #[test]
+    fn test_rejects_bad_definitions() {
+        assert!(Experiments::from_json(
+            r#"[{ "id": "a", "traffic": 0.5, "variants": [{ "id": "x" }] }]"#
+        )
+        .is_err());
+        assert!(Experiments::from_json(
+            r#"[{ "id": "a", "traffic": 0.7, "variants": [{ "id": "x" }, { "id": "y" }] },
+                { "id": "b", "traffic": 0.7, "variants": [{ "id": "x" }, { "id": "y" }] }]"#
+        )
+        .is_err());
+        let experiments = Experiments::from_json(
+            r#"[{ "id": "a", "traffic": 0.1, "variants": [{ "id": "x" }, { "id": "y", "profile": "nope" }] }]"#,
+        )
+        .unwrap();
+        assert!(experiments
+            .validate(&ProfileSet::default(), &RuleSet::default())
+            .is_err());
+    }
+}
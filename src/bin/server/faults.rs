@@ -0,0 +1,95 @@
+//! Fault injection for resilience tests.
+//!
+//! With the `testing` feature, faults are toggled at runtime through
+//! `/admin/faults`; without it, every probe compiles to a constant so
+//! production builds carry no switches.
+
+#[cfg(feature = "testing")]
+pub use enabled::*;
+
+#[cfg(not(feature = "testing"))]
+#[derive(Default)]
+pub struct Faults {}
+
+#[cfg(not(feature = "testing"))]
+impl Faults {
+    #[inline]
+    pub fn metric_delay(&self) -> Option<std::time::Duration> {
+        None
+    }
+
+    #[inline]
+    pub fn session_store_failing(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(feature = "testing")]
+mod enabled {
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::time::Duration;
+
+    /// Current fault settings, as read and written by `/admin/faults`.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct FaultSettings {
+        /// Extra delay added to every metric computation.
+        pub slow_metric_ms: u64,
+        /// Make every session store operation fail.
+        pub fail_sessions: bool,
+    }
+
+    #[derive(Default)]
+    pub struct Faults {
+        slow_metric_ms: AtomicU64,
+        fail_sessions: AtomicBool,
+    }
+
+    impl Faults {
+        pub fn metric_delay(&self) -> Option<Duration> {
+            match self.slow_metric_ms.load(Ordering::Relaxed) {
+                0 => None,
+                ms => Some(Duration::from_millis(ms)),
+            }
+        }
+
+        pub fn session_store_failing(&self) -> bool {
+            self.fail_sessions.load(Ordering::Relaxed)
+        }
+
+        pub fn settings(&self) -> FaultSettings {
+            FaultSettings {
+                slow_metric_ms: self.slow_metric_ms.load(Ordering::Relaxed),
+                fail_sessions: self.fail_sessions.load(Ordering::Relaxed),
+            }
+        }
+
+        pub fn apply(&self, settings: FaultSettings) {
+            self.slow_metric_ms
+                .store(settings.slow_metric_ms, Ordering::Relaxed);
+            self.fail_sessions
+                .store(settings.fail_sessions, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_apply_and_clear_faults() {
+            let faults = Faults::default();
+            assert_eq!(faults.metric_delay(), None);
+
+            let settings: FaultSettings =
+                serde_json::from_str(r#"{"slow_metric_ms": 25, "fail_sessions": true}"#).unwrap();
+            faults.apply(settings);
+            assert_eq!(faults.metric_delay(), Some(Duration::from_millis(25)));
+            assert!(faults.session_store_failing());
+
+            faults.apply(FaultSettings::default());
+            assert_eq!(faults.settings(), FaultSettings::default());
+        }
+    }
+}
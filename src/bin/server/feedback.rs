@@ -0,0 +1,255 @@
+//! Reviewer verdict corrections, keyed by trace hex ID.
+//!
+//! Reviewers post the verdict a scored message should have received; the
+//! latest label per trace wins. Joined with the trace store's metrics they
+//! form the labeled samples `word_math_guard::tuning` fits a config to.
+//! With an embedded `KvStore` labels live next to the traces, under
+//! `feedback/<hex_id>`, and outlive the traces they refer to, so agreement
+//! with reviewers can be tracked over time.
+
+use crate::kv::KvStore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+use word_math_guard::batch::VerdictCounts;
+use word_math_guard::Verdict;
+
+const KV_PREFIX: &str = "feedback/";
+
+/// Body of `POST /feedback`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeedbackRequest {
+    pub hex_id: String,
+    /// The verdict the reviewer says the message deserved.
+    pub label: Verdict,
+    #[serde(default)]
+    pub reviewer: Option<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// A stored label, with the verdict the message was served.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Feedback {
+    pub hex_id: String,
+    pub label: Verdict,
+    pub served: Verdict,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviewer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// How a served verdict compares with the reviewer's label. Warn and Block
+/// both count as flagged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackClass {
+    /// Flagged, and the reviewer agreed it should be.
+    TruePositive,
+    /// Allowed, and the reviewer agreed.
+    TrueNegative,
+    /// Flagged, but the reviewer would have allowed it.
+    FalsePositive,
+    /// Allowed, but the reviewer would have flagged it.
+    FalseNegative,
+}
+
+impl FeedbackClass {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "true_positive" => Some(FeedbackClass::TruePositive),
+            "true_negative" => Some(FeedbackClass::TrueNegative),
+            "false_positive" => Some(FeedbackClass::FalsePositive),
+            "false_negative" => Some(FeedbackClass::FalseNegative),
+            _ => None,
+        }
+    }
+}
+
+impl Feedback {
+    pub fn class(&self) -> FeedbackClass {
+        match (self.served != Verdict::Allow, self.label != Verdict::Allow) {
+            (true, true) => FeedbackClass::TruePositive,
+            (false, false) => FeedbackClass::TrueNegative,
+            (true, false) => FeedbackClass::FalsePositive,
+            (false, true) => FeedbackClass::FalseNegative,
+        }
+    }
+}
+
+/// Agreement between served verdicts and reviewer labels.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FeedbackStats {
+    pub labels: usize,
+    pub true_positive: usize,
+    pub true_negative: usize,
+    pub false_positive: usize,
+    pub false_negative: usize,
+    /// Exact verdict matches, Warn and Block told apart.
+    pub exact_matches: usize,
+    /// Share of flagged messages the reviewers also flagged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precision: Option<f64>,
+    /// Share of reviewer-flagged messages that were flagged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recall: Option<f64>,
+    pub labeled: VerdictCounts,
+}
+
+#[derive(Default)]
+pub struct FeedbackStore {
+    kv: Option<Arc<KvStore>>,
+    labels: Mutex<HashMap<String, Feedback>>,
+}
+
+impl FeedbackStore {
+    /// Persist labels in `kv`, first loading the ones stored there.
+    pub fn with_persistence(mut self, kv: Arc<KvStore>) -> Self {
+        self.kv = Some(kv);
+        self.reload();
+        self
+    }
+
+    /// Replace the in-memory labels with the persisted ones.
+    pub fn reload(&self) -> usize {
+        let Some(kv) = &self.kv else { return 0 };
+        let mut labels = self.lock();
+        labels.clear();
+        for (key, value) in kv.scan_prefix(KV_PREFIX) {
+            match serde_json::from_str::<Feedback>(&value) {
+                Ok(feedback) => {
+                    labels.insert(feedback.hex_id.clone(), feedback);
+                }
+                Err(e) => warn!("skipping unreadable stored feedback {}: {}", key, e),
+            }
+        }
+        labels.len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Feedback>> {
+        self.labels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Store a label, replacing any earlier one for the same trace.
+    pub fn record(&self, feedback: Feedback) -> std::io::Result<()> {
+        if let Some(kv) = &self.kv {
+            let json = serde_json::to_string(&feedback).map_err(std::io::Error::other)?;
+            kv.put(&format!("{}{}", KV_PREFIX, feedback.hex_id), json)?;
+        }
+        self.lock().insert(feedback.hex_id.clone(), feedback);
+        Ok(())
+    }
+
+    pub fn get(&self, hex_id: &str) -> Option<Feedback> {
+        self.lock().get(hex_id).cloned()
+    }
+
+    /// Every current label, in hex ID (time) order.
+    pub fn all(&self) -> Vec<Feedback> {
+        let mut all: Vec<Feedback> = self.lock().values().cloned().collect();
+        all.sort_by(|a, b| a.hex_id.cmp(&b.hex_id));
+        all
+    }
+
+    /// Precision and recall of the served verdicts against the labels
+    /// whose hex ID is at least `since` (hex IDs sort by time).
+    pub fn stats(&self, since: Option<&str>) -> FeedbackStats {
+        let mut stats = FeedbackStats::default();
+        for feedback in self.lock().values() {
+            if since.is_some_and(|since| feedback.hex_id.as_str() < since) {
+                continue;
+            }
+            stats.labels += 1;
+            stats.labeled.record(feedback.label);
+            stats.exact_matches += usize::from(feedback.label == feedback.served);
+            match feedback.class() {
+                FeedbackClass::TruePositive => stats.true_positive += 1,
+                FeedbackClass::TrueNegative => stats.true_negative += 1,
+                FeedbackClass::FalsePositive => stats.false_positive += 1,
+                FeedbackClass::FalseNegative => stats.false_negative += 1,
+            }
+        }
+        let ratio = |hits: usize, misses: usize| {
+            (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64)
+        };
+        stats.precision = ratio(stats.true_positive, stats.false_positive);
+        stats.recall = ratio(stats.true_positive, stats.false_negative);
+        stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use word_math_guard::generate_hex_id;
+
+    fn feedback(hex_id: &str, served: Verdict, label: Verdict) -> Feedback {
+        Feedback {
+            hex_id: hex_id.to_string(),
+            label,
+            served,
+            reviewer: None,
+            note: None,
+        }
+    }
+
+    #[test]
+    fn test_latest_label_wins() {
+        let store = FeedbackStore::default();
+        for (hex_id, label) in [
+            ("0002", Verdict::Block),
+            ("0001", Verdict::Allow),
+            ("0002", Verdict::Warn),
+        ] {
+            store
+                .record(feedback(hex_id, Verdict::Allow, label))
+                .unwrap();
+        }
+        let all = store.all();
+        assert_eq!(store.len(), 2);
+        assert_eq!(all[0].hex_id, "0001");
+        assert_eq!(all[1].label, Verdict::Warn);
+    }
+
+    #[test]
+    fn test_stats_and_persistence() {
+        let dir = std::env::temp_dir().join(format!("wordmath-feedback-{}", generate_hex_id()));
+        let kv = Arc::new(KvStore::open(&dir).unwrap());
+        let store = FeedbackStore::default().with_persistence(Arc::clone(&kv));
+        for record in [
+            feedback("0001", Verdict::Block, Verdict::Warn),
+            feedback("0002", Verdict::Warn, Verdict::Allow),
+            feedback("0003", Verdict::Allow, Verdict::Block),
+            feedback("0004", Verdict::Block, Verdict::Block),
+        ] {
+            store.record(record).unwrap();
+        }
+        assert_eq!(
+            store.get("0002").unwrap().class(),
+            FeedbackClass::FalsePositive
+        );
+
+        let reloaded = FeedbackStore::default().with_persistence(kv);
+        let stats = reloaded.stats(None);
+        assert_eq!(stats.labels, 4);
+        assert_eq!(
+            (
+                stats.true_positive,
+                stats.false_positive,
+                stats.false_negative
+            ),
+            (2, 1, 1)
+        );
+        assert_eq!(stats.exact_matches, 1);
+        assert_eq!(stats.precision, Some(2.0 / 3.0));
+        assert_eq!(stats.recall, Some(2.0 / 3.0));
+        assert_eq!(reloaded.stats(Some("0003")).labels, 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
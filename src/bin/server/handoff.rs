@@ -0,0 +1,124 @@
+//! Warm state handoff between instances during blue/green deploys.
+//!
+//! Sessions keep exponentially smoothed scores and the replay tracker keeps
+//! per-client MinHash windows; both live in memory, so a fresh instance
+//! starts cold and smoothed session verdicts flip as scores re-converge.
+//! `GET /admin/handoff` on the old instance writes every live session and
+//! client window as NDJSON, one record per line, and `POST /admin/handoff`
+//! on the new one adopts them. Records for sessions or clients the new
+//! instance has already seen are skipped, since its own state is newer.
+//!
+//! Instead of pushing, a new instance can pull at startup: with
+//! WORD_MATH_HANDOFF_FROM=http://old-instance:3000 set it fetches the
+//! handoff before serving, authenticating with the admin key in
+//! WORD_MATH_HANDOFF_KEY (which accepts the `word_math_guard::secret`
+//! forms). A failed pull is logged and the instance starts cold.
+
+use crate::proxy::Upstream;
+use crate::replay::{ClientSnapshot, API_KEY_HEADER};
+use axum::http::{HeaderMap, HeaderValue, Method};
+use serde::{Deserialize, Serialize};
+use std::io;
+use word_math_guard::secret::{self, Secret};
+use word_math_guard::ConversationSnapshot;
+
+pub const PATH: &str = "/admin/handoff";
+/// Largest handoff stream pulled from another instance.
+const MAX_STREAM_BYTES: usize = 512 * 1024 * 1024;
+
+/// One line of a handoff stream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HandoffRecord {
+    Session {
+        id: String,
+        snapshot: ConversationSnapshot,
+    },
+    Client(ClientSnapshot),
+}
+
+/// What an import adopted and skipped.
+#[derive(Debug, Default, Serialize)]
+pub struct HandoffReport {
+    pub sessions: usize,
+    pub clients: usize,
+    /// Records for state this instance already had.
+    pub skipped: usize,
+}
+
+pub fn encode(records: &[HandoffRecord]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for record in records {
+        if let Ok(line) = serde_json::to_vec(record) {
+            out.extend_from_slice(&line);
+            out.push(b'\n');
+        }
+    }
+    out
+}
+
+/// Parse an NDJSON handoff stream; one bad line rejects the whole stream.
+pub fn decode(body: &[u8]) -> Result<Vec<HandoffRecord>, String> {
+    body.split(|&b| b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+        .map(|(i, line)| serde_json::from_slice(line).map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect()
+}
+
+/// The instance to pull from at startup, and the key to pull with.
+pub fn source_from_env() -> Result<Option<(Upstream, Option<Secret>)>, String> {
+    let Ok(url) = std::env::var("WORD_MATH_HANDOFF_FROM") else {
+        return Ok(None);
+    };
+    let source = Upstream::parse(url.trim())?.with_max_response_bytes(MAX_STREAM_BYTES);
+    let key = secret::from_env("WORD_MATH_HANDOFF_KEY").map_err(|e| e.to_string())?;
+    Ok(Some((source, key)))
+}
+
+/// Fetch the handoff stream of the instance at `source`.
+pub async fn pull(source: &Upstream, key: Option<&Secret>) -> io::Result<Vec<HandoffRecord>> {
+    let mut headers = HeaderMap::new();
+    if let Some(key) = key {
+        let mut value = HeaderValue::from_str(key.expose()).map_err(io::Error::other)?;
+        value.set_sensitive(true);
+        headers.insert(API_KEY_HEADER, value);
+    }
+    let response = source.send(&Method::GET, PATH, &headers, &[]).await?;
+    if !response.status.is_success() {
+        return Err(io::Error::other(format!(
+            "{} answered {}",
+            source.authority(),
+            response.status
+        )));
+    }
+    decode(&response.body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use word_math_guard::{ConversationAnalyzer, WordMathConfig};
+
+    #[test]
+    fn test_records_round_trip_as_ndjson() {
+        let mut conv = ConversationAnalyzer::new("rust web server", WordMathConfig::default());
+        conv.push("the rust web server is up");
+        let records = vec![HandoffRecord::Session {
+            id: "s1".to_string(),
+            snapshot: conv.snapshot(),
+        }];
+        let encoded = encode(&records);
+        assert_eq!(encoded.iter().filter(|&&b| b == b'\n').count(), 1);
+        let decoded = decode(&encoded).unwrap();
+        let HandoffRecord::Session { id, snapshot } = &decoded[0] else {
+            panic!("expected a session record");
+        };
+        assert_eq!(id, "s1");
+        assert_eq!(
+            ConversationAnalyzer::restore(snapshot.clone()).session_score(),
+            conv.session_score()
+        );
+        assert!(decode(b"{\"kind\":\"session\"}\n").is_err());
+    }
+}
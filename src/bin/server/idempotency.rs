@@ -0,0 +1,317 @@
+//! Replay cache for requests carrying an `Idempotency-Key` header.
+//!
+//! Clients retry on network timeouts; without this every retry would get a
+//! fresh hex ID and a duplicate audit line. A keyed request seen within the
+//! TTL returns the original response instead of being re-analyzed.
+//!
+//! The first request with a key reserves it before scoring. A retry that
+//! arrives while that request is still in flight waits for its response
+//! instead of scoring the message a second time; if the first request
+//! fails, one waiter takes the reservation over. Keys are scoped by the
+//! caller's API key, so one client cannot read another's cached response
+//! by guessing its idempotency key.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use word_math_guard::hash::FxHasher;
+use word_math_guard::merkle;
+
+pub const HEADER: &str = "idempotency-key";
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+const DEFAULT_TTL: Duration = Duration::from_secs(300);
+const DEFAULT_MAX_ENTRIES: usize = 10_000;
+
+/// Outcome of looking up a key.
+pub enum Lookup<'a, V> {
+    /// Key unseen or expired: the caller computes the response and
+    /// `complete`s the reservation.
+    Miss(Reservation<'a, V>),
+    /// Same key and same request: replay the stored response.
+    Hit(V),
+    /// Same key reused for a different request.
+    Conflict,
+}
+
+/// Cache key: the caller's scope (a digest of its API key) and the
+/// idempotency key it sent.
+type Slot = (Option<String>, String);
+
+enum State<V> {
+    Pending {
+        reservation: u64,
+        done: watch::Receiver<Option<V>>,
+    },
+    Done {
+        inserted: Instant,
+        value: V,
+    },
+}
+
+struct Entry<V> {
+    fingerprint: u64,
+    state: State<V>,
+}
+
+pub struct IdempotencyCache<V> {
+    ttl: Duration,
+    max_entries: usize,
+    reservations: AtomicU64,
+    entries: Mutex<HashMap<Slot, Entry<V>>>,
+}
+
+/// An in-flight request holding its key. Dropping it without `complete`
+/// (the request failed or was cancelled) releases the key.
+pub struct Reservation<'a, V> {
+    cache: &'a IdempotencyCache<V>,
+    slot: Option<Slot>,
+    fingerprint: u64,
+    id: u64,
+    done: watch::Sender<Option<V>>,
+}
+
+impl<V: Clone> IdempotencyCache<V> {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries: max_entries.max(1),
+            reservations: AtomicU64::new(0),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// TTL from WORD_MATH_IDEMPOTENCY_TTL_SECS (default 300s).
+    pub fn from_env() -> Self {
+        let ttl = std::env::var("WORD_MATH_IDEMPOTENCY_TTL_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .map_or(DEFAULT_TTL, Duration::from_secs);
+        Self::new(ttl, DEFAULT_MAX_ENTRIES)
+    }
+
+    /// Look `key` up for the caller with `api_key`, reserving it when
+    /// unseen and waiting while another request holds it.
+    pub async fn get(&self, api_key: Option<&str>, key: &str, fingerprint: u64) -> Lookup<'_, V> {
+        let scope = api_key.map(|api_key| merkle::to_hex(&merkle::leaf_hash(api_key.as_bytes())));
+        let slot = (scope, key.to_string());
+        loop {
+            let mut pending = {
+                let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+                match entries.get(&slot) {
+                    Some(entry) if entry.fingerprint != fingerprint && self.live(entry) => {
+                        return Lookup::Conflict;
+                    }
+                    Some(Entry {
+                        state: State::Done { inserted, value },
+                        ..
+                    }) if inserted.elapsed() < self.ttl => return Lookup::Hit(value.clone()),
+                    Some(Entry {
+                        state: State::Pending { done, .. },
+                        ..
+                    }) => done.clone(),
+                    _ => {
+                        let id = self.reservations.fetch_add(1, Ordering::Relaxed);
+                        let (done, waiting) = watch::channel(None);
+                        self.evict(&mut entries);
+                        entries.insert(
+                            slot.clone(),
+                            Entry {
+                                fingerprint,
+                                state: State::Pending {
+                                    reservation: id,
+                                    done: waiting,
+                                },
+                            },
+                        );
+                        return Lookup::Miss(Reservation {
+                            cache: self,
+                            slot: Some(slot),
+                            fingerprint,
+                            id,
+                            done,
+                        });
+                    }
+                }
+            };
+            // The holder either publishes its response or drops the
+            // reservation, which closes the channel; then look again.
+            let published = match pending.wait_for(Option::is_some).await {
+                Ok(value) => value.clone(),
+                Err(_) => None,
+            };
+            if let Some(value) = published {
+                return Lookup::Hit(value);
+            }
+        }
+    }
+
+    fn live(&self, entry: &Entry<V>) -> bool {
+        match &entry.state {
+            State::Pending { .. } => true,
+            State::Done { inserted, .. } => inserted.elapsed() < self.ttl,
+        }
+    }
+
+    /// Make room for one entry: drop expired responses, then the oldest.
+    /// In-flight reservations are kept.
+    fn evict(&self, entries: &mut HashMap<Slot, Entry<V>>) {
+        if entries.len() < self.max_entries {
+            return;
+        }
+        entries.retain(|_, entry| self.live(entry));
+        if entries.len() >= self.max_entries {
+            let oldest = entries
+                .iter()
+                .filter_map(|(slot, entry)| match entry.state {
+                    State::Done { inserted, .. } => Some((inserted, slot)),
+                    State::Pending { .. } => None,
+                })
+                .min_by_key(|(inserted, _)| *inserted)
+                .map(|(_, slot)| slot.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl<V: Clone> Reservation<'_, V> {
+    /// Store the response for replays and hand it to waiting retries.
+    pub fn complete(mut self, value: V) {
+        let Some(slot) = self.slot.take() else {
+            return;
+        };
+        let mut entries = self.cache.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if !entries.contains_key(&slot) {
+            self.cache.evict(&mut entries);
+        }
+        entries.insert(
+            slot,
+            Entry {
+                fingerprint: self.fingerprint,
+                state: State::Done {
+                    inserted: Instant::now(),
+                    value: value.clone(),
+                },
+            },
+        );
+        drop(entries);
+        self.done.send_replace(Some(value));
+    }
+}
+
+impl<V> Drop for Reservation<'_, V> {
+    fn drop(&mut self) {
+        let Some(slot) = self.slot.take() else {
+            return;
+        };
+        let mut entries = self.cache.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let ours = matches!(
+            entries.get(&slot),
+            Some(Entry { state: State::Pending { reservation, .. }, .. }) if *reservation == self.id
+        );
+        if ours {
+            entries.remove(&slot);
+        }
+    }
+}
+
+/// Stable fingerprint of the request fields that determine the response.
+pub fn fingerprint(parts: &[Option<&str>]) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = FxHasher::default();
+    parts.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn complete(lookup: Lookup<'_, u32>, value: u32) {
+        match lookup {
+            Lookup::Miss(reservation) => reservation.complete(value),
+            _ => panic!("expected a miss"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_conflict_and_expiry() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60), 2);
+        let fp = fingerprint(&[Some("hello"), None]);
+        complete(cache.get(None, "k1", fp).await, 7);
+        assert!(matches!(cache.get(None, "k1", fp).await, Lookup::Hit(7)));
+        let other = fingerprint(&[Some("hello"), Some("topic")]);
+        assert!(matches!(
+            cache.get(None, "k1", other).await,
+            Lookup::Conflict
+        ));
+
+        let expired = IdempotencyCache::new(Duration::ZERO, 2);
+        complete(expired.get(None, "k1", fp).await, 7);
+        assert!(matches!(expired.get(None, "k1", fp).await, Lookup::Miss(_)));
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_scoped_by_api_key() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60), 8);
+        complete(cache.get(Some("tenant-a"), "k1", 1).await, 7);
+        assert!(matches!(
+            cache.get(Some("tenant-a"), "k1", 1).await,
+            Lookup::Hit(7)
+        ));
+        assert!(matches!(
+            cache.get(Some("tenant-b"), "k1", 1).await,
+            Lookup::Miss(_)
+        ));
+        assert!(matches!(cache.get(None, "k1", 1).await, Lookup::Miss(_)));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_retry_waits_for_the_first_response() {
+        let cache = Arc::new(IdempotencyCache::new(Duration::from_secs(60), 8));
+        let Lookup::Miss(first) = cache.get(None, "k1", 1).await else {
+            panic!("expected a miss");
+        };
+        let retry = {
+            let cache = Arc::clone(&cache);
+            tokio::spawn(async move { matches!(cache.get(None, "k1", 1).await, Lookup::Hit(9)) })
+        };
+        assert!(matches!(cache.get(None, "k1", 2).await, Lookup::Conflict));
+        tokio::task::yield_now().await;
+        assert!(!retry.is_finished());
+        first.complete(9);
+        assert!(retry.await.unwrap());
+
+        // A failed first attempt hands the key to the waiting retry.
+        let Lookup::Miss(failed) = cache.get(None, "k2", 1).await else {
+            panic!("expected a miss");
+        };
+        let retry = {
+            let cache = Arc::clone(&cache);
+            tokio::spawn(async move {
+                complete(cache.get(None, "k2", 1).await, 4);
+            })
+        };
+        tokio::task::yield_now().await;
+        drop(failed);
+        retry.await.unwrap();
+        assert!(matches!(cache.get(None, "k2", 1).await, Lookup::Hit(4)));
+    }
+
+    #[tokio::test]
+    async fn test_capacity_evicts_oldest() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60), 2);
+        complete(cache.get(None, "a", 1).await, 1);
+        std::thread::sleep(Duration::from_millis(2));
+        complete(cache.get(None, "b", 2).await, 2);
+        complete(cache.get(None, "c", 3).await, 3);
+        assert!(matches!(cache.get(None, "a", 1).await, Lookup::Miss(_)));
+        assert!(matches!(cache.get(None, "c", 3).await, Lookup::Hit(3)));
+    }
+}
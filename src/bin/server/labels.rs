@@ -0,0 +1,143 @@
+//! Static deployment labels, such as environment, region and service name.
+//!
+//! Set WORD_MATH_LABELS=environment=production,region=eu-west-1,service=guard
+//! to stamp the labels into every trace record (and so into verdict sink
+//! deliveries, including webhook payloads), every published Merkle digest,
+//! and every sample on `/metrics`. That lets downstream aggregation across
+//! deployments tell staging records from production ones. Names follow the
+//! Prometheus rules and must not clash with the labels the metrics already
+//! use.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Label names the exported metrics use themselves.
+const RESERVED: [&str; 6] = ["le", "quantile", "reason", "sink", "trace_id", "verdict"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Labels(BTreeMap<String, String>);
+
+impl Labels {
+    /// Parse `name=value` pairs separated by commas.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut labels = BTreeMap::new();
+        for pair in spec.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let (name, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected name=value, got {:?}", pair))?;
+            let (name, value) = (name.trim(), value.trim());
+            let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !name.starts_with("__");
+            if !valid {
+                return Err(format!("invalid label name {:?}", name));
+            }
+            if RESERVED.contains(&name) {
+                return Err(format!("label name {:?} is used by the metrics", name));
+            }
+            if labels.insert(name.to_string(), value.to_string()).is_some() {
+                return Err(format!("duplicate label {:?}", name));
+            }
+        }
+        Ok(Self(labels))
+    }
+
+    /// Labels from WORD_MATH_LABELS; none when unset.
+    pub fn from_env() -> Result<Self, String> {
+        std::env::var("WORD_MATH_LABELS").map_or(Ok(Self::default()), |spec| Self::parse(&spec))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The labels in Prometheus syntax, without braces.
+    fn render(&self) -> String {
+        self.0
+            .iter()
+            .map(|(name, value)| {
+                let value = value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                format!("{}=\"{}\"", name, value)
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// `body` in the Prometheus text format with the labels added to every
+    /// sample; comments and exemplars are left as they are.
+    pub fn stamp_metrics(&self, body: &str) -> String {
+        if self.is_empty() {
+            return body.to_string();
+        }
+        let labels = self.render();
+        let mut out = String::with_capacity(body.len() + body.lines().count() * labels.len());
+        for line in body.lines() {
+            if line.starts_with('#') || line.is_empty() {
+                out.push_str(line);
+            } else {
+                let name_end = line.find(['{', ' ']).unwrap_or(line.len());
+                let (name, rest) = line.split_at(name_end);
+                out.push_str(name);
+                match rest.strip_prefix('{') {
+                    Some(rest) if rest.starts_with('}') => {
+                        out.push('{');
+                        out.push_str(&labels);
+                        out.push_str(rest);
+                    }
+                    Some(rest) => {
+                        out.push('{');
+                        out.push_str(&labels);
+                        out.push(',');
+                        out.push_str(rest);
+                    }
+                    None => {
+                        out.push('{');
+                        out.push_str(&labels);
+                        out.push('}');
+                        out.push_str(rest);
+                    }
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_parse_and_stamp_every_sample() {
+        let labels = Labels::parse("environment=staging, region=eu-west-1,service=guard").unwrap();
+        let body = "# HELP wordmath_requests_total Requests.\n\
+                    # TYPE wordmath_requests_total counter\n\
+                    wordmath_requests_total 7\n\
+                    wordmath_verdicts_total{verdict=\"warn\"} 2\n\
+                    wordmath_score_bucket{le=\"0.5\"} 3 # {trace_id=\"ab\"} 0.4 1.000\n";
+        assert_eq!(
+            labels.stamp_metrics(body),
+            "# HELP wordmath_requests_total Requests.\n\
+             # TYPE wordmath_requests_total counter\n\
+             wordmath_requests_total{environment=\"staging\",region=\"eu-west-1\",service=\"guard\"} 7\n\
+             wordmath_verdicts_total{environment=\"staging\",region=\"eu-west-1\",service=\"guard\",verdict=\"warn\"} 2\n\
+             wordmath_score_bucket{environment=\"staging\",region=\"eu-west-1\",service=\"guard\",le=\"0.5\"} 3 # {trace_id=\"ab\"} 0.4 1.000\n"
+        );
+        assert_eq!(
+            serde_json::to_value(&labels).unwrap(),
+            serde_json::json!({"environment": "staging", "region": "eu-west-1", "service": "guard"})
+        );
+        assert_eq!(Labels::default().stamp_metrics(body), body);
+
+        assert!(Labels::parse("").unwrap().is_empty());
+        assert!(Labels::parse("environment").is_err());
+        assert!(Labels::parse("1env=prod").is_err());
+        assert!(Labels::parse("verdict=prod").is_err());
+        assert!(Labels::parse("env=a,env=b").is_err());
+    }
+}
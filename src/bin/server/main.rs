@@ -0,0 +1,2225 @@
+mod audit;
+mod auth;
+mod cipher;
+mod experiments;
+mod faults;
+mod feedback;
+mod handoff;
+mod idempotency;
+mod kv;
+mod labels;
+mod metrics;
+mod migrations;
+mod precision;
+mod privacy;
+#[cfg(all(feature = "ops", target_os = "linux"))]
+mod profiler;
+mod proxy;
+mod replay;
+mod resources;
+mod rollups;
+mod runtime;
+mod schema;
+mod sessions;
+#[cfg(unix)]
+mod signals;
+mod sinks;
+mod switches;
+mod traces;
+
+use auth::{ApiKeys, Role};
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, OriginalUri, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use experiments::{ExperimentReport, Experiments, Variant};
+use faults::Faults;
+use feedback::{Feedback, FeedbackClass, FeedbackRequest, FeedbackStats, FeedbackStore};
+use idempotency::IdempotencyCache;
+use labels::Labels;
+use metrics::{Histogram, RecentRequests, VerdictCounters};
+use precision::Precision;
+use privacy::CountNoise;
+use proxy::Upstream;
+use replay::ReplayTracker;
+use resources::{ResourceSet, ResourceVersion, Resources};
+use serde::{Deserialize, Serialize};
+use sessions::SessionStore;
+use std::time::{Duration, Instant};
+use std::{net::SocketAddr, sync::Arc};
+use switches::{SwitchChange, SwitchKind, SwitchReport, Switches};
+use traces::{TraceRecord, TraceStore};
+use tracing::{debug, info, warn};
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
+use word_math_guard::{
+    analyze_with_corpus, analyze_with_domain, analyze_with_topic,
+    asr::{self, WeightedWord},
+    batch::{self, BatchSummary},
+    best_topic,
+    clusters::{self, TermCluster},
+    compare::{self, MessageComparison, SimilarityMatrix},
+    conversation::TurnSummary,
+    generate_hex_id,
+    rewrite::{self, Sanitized, TrimSuggestion},
+    tuning::{self, LabeledSample, TuningReport},
+    verdict, CompiledTopic, ConversationSnapshot, Corpus, Severity, TopicCache, Verdict,
+    VerdictExplanation, WordMathAnalysis, WordMathConfig,
+};
+
+#[derive(Debug, Deserialize)]
+struct AnalyzeParams {
+    /// The user message to score.
+    message: String,
+    /// A short topic summary for the session.
+    topic: Option<String>,
+    /// ID of a registry topic; used instead of `topic` when given.
+    topic_id: Option<String>,
+    /// Comma-separated registry topic IDs. The message is scored against
+    /// the closest one, and the response names it with its margin.
+    topic_ids: Option<String>,
+    /// ID of a reference-document corpus; drift is then measured against
+    /// the documents instead of a topic. Not combinable with sessions.
+    corpus_id: Option<String>,
+    /// ID of a corpus used as the topic's domain: drift against the topic
+    /// weighs words by their IDF in its documents. Not combinable with
+    /// `corpus_id` or sessions.
+    domain_id: Option<String>,
+    /// Optional named scoring profile; defaults to the env config.
+    profile: Option<String>,
+    /// Optional conversation session; the message is scored as its next turn.
+    session_id: Option<String>,
+    /// Return a repaired copy of Warn/Block messages (tail loop cut,
+    /// repeated sentences collapsed) with its own score and verdict.
+    #[serde(default)]
+    sanitize: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct AnalyzeResponse {
+    y_repetition: f64,
+    z_drift: f64,
+    score: f64,
+    raw_score: f64,
+    /// Combined homoglyph, split-letter and padding signal; see
+    /// `evasion::risk`.
+    evasion_risk: f64,
+    verdict: Verdict,
+    /// Graded severity from the profile's score bands.
+    severity: Severity,
+    /// Present when the verdict is Warn or Block.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explanation: Option<Explanation>,
+    /// Present when the request named a session.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session: Option<SessionInfo>,
+    /// True when a requested session could not be used and the message was
+    /// scored on its own.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    degraded: bool,
+    /// Share of this API key's recent messages replayed across sessions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_replay_ratio: Option<f64>,
+    /// Present when `sanitize` was requested and the verdict was not Allow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sanitized: Option<SanitizedInfo>,
+    /// Detected message language, when calibration tables are loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'static str>,
+    /// Present when `topic_ids` named candidate topics.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    topic_match: Option<TopicMatchInfo>,
+    hex_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TopicMatchInfo {
+    /// The candidate with the lowest drift; the message is scored against it.
+    topic_id: String,
+    drift: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runner_up: Option<String>,
+    /// Runner-up drift minus the best drift.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    margin: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SanitizedInfo {
+    #[serde(flatten)]
+    repair: Sanitized,
+    score_before: f64,
+    score_after: f64,
+    verdict_after: Verdict,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SessionInfo {
+    id: String,
+    turn: usize,
+    session_score: f64,
+    session_verdict: Verdict,
+    /// y / z / score of the last WORD_MATH_TRACE_HISTORY turns, oldest first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    history: Vec<TurnSummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Explanation {
+    reason: String,
+    #[serde(flatten)]
+    detail: VerdictExplanation,
+    /// Off-topic terms grouped by co-occurrence, largest group first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    drift_clusters: Vec<TermCluster>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchRequest {
+    items: Vec<BatchRequestItem>,
+    /// Optional named scoring profile for items that don't name their own.
+    profile: Option<String>,
+    /// How many of the worst items to list in the summary.
+    worst_k: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchRequestItem {
+    message: String,
+    topic: Option<String>,
+    topic_id: Option<String>,
+    /// Overrides the batch-level profile for this item.
+    profile: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchResponseItem {
+    y_repetition: f64,
+    z_drift: f64,
+    score: f64,
+    verdict: Verdict,
+    /// Profile the item was scored with, when not the env config.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
+    hex_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchResponse {
+    items: Vec<BatchResponseItem>,
+    summary: BatchSummary,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompareRequest {
+    a: String,
+    b: String,
+    topic: Option<String>,
+    topic_id: Option<String>,
+    profile: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CompareResponse {
+    #[serde(flatten)]
+    comparison: MessageComparison,
+    drift_delta: f64,
+    hex_id: String,
+}
+
+/// A speech-recognition transcript, word by word with confidences.
+#[derive(Debug, Deserialize)]
+struct TranscriptRequest {
+    words: Vec<WeightedWord>,
+    topic: Option<String>,
+    topic_id: Option<String>,
+    profile: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct TranscriptResponse {
+    y_repetition: f64,
+    z_drift: f64,
+    score: f64,
+    verdict: Verdict,
+    mean_confidence: f64,
+    hex_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SimilarityRequest {
+    /// One message, split into sentences.
+    message: Option<String>,
+    /// A transcript, one unit per turn; used instead of `message` when given.
+    messages: Option<Vec<String>>,
+    max_dim: Option<usize>,
+    profile: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TrimRequest {
+    message: String,
+    profile: Option<String>,
+}
+
+struct AppState {
+    cfg: WordMathConfig,
+    /// Profiles, registry topics, corpora, calibration and policy rules,
+    /// swapped as one set on `/admin/reload`.
+    resources: Resources,
+    /// Runtime kill switches for metrics and rules.
+    switches: Switches,
+    /// A/B experiments from WORD_MATH_EXPERIMENTS.
+    experiments: Experiments,
+    /// Compiled free-text topics shared across requests.
+    topic_cache: TopicCache,
+    /// Responses to keyed requests, replayed on client retries.
+    idempotency: IdempotencyCache<AnalyzeResponse>,
+    sessions: SessionStore,
+    /// Recent analysis records, queryable by hex ID.
+    traces: Arc<TraceStore>,
+    /// Reviewer verdict corrections for traced messages.
+    feedback: FeedbackStore,
+    /// Latest Merkle root over `traces`.
+    auditor: Arc<audit::MerkleAuditor>,
+    /// Embedded store behind `sessions` and `traces`, if persistence is on.
+    kv: Option<Arc<kv::KvStore>>,
+    replay: ReplayTracker,
+    /// Scores of single-message analyses; Warn/Block results carry exemplars.
+    score_hist: Histogram,
+    /// Time spent scoring; requests slower than `slow_request` carry exemplars.
+    latency_hist: Histogram,
+    slow_request: Duration,
+    verdicts: VerdictCounters,
+    /// Requests and scores over the last hour, for `/stats`.
+    recent: RecentRequests,
+    faults: Faults,
+    /// Decimal places of floats in responses and traces.
+    precision: Precision,
+    /// LLM backend for proxy mode, from WORD_MATH_UPSTREAM.
+    upstream: Option<Upstream>,
+    /// Noise on exported counts, from WORD_MATH_DP_EPSILON.
+    count_noise: Option<CountNoise>,
+}
+
+impl AppState {
+    /// Operator-facing snapshot of config and counters.
+    fn dump(&self) -> serde_json::Value {
+        let (evicted_lru, evicted_ttl) = self.sessions.evictions();
+        let cache = self.topic_cache.stats();
+        let resources = self.resources.current();
+        serde_json::json!({
+            "hex_id": generate_hex_id(),
+            "config": self.cfg,
+            "profiles": resources.profiles.names(),
+            "registry_topics": resources.topics.len(),
+            "corpora": resources.corpora.len(),
+            "policy_rules": resources.rules.len(),
+            "experiments": self.experiments.len(),
+            "topic_cache": {
+                "hits": cache.hits,
+                "misses": cache.misses,
+                "entries": cache.entries,
+            },
+            "sessions": {
+                "active": self.sessions.len(),
+                "max": self.sessions.max_sessions(),
+                "evicted_lru": evicted_lru,
+                "evicted_ttl": evicted_ttl,
+            },
+            "traces_stored": self.traces.len(),
+            "verdict_sinks": self.traces.sinks().names(),
+            "labels": self.traces.labels(),
+            "feedback_labels": self.feedback.len(),
+            "verdicts": self.verdicts.snapshot(),
+        })
+    }
+
+    /// Post-scoring pipeline shared by every scoring path: drop switched-off
+    /// metrics (and those of the experiment variant), calibrate by language,
+    /// apply thresholds, then enabled rules.
+    fn judge(
+        &self,
+        message: &str,
+        analysis: &mut WordMathAnalysis,
+        cfg: &WordMathConfig,
+        variant: Option<&Variant>,
+    ) -> (VerdictExplanation, Option<&'static str>) {
+        self.switches.apply_metrics(analysis, cfg);
+        if let Some(variant) = variant {
+            switches::drop_metrics(
+                analysis,
+                cfg,
+                variant.metric_disabled("repetition"),
+                variant.metric_disabled("drift"),
+            );
+        }
+        let resources = self.resources.current();
+        let language = resources.calibration.calibrate(message, analysis);
+        let mut explanation = verdict::evaluate(analysis, cfg);
+        resources
+            .rules
+            .apply_where(analysis, &mut explanation, |rule| {
+                self.switches.rule_enabled(&rule.id)
+                    && variant.is_none_or(|variant| !variant.rule_disabled(&rule.id))
+            });
+        (explanation, language)
+    }
+
+    /// Resolve the config for an optional profile name.
+    fn config_for(&self, profile: Option<&str>) -> Result<WordMathConfig, (StatusCode, String)> {
+        match profile {
+            None => Ok(self.cfg),
+            Some(name) => self.resources.current().profiles.get(name).ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("unknown profile: {}", name),
+                )
+            }),
+        }
+    }
+
+    /// Resolve a corpus ID given as `field`; corpora score single
+    /// messages only.
+    fn corpus_for(
+        &self,
+        field: &str,
+        id: &str,
+        in_session: bool,
+    ) -> Result<Arc<Corpus>, (StatusCode, String)> {
+        if in_session {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("{} cannot be combined with session_id", field),
+            ));
+        }
+        self.resources.current().corpora.get(id).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("unknown {}: {}", field, id),
+            )
+        })
+    }
+
+    /// Resolve comma-separated registry topic IDs and pick the one closest
+    /// to `message`.
+    fn match_topics(
+        &self,
+        message: &str,
+        ids: &str,
+        with_corpus: bool,
+        cfg: &WordMathConfig,
+    ) -> Result<(Arc<CompiledTopic>, TopicMatchInfo), (StatusCode, String)> {
+        if with_corpus {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "topic_ids cannot be combined with corpus_id".to_string(),
+            ));
+        }
+        let ids: Vec<&str> = ids
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .collect();
+        let topics = ids
+            .iter()
+            .map(|id| self.topic_for(None, Some(id), cfg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let candidates: Vec<&CompiledTopic> = topics.iter().map(|t| t.as_ref()).collect();
+        let found = best_topic(message, &candidates, *cfg).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                "topic_ids names no topics".to_string(),
+            )
+        })?;
+        let info = TopicMatchInfo {
+            topic_id: ids[found.best].to_string(),
+            drift: found.drift,
+            runner_up: found.runner_up.map(|i| ids[i].to_string()),
+            margin: found.margin,
+        };
+        Ok((Arc::clone(&topics[found.best]), info))
+    }
+
+    /// Resolve a request's topic to a shared compiled topic.
+    fn topic_for(
+        &self,
+        topic: Option<&str>,
+        topic_id: Option<&str>,
+        cfg: &WordMathConfig,
+    ) -> Result<Arc<CompiledTopic>, (StatusCode, String)> {
+        match (topic_id, topic) {
+            (Some(id), _) => {
+                let compiled = self.resources.current().topics.get(id).ok_or_else(|| {
+                    (StatusCode::BAD_REQUEST, format!("unknown topic_id: {}", id))
+                })?;
+                self.topic_cache.record_hit();
+                Ok(compiled)
+            }
+            (None, Some(text)) => Ok(self.topic_cache.get_or_compile(text, cfg.emoji_mode)),
+            (None, None) => Err((
+                StatusCode::BAD_REQUEST,
+                "either topic or topic_id is required".to_string(),
+            )),
+        }
+    }
+}
+
+/// Upgrade the store's schema, refusing to go on if it is newer than
+/// this binary.
+fn migrate_store(kv: &kv::KvStore) {
+    migrate_store_checked(kv).expect("migrating WORD_MATH_DATA_DIR failed");
+}
+
+fn migrate_store_checked(kv: &kv::KvStore) -> std::io::Result<()> {
+    let report = migrations::migrate(kv)?;
+    for step in &report.applied {
+        info!("applied store migration: {}", step);
+    }
+    info!(
+        "store schema v{} (was v{}, {} entries changed)",
+        report.to, report.from, report.entries_changed
+    );
+    Ok(())
+}
+
+fn main() {
+    // `--migrate-only` upgrades the data store's schema and exits.
+    let migrate_only = std::env::args().skip(1).any(|arg| arg == "--migrate-only");
+    let runtime_cfg = runtime::RuntimeConfig::from_env();
+    runtime_cfg
+        .build()
+        .expect("building the tokio runtime failed")
+        .block_on(serve(runtime_cfg, migrate_only));
+}
+
+async fn serve(runtime_cfg: runtime::RuntimeConfig, migrate_only: bool) {
+    // Initialize logging with env-based filter, e.g. RUST_LOG=info
+    let builder = FmtSubscriber::builder()
+        .with_env_filter("info")
+        .with_filter_reloading();
+    let log_filter = builder.reload_handle();
+    tracing::subscriber::set_global_default(builder.finish())
+        .expect("setting default subscriber failed");
+
+    // Load configuration from environment variables.
+    let cfg = WordMathConfig::from_env();
+    info!("Word-Math config: alpha={}, beta={}", cfg.alpha, cfg.beta);
+    info!(
+        "runtime: worker_threads={}, blocking_threads={}, max_connections={}",
+        runtime_cfg
+            .worker_threads
+            .map_or("auto".to_string(), |n| n.to_string()),
+        runtime_cfg.blocking_threads,
+        runtime_cfg.max_connections
+    );
+    info!(
+        "http: keep_alive={}, keep_alive_timeout={}s, header_read_timeout={}s, \
+         h2_max_concurrent_streams={}",
+        runtime_cfg.keep_alive,
+        runtime_cfg.keep_alive_timeout.as_secs(),
+        runtime_cfg.header_read_timeout.as_secs(),
+        runtime_cfg.h2_max_concurrent_streams
+    );
+
+    if migrate_only {
+        match kv::KvStore::from_env().expect("opening WORD_MATH_DATA_DIR failed") {
+            Some(kv) => migrate_store(&kv),
+            None => info!("WORD_MATH_DATA_DIR is not set; nothing to migrate"),
+        }
+        return;
+    }
+
+    // Optional file-backed resources: named profiles, the topic registry
+    // (compiled once so requests only pay for the message side), document
+    // corpora, per-language calibration and policy rules.
+    let resources = ResourceSet::load(cfg.emoji_mode).expect("loading scoring resources failed");
+    for resource in &resources.versions {
+        info!(
+            "loaded {}: {} entries, version {}",
+            resource.name, resource.entries, resource.version
+        );
+    }
+
+    // Optional A/B experiments over profiles, metrics and rules.
+    let experiments = Experiments::from_env().expect("loading WORD_MATH_EXPERIMENTS failed");
+    experiments
+        .validate(&resources.profiles, &resources.rules)
+        .expect("invalid WORD_MATH_EXPERIMENTS");
+    info!("running {} experiment(s)", experiments.len());
+
+    let upstream = Upstream::from_env().expect("invalid WORD_MATH_UPSTREAM");
+    if let Some(upstream) = &upstream {
+        info!("proxying completions to {}", upstream.authority());
+    }
+    let count_noise = CountNoise::from_env().expect("invalid WORD_MATH_DP_EPSILON");
+    if let Some(noise) = &count_noise {
+        info!(
+            "adding epsilon={} noise to exported counts",
+            noise.epsilon()
+        );
+    }
+
+    // Optional single-node persistence in WORD_MATH_DATA_DIR.
+    let mut sessions = SessionStore::from_env();
+    let mut traces = TraceStore::from_env();
+    let mut switches = Switches::default();
+    let mut feedback = FeedbackStore::default();
+    let kv = kv::KvStore::from_env()
+        .expect("opening WORD_MATH_DATA_DIR failed")
+        .map(Arc::new);
+    if let Some(kv) = &kv {
+        info!("persisting sessions and traces in {}", kv.path().display());
+        if let Some(key_id) = kv.key_id() {
+            info!("encrypting persisted values with key {}", key_id);
+        }
+        match kv.unsealed() {
+            0 => migrate_store(kv),
+            unsealed => warn!(
+                "{} value(s) were written before encryption was turned on and are \
+                 ignored until `wordmath admin reencrypt` migrates them",
+                unsealed
+            ),
+        }
+        sessions = sessions.with_persistence(Arc::clone(kv));
+        traces = traces.with_persistence(Arc::clone(kv));
+        switches = switches.with_persistence(Arc::clone(kv));
+        feedback = feedback.with_persistence(Arc::clone(kv));
+        kv.compact().expect("compacting the data store failed");
+        kv::spawn_compactor(Arc::clone(kv));
+    }
+
+    for record in traces.all() {
+        experiments.observe(&record);
+    }
+    let labels = Labels::from_env().expect("invalid WORD_MATH_LABELS");
+    if !labels.is_empty() {
+        info!("stamping records and metrics with {:?}", labels);
+    }
+    let traces = Arc::new(
+        traces
+            .with_labels(labels)
+            .with_sinks(sinks::Sinks::from_env().expect("invalid WORD_MATH_SINKS")),
+    );
+    let auditor = Arc::new(audit::MerkleAuditor::default());
+    audit::spawn(Arc::clone(&auditor), Arc::clone(&traces));
+
+    let state = AppState {
+        cfg,
+        resources: Resources::new(resources),
+        switches,
+        experiments,
+        topic_cache: TopicCache::default(),
+        idempotency: IdempotencyCache::from_env(),
+        sessions,
+        traces: Arc::clone(&traces),
+        feedback,
+        auditor: Arc::clone(&auditor),
+        kv,
+        replay: ReplayTracker::default(),
+        score_hist: Histogram::new(
+            "wordmath_score",
+            "Scores of analyzed messages.",
+            metrics::SCORE_BUCKETS,
+        ),
+        latency_hist: Histogram::new(
+            "wordmath_analyze_seconds",
+            "Time spent scoring a message.",
+            metrics::LATENCY_BUCKETS,
+        ),
+        slow_request: std::env::var("WORD_MATH_SLOW_MS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .map_or(Duration::from_millis(50), Duration::from_millis),
+        verdicts: VerdictCounters::default(),
+        recent: RecentRequests::default(),
+        faults: Faults::default(),
+        precision: Precision::from_env(),
+        upstream,
+        count_noise,
+    };
+
+    // Role-gated routes; see auth.rs for what each role may do.
+    let keys = Arc::new(ApiKeys::from_env().expect("parsing WORD_MATH_API_KEYS failed"));
+    if keys.is_enabled() {
+        info!("access control enabled for {} API key(s)", keys.len());
+    } else if keys.serves(Role::Admin) {
+        warn!("WORD_MATH_AUTH_DISABLED is set: operator and admin routes are open");
+    } else {
+        warn!(
+            "no WORD_MATH_API_KEYS: operator and admin routes are off \
+             (set WORD_MATH_AUTH_DISABLED=1 to serve them without keys)"
+        );
+    }
+    let gate = |role| middleware::from_fn_with_state((Arc::clone(&keys), role), auth::require);
+
+    let reader = Router::new()
+        .route("/sessions/:id/export", get(export_session_handler))
+        .route("/traces", get(recent_traces_handler))
+        .route("/traces/:hex_id", get(trace_handler))
+        .route("/rollups", get(rollups_handler))
+        .route("/experiments", get(experiments_handler))
+        .route("/feedback", post(feedback_handler))
+        .route("/feedback/stats", get(feedback_stats_handler))
+        .route("/audit/merkle", get(merkle_handler))
+        .route("/audit/merkle/proof/:hex_id", get(merkle_proof_handler))
+        .route("/audit/merkle/consistency", get(merkle_consistency_handler))
+        .route_layer(gate(Role::Reader));
+    let operator = Router::new()
+        .route("/admin/replay", get(replay_offenders_handler))
+        .route("/admin/reload", post(reload_handler))
+        .route("/feedback/proposal", get(proposal_handler));
+    #[cfg(feature = "testing")]
+    let operator = operator.route(
+        "/admin/faults",
+        get(get_faults_handler).post(set_faults_handler),
+    );
+    let operator = operator.route_layer(gate(Role::Operator));
+    let admin = Router::new()
+        .route("/sessions/:id/import", post(import_session_handler))
+        .route("/admin/backup", get(backup_handler))
+        .route(
+            "/admin/restore",
+            post(restore_handler).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/admin/reencrypt", post(reencrypt_handler))
+        .route(
+            handoff::PATH,
+            get(handoff_export_handler)
+                .post(handoff_import_handler)
+                .layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/admin/switches",
+            get(get_switches_handler).post(set_switch_handler),
+        );
+    #[cfg(all(feature = "ops", target_os = "linux"))]
+    let admin = admin
+        .route("/debug/pprof/profile", get(pprof_handler))
+        .route("/debug/pprof/pcs", get(pc_profile_handler))
+        .route("/debug/pprof/heap", get(heap_handler));
+    let admin = admin.route_layer(gate(Role::Admin));
+
+    let app = Router::new()
+        .route("/analyze", get(analyze_handler))
+        .route("/analyze/batch", post(batch_handler))
+        .route("/analyze/transcript", post(transcript_handler))
+        .route("/compare", post(compare_handler))
+        .route("/rewrite/suggest", post(trim_handler))
+        .route("/similarity", post(similarity_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/stats", get(stats_handler))
+        .route("/version", get(version_handler))
+        .route("/schema", get(schema_handler))
+        .merge(reader);
+    let app = if keys.serves(Role::Operator) {
+        app.merge(operator)
+    } else {
+        app
+    };
+    let app = if keys.serves(Role::Admin) {
+        app.merge(admin)
+    } else {
+        app
+    };
+    // Proxied responses are the upstream's, so they skip output rounding.
+    let proxied = Router::new()
+        .route("/v1/chat/completions", post(proxy_handler))
+        .route("/v1/completions", post(proxy_handler));
+    let state = Arc::new(state);
+    if let Some((source, key)) = handoff::source_from_env().expect("invalid WORD_MATH_HANDOFF_FROM")
+    {
+        match handoff::pull(&source, key.as_ref()).await {
+            Ok(records) => {
+                let report = adopt_handoff(&state, records);
+                info!(
+                    "took over {} session(s) and {} client window(s) from {} ({} skipped)",
+                    report.sessions,
+                    report.clients,
+                    source.authority(),
+                    report.skipped
+                );
+            }
+            Err(e) => warn!(
+                "state handoff from {} failed, starting cold: {}",
+                source.authority(),
+                e
+            ),
+        }
+    }
+    #[cfg(unix)]
+    {
+        let state = Arc::clone(&state);
+        signals::install(
+            move || state.dump(),
+            move |on| {
+                let filter = if on { "info,server=debug" } else { "info" };
+                let _ = log_filter.reload(EnvFilter::new(filter));
+            },
+        );
+    }
+
+    let precision = state.precision;
+    if let Some(places) = precision.places() {
+        info!("rounding output floats to {} decimal places", places);
+    }
+    let app = app
+        .layer(middleware::from_fn_with_state(
+            precision,
+            precision::round_json,
+        ))
+        .merge(proxied)
+        .with_state(state);
+
+    // Bind to localhost:3000, or the port in WORD_MATH_PORT.
+    let port = std::env::var("WORD_MATH_PORT")
+        .ok()
+        .map(|s| s.trim().parse::<u16>().expect("invalid WORD_MATH_PORT"))
+        .unwrap_or(3000);
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    info!("listening on {}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    runtime::serve(listener, app, runtime_cfg).await;
+}
+
+async fn analyze_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<AnalyzeParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let key = headers
+        .get(idempotency::HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let api_key = headers
+        .get(replay::API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let params = Arc::new(params);
+    let score = {
+        let state = Arc::clone(&state);
+        let params = Arc::clone(&params);
+        let api_key = api_key.clone();
+        move || runtime::cpu(move || analyze(&state, &params, api_key.as_deref()))
+    };
+    let Some(key) = key else {
+        return Ok(Json(score().await?).into_response());
+    };
+
+    let fingerprint = idempotency::fingerprint(&[
+        Some(params.message.as_str()),
+        params.topic.as_deref(),
+        params.topic_id.as_deref(),
+        params.topic_ids.as_deref(),
+        params.corpus_id.as_deref(),
+        params.domain_id.as_deref(),
+        params.profile.as_deref(),
+        params.session_id.as_deref(),
+        params.sanitize.then_some("sanitize"),
+    ]);
+    match state
+        .idempotency
+        .get(api_key.as_deref(), &key, fingerprint)
+        .await
+    {
+        idempotency::Lookup::Hit(response) => {
+            info!("HEX[{}]: replayed for idempotency key", response.hex_id);
+            Ok(([(idempotency::REPLAYED_HEADER, "true")], Json(response)).into_response())
+        }
+        idempotency::Lookup::Conflict => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "idempotency key was already used for a different request".to_string(),
+        )),
+        idempotency::Lookup::Miss(reservation) => {
+            let response = score().await?;
+            reservation.complete(response.clone());
+            Ok(Json(response).into_response())
+        }
+    }
+}
+
+fn analyze(
+    state: &AppState,
+    params: &AnalyzeParams,
+    api_key: Option<&str>,
+) -> Result<AnalyzeResponse, (StatusCode, String)> {
+    let started = Instant::now();
+    // Sticky enrollment: the same session (or client, or message) always
+    // lands in the same variant.
+    let unit = params
+        .session_id
+        .as_deref()
+        .or(api_key)
+        .unwrap_or(&params.message);
+    let assignment = state.experiments.assign(unit);
+    let variant = assignment.map(|a| a.variant);
+    let mut cfg = state.config_for(params.profile.as_deref())?;
+    let variant_profile = variant.and_then(|v| v.profile.as_deref());
+    if variant_profile.is_some() {
+        cfg = state.config_for(variant_profile)?;
+    }
+    let profile = variant_profile.or(params.profile.as_deref());
+    let corpus = params
+        .corpus_id
+        .as_deref()
+        .map(|id| state.corpus_for("corpus_id", id, params.session_id.is_some()))
+        .transpose()?;
+    if corpus.is_some() && params.domain_id.is_some() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "domain_id cannot be combined with corpus_id".to_string(),
+        ));
+    }
+    let domain = params
+        .domain_id
+        .as_deref()
+        .map(|id| state.corpus_for("domain_id", id, params.session_id.is_some()))
+        .transpose()?;
+    let score_topic = |text: &str, topic: &CompiledTopic| match &domain {
+        Some(domain) => analyze_with_domain(text, topic, domain, cfg),
+        None => analyze_with_topic(text, topic, cfg),
+    };
+    let topic_match = params
+        .topic_ids
+        .as_deref()
+        .map(|ids| state.match_topics(&params.message, ids, corpus.is_some(), &cfg))
+        .transpose()?;
+    let topic = match (&corpus, &topic_match) {
+        (Some(_), _) => None,
+        (None, Some((topic, _))) => Some(Arc::clone(topic)),
+        (None, None) => {
+            Some(state.topic_for(params.topic.as_deref(), params.topic_id.as_deref(), &cfg)?)
+        }
+    };
+    let topic_match = topic_match.map(|(_, info)| info);
+
+    if let Some(delay) = state.faults.metric_delay() {
+        std::thread::sleep(delay);
+    }
+
+    // Degraded mode: if the session store is unavailable, still score the
+    // message on its own rather than failing the request.
+    let degraded = params.session_id.is_some() && state.faults.session_store_failing();
+    if degraded {
+        info!(
+            "session store unavailable; scoring session {:?} statelessly",
+            params.session_id
+        );
+    }
+
+    let session_id = params.session_id.as_deref().filter(|_| !degraded);
+    let (mut analysis, trace, cfg, session) = match (session_id, &topic, &corpus) {
+        (_, _, Some(corpus)) => {
+            let (analysis, trace) = analyze_with_corpus(&params.message, corpus, cfg);
+            (analysis, trace, cfg, None)
+        }
+        (None, Some(topic), _) => {
+            let (analysis, trace) = score_topic(&params.message, topic);
+            (analysis, trace, cfg, None)
+        }
+        (Some(id), Some(topic), _) => {
+            let (turn, session_cfg) = state.sessions.push(id, topic.text(), cfg, &params.message);
+            let info = SessionInfo {
+                id: id.to_string(),
+                turn: turn.turn,
+                session_score: turn.session_score,
+                session_verdict: turn.session_verdict,
+                history: turn.recent,
+            };
+            (turn.analysis, turn.trace, session_cfg, Some(info))
+        }
+        (_, None, None) => unreachable!("either a topic or a corpus was resolved"),
+    };
+    let (explanation, language) = state.judge(&params.message, &mut analysis, &cfg, variant);
+    // Repair instead of reject: rescore the sanitized text the same way.
+    // Session turns are not rescored, so the session state stays untouched.
+    let sanitized = (params.sanitize && explanation.verdict != Verdict::Allow && session.is_none())
+        .then(|| {
+            let repair = rewrite::sanitize(&params.message, &cfg);
+            let (mut after, _) = match (&topic, &corpus) {
+                (_, Some(corpus)) => analyze_with_corpus(&repair.text, corpus, cfg),
+                (Some(topic), None) => score_topic(&repair.text, topic),
+                (None, None) => unreachable!("either a topic or a corpus was resolved"),
+            };
+            let (after_explanation, _) = state.judge(&repair.text, &mut after, &cfg, variant);
+            SanitizedInfo {
+                repair,
+                score_before: analysis.score,
+                score_after: after.score,
+                verdict_after: after_explanation.verdict,
+            }
+        });
+    let client_replay_ratio = api_key.map(|key| {
+        state
+            .replay
+            .observe(key, params.session_id.as_deref(), &params.message)
+    });
+
+    state.verdicts.record(explanation.verdict);
+    state.recent.record(analysis.score);
+    debug!(
+        "HEX[{}]: emoji={}, invisible_stripped={}, obfuscation={:.4}, homoglyphs={}, split_letters={}, evasion_risk={:.4}, malformed={:.4}, alpha={}, beta={}, fired={}",
+        trace.hex_id,
+        analysis.emoji_count,
+        analysis.invisible_stripped,
+        analysis.obfuscation,
+        analysis.homoglyphs,
+        analysis.split_letters,
+        analysis.evasion_risk,
+        analysis.malformed_ratio,
+        cfg.alpha,
+        cfg.beta,
+        explanation.fired.len()
+    );
+    let elapsed = started.elapsed();
+    let outlier = |flag: bool| flag.then_some(trace.hex_id.as_str());
+    state.score_hist.observe(
+        analysis.score,
+        outlier(explanation.verdict != Verdict::Allow),
+    );
+    state.latency_hist.observe(
+        elapsed.as_secs_f64(),
+        outlier(elapsed >= state.slow_request),
+    );
+
+    // Hex-stamped, auditable trace log.
+    info!(
+        "HEX[{}]: y={:.4}, z={:.4}, raw={:.4}, score={:.4}, verdict={}, severity={}, msg_len={}, topic_len={}, client_replay_ratio={}",
+        trace.hex_id,
+        analysis.y_repetition,
+        analysis.z_drift,
+        trace.raw_score,
+        analysis.score,
+        explanation.verdict.as_str(),
+        explanation.severity.as_str(),
+        trace.message_len,
+        trace.topic_len,
+        client_replay_ratio.map_or("-".to_string(), |r| format!("{:.4}", r))
+    );
+    if let Some(info) = session.as_ref().filter(|info| !info.history.is_empty()) {
+        let series = |f: fn(&TurnSummary) -> f64| {
+            let values: Vec<String> = info
+                .history
+                .iter()
+                .map(|t| format!("{:.3}", f(t)))
+                .collect();
+            values.join(",")
+        };
+        info!(
+            "HEX[{}]: session {} turn {} trajectory y=[{}] z=[{}]",
+            trace.hex_id,
+            info.id,
+            info.turn,
+            series(|t| t.y_repetition),
+            series(|t| t.z_drift)
+        );
+    }
+
+    let record = TraceRecord {
+        hex_id: trace.hex_id.clone(),
+        y_repetition: analysis.y_repetition,
+        z_drift: analysis.z_drift,
+        raw_score: trace.raw_score,
+        score: analysis.score,
+        verdict: explanation.verdict,
+        profile: profile.map(str::to_string),
+        session_id: session.as_ref().map(|info| info.id.clone()),
+        topic_id: topic_match
+            .as_ref()
+            .map(|m| m.topic_id.clone())
+            .or_else(|| params.topic_id.clone()),
+        rule_hits: explanation.rule_hits.iter().map(|r| r.id.clone()).collect(),
+        experiment: assignment.map(|a| a.experiment.id.clone()),
+        variant: variant.map(|v| v.id.clone()),
+        labels: Labels::default(),
+    }
+    .rounded(state.precision);
+    state.experiments.observe(&record);
+    state.traces.record(record);
+
+    Ok(AnalyzeResponse {
+        y_repetition: analysis.y_repetition,
+        z_drift: analysis.z_drift,
+        score: analysis.score,
+        raw_score: trace.raw_score,
+        evasion_risk: analysis.evasion_risk,
+        verdict: explanation.verdict,
+        severity: explanation.severity,
+        explanation: (explanation.verdict != Verdict::Allow).then(|| Explanation {
+            reason: explanation.summary(),
+            detail: explanation,
+            drift_clusters: topic.as_ref().map_or_else(Vec::new, |topic| {
+                clusters::drift_clusters(&params.message, topic, &cfg)
+            }),
+        }),
+        session,
+        degraded,
+        client_replay_ratio,
+        sanitized,
+        language,
+        topic_match,
+        hex_id: trace.hex_id,
+    })
+}
+
+async fn batch_handler(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<BatchRequest>,
+) -> Result<Json<BatchResponse>, (StatusCode, String)> {
+    state.config_for(request.profile.as_deref())?;
+    let worst_k = request.worst_k.unwrap_or(batch::DEFAULT_WORST_K);
+    // Resolve every item's profile and topic up front, so one bad item
+    // rejects the batch before anything is scored.
+    let mut profiles = Vec::with_capacity(request.items.len());
+    let mut resolved = Vec::with_capacity(request.items.len());
+    for (i, item) in request.items.iter().enumerate() {
+        let profile = item.profile.clone().or_else(|| request.profile.clone());
+        let in_item =
+            |(status, msg): (StatusCode, String)| (status, format!("item {}: {}", i, msg));
+        let cfg = state.config_for(profile.as_deref()).map_err(in_item)?;
+        let topic = state
+            .topic_for(item.topic.as_deref(), item.topic_id.as_deref(), &cfg)
+            .map_err(in_item)?;
+        profiles.push(profile);
+        resolved.push((topic, cfg));
+    }
+    let topic_ids: Vec<Option<String>> = request
+        .items
+        .iter()
+        .map(|item| item.topic_id.clone())
+        .collect();
+    let judge_state = Arc::clone(&state);
+    let (result, rule_hits) = runtime::cpu(move || {
+        let mut result = batch::analyze_batch_with_configs(
+            request
+                .items
+                .iter()
+                .zip(&resolved)
+                .map(|(item, (topic, cfg))| (item.message.as_str(), topic.as_ref(), *cfg)),
+            worst_k,
+        );
+        let mut rule_hits = Vec::with_capacity(result.items.len());
+        for ((item, request_item), (_, cfg)) in
+            result.items.iter_mut().zip(&request.items).zip(&resolved)
+        {
+            let (explanation, _) =
+                judge_state.judge(&request_item.message, &mut item.analysis, cfg, None);
+            item.verdict = explanation.verdict;
+            rule_hits.push(explanation.rule_hits.into_iter().map(|r| r.id).collect());
+        }
+        result.summary = batch::summarize(&result.items, worst_k);
+        Ok((result, rule_hits))
+    })
+    .await?;
+
+    let summary = &result.summary;
+    for (((item, topic_id), rule_hits), profile) in result
+        .items
+        .iter()
+        .zip(topic_ids)
+        .zip(rule_hits)
+        .zip(&profiles)
+    {
+        state.verdicts.record(item.verdict);
+        state.recent.record(item.analysis.score);
+        state.traces.record(
+            TraceRecord {
+                hex_id: item.trace.hex_id.clone(),
+                y_repetition: item.analysis.y_repetition,
+                z_drift: item.analysis.z_drift,
+                raw_score: item.trace.raw_score,
+                score: item.analysis.score,
+                verdict: item.verdict,
+                profile: profile.clone(),
+                session_id: None,
+                topic_id,
+                rule_hits,
+                experiment: None,
+                variant: None,
+                labels: Labels::default(),
+            }
+            .rounded(state.precision),
+        );
+    }
+    info!(
+        "batch: n={}, p50={:.4}, p90={:.4}, p99={:.4}, allow={}, warn={}, block={}",
+        summary.count,
+        summary.p50,
+        summary.p90,
+        summary.p99,
+        summary.verdicts.allow,
+        summary.verdicts.warn,
+        summary.verdicts.block
+    );
+
+    Ok(Json(BatchResponse {
+        items: result
+            .items
+            .into_iter()
+            .zip(profiles)
+            .map(|(item, profile)| BatchResponseItem {
+                y_repetition: item.analysis.y_repetition,
+                z_drift: item.analysis.z_drift,
+                score: item.analysis.score,
+                verdict: item.verdict,
+                profile,
+                hex_id: item.trace.hex_id,
+            })
+            .collect(),
+        summary: result.summary,
+    }))
+}
+
+async fn compare_handler(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<CompareRequest>,
+) -> Result<Json<CompareResponse>, (StatusCode, String)> {
+    let cfg = state.config_for(request.profile.as_deref())?;
+    let topic = state.topic_for(request.topic.as_deref(), request.topic_id.as_deref(), &cfg)?;
+    let comparison = compare::compare_with_topic(&request.a, &request.b, &topic, cfg);
+    let hex_id = generate_hex_id();
+
+    info!(
+        "HEX[{}]: compare overlap={:.4}, similarity={:.4}, drift_a={:.4}, drift_b={:.4}",
+        hex_id,
+        comparison.lexical_overlap,
+        comparison.similarity,
+        comparison.drift_a,
+        comparison.drift_b
+    );
+
+    Ok(Json(CompareResponse {
+        drift_delta: comparison.drift_delta(),
+        comparison,
+        hex_id,
+    }))
+}
+
+/// Score an ASR transcript with repetition and drift weighted by each
+/// word's confidence (see `asr`).
+async fn transcript_handler(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<TranscriptRequest>,
+) -> Result<Json<TranscriptResponse>, (StatusCode, String)> {
+    if let Some(i) = request
+        .words
+        .iter()
+        .position(|w| !(0.0..=1.0).contains(&w.confidence))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("word {}: confidence must be within [0, 1]", i),
+        ));
+    }
+    let cfg = state.config_for(request.profile.as_deref())?;
+    let topic = state.topic_for(request.topic.as_deref(), request.topic_id.as_deref(), &cfg)?;
+    let judge_state = Arc::clone(&state);
+    let words = request.words;
+    let (analysis, trace, explanation, mean_confidence) = runtime::cpu(move || {
+        let (mut analysis, trace) = asr::analyze_transcript(&words, &topic, cfg);
+        let (explanation, _) =
+            judge_state.judge(&asr::transcript_text(&words), &mut analysis, &cfg, None);
+        Ok((analysis, trace, explanation, asr::mean_confidence(&words)))
+    })
+    .await?;
+
+    state.verdicts.record(explanation.verdict);
+    state.recent.record(analysis.score);
+    state.traces.record(
+        TraceRecord {
+            hex_id: trace.hex_id.clone(),
+            y_repetition: analysis.y_repetition,
+            z_drift: analysis.z_drift,
+            raw_score: trace.raw_score,
+            score: analysis.score,
+            verdict: explanation.verdict,
+            profile: request.profile,
+            session_id: None,
+            topic_id: request.topic_id,
+            rule_hits: explanation.rule_hits.into_iter().map(|r| r.id).collect(),
+            experiment: None,
+            variant: None,
+            labels: Labels::default(),
+        }
+        .rounded(state.precision),
+    );
+    info!(
+        "HEX[{}]: transcript y={:.4}, z={:.4}, score={:.4}, confidence={:.4}, verdict={:?}",
+        trace.hex_id,
+        analysis.y_repetition,
+        analysis.z_drift,
+        analysis.score,
+        mean_confidence,
+        explanation.verdict
+    );
+
+    Ok(Json(TranscriptResponse {
+        y_repetition: analysis.y_repetition,
+        z_drift: analysis.z_drift,
+        score: analysis.score,
+        verdict: explanation.verdict,
+        mean_confidence,
+        hex_id: trace.hex_id,
+    }))
+}
+
+/// Score and verdict of one side of a proxied exchange.
+#[derive(Debug, Clone, Serialize)]
+struct ProxyDecision {
+    y_repetition: f64,
+    z_drift: f64,
+    score: f64,
+    verdict: Verdict,
+    #[serde(rename = "trace_id")]
+    hex_id: String,
+}
+
+/// Analysis of a proxied exchange, for annotated responses.
+#[derive(Debug, Serialize)]
+struct ProxyAnalysis<'a> {
+    prompt: &'a ProxyDecision,
+    /// None when the upstream response held no completion.
+    completion: Option<&'a ProxyDecision>,
+}
+
+impl ProxyDecision {
+    fn apply(&self, headers: &mut HeaderMap, precision: Precision) {
+        let pairs = [
+            (proxy::SCORE_HEADER, precision.round(self.score).to_string()),
+            (proxy::VERDICT_HEADER, self.verdict.as_str().to_string()),
+            (proxy::TRACE_HEADER, self.hex_id.clone()),
+        ];
+        for (name, value) in pairs {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+    }
+}
+
+/// Score one side of a proxied exchange and record its trace.
+fn score_proxied(
+    state: &AppState,
+    text: &str,
+    topic: &CompiledTopic,
+    cfg: &WordMathConfig,
+    profile: Option<&str>,
+    topic_id: Option<&str>,
+) -> ProxyDecision {
+    let (mut analysis, trace) = analyze_with_topic(text, topic, *cfg);
+    let (explanation, _) = state.judge(text, &mut analysis, cfg, None);
+    state.verdicts.record(explanation.verdict);
+    state.recent.record(analysis.score);
+    state.traces.record(
+        TraceRecord {
+            hex_id: trace.hex_id.clone(),
+            y_repetition: analysis.y_repetition,
+            z_drift: analysis.z_drift,
+            raw_score: trace.raw_score,
+            score: analysis.score,
+            verdict: explanation.verdict,
+            profile: profile.map(str::to_string),
+            session_id: None,
+            topic_id: topic_id.map(str::to_string),
+            rule_hits: explanation.rule_hits.into_iter().map(|r| r.id).collect(),
+            experiment: None,
+            variant: None,
+            labels: Labels::default(),
+        }
+        .rounded(state.precision),
+    );
+    ProxyDecision {
+        y_repetition: analysis.y_repetition,
+        z_drift: analysis.z_drift,
+        score: analysis.score,
+        verdict: explanation.verdict,
+        hex_id: trace.hex_id,
+    }
+}
+
+/// Forward an OpenAI-compatible completion request upstream, scoring the
+/// prompt and the completion on the way (see proxy.rs).
+async fn proxy_handler(
+    State(state): State<Arc<AppState>>,
+    OriginalUri(uri): OriginalUri,
+    mut headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(upstream) = state.upstream.clone() else {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "proxy mode is off: WORD_MATH_UPSTREAM is not set".to_string(),
+        ));
+    };
+    let request: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("invalid JSON body: {}", e)))?;
+    if request.get("stream").and_then(|v| v.as_bool()) == Some(true) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "streamed completions are not supported in proxy mode".to_string(),
+        ));
+    }
+    let prompt = proxy::prompt_text(&request)
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "request has no prompt".to_string()))?;
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let annotation = match header(proxy::ANNOTATE_HEADER) {
+        Some(name) => proxy::Annotation::parse(&name).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("unknown {}: {}", proxy::ANNOTATE_HEADER, name),
+            )
+        })?,
+        None => proxy::Annotation::None,
+    };
+    let profile = header(proxy::PROFILE_HEADER);
+    let topic_id = header(proxy::TOPIC_ID_HEADER);
+    let topic_text = header(proxy::TOPIC_HEADER);
+    let cfg = state.config_for(profile.as_deref())?;
+    let explicit = match (&topic_id, &topic_text) {
+        (None, None) => None,
+        _ => Some(state.topic_for(topic_text.as_deref(), topic_id.as_deref(), &cfg)?),
+    };
+
+    let prompt_decision = {
+        let state = Arc::clone(&state);
+        let (explicit, profile, topic_id) = (explicit.clone(), profile.clone(), topic_id.clone());
+        let system = proxy::system_text(&request);
+        let prompt = prompt.clone();
+        runtime::cpu(move || {
+            let topic = explicit.unwrap_or_else(|| {
+                let text = system.as_deref().unwrap_or(&prompt);
+                state.topic_cache.get_or_compile(text, cfg.emoji_mode)
+            });
+            Ok(score_proxied(
+                &state,
+                &prompt,
+                &topic,
+                &cfg,
+                profile.as_deref(),
+                topic_id.as_deref(),
+            ))
+        })
+        .await?
+    };
+    proxy::strip_control_headers(&mut headers);
+    prompt_decision.apply(&mut headers, state.precision);
+
+    let path = uri.path_and_query().map_or(uri.path(), |pq| pq.as_str());
+    let upstream_response = upstream
+        .send(&Method::POST, path, &headers, &body)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_GATEWAY,
+                format!("upstream {}: {}", upstream.authority(), e),
+            )
+        })?;
+
+    if let Some(coding) = proxy::content_encoding(&upstream_response.headers) {
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            format!(
+                "upstream {} sent a {}-encoded response that can't be scored",
+                upstream.authority(),
+                coding
+            ),
+        ));
+    }
+    let completion = serde_json::from_slice(&upstream_response.body)
+        .ok()
+        .and_then(|response: serde_json::Value| proxy::completion_text(&response));
+    let completion_decision = match completion {
+        Some(completion) if upstream_response.status.is_success() => {
+            let state = Arc::clone(&state);
+            let decision = runtime::cpu(move || {
+                let topic = explicit
+                    .unwrap_or_else(|| state.topic_cache.get_or_compile(&prompt, cfg.emoji_mode));
+                Ok(score_proxied(
+                    &state,
+                    &completion,
+                    &topic,
+                    &cfg,
+                    profile.as_deref(),
+                    topic_id.as_deref(),
+                ))
+            })
+            .await?;
+            Some(decision)
+        }
+        _ => None,
+    };
+    let response_decision = completion_decision.as_ref().unwrap_or(&prompt_decision);
+    info!(
+        "HEX[{}]: proxied {} -> {}, prompt {} {:.4}, completion {} {:.4}",
+        response_decision.hex_id,
+        path,
+        upstream_response.status,
+        prompt_decision.verdict.as_str(),
+        prompt_decision.score,
+        response_decision.verdict.as_str(),
+        response_decision.score
+    );
+
+    let mut headers = upstream_response.headers;
+    let mut analysis = serde_json::to_value(ProxyAnalysis {
+        prompt: &prompt_decision,
+        completion: completion_decision.as_ref(),
+    })
+    .unwrap_or_default();
+    state.precision.round_value(&mut analysis);
+    let body = match annotation.apply(&upstream_response.body, analysis, &response_decision.hex_id)
+    {
+        Some(annotated) => {
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            annotated
+        }
+        None => upstream_response.body,
+    };
+    let mut response = Response::new(axum::body::Body::from(body));
+    *response.status_mut() = upstream_response.status;
+    *response.headers_mut() = headers;
+    response_decision.apply(response.headers_mut(), state.precision);
+    let scored = if completion_decision.is_some() {
+        "scored"
+    } else {
+        "unscored"
+    };
+    response
+        .headers_mut()
+        .insert(proxy::COMPLETION_HEADER, HeaderValue::from_static(scored));
+    Ok(response)
+}
+
+/// Removals that would bring a repetitive message under the threshold.
+async fn trim_handler(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<TrimRequest>,
+) -> Result<Json<TrimSuggestion>, (StatusCode, String)> {
+    let cfg = state.config_for(request.profile.as_deref())?;
+    let suggestion = rewrite::suggest_trims(&request.message, &cfg);
+    info!(
+        "HEX[{}]: trim y={:.4} -> {:.4} with {} removal(s)",
+        generate_hex_id(),
+        suggestion.y_before,
+        suggestion.y_after,
+        suggestion.removals.len()
+    );
+    Ok(Json(suggestion))
+}
+
+/// Downsampled self-similarity matrix of a message or transcript.
+async fn similarity_handler(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<SimilarityRequest>,
+) -> Result<Json<SimilarityMatrix>, (StatusCode, String)> {
+    let cfg = state.config_for(request.profile.as_deref())?;
+    let max_dim = request.max_dim.unwrap_or(compare::DEFAULT_MATRIX_DIM);
+    let matrix = runtime::cpu(move || match (request.messages, request.message) {
+        (Some(messages), _) => Ok(compare::similarity_matrix(&messages, max_dim, &cfg)),
+        (None, Some(message)) => Ok(compare::message_similarity_matrix(&message, max_dim, &cfg)),
+        (None, None) => Err((
+            StatusCode::BAD_REQUEST,
+            "either message or messages is required".to_string(),
+        )),
+    })
+    .await?;
+    Ok(Json(matrix))
+}
+
+async fn export_session_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ConversationSnapshot>, (StatusCode, String)> {
+    state
+        .sessions
+        .snapshot(&id)
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("unknown session: {}", id)))
+}
+
+async fn import_session_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(snapshot): Json<ConversationSnapshot>,
+) -> StatusCode {
+    info!(
+        "session {} imported with {} turn(s)",
+        id,
+        snapshot.turns.max(snapshot.history.len())
+    );
+    state.sessions.restore(&id, snapshot);
+    StatusCode::NO_CONTENT
+}
+
+#[derive(Debug, Deserialize)]
+struct RecentParams {
+    limit: Option<usize>,
+    /// Only records where this policy rule matched.
+    rule: Option<String>,
+    /// Only reviewed records of this class, e.g. `false_positive`.
+    feedback: Option<String>,
+}
+
+/// Most recent analysis records, newest first.
+async fn recent_traces_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<RecentParams>,
+) -> Result<Json<Vec<TraceRecord>>, (StatusCode, String)> {
+    let limit = params.limit.unwrap_or(100);
+    let class = params
+        .feedback
+        .as_deref()
+        .map(|name| {
+            FeedbackClass::parse(name).ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("unknown feedback class: {}", name),
+                )
+            })
+        })
+        .transpose()?;
+    let rule = params.rule.as_deref();
+    Ok(Json(state.traces.recent_matching(limit, |r| {
+        rule.is_none_or(|rule| r.rule_hits.iter().any(|id| id == rule))
+            && class.is_none_or(|class| {
+                state
+                    .feedback
+                    .get(&r.hex_id)
+                    .is_some_and(|f| f.class() == class)
+            })
+    })))
+}
+
+async fn trace_handler(
+    State(state): State<Arc<AppState>>,
+    Path(hex_id): Path<String>,
+) -> Result<Json<TraceRecord>, (StatusCode, String)> {
+    state
+        .traces
+        .get(&hex_id)
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("unknown trace: {}", hex_id)))
+}
+
+/// Record a reviewer's verdict correction for a traced message.
+async fn feedback_handler(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<FeedbackRequest>,
+) -> Result<Json<Feedback>, (StatusCode, String)> {
+    let trace = state.traces.get(&request.hex_id).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("unknown trace: {}", request.hex_id),
+        )
+    })?;
+    let feedback = Feedback {
+        hex_id: request.hex_id,
+        label: request.label,
+        served: trace.verdict,
+        reviewer: request.reviewer,
+        note: request.note,
+    };
+    info!(
+        "HEX[{}]: feedback label={} served={} reviewer={}",
+        feedback.hex_id,
+        feedback.label.as_str(),
+        trace.verdict.as_str(),
+        feedback.reviewer.as_deref().unwrap_or("-")
+    );
+    state
+        .feedback
+        .record(feedback.clone())
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(feedback))
+}
+
+#[derive(Debug, Deserialize)]
+struct FeedbackStatsParams {
+    /// Only labels for traces from this hex ID on.
+    since: Option<String>,
+}
+
+/// Precision and recall of served verdicts against reviewer labels.
+async fn feedback_stats_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<FeedbackStatsParams>,
+) -> Json<FeedbackStats> {
+    Json(state.feedback.stats(params.since.as_deref()))
+}
+
+#[derive(Debug, Deserialize)]
+struct ProposalParams {
+    /// Tune this profile, on feedback for messages scored with it.
+    profile: Option<String>,
+}
+
+/// Weights and thresholds that best match the reviewer labels so far.
+async fn proposal_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ProposalParams>,
+) -> Result<Json<TuningReport>, (StatusCode, String)> {
+    let cfg = state.config_for(params.profile.as_deref())?;
+    let samples: Vec<LabeledSample> = state
+        .feedback
+        .all()
+        .into_iter()
+        .filter_map(|feedback| {
+            let trace = state.traces.get(&feedback.hex_id)?;
+            (trace.profile == params.profile).then_some(LabeledSample {
+                y_repetition: trace.y_repetition,
+                z_drift: trace.z_drift,
+                label: feedback.label,
+            })
+        })
+        .collect();
+    if samples.is_empty() {
+        return Err((
+            StatusCode::CONFLICT,
+            "no reviewer feedback for traces still held".to_string(),
+        ));
+    }
+    let report = runtime::cpu(move || Ok(tuning::tune(&samples, cfg))).await?;
+    info!(
+        "tuning proposal from {} label(s): disagreements {} -> {}",
+        report.samples, report.disagreements_before, report.disagreements_after
+    );
+    Ok(Json(report))
+}
+
+#[derive(Debug, Serialize)]
+struct StatsResponse {
+    last_minute: metrics::WindowTotals,
+    last_hour: metrics::WindowTotals,
+    verdicts: batch::VerdictCounts,
+    active_sessions: usize,
+    /// Components currently running in a fallback mode.
+    degraded: Vec<&'static str>,
+    /// Present when the counts carry differential-privacy noise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    noise_epsilon: Option<f64>,
+    hex_id: String,
+}
+
+/// Small JSON summary for dashboards and smoke tests that don't scrape
+/// Prometheus.
+async fn stats_handler(State(state): State<Arc<AppState>>) -> Json<StatsResponse> {
+    let mut degraded = Vec::new();
+    if state.faults.session_store_failing() {
+        degraded.push("sessions");
+    }
+    let mut stats = StatsResponse {
+        last_minute: state.recent.window(60),
+        last_hour: state.recent.window(3_600),
+        verdicts: state.verdicts.snapshot(),
+        active_sessions: state.sessions.len(),
+        degraded,
+        noise_epsilon: None,
+        hex_id: generate_hex_id(),
+    };
+    if let Some(noise) = &state.count_noise {
+        stats.last_minute = noise.window("stats/last_minute", stats.last_minute);
+        stats.last_hour = noise.window("stats/last_hour", stats.last_hour);
+        stats.verdicts = noise.verdicts("stats/verdicts", stats.verdicts);
+        stats.active_sessions =
+            noise.count("stats/active_sessions", stats.active_sessions as u64) as usize;
+        stats.noise_epsilon = Some(noise.epsilon());
+    }
+    Json(stats)
+}
+
+/// Hourly/daily score rollups, filtered by period, profile, topic and start.
+async fn rollups_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<rollups::RollupQuery>,
+) -> Result<Json<Vec<rollups::RollupRow>>, (StatusCode, String)> {
+    let mut rows = state
+        .traces
+        .rollups()
+        .query(&query)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if let Some(noise) = &state.count_noise {
+        rows.iter_mut().for_each(|row| noise.rollup(row));
+    }
+    Ok(Json(rows))
+}
+
+/// Per-variant score and verdict aggregates of the running experiments.
+/// Refused under differential privacy: the tallies are exact.
+async fn experiments_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ExperimentReport>>, (StatusCode, String)> {
+    if state.count_noise.is_some() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "experiment tallies are exact and hidden while WORD_MATH_DP_EPSILON is set".to_string(),
+        ));
+    }
+    Ok(Json(state.experiments.report()))
+}
+
+/// Latest published Merkle root over the audit records.
+async fn merkle_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<audit::MerkleDigest>, (StatusCode, String)> {
+    state
+        .auditor
+        .latest()
+        .map(|digest| Json((*digest).clone()))
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "no digest yet".to_string()))
+}
+
+/// Inclusion proof of one record in the latest published root.
+async fn merkle_proof_handler(
+    State(state): State<Arc<AppState>>,
+    Path(hex_id): Path<String>,
+) -> Result<Json<audit::InclusionProof>, (StatusCode, String)> {
+    state
+        .auditor
+        .latest()
+        .and_then(|digest| state.traces.audit().proof(&hex_id, digest.leaves))
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("{} is not covered by the latest digest", hex_id),
+            )
+        })
+}
+
+#[derive(Debug, Deserialize)]
+struct ConsistencyParams {
+    first: u64,
+    /// Defaults to the tree size of the latest digest.
+    second: Option<u64>,
+}
+
+/// Proof that a later tree of the audit log extends an earlier one.
+async fn merkle_consistency_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ConsistencyParams>,
+) -> Result<Json<audit::ConsistencyProof>, (StatusCode, String)> {
+    let second = match params.second {
+        Some(second) => second,
+        None => {
+            state
+                .auditor
+                .latest()
+                .ok_or_else(|| (StatusCode::NOT_FOUND, "no digest yet".to_string()))?
+                .leaves
+        }
+    };
+    state
+        .traces
+        .audit()
+        .consistency(params.first, second)
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "need first <= second <= {} (the audit log size)",
+                    state.traces.audit().size()
+                ),
+            )
+        })
+}
+
+fn data_store(state: &AppState) -> Result<&kv::KvStore, (StatusCode, String)> {
+    state.kv.as_deref().ok_or_else(|| {
+        (
+            StatusCode::CONFLICT,
+            "persistence is off (WORD_MATH_DATA_DIR is not set)".to_string(),
+        )
+    })
+}
+
+/// Consistent snapshot of the embedded session and trace stores.
+async fn backup_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, (StatusCode, String)> {
+    let backup = data_store(&state)?
+        .export()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    info!("backup exported ({} bytes)", backup.len());
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], backup).into_response())
+}
+
+#[derive(Debug, Serialize)]
+struct RestoreResponse {
+    entries: usize,
+    sessions: usize,
+    traces: usize,
+    feedback: usize,
+}
+
+/// Replace the embedded stores with a backup and reload them.
+async fn restore_handler(
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
+) -> Result<Json<RestoreResponse>, (StatusCode, String)> {
+    let entries = data_store(&state)?
+        .import(&body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let response = RestoreResponse {
+        entries,
+        sessions: state.sessions.reload(),
+        traces: state.traces.reload(),
+        feedback: state.feedback.reload(),
+    };
+    info!(
+        "restored {} entries: {} session(s), {} trace(s), {} feedback label(s)",
+        response.entries, response.sessions, response.traces, response.feedback
+    );
+    Ok(Json(response))
+}
+
+/// Adopt handed-off sessions and client windows this instance lacks.
+fn adopt_handoff(state: &AppState, records: Vec<handoff::HandoffRecord>) -> handoff::HandoffReport {
+    let mut report = handoff::HandoffReport::default();
+    for record in records {
+        let (adopted, count) = match record {
+            handoff::HandoffRecord::Session { id, snapshot } => {
+                (state.sessions.adopt(&id, snapshot), &mut report.sessions)
+            }
+            handoff::HandoffRecord::Client(client) => {
+                (state.replay.import(client), &mut report.clients)
+            }
+        };
+        if adopted {
+            *count += 1;
+        } else {
+            report.skipped += 1;
+        }
+    }
+    report
+}
+
+/// Live sessions and client windows as NDJSON, for the next instance.
+async fn handoff_export_handler(State(state): State<Arc<AppState>>) -> Response {
+    let mut records: Vec<handoff::HandoffRecord> = state
+        .sessions
+        .export()
+        .into_iter()
+        .map(|(id, snapshot)| handoff::HandoffRecord::Session { id, snapshot })
+        .collect();
+    let sessions = records.len();
+    records.extend(
+        state
+            .replay
+            .export()
+            .into_iter()
+            .map(handoff::HandoffRecord::Client),
+    );
+    info!(
+        "handing off {} session(s) and {} client window(s)",
+        sessions,
+        records.len() - sessions
+    );
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        handoff::encode(&records),
+    )
+        .into_response()
+}
+
+/// Adopt the state handed off by the previous instance.
+async fn handoff_import_handler(
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
+) -> Result<Json<handoff::HandoffReport>, (StatusCode, String)> {
+    let records = handoff::decode(&body).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let report = adopt_handoff(&state, records);
+    info!(
+        "took over {} session(s) and {} client window(s) ({} skipped)",
+        report.sessions, report.clients, report.skipped
+    );
+    Ok(Json(report))
+}
+
+#[derive(Debug, Serialize)]
+struct ReencryptResponse {
+    entries: usize,
+    key_id: String,
+}
+
+/// Re-seal every persisted value under the active key after a rotation.
+async fn reencrypt_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ReencryptResponse>, (StatusCode, String)> {
+    let kv = data_store(&state)?;
+    let entries = kv
+        .reencrypt()
+        .map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+    let key_id = kv.key_id().unwrap_or_default().to_string();
+    info!("re-encrypted {} entries under key {}", entries, key_id);
+    // Values written before encryption was turned on were skipped at
+    // startup, along with the schema check; pick them up now.
+    migrate_store_checked(kv).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    state.sessions.reload();
+    state.traces.reload();
+    state.feedback.reload();
+    Ok(Json(ReencryptResponse { entries, key_id }))
+}
+
+#[derive(Debug, Serialize)]
+struct VersionResponse {
+    version: &'static str,
+    /// Newest data store schema this binary understands.
+    schema_version: u32,
+    /// Hex ID stamped when the serving resource set was loaded.
+    resources_loaded_at: String,
+    resources: Vec<ResourceVersion>,
+}
+
+async fn version_handler(State(state): State<Arc<AppState>>) -> Json<VersionResponse> {
+    let resources = state.resources.current();
+    Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        schema_version: migrations::SCHEMA_VERSION,
+        resources_loaded_at: resources.loaded_at.clone(),
+        resources: resources.versions.clone(),
+    })
+}
+
+/// Re-read every resource file and swap in the new set. Anything invalid,
+/// including experiments that no longer resolve, keeps the current set.
+async fn reload_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<VersionResponse>, (StatusCode, String)> {
+    let emoji_mode = state.cfg.emoji_mode;
+    let set = runtime::cpu(move || {
+        ResourceSet::load(emoji_mode).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))
+    })
+    .await?;
+    state
+        .experiments
+        .validate(&set.profiles, &set.rules)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    for resource in &set.versions {
+        info!(
+            "reloaded {}: {} entries, version {}",
+            resource.name, resource.entries, resource.version
+        );
+    }
+    state.resources.swap(set);
+    Ok(version_handler(State(state)).await)
+}
+
+async fn get_switches_handler(State(state): State<Arc<AppState>>) -> Json<SwitchReport> {
+    Json(state.switches.report())
+}
+
+/// Enable or disable one metric or policy rule; the change is audited.
+async fn set_switch_handler(
+    State(state): State<Arc<AppState>>,
+    Json(change): Json<SwitchChange>,
+) -> Result<Json<SwitchChange>, (StatusCode, String)> {
+    let known = match change.kind {
+        SwitchKind::Metric => switches::METRICS.contains(&change.name.as_str()),
+        SwitchKind::Rule => state
+            .resources
+            .current()
+            .rules
+            .rules()
+            .iter()
+            .any(|r| r.id == change.name),
+    };
+    if !known {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("unknown {} switch: {}", change.kind.label(), change.name),
+        ));
+    }
+    state
+        .switches
+        .set(change)
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+struct OffenderParams {
+    limit: Option<usize>,
+}
+
+/// API keys with the highest cross-session replay ratio.
+async fn replay_offenders_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<OffenderParams>,
+) -> Json<Vec<replay::Offender>> {
+    Json(state.replay.worst_offenders(params.limit.unwrap_or(20)))
+}
+
+#[cfg(feature = "testing")]
+async fn get_faults_handler(State(state): State<Arc<AppState>>) -> Json<faults::FaultSettings> {
+    Json(state.faults.settings())
+}
+
+#[cfg(feature = "testing")]
+async fn set_faults_handler(
+    State(state): State<Arc<AppState>>,
+    Json(settings): Json<faults::FaultSettings>,
+) -> Json<faults::FaultSettings> {
+    info!("fault injection updated: {:?}", settings);
+    state.faults.apply(settings);
+    Json(state.faults.settings())
+}
+
+/// JSON Schema of the request, response and audit types.
+async fn schema_handler() -> Json<serde_json::Value> {
+    Json(schema::document())
+}
+
+#[cfg(all(feature = "ops", target_os = "linux"))]
+#[derive(Debug, Deserialize)]
+struct ProfileParams {
+    seconds: Option<u64>,
+    hz: Option<u32>,
+}
+
+/// Sample the process's CPU time as `params` ask.
+#[cfg(all(feature = "ops", target_os = "linux"))]
+async fn cpu_profile(params: ProfileParams) -> Result<profiler::Profile, (StatusCode, String)> {
+    let (duration, hz) = profiler::bounds(params.seconds, params.hz);
+    let sampling = profiler::Sampling::start(hz)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((
+            StatusCode::CONFLICT,
+            "a profile is already running".to_string(),
+        ))?;
+    info!("profiling for {:?} at {} Hz", duration, hz);
+    tokio::time::sleep(duration).await;
+    let profile = sampling.finish();
+    if profile.dropped > 0 {
+        warn!("profile dropped {} sample(s)", profile.dropped);
+    }
+    info!(
+        "profile done: {} sample(s) at {} address(es)",
+        profile.samples,
+        profile.pcs.len()
+    );
+    Ok(profile)
+}
+
+/// CPU profile of the process in pprof format.
+#[cfg(all(feature = "ops", target_os = "linux"))]
+async fn pprof_handler(
+    Query(params): Query<ProfileParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let profile = cpu_profile(params).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/octet-stream")],
+        profile.pprof(),
+    )
+        .into_response())
+}
+
+/// Flat histogram of sampled program counters across the process.
+#[cfg(all(feature = "ops", target_os = "linux"))]
+async fn pc_profile_handler(
+    Query(params): Query<ProfileParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let profile = cpu_profile(params).await?;
+    Ok(([(header::CONTENT_TYPE, "text/plain")], profile.flat()).into_response())
+}
+
+#[cfg(all(feature = "ops", target_os = "linux"))]
+async fn heap_handler() -> Result<Json<profiler::HeapStats>, (StatusCode, String)> {
+    profiler::heap_stats()
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Prometheus text exposition of the server's counters.
+///
+/// Scrapers that accept OpenMetrics also get exemplars on the histograms.
+async fn metrics_handler(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let openmetrics = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/openmetrics-text"));
+    let cache = state.topic_cache.stats();
+    let mut body = format!(
+        "# HELP wordmath_topic_cache_entries Compiled free-text topics currently cached.\n\
+         # TYPE wordmath_topic_cache_entries gauge\n\
+         wordmath_topic_cache_entries {}\n\
+         # HELP wordmath_registry_topics Topics compiled from the registry.\n\
+         # TYPE wordmath_registry_topics gauge\n\
+         wordmath_registry_topics {}\n\
+         # HELP wordmath_max_sessions Configured cap on in-memory sessions.\n\
+         # TYPE wordmath_max_sessions gauge\n\
+         wordmath_max_sessions {}\n",
+        cache.entries,
+        state.resources.current().topics.len(),
+        state.sessions.max_sessions(),
+    );
+    // Anything counting traffic exactly would undo the noise on `/stats`
+    // and `/rollups`; see `privacy`.
+    if state.count_noise.is_none() {
+        let (evicted_lru, evicted_ttl) = state.sessions.evictions();
+        let verdicts = state.verdicts.snapshot();
+        body.push_str(&format!(
+            "# HELP wordmath_topic_cache_hits_total Topic lookups served without compiling.\n\
+             # TYPE wordmath_topic_cache_hits_total counter\n\
+             wordmath_topic_cache_hits_total {}\n\
+             # HELP wordmath_topic_cache_misses_total Topic lookups that compiled a topic.\n\
+             # TYPE wordmath_topic_cache_misses_total counter\n\
+             wordmath_topic_cache_misses_total {}\n\
+             # HELP wordmath_active_sessions Conversation sessions held in memory.\n\
+             # TYPE wordmath_active_sessions gauge\n\
+             wordmath_active_sessions {}\n\
+             # HELP wordmath_sessions_evicted_total Sessions dropped by the store.\n\
+             # TYPE wordmath_sessions_evicted_total counter\n\
+             wordmath_sessions_evicted_total{{reason=\"lru\"}} {}\n\
+             wordmath_sessions_evicted_total{{reason=\"ttl\"}} {}\n\
+             # HELP wordmath_traces_stored Analysis records held in the trace store.\n\
+             # TYPE wordmath_traces_stored gauge\n\
+             wordmath_traces_stored {}\n\
+             # HELP wordmath_verdicts_total Verdicts served, by verdict.\n\
+             # TYPE wordmath_verdicts_total counter\n\
+             wordmath_verdicts_total{{verdict=\"allow\"}} {}\n\
+             wordmath_verdicts_total{{verdict=\"warn\"}} {}\n\
+             wordmath_verdicts_total{{verdict=\"block\"}} {}\n",
+            cache.hits,
+            cache.misses,
+            state.sessions.len(),
+            evicted_lru,
+            evicted_ttl,
+            state.traces.len(),
+            verdicts.allow,
+            verdicts.warn,
+            verdicts.block
+        ));
+        state.score_hist.render(&mut body, openmetrics);
+        state.latency_hist.render(&mut body, openmetrics);
+        state.traces.sinks().render(&mut body);
+    }
+    let body = state.traces.labels().stamp_metrics(&body);
+
+    if openmetrics {
+        let body = metrics::to_openmetrics(&body);
+        (
+            [(header::CONTENT_TYPE, metrics::OPENMETRICS_CONTENT_TYPE)],
+            body,
+        )
+            .into_response()
+    } else {
+        (
+            [(header::CONTENT_TYPE, metrics::PROMETHEUS_CONTENT_TYPE)],
+            body,
+        )
+            .into_response()
+    }
+}
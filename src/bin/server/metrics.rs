@@ -0,0 +1,281 @@
+//! Histograms for `/metrics`, with exemplars linking outliers to traces.
+//!
+//! Exemplars are only part of the OpenMetrics format, so they are rendered
+//! when the scraper asks for `application/openmetrics-text`; the plain
+//! Prometheus text format gets the same histograms without them.
+
+use serde::Serialize;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use word_math_guard::batch::VerdictCounts;
+use word_math_guard::Verdict;
+
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+pub const SCORE_BUCKETS: &[f64] = &[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0];
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+];
+
+#[derive(Debug, Clone)]
+struct Exemplar {
+    hex_id: String,
+    value: f64,
+    timestamp: f64,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Non-cumulative counts per bucket, with `+Inf` last.
+    counts: Vec<u64>,
+    exemplars: Vec<Option<Exemplar>>,
+    sum: f64,
+    count: u64,
+}
+
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    bounds: &'static [f64],
+    inner: Mutex<Inner>,
+}
+
+impl Histogram {
+    pub fn new(name: &'static str, help: &'static str, bounds: &'static [f64]) -> Self {
+        Self {
+            name,
+            help,
+            bounds,
+            inner: Mutex::new(Inner {
+                counts: vec![0; bounds.len() + 1],
+                exemplars: vec![None; bounds.len() + 1],
+                ..Inner::default()
+            }),
+        }
+    }
+
+    /// Record `value`. Outlier observations pass their trace ID, which
+    /// becomes the exemplar of the bucket they land in.
+    pub fn observe(&self, value: f64, exemplar: Option<&str>) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|&le| value <= le)
+            .unwrap_or(self.bounds.len());
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.counts[bucket] += 1;
+        inner.sum += value;
+        inner.count += 1;
+        if let Some(hex_id) = exemplar {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+            inner.exemplars[bucket] = Some(Exemplar {
+                hex_id: hex_id.to_string(),
+                value,
+                timestamp,
+            });
+        }
+    }
+
+    pub fn render(&self, out: &mut String, openmetrics: bool) {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} histogram", self.name);
+
+        let mut cumulative = 0;
+        for (i, count) in inner.counts.iter().enumerate() {
+            cumulative += count;
+            let le = self
+                .bounds
+                .get(i)
+                .map_or("+Inf".to_string(), |b| b.to_string());
+            let _ = write!(out, "{}_bucket{{le=\"{}\"}} {}", self.name, le, cumulative);
+            if let (true, Some(ex)) = (openmetrics, &inner.exemplars[i]) {
+                let _ = write!(
+                    out,
+                    " # {{trace_id=\"{}\"}} {} {:.3}",
+                    ex.hex_id, ex.value, ex.timestamp
+                );
+            }
+            out.push('\n');
+        }
+        let _ = writeln!(out, "{}_sum {}", self.name, inner.sum);
+        let _ = writeln!(out, "{}_count {}", self.name, inner.count);
+    }
+}
+
+/// Running totals of verdicts served.
+#[derive(Default)]
+pub struct VerdictCounters {
+    allow: AtomicUsize,
+    warn: AtomicUsize,
+    block: AtomicUsize,
+}
+
+impl VerdictCounters {
+    pub fn record(&self, verdict: Verdict) {
+        let counter = match verdict {
+            Verdict::Allow => &self.allow,
+            Verdict::Warn => &self.warn,
+            Verdict::Block => &self.block,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> VerdictCounts {
+        VerdictCounts {
+            allow: self.allow.load(Ordering::Relaxed),
+            warn: self.warn.load(Ordering::Relaxed),
+            block: self.block.load(Ordering::Relaxed),
+        }
+    }
+}
+
+const WINDOW_SECS: usize = 3_600;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Slot {
+    /// Second (since `started`) this slot currently counts.
+    second: u64,
+    count: u64,
+    score_sum: f64,
+}
+
+/// Request count and mean score over a sliding window of up to an hour,
+/// in one-second slots.
+pub struct RecentRequests {
+    started: Instant,
+    slots: Mutex<Vec<Slot>>,
+}
+
+/// Totals over one window of `RecentRequests`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct WindowTotals {
+    pub requests: u64,
+    pub mean_score: Option<f64>,
+}
+
+impl Default for RecentRequests {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            slots: Mutex::new(vec![Slot::default(); WINDOW_SECS]),
+        }
+    }
+}
+
+impl RecentRequests {
+    pub fn record(&self, score: f64) {
+        self.record_at(self.started.elapsed().as_secs(), score);
+    }
+
+    fn record_at(&self, second: u64, score: f64) {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        let slot = &mut slots[second as usize % WINDOW_SECS];
+        if slot.second != second {
+            *slot = Slot {
+                second,
+                ..Slot::default()
+            };
+        }
+        slot.count += 1;
+        slot.score_sum += score;
+    }
+
+    /// Totals over the last `secs` seconds (at most an hour).
+    pub fn window(&self, secs: u64) -> WindowTotals {
+        self.window_at(self.started.elapsed().as_secs(), secs)
+    }
+
+    fn window_at(&self, now: u64, secs: u64) -> WindowTotals {
+        let secs = secs.min(WINDOW_SECS as u64);
+        let slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        let (requests, score_sum) = slots
+            .iter()
+            .filter(|slot| slot.count > 0 && slot.second <= now && now - slot.second < secs)
+            .fold((0, 0.0), |(n, sum), slot| {
+                (n + slot.count, sum + slot.score_sum)
+            });
+        WindowTotals {
+            requests,
+            mean_score: (requests > 0).then(|| score_sum / requests as f64),
+        }
+    }
+}
+
+/// Rewrite Prometheus text into OpenMetrics: counter families drop their
+/// `_total` suffix in metadata lines, and the exposition ends with `# EOF`.
+pub fn to_openmetrics(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 8);
+    for line in text.lines() {
+        let meta = line
+            .strip_prefix("# HELP ")
+            .map(|rest| ("HELP", rest))
+            .or_else(|| line.strip_prefix("# TYPE ").map(|rest| ("TYPE", rest)));
+        match meta {
+            Some((kind, rest)) => {
+                let (name, tail) = rest.split_once(' ').unwrap_or((rest, ""));
+                let name = name.strip_suffix("_total").unwrap_or(name);
+                let _ = writeln!(out, "# {} {} {}", kind, name, tail);
+            }
+            None => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exemplars_only_in_openmetrics() {
+        let hist = Histogram::new("wordmath_score", "Scores.", SCORE_BUCKETS);
+        hist.observe(0.95, None);
+        hist.observe(0.12, Some("00abcdef"));
+
+        let mut plain = String::new();
+        hist.render(&mut plain, false);
+        assert!(plain.contains("wordmath_score_bucket{le=\"0.2\"} 1\n"));
+        assert!(plain.contains("wordmath_score_count 2\n"));
+        assert!(!plain.contains("trace_id"));
+
+        let mut om = String::new();
+        hist.render(&mut om, true);
+        assert!(om.contains("wordmath_score_bucket{le=\"0.2\"} 1 # {trace_id=\"00abcdef\"} 0.12 "));
+    }
+
+    #[test]
+    fn test_openmetrics_counter_names() {
+        let text = "# HELP x_total Things.\n# TYPE x_total counter\nx_total 3\n";
+        assert_eq!(
+            to_openmetrics(text),
+            "# HELP x Things.\n# TYPE x counter\nx_total 3\n# EOF\n"
+        );
+    }
+
+    #[test]
+    fn test_recent_request_windows() {
+        let recent = RecentRequests::default();
+        recent.record_at(10, 0.2);
+        recent.record_at(100, 0.4);
+        recent.record_at(130, 0.6);
+        assert_eq!(recent.window_at(130, 60).requests, 2);
+        assert!((recent.window_at(130, 60).mean_score.unwrap() - 0.5).abs() < 1e-9);
+        assert_eq!(recent.window_at(130, 3_600).requests, 3);
+        // Slot 10 is reused an hour later.
+        recent.record_at(3_610, 0.9);
+        assert_eq!(recent.window_at(3_610, 3_600).requests, 3);
+        assert_eq!(recent.window_at(9_000, 60).mean_score, None);
+    }
+}
@@ -0,0 +1,127 @@
+//! Schema versioning for the embedded store.
+//!
+//! The layout of the values under each key prefix is versioned by the
+//! `meta/schema` entry. On startup `migrate` runs every migration newer
+//! than the stored version, in order, stamping the version after each one
+//! so an interrupted run resumes where it stopped. A store written by a
+//! newer binary is refused rather than served: its values may not parse,
+//! and writing to it would mix layouts. `server --migrate-only` migrates
+//! and exits, for running upgrades ahead of a rollout.
+
+use crate::kv::KvStore;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+const SCHEMA_KEY: &str = "meta/schema";
+
+struct Migration {
+    version: u32,
+    description: &'static str,
+    /// Rewrites the store; returns the number of entries changed.
+    run: fn(&KvStore) -> io::Result<usize>,
+}
+
+/// Every migration, oldest first. Versions must count up from 1.
+const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "baseline: sessions, traces, switches, switch audit and feedback",
+    run: |_| Ok(0),
+}];
+
+/// The newest schema this binary reads and writes.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+#[derive(Serialize, Deserialize)]
+struct SchemaMarker {
+    version: u32,
+    hex_id: String,
+}
+
+/// What `migrate` did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    /// Descriptions of the migrations applied, in order.
+    pub applied: Vec<&'static str>,
+    pub entries_changed: usize,
+}
+
+/// The schema version stamped in `kv`; 0 for a store that predates
+/// versioning (or is new).
+pub fn stored_version(kv: &KvStore) -> io::Result<u32> {
+    match kv.get(SCHEMA_KEY)? {
+        None => Ok(0),
+        Some(json) => serde_json::from_str::<SchemaMarker>(&json)
+            .map(|marker| marker.version)
+            .map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", SCHEMA_KEY, e))
+            }),
+    }
+}
+
+/// Bring `kv` up to `SCHEMA_VERSION`, refusing stores that are newer.
+pub fn migrate(kv: &KvStore) -> io::Result<MigrationReport> {
+    let from = stored_version(kv)?;
+    if from > SCHEMA_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "store schema v{} is newer than this binary understands (v{})",
+                from, SCHEMA_VERSION
+            ),
+        ));
+    }
+    let mut report = MigrationReport {
+        from,
+        to: from,
+        applied: Vec::new(),
+        entries_changed: 0,
+    };
+    for migration in MIGRATIONS.iter().filter(|m| m.version > from) {
+        report.entries_changed += (migration.run)(kv)?;
+        let marker = SchemaMarker {
+            version: migration.version,
+            hex_id: word_math_guard::generate_hex_id(),
+        };
+        kv.put(
+            SCHEMA_KEY,
+            serde_json::to_string(&marker).map_err(io::Error::other)?,
+        )?;
+        report.to = migration.version;
+        report.applied.push(migration.description);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use word_math_guard::generate_hex_id;
+
+    #[test]
+    fn test_migrate_stamps_and_refuses_newer_stores() {
+        assert!(MIGRATIONS
+            .iter()
+            .enumerate()
+            .all(|(i, m)| m.version as usize == i + 1));
+
+        let dir = std::env::temp_dir().join(format!("wordmath-migrate-{}", generate_hex_id()));
+        let kv = KvStore::open(&dir).unwrap();
+        let report = migrate(&kv).unwrap();
+        assert_eq!((report.from, report.to), (0, SCHEMA_VERSION));
+        assert_eq!(report.applied.len(), MIGRATIONS.len());
+        assert!(migrate(&kv).unwrap().applied.is_empty());
+
+        let marker = SchemaMarker {
+            version: SCHEMA_VERSION + 1,
+            hex_id: generate_hex_id(),
+        };
+        kv.put(SCHEMA_KEY, serde_json::to_string(&marker).unwrap())
+            .unwrap();
+        let err = migrate(&kv).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(stored_version(&kv).unwrap(), SCHEMA_VERSION + 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+//! Output precision for scores and other floats.
+//!
+//! With WORD_MATH_OUTPUT_PRECISION=<places> set (0 to 15), every float in
+//! a JSON response is rounded half away from zero to that many decimal
+//! places, and so are the metrics of stored trace records. Responses,
+//! `/traces` queries and session exports then agree digit for digit, and
+//! pipelines comparing records don't diff on float noise. Rounded
+//! responses are re-serialized with object keys in sorted order. Scoring
+//! itself and verdicts still use full precision. Proxied upstream
+//! responses are passed on untouched, apart from the score header.
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use serde_json::Value;
+
+const MAX_PLACES: u32 = 15;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Precision {
+    places: Option<u32>,
+}
+
+impl Precision {
+    pub fn new(places: Option<u32>) -> Self {
+        Self {
+            places: places.map(|p| p.min(MAX_PLACES)),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("WORD_MATH_OUTPUT_PRECISION")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+        )
+    }
+
+    pub fn places(&self) -> Option<u32> {
+        self.places
+    }
+
+    pub fn round(&self, x: f64) -> f64 {
+        match self.places {
+            Some(places) if x.is_finite() => {
+                let scale = 10f64.powi(places as i32);
+                (x * scale).round() / scale
+            }
+            _ => x,
+        }
+    }
+
+    /// Round every float in `value`; integers are left alone.
+    pub fn round_value(&self, value: &mut Value) {
+        match value {
+            Value::Number(n) if n.is_f64() => {
+                if let Some(rounded) = n
+                    .as_f64()
+                    .and_then(|x| serde_json::Number::from_f64(self.round(x)))
+                {
+                    *n = rounded;
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|v| self.round_value(v)),
+            Value::Object(fields) => fields.values_mut().for_each(|v| self.round_value(v)),
+            _ => {}
+        }
+    }
+}
+
+/// Middleware rounding the floats of JSON responses.
+pub async fn round_json(
+    State(precision): State<Precision>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if precision.places.is_none() || !is_json {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = to_bytes(body, usize::MAX).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    let body = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(mut value) => {
+            precision.round_value(&mut value);
+            serde_json::to_vec(&value).map_or(bytes, Into::into)
+        }
+        Err(_) => bytes,
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_value_rounds_floats_only() {
+        let precision = Precision::new(Some(4));
+        let mut value = serde_json::json!({
+            "score": 0.542857142857142_9,
+            "count": 3,
+            "nested": [{ "z": 0.099_999_999_999_999_98 }],
+            "hex_id": "18dedb",
+        });
+        precision.round_value(&mut value);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "score": 0.5429,
+                "count": 3,
+                "nested": [{ "z": 0.1 }],
+                "hex_id": "18dedb",
+            })
+        );
+        assert_eq!(Precision::default().round(0.123_456_7), 0.123_456_7);
+        assert_eq!(Precision::new(Some(99)).places(), Some(MAX_PLACES));
+    }
+}
@@ -0,0 +1,242 @@
+//! Differentially private release of traffic counts.
+//!
+//! With WORD_MATH_DP_EPSILON=<epsilon> set, the counts `/stats` and
+//! `/rollups` export (requests per window, verdict totals, active
+//! sessions, and per-bucket message and block counts) get two-sided
+//! geometric noise, the discrete Laplace mechanism with sensitivity 1:
+//! adding or removing one message changes each count by at most 1, so
+//! each released count is epsilon-differentially private. A message is
+//! counted in several places (its hourly and daily bucket, both `/stats`
+//! windows), so one response spends a small multiple of epsilon. Smaller
+//! epsilon means more noise; at epsilon = 1 a count is off by less than 1
+//! on average, at 0.1 by about 10.
+//!
+//! Noise is drawn from a per-process keyed hash of the count's label and
+//! true value, so repeating a query returns the same noisy value instead
+//! of letting callers average the noise away. Block rates are recomputed
+//! from the noisy counts. The mean score of a bucket or `/stats` window is
+//! the noisy sum of its scores divided by its noisy count: scores lie in
+//! [0, 1], so the sum has sensitivity 1 and gets Laplace noise of scale
+//! 1/epsilon.
+//!
+//! Everything else that counts traffic exactly would give the true values
+//! away, so while noise is on `/metrics` leaves out the verdict, session,
+//! trace, sink and topic cache counters and the score and latency
+//! histograms, and `/experiments`, whose per-variant tallies are exact, is
+//! refused.
+
+use crate::metrics::WindowTotals;
+use crate::rollups::RollupRow;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use word_math_guard::batch::VerdictCounts;
+
+#[derive(Debug, Clone)]
+pub struct CountNoise {
+    epsilon: f64,
+    keys: RandomState,
+}
+
+impl CountNoise {
+    pub fn new(epsilon: f64) -> Result<Self, String> {
+        if !(epsilon.is_finite() && epsilon > 0.0) {
+            return Err(format!("epsilon must be positive, got {}", epsilon));
+        }
+        Ok(Self {
+            epsilon,
+            keys: RandomState::new(),
+        })
+    }
+
+    /// Noise from WORD_MATH_DP_EPSILON, or None when unset.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        match std::env::var("WORD_MATH_DP_EPSILON") {
+            Ok(raw) => {
+                let epsilon = raw
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("not a number: {}", raw))?;
+                Self::new(epsilon).map(Some)
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn epsilon(&self) -> f64 {
+        self.epsilon
+    }
+
+    /// Uniform draws in (0, 1], seeded from the keyed hash of `label` and
+    /// `value`.
+    fn units(&self, label: &str, value: u64) -> impl FnMut() -> f64 {
+        let mut state = self.keys.hash_one((label, value)) | 1;
+        move || {
+            // xorshift64*, mapped into (0, 1].
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            ((state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) + 1) as f64 / (1u64 << 53) as f64
+        }
+    }
+
+    /// `value` plus two-sided geometric noise, clamped at 0.
+    pub fn count(&self, label: &str, value: u64) -> u64 {
+        let mut unit = self.units(label, value);
+        // The difference of two geometric variables with success
+        // probability 1 - e^-epsilon is discrete-Laplace distributed.
+        let geometric = |u: f64| (u.ln() / -self.epsilon).floor();
+        let noise = geometric(unit()) - geometric(unit());
+        (value as f64 + noise).max(0.0) as u64
+    }
+
+    /// `value` plus Laplace noise for sensitivity 1, clamped at 0.
+    pub fn sum(&self, label: &str, value: f64) -> f64 {
+        let mut unit = self.units(label, value.to_bits());
+        // The difference of two exponential variables with rate epsilon is
+        // Laplace distributed with scale 1/epsilon.
+        let noise = (unit().ln() - unit().ln()) / self.epsilon;
+        (value + noise).max(0.0)
+    }
+
+    pub fn verdicts(&self, label: &str, counts: VerdictCounts) -> VerdictCounts {
+        let noisy = |verdict: &str, count: usize| {
+            self.count(&format!("{}/{}", label, verdict), count as u64) as usize
+        };
+        VerdictCounts {
+            allow: noisy("allow", counts.allow),
+            warn: noisy("warn", counts.warn),
+            block: noisy("block", counts.block),
+        }
+    }
+
+    /// Mean of scores summing to `score_sum`, from its noisy sum over the
+    /// already noisy `count`.
+    fn mean(&self, label: &str, score_sum: f64, count: u64) -> f64 {
+        let score_sum = self.sum(&format!("{}/score_sum", label), score_sum);
+        if count == 0 {
+            0.0
+        } else {
+            (score_sum / count as f64).min(1.0)
+        }
+    }
+
+    /// Noisy request count and mean score of a `/stats` window; the mean
+    /// is dropped when the noisy count is zero.
+    pub fn window(&self, label: &str, totals: WindowTotals) -> WindowTotals {
+        let score_sum = totals.mean_score.unwrap_or(0.0) * totals.requests as f64;
+        let requests = self.count(label, totals.requests);
+        WindowTotals {
+            requests,
+            mean_score: (requests > 0).then(|| self.mean(label, score_sum, requests)),
+        }
+    }
+
+    /// Noisy message and block counts and mean score of a rollup bucket.
+    pub fn rollup(&self, row: &mut RollupRow) {
+        let label = format!(
+            "rollup/{:?}/{}/{}/{}",
+            row.period, row.start, row.profile, row.topic
+        );
+        let blocks = (row.block_rate * row.count as f64).round() as u64;
+        let score_sum = row.mean_score * row.count as f64;
+        row.count = self.count(&label, row.count);
+        row.mean_score = self.mean(&label, score_sum, row.count);
+        let blocks = self
+            .count(&format!("{}/blocks", label), blocks)
+            .min(row.count);
+        row.block_rate = blocks as f64 / row.count.max(1) as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_is_stable_per_value_and_scales_with_epsilon() {
+        let noise = CountNoise::new(1.0).unwrap();
+        assert_eq!(noise.count("stats", 500), noise.count("stats", 500));
+
+        let mean_error = |epsilon: f64| {
+            let noise = CountNoise::new(epsilon).unwrap();
+            (0..2_000)
+                .map(|i| (noise.count(&format!("c{}", i), 1_000) as f64 - 1_000.0).abs())
+                .sum::<f64>()
+                / 2_000.0
+        };
+        // E|X| = 2e^-eps / (1 - e^-2eps): about 0.85 at eps = 1, 10 at 0.1.
+        let (coarse, fine) = (mean_error(1.0), mean_error(0.1));
+        assert!(coarse > 0.5 && coarse < 1.3, "{}", coarse);
+        assert!(fine > 7.0 && fine < 13.0, "{}", fine);
+
+        assert!(CountNoise::new(0.0).is_err());
+        assert_eq!(noise.count("empty", 0), noise.count("empty", 0));
+    }
+
+    #[test]
+    fn test_rollup_mean_score_is_noised() {
+        use crate::rollups::Period;
+
+        let noise = CountNoise::new(1.0).unwrap();
+        let row = |topic: usize, count: u64| RollupRow {
+            period: Period::Hour,
+            start: 3_600,
+            profile: "default".to_string(),
+            topic: format!("t{}", topic),
+            count,
+            mean_score: 0.9,
+            block_rate: 0.0,
+        };
+        // A single message's score must not come out exactly.
+        let exact = (0..200)
+            .filter(|&i| {
+                let mut small = row(i, 1);
+                noise.rollup(&mut small);
+                assert!((0.0..=1.0).contains(&small.mean_score));
+                (small.mean_score - 0.9).abs() < 1e-9
+            })
+            .count();
+        assert!(exact < 10, "{} exact means", exact);
+
+        // Large buckets stay useful and repeat queries agree.
+        let (mut large, mut again) = (row(0, 10_000), row(0, 10_000));
+        noise.rollup(&mut large);
+        noise.rollup(&mut again);
+        assert!(
+            (large.mean_score - 0.9).abs() < 0.01,
+            "{}",
+            large.mean_score
+        );
+        assert_eq!(large.mean_score, again.mean_score);
+    }
+
+    #[test]
+    fn test_window_mean_score_is_noised() {
+        let noise = CountNoise::new(1.0).unwrap();
+        let totals = |requests: u64| WindowTotals {
+            requests,
+            mean_score: Some(0.9),
+        };
+        let exact = (0..200)
+            .filter(|&i| {
+                let small = noise.window(&format!("w{}", i), totals(1));
+                small
+                    .mean_score
+                    .is_some_and(|mean| (mean - 0.9).abs() < 1e-9)
+            })
+            .count();
+        assert!(exact < 10, "{} exact means", exact);
+
+        let large = noise.window("w", totals(10_000));
+        assert!(
+            (large.mean_score.unwrap() - 0.9).abs() < 0.01,
+            "{:?}",
+            large
+        );
+        assert_eq!(large, noise.window("w", totals(10_000)));
+        // An empty window may come out non-empty; the mean follows the
+        // noisy count, not the true one.
+        let empty = noise.window("w", WindowTotals::default());
+        assert_eq!(empty.mean_score.is_some(), empty.requests > 0);
+    }
+}
@@ -0,0 +1,593 @@
+//! CPU profiles and heap statistics for production debugging.
+//!
+//! Only built with the `ops` feature on Linux; the endpoints are
+//! admin-only.
+//!
+//! * `GET /debug/pprof/profile?seconds=10&hz=99` samples the process for
+//!   `seconds` (at most `MAX_SECONDS`) with `ITIMER_PROF`: every 1/hz
+//!   seconds of CPU time, `SIGPROF` records the program counter and thread
+//!   of whichever thread was running. The response is an uncompressed
+//!   pprof protobuf (`profile.proto`) with `samples/count` and
+//!   `cpu/nanoseconds` values, a `thread` label per sample and the
+//!   process's executable mappings, so `pprof -top <binary> <file>`
+//!   symbolizes it.
+//! * `GET /debug/pprof/pcs` takes the same parameters and returns the same
+//!   samples as plain text, one tab-separated `count thread
+//!   object+0xaddress` line per location, hottest first. Addresses are
+//!   relative to the object's load base, so `addr2line -f -C -e <object>
+//!   <address>` names the function.
+//!
+//! Stacks are not unwound: release builds omit frame pointers, and walking
+//! them from a signal handler without them could fault. Each sample is the
+//! single leaf frame, so profiles show where CPU time is spent, not who
+//! called it, and flamegraphs come out one level deep; use `perf record -g`
+//! for call graphs.
+//! * `GET /debug/pprof/heap` reports resident and peak memory from
+//!   `/proc/self/status` and, with glibc, the allocator's view from
+//!   `mallinfo2`.
+//!
+//! One profile runs at a time; the signal handler only stores to atomics.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+pub const DEFAULT_SECONDS: u64 = 10;
+pub const MAX_SECONDS: u64 = 120;
+pub const DEFAULT_HZ: u32 = 99;
+pub const MAX_HZ: u32 = 1_000;
+
+/// Samples kept per profile; later ones are counted as dropped.
+const CAPACITY: usize = 1 << 16;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+static PCS: [AtomicUsize; CAPACITY] = [const { AtomicUsize::new(0) }; CAPACITY];
+static TIDS: [AtomicUsize; CAPACITY] = [const { AtomicUsize::new(0) }; CAPACITY];
+
+/// Program counter of the interrupted code.
+///
+/// # Safety
+/// `context` must be the `ucontext_t` the kernel passed to the handler.
+unsafe fn interrupted_pc(context: *mut libc::c_void) -> usize {
+    let context = &*(context as *const libc::ucontext_t);
+    #[cfg(target_arch = "x86_64")]
+    return context.uc_mcontext.gregs[libc::REG_RIP as usize] as usize;
+    #[cfg(target_arch = "aarch64")]
+    return context.uc_mcontext.pc as usize;
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        let _ = context;
+        0
+    }
+}
+
+extern "C" fn on_sigprof(_: libc::c_int, _: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    let slot = NEXT.fetch_add(1, Ordering::Relaxed);
+    if slot < CAPACITY {
+        // SAFETY: the kernel passes the interrupted context; reading it and
+        // gettid are async-signal-safe.
+        let (pc, tid) = unsafe { (interrupted_pc(context), libc::gettid() as usize) };
+        PCS[slot].store(pc, Ordering::Relaxed);
+        TIDS[slot].store(tid, Ordering::Relaxed);
+    }
+}
+
+/// Timer interval for `hz` samples per second; zero disarms.
+fn interval(hz: u32) -> libc::timeval {
+    let micros = if hz == 0 { 0 } else { 1_000_000 / hz as i64 };
+    // tv_usec must stay below a second, or setitimer fails with EINVAL.
+    libc::timeval {
+        tv_sec: (micros / 1_000_000) as libc::time_t,
+        tv_usec: (micros % 1_000_000) as libc::suseconds_t,
+    }
+}
+
+fn set_timer(hz: u32) -> std::io::Result<()> {
+    let tick = interval(hz);
+    let timer = libc::itimerval {
+        it_interval: tick,
+        it_value: tick,
+    };
+    // SAFETY: plain syscall with a valid itimerval.
+    match unsafe { libc::setitimer(libc::ITIMER_PROF, &timer, std::ptr::null_mut()) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// A running profile; stopped when dropped, so an abandoned request
+/// does not leave the timer running.
+pub struct Sampling {
+    previous: libc::sigaction,
+    hz: u32,
+    started: SystemTime,
+    clock: Instant,
+}
+
+impl Sampling {
+    /// Start sampling at `hz`; None while another profile is running.
+    pub fn start(hz: u32) -> std::io::Result<Option<Self>> {
+        if RUNNING.swap(true, Ordering::SeqCst) {
+            return Ok(None);
+        }
+        NEXT.store(0, Ordering::SeqCst);
+        // SAFETY: `on_sigprof` only touches atomics and gettid; the
+        // previous action is restored on drop.
+        let previous = unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_sigprof
+                as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void)
+                as libc::sighandler_t;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(libc::SIGPROF, &action, &mut previous) != 0 {
+                RUNNING.store(false, Ordering::SeqCst);
+                return Err(std::io::Error::last_os_error());
+            }
+            previous
+        };
+        let hz = hz.clamp(1, MAX_HZ);
+        let sampling = Self {
+            previous,
+            hz,
+            started: SystemTime::now(),
+            clock: Instant::now(),
+        };
+        set_timer(hz)?;
+        Ok(Some(sampling))
+    }
+
+    /// Stop and aggregate the samples.
+    pub fn finish(self) -> Profile {
+        self.stop();
+        let taken = NEXT.load(Ordering::SeqCst);
+        let kept = taken.min(CAPACITY);
+        let mut counts: HashMap<(usize, usize), u64> = HashMap::new();
+        for slot in 0..kept {
+            let key = (
+                TIDS[slot].load(Ordering::Relaxed),
+                PCS[slot].load(Ordering::Relaxed),
+            );
+            *counts.entry(key).or_default() += 1;
+        }
+        let mut threads = HashMap::new();
+        let mut pcs: HashMap<(String, usize), u64> = HashMap::new();
+        for ((tid, pc), count) in counts {
+            let thread = threads
+                .entry(tid)
+                .or_insert_with(|| thread_name(tid))
+                .clone();
+            *pcs.entry((thread, pc)).or_default() += count;
+        }
+        let mut pcs: Vec<_> = pcs.into_iter().collect();
+        pcs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Profile {
+            samples: kept as u64,
+            dropped: (taken - kept) as u64,
+            pcs,
+            objects: mapped_objects(),
+            hz: self.hz,
+            started: self.started,
+            duration: self.clock.elapsed(),
+        }
+    }
+
+    fn stop(&self) {
+        let _ = set_timer(0);
+        // SAFETY: restores the action saved in `start`.
+        unsafe {
+            libc::sigaction(libc::SIGPROF, &self.previous, std::ptr::null_mut());
+        }
+    }
+}
+
+impl Drop for Sampling {
+    fn drop(&mut self) {
+        self.stop();
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Aggregated samples of one profile.
+#[derive(Debug)]
+pub struct Profile {
+    pub samples: u64,
+    /// Samples beyond `CAPACITY`.
+    pub dropped: u64,
+    /// ((thread, program counter), samples), hottest first.
+    pub pcs: Vec<((String, usize), u64)>,
+    objects: Vec<Object>,
+    hz: u32,
+    started: SystemTime,
+    duration: Duration,
+}
+
+impl Profile {
+    /// One `count<TAB>thread<TAB>location` line per location.
+    pub fn flat(&self) -> String {
+        let mut locations: HashMap<(&str, String), u64> = HashMap::new();
+        for ((thread, pc), count) in &self.pcs {
+            *locations
+                .entry((thread, locate(&self.objects, *pc)))
+                .or_default() += count;
+        }
+        let mut locations: Vec<_> = locations.into_iter().collect();
+        locations.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        locations
+            .iter()
+            .map(|((thread, location), count)| format!("{}\t{}\t{}\n", count, thread, location))
+            .collect()
+    }
+
+    /// The profile as an uncompressed pprof `Profile` message.
+    pub fn pprof(&self) -> Vec<u8> {
+        let mut strings = Strings::default();
+        let value_type = |strings: &mut Strings, kind: &str, unit: &str| {
+            let mut message = Vec::new();
+            proto::uint(&mut message, 1, strings.index(kind));
+            proto::uint(&mut message, 2, strings.index(unit));
+            message
+        };
+        let period = 1_000_000_000 / self.hz.max(1) as u64;
+        let mut out = Vec::new();
+        for (kind, unit) in [("samples", "count"), ("cpu", "nanoseconds")] {
+            let sample_type = value_type(&mut strings, kind, unit);
+            proto::bytes(&mut out, 1, &sample_type);
+        }
+
+        let mut locations: HashMap<usize, u64> = HashMap::new();
+        let thread_key = strings.index("thread");
+        for ((thread, pc), count) in &self.pcs {
+            let next = locations.len() as u64 + 1;
+            let location = *locations.entry(*pc).or_insert(next);
+            let mut label = Vec::new();
+            proto::uint(&mut label, 1, thread_key);
+            proto::uint(&mut label, 2, strings.index(thread));
+            let mut sample = Vec::new();
+            proto::packed(&mut sample, 1, &[location]);
+            proto::packed(&mut sample, 2, &[*count, count * period]);
+            proto::bytes(&mut sample, 3, &label);
+            proto::bytes(&mut out, 2, &sample);
+        }
+
+        for (id, object) in self.objects.iter().enumerate() {
+            let mut mapping = Vec::new();
+            proto::uint(&mut mapping, 1, id as u64 + 1);
+            proto::uint(&mut mapping, 2, object.start as u64);
+            proto::uint(&mut mapping, 3, object.end as u64);
+            proto::uint(&mut mapping, 4, object.offset as u64);
+            proto::uint(&mut mapping, 5, strings.index(&object.path));
+            proto::bytes(&mut out, 3, &mapping);
+        }
+        let mut locations: Vec<_> = locations.into_iter().collect();
+        locations.sort_by_key(|&(_, id)| id);
+        for (pc, id) in locations {
+            let mut location = Vec::new();
+            proto::uint(&mut location, 1, id);
+            if let Some(mapping) = self.objects.iter().position(|o| o.contains(pc)) {
+                proto::uint(&mut location, 2, mapping as u64 + 1);
+            }
+            proto::uint(&mut location, 3, pc as u64);
+            proto::bytes(&mut out, 4, &location);
+        }
+
+        let period_type = value_type(&mut strings, "cpu", "nanoseconds");
+        for string in &strings.table {
+            proto::bytes(&mut out, 6, string.as_bytes());
+        }
+        let since_epoch = self
+            .started
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        proto::uint(&mut out, 9, since_epoch.as_nanos() as u64);
+        proto::uint(&mut out, 10, self.duration.as_nanos() as u64);
+        proto::bytes(&mut out, 11, &period_type);
+        proto::uint(&mut out, 12, period);
+        out
+    }
+}
+
+/// The pprof string table; index 0 is the empty string.
+#[derive(Default)]
+struct Strings {
+    table: Vec<String>,
+    index: HashMap<String, u64>,
+}
+
+impl Strings {
+    fn index(&mut self, string: &str) -> u64 {
+        if self.table.is_empty() {
+            self.table.push(String::new());
+            self.index.insert(String::new(), 0);
+        }
+        if let Some(&index) = self.index.get(string) {
+            return index;
+        }
+        let index = self.table.len() as u64;
+        self.table.push(string.to_string());
+        self.index.insert(string.to_string(), index);
+        index
+    }
+}
+
+/// Just enough of the protobuf wire format for `Profile::pprof`.
+mod proto {
+    pub fn varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    pub fn uint(out: &mut Vec<u8>, field: u32, value: u64) {
+        varint(out, (field as u64) << 3);
+        varint(out, value);
+    }
+
+    pub fn bytes(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+        varint(out, (field as u64) << 3 | 2);
+        varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+
+    pub fn packed(out: &mut Vec<u8>, field: u32, values: &[u64]) {
+        let mut encoded = Vec::new();
+        for &value in values {
+            varint(&mut encoded, value);
+        }
+        bytes(out, field, &encoded);
+    }
+}
+
+/// An executable mapping from `/proc/self/maps`.
+#[derive(Debug)]
+struct Object {
+    start: usize,
+    end: usize,
+    /// Offset of `start` in the file.
+    offset: usize,
+    /// Where the file's first byte is mapped.
+    base: usize,
+    path: String,
+}
+
+impl Object {
+    fn contains(&self, pc: usize) -> bool {
+        (self.start..self.end).contains(&pc)
+    }
+}
+
+/// Executable mappings of the process.
+fn mapped_objects() -> Vec<Object> {
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap_or_default();
+    let mut bases: HashMap<String, usize> = HashMap::new();
+    let mut objects = Vec::new();
+    for line in maps.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [range, perms, offset, _, _, path, ..] = fields[..] else {
+            continue;
+        };
+        let Some((start, end)) = range.split_once('-') else {
+            continue;
+        };
+        let (Ok(start), Ok(end), Ok(offset)) = (
+            usize::from_str_radix(start, 16),
+            usize::from_str_radix(end, 16),
+            usize::from_str_radix(offset, 16),
+        ) else {
+            continue;
+        };
+        if offset == 0 {
+            bases.entry(path.to_string()).or_insert(start);
+        }
+        if perms.contains('x') {
+            objects.push(Object {
+                start,
+                end,
+                offset,
+                base: start,
+                path: path.to_string(),
+            });
+        }
+    }
+    for object in &mut objects {
+        if let Some(&base) = bases.get(&object.path) {
+            object.base = base;
+        }
+    }
+    objects
+}
+
+/// `object+0xaddress` for `pc`, or the raw address outside any object.
+fn locate(objects: &[Object], pc: usize) -> String {
+    objects
+        .iter()
+        .find(|object| object.contains(pc))
+        .map_or_else(
+            || format!("0x{:x}", pc),
+            |object| format!("{}+0x{:x}", object.path, pc - object.base),
+        )
+}
+
+fn thread_name(tid: usize) -> String {
+    std::fs::read_to_string(format!("/proc/self/task/{}/comm", tid))
+        .map(|name| name.trim().to_string())
+        .unwrap_or_else(|_| format!("thread-{}", tid))
+}
+
+/// Sampling duration and rate from query parameters.
+pub fn bounds(seconds: Option<u64>, hz: Option<u32>) -> (Duration, u32) {
+    (
+        Duration::from_secs(seconds.unwrap_or(DEFAULT_SECONDS).clamp(1, MAX_SECONDS)),
+        hz.unwrap_or(DEFAULT_HZ).clamp(1, MAX_HZ),
+    )
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct HeapStats {
+    pub rss_bytes: u64,
+    pub peak_rss_bytes: u64,
+    pub virtual_bytes: u64,
+    pub data_bytes: u64,
+    pub threads: u64,
+    /// glibc allocator statistics.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub malloc: Option<MallocStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MallocStats {
+    /// Bytes obtained from the system through brk.
+    pub arena_bytes: u64,
+    /// Bytes obtained through mmap.
+    pub mmap_bytes: u64,
+    pub in_use_bytes: u64,
+    pub free_bytes: u64,
+}
+
+pub fn heap_stats() -> std::io::Result<HeapStats> {
+    let status = std::fs::read_to_string("/proc/self/status")?;
+    let mut stats = HeapStats::default();
+    for line in status.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let number = value
+            .split_whitespace()
+            .next()
+            .and_then(|n| n.parse::<u64>().ok())
+            .unwrap_or(0);
+        match key {
+            "VmRSS" => stats.rss_bytes = number * 1024,
+            "VmHWM" => stats.peak_rss_bytes = number * 1024,
+            "VmSize" => stats.virtual_bytes = number * 1024,
+            "VmData" => stats.data_bytes = number * 1024,
+            "Threads" => stats.threads = number,
+            _ => {}
+        }
+    }
+    #[cfg(target_env = "gnu")]
+    {
+        // SAFETY: mallinfo2 only reads allocator state.
+        let info = unsafe { libc::mallinfo2() };
+        stats.malloc = Some(MallocStats {
+            arena_bytes: info.arena as u64,
+            mmap_bytes: info.hblkhd as u64,
+            in_use_bytes: (info.uordblks + info.hblkhd) as u64,
+            free_bytes: info.fordblks as u64,
+        });
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_samples_a_busy_thread_and_heap_is_reported() {
+        let sampling = Sampling::start(MAX_HZ).unwrap().unwrap();
+        assert!(Sampling::start(MAX_HZ).unwrap().is_none());
+        let started = std::time::Instant::now();
+        let mut x = 1u64;
+        while started.elapsed() < Duration::from_millis(300) {
+            x = std::hint::black_box(x.wrapping_mul(6364136223846793005).wrapping_add(1));
+        }
+        let profile = sampling.finish();
+        assert!(profile.samples > 10, "{:?}", profile);
+        assert_eq!(profile.dropped, 0);
+        // The hottest spot may be the clock read in the vDSO, but the loop
+        // itself shows up in the test binary.
+        let exe = std::env::current_exe().unwrap();
+        let own = format!("\t{}+0x", exe.display());
+        let flat = profile.flat();
+        assert!(flat.lines().any(|line| line.contains(&own)), "{}", flat);
+        assert!(flat.lines().all(|line| line.split('\t').count() == 3));
+
+        let pprof = profile.pprof();
+        let fields = decode(&pprof);
+        let strings: Vec<String> = fields
+            .iter()
+            .filter(|(field, _)| *field == 6)
+            .map(|(_, value)| String::from_utf8(value.clone().unwrap_err()).unwrap())
+            .collect();
+        assert_eq!(strings[0], "");
+        for expected in ["samples", "cpu", "nanoseconds", "thread"] {
+            assert!(strings.iter().any(|s| s == expected), "{:?}", strings);
+        }
+        assert!(strings.contains(&exe.display().to_string()));
+        // Sample values are packed [count, nanoseconds].
+        let total: u64 = fields
+            .iter()
+            .filter(|(field, _)| *field == 2)
+            .map(|(_, sample)| {
+                let sample = decode(sample.as_ref().unwrap_err());
+                let (_, values) = sample.iter().find(|(field, _)| *field == 2).unwrap();
+                let mut values = values.as_ref().unwrap_err().as_slice();
+                let count = varint(&mut values);
+                assert_eq!(varint(&mut values), count * (1_000_000_000 / MAX_HZ as u64));
+                count
+            })
+            .sum();
+        assert_eq!(total, profile.samples);
+        assert!(fields.contains(&(12, Ok(1_000_000_000 / MAX_HZ as u64))));
+        assert!(Sampling::start(MAX_HZ).unwrap().is_some());
+
+        let heap = heap_stats().unwrap();
+        assert!(heap.rss_bytes > 0 && heap.peak_rss_bytes >= heap.rss_bytes);
+        assert_eq!(
+            bounds(Some(9_999), Some(0)),
+            (Duration::from_secs(MAX_SECONDS), 1)
+        );
+    }
+
+    #[test]
+    fn test_timer_interval_keeps_microseconds_below_a_second() {
+        let parts = |hz| {
+            let tick = interval(hz);
+            (tick.tv_sec, tick.tv_usec)
+        };
+        assert_eq!(parts(1), (1, 0));
+        assert_eq!(parts(99), (0, 10_101));
+        assert_eq!(parts(MAX_HZ), (0, 1_000));
+        assert_eq!(parts(0), (0, 0));
+    }
+
+    fn varint(buf: &mut &[u8]) -> u64 {
+        let mut value = 0;
+        for shift in (0..).step_by(7) {
+            let byte = buf[0];
+            *buf = &buf[1..];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        value
+    }
+
+    /// Top-level fields of a protobuf message: varints as Ok, length-
+    /// delimited fields as Err.
+    fn decode(mut buf: &[u8]) -> Vec<(u64, Result<u64, Vec<u8>>)> {
+        let mut fields = Vec::new();
+        while !buf.is_empty() {
+            let key = varint(&mut buf);
+            let value = match key & 7 {
+                0 => Ok(varint(&mut buf)),
+                2 => {
+                    let len = varint(&mut buf) as usize;
+                    let (bytes, rest) = buf.split_at(len);
+                    buf = rest;
+                    Err(bytes.to_vec())
+                }
+                wire => panic!("unexpected wire type {}", wire),
+            };
+            fields.push((key >> 3, value));
+        }
+        fields
+    }
+}
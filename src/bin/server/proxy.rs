@@ -0,0 +1,577 @@
+//! Proxy mode: scoring OpenAI-compatible traffic on its way to the model.
+//!
+//! With WORD_MATH_UPSTREAM=http://host:port[/base] set, `POST
+//! /v1/chat/completions` and `POST /v1/completions` are forwarded to that
+//! backend. The prompt (the last user message, or `prompt`) is scored
+//! before forwarding and the completion after; the forwarded request
+//! carries the prompt's decision and the response the completion's (the
+//! prompt's when the response holds no completion) as `x-wordmath-score`,
+//! `x-wordmath-verdict` and `x-wordmath-trace-id` headers, so services on
+//! either side can react without parsing bodies. Nothing is blocked, but a
+//! response never passes for scored when it isn't: `x-wordmath-completion`
+//! says `scored` or `unscored` (no completion found, or an upstream error),
+//! and a compressed reply, which could not be read, fails with 502.
+//!
+//! Clients pick a topic with `x-wordmath-topic-id` or `x-wordmath-topic`
+//! and a profile with `x-wordmath-profile`. Without a topic, the prompt is
+//! scored against the system message (or itself, so that only repetition
+//! counts) and the completion against the prompt. The client's `x-api-key`
+//! and every `x-wordmath-*` header it sent are dropped before forwarding,
+//! so neither WordMath credentials nor spoofed decision headers reach the
+//! backend, and so is its `accept-encoding`: the upstream is asked for an
+//! uncompressed reply, so the completion can be scored.
+//!
+//! Clients that would rather read the analysis from the body send
+//! `x-wordmath-annotate: envelope`, to get `{completion, analysis,
+//! trace_id}` with the upstream response as `completion`, or `metadata`, to
+//! get the upstream response with the analysis under `metadata.wordmath`.
+//! Non-JSON upstream responses are never rewritten.
+//!
+//! The upstream is spoken to in plain HTTP/1.1, one connection per
+//! request; put TLS backends behind a local sidecar. Streamed completions
+//! (`"stream": true`) are refused. WORD_MATH_UPSTREAM_TIMEOUT_MS bounds each
+//! upstream exchange (default 60000) and WORD_MATH_UPSTREAM_MAX_BYTES the
+//! size of its response (default 2 MiB, axum's request body limit).
+
+use crate::replay::API_KEY_HEADER;
+use axum::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use serde_json::Value;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub const SCORE_HEADER: &str = "x-wordmath-score";
+pub const VERDICT_HEADER: &str = "x-wordmath-verdict";
+pub const TRACE_HEADER: &str = "x-wordmath-trace-id";
+pub const TOPIC_HEADER: &str = "x-wordmath-topic";
+pub const TOPIC_ID_HEADER: &str = "x-wordmath-topic-id";
+pub const PROFILE_HEADER: &str = "x-wordmath-profile";
+pub const ANNOTATE_HEADER: &str = "x-wordmath-annotate";
+pub const COMPLETION_HEADER: &str = "x-wordmath-completion";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_RESPONSE_BYTES: usize = 2 * 1024 * 1024;
+/// Prefix of the headers WordMath reads or sets itself.
+const CONTROL_PREFIX: &str = "x-wordmath-";
+
+/// Headers that describe one connection rather than the message.
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+    "content-length",
+];
+
+/// The LLM backend requests are forwarded to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upstream {
+    /// `host:port`, also sent as the Host header.
+    authority: String,
+    /// Path prefix prepended to forwarded paths, without a trailing slash.
+    base_path: String,
+    timeout: Duration,
+    /// Largest response read, head included.
+    max_response_bytes: usize,
+}
+
+/// A buffered upstream response.
+#[derive(Debug)]
+pub struct UpstreamResponse {
+    pub status: StatusCode,
+    /// End-to-end headers only.
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl Upstream {
+    /// Parse an `http://host[:port][/base]` URL.
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("upstream must be an http:// URL: {}", url))?;
+        let (authority, base_path) = match rest.find('/') {
+            Some(i) => (&rest[..i], rest[i..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        if authority.is_empty() {
+            return Err(format!("upstream URL has no host: {}", url));
+        }
+        let authority = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{}:80", authority)
+        };
+        Ok(Self {
+            authority,
+            base_path: base_path.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+        })
+    }
+
+    /// The backend named by WORD_MATH_UPSTREAM, if set.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Ok(url) = std::env::var("WORD_MATH_UPSTREAM") else {
+            return Ok(None);
+        };
+        let mut upstream = Self::parse(url.trim())?;
+        if let Some(ms) = std::env::var("WORD_MATH_UPSTREAM_TIMEOUT_MS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+        {
+            upstream.timeout = Duration::from_millis(ms);
+        }
+        if let Ok(bytes) = std::env::var("WORD_MATH_UPSTREAM_MAX_BYTES") {
+            let bytes = bytes
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid WORD_MATH_UPSTREAM_MAX_BYTES: {}", bytes))?;
+            upstream = upstream.with_max_response_bytes(bytes);
+        }
+        Ok(Some(upstream))
+    }
+
+    /// Refuse responses larger than `bytes`, head included.
+    pub fn with_max_response_bytes(mut self, bytes: usize) -> Self {
+        self.max_response_bytes = bytes;
+        self
+    }
+
+    pub fn authority(&self) -> &str {
+        &self.authority
+    }
+
+    /// Send one request and read the whole response.
+    pub async fn send(
+        &self,
+        method: &Method,
+        path: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> io::Result<UpstreamResponse> {
+        let exchange = async {
+            let mut stream = TcpStream::connect(&self.authority).await?;
+            let mut head = format!(
+                "{} {}{} HTTP/1.1\r\nhost: {}\r\nconnection: close\r\n\
+                 accept-encoding: identity\r\ncontent-length: {}\r\n",
+                method,
+                self.base_path,
+                path,
+                self.authority,
+                body.len()
+            );
+            let forwarded = end_to_end(headers).filter(|(name, _)| *name != ACCEPT_ENCODING);
+            for (name, value) in forwarded {
+                if let Ok(value) = value.to_str() {
+                    head.push_str(&format!("{}: {}\r\n", name, value));
+                }
+            }
+            head.push_str("\r\n");
+            stream.write_all(head.as_bytes()).await?;
+            stream.write_all(body).await?;
+            let mut raw = Vec::new();
+            let limit = self.max_response_bytes as u64;
+            stream
+                .take(limit.saturating_add(1))
+                .read_to_end(&mut raw)
+                .await?;
+            if raw.len() as u64 > limit {
+                return Err(invalid(&format!(
+                    "upstream response exceeds {} bytes",
+                    self.max_response_bytes
+                )));
+            }
+            parse_response(&raw)
+        };
+        tokio::time::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "upstream timed out"))?
+    }
+}
+
+/// `headers` without hop-by-hop ones.
+pub fn end_to_end(headers: &HeaderMap) -> impl Iterator<Item = (&HeaderName, &HeaderValue)> {
+    headers
+        .iter()
+        .filter(|(name, _)| !HOP_BY_HOP.contains(&name.as_str()))
+}
+
+/// Drop the client's API key, `x-wordmath-*` and `accept-encoding` headers
+/// from a request about to be forwarded.
+pub fn strip_control_headers(headers: &mut HeaderMap) {
+    let control: Vec<HeaderName> = headers
+        .keys()
+        .filter(|name| name.as_str().starts_with(CONTROL_PREFIX))
+        .cloned()
+        .collect();
+    for name in control {
+        headers.remove(name);
+    }
+    headers.remove(API_KEY_HEADER);
+    headers.remove(ACCEPT_ENCODING);
+}
+
+/// The content coding of a response body, unless it is plain.
+pub fn content_encoding(headers: &HeaderMap) -> Option<&str> {
+    let coding = headers.get(CONTENT_ENCODING)?.to_str().unwrap_or("unknown");
+    (!coding.trim().eq_ignore_ascii_case("identity")).then_some(coding)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Parse a complete HTTP/1.1 response read up to connection close.
+fn parse_response(raw: &[u8]) -> io::Result<UpstreamResponse> {
+    let split = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid("upstream response has no header end"))?;
+    let head = std::str::from_utf8(&raw[..split]).map_err(|_| invalid("non-UTF-8 header"))?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .ok_or_else(|| invalid("bad upstream status line"))?;
+
+    let mut headers = HeaderMap::new();
+    let mut chunked = false;
+    let mut length = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim().to_ascii_lowercase(), value.trim());
+        match name.as_str() {
+            "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+            "content-length" => length = value.parse::<usize>().ok(),
+            _ if HOP_BY_HOP.contains(&name.as_str()) => {}
+            _ => {
+                if let (Ok(name), Ok(value)) =
+                    (HeaderName::try_from(name), HeaderValue::from_str(value))
+                {
+                    headers.append(name, value);
+                }
+            }
+        }
+    }
+
+    let rest = &raw[split + 4..];
+    let body = if chunked {
+        decode_chunked(rest)?
+    } else {
+        match length {
+            Some(n) => rest
+                .get(..n)
+                .ok_or_else(|| invalid("truncated body"))?
+                .to_vec(),
+            None => rest.to_vec(),
+        }
+    };
+    Ok(UpstreamResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn decode_chunked(mut rest: &[u8]) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = rest
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| invalid("truncated chunk size"))?;
+        let size = std::str::from_utf8(&rest[..line_end])
+            .ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next()?.trim(), 16).ok())
+            .ok_or_else(|| invalid("bad chunk size"))?;
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        body.extend_from_slice(rest.get(..size).ok_or_else(|| invalid("truncated chunk"))?);
+        rest = rest
+            .get(size + 2..)
+            .ok_or_else(|| invalid("truncated chunk"))?;
+    }
+}
+
+/// Where the analysis goes besides the response headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Annotation {
+    #[default]
+    None,
+    /// `{completion, analysis, trace_id}` around the upstream response.
+    Envelope,
+    /// The analysis under the response's `metadata.wordmath`.
+    Metadata,
+}
+
+impl Annotation {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "none" => Some(Self::None),
+            "envelope" => Some(Self::Envelope),
+            "metadata" => Some(Self::Metadata),
+            _ => None,
+        }
+    }
+
+    /// `body` annotated with `analysis`, or None to pass it on unchanged.
+    pub fn apply(self, body: &[u8], analysis: Value, trace_id: &str) -> Option<Vec<u8>> {
+        if self == Self::None {
+            return None;
+        }
+        let mut response: Value = serde_json::from_slice(body).ok()?;
+        let annotated = match self {
+            Self::Envelope => serde_json::json!({
+                "completion": response,
+                "analysis": analysis,
+                "trace_id": trace_id,
+            }),
+            _ => {
+                let fields = response.as_object_mut()?;
+                let metadata = fields
+                    .entry("metadata")
+                    .or_insert_with(|| Value::Object(Default::default()));
+                if !metadata.is_object() {
+                    *metadata = Value::Object(Default::default());
+                }
+                metadata
+                    .as_object_mut()?
+                    .insert("wordmath".to_string(), analysis);
+                response
+            }
+        };
+        serde_json::to_vec(&annotated).ok()
+    }
+}
+
+/// Text of a message `content`: a string, or the text parts of an array.
+fn content_text(content: &Value) -> Option<String> {
+    match content {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(parts) => {
+            let texts: Vec<&str> = parts
+                .iter()
+                .filter_map(|part| part.get("text")?.as_str())
+                .collect();
+            (!texts.is_empty()).then(|| texts.join("\n"))
+        }
+        _ => None,
+    }
+}
+
+fn last_message(request: &Value, role: &str) -> Option<String> {
+    request
+        .get("messages")?
+        .as_array()?
+        .iter()
+        .rev()
+        .find(|m| m.get("role").and_then(Value::as_str) == Some(role))
+        .and_then(|m| content_text(m.get("content")?))
+}
+
+/// The prompt of a chat or legacy completion request.
+pub fn prompt_text(request: &Value) -> Option<String> {
+    last_message(request, "user").or_else(|| match request.get("prompt")? {
+        Value::String(prompt) => Some(prompt.clone()),
+        Value::Array(prompts) => prompts.first()?.as_str().map(str::to_string),
+        _ => None,
+    })
+}
+
+/// The system message of a chat request.
+pub fn system_text(request: &Value) -> Option<String> {
+    last_message(request, "system")
+}
+
+/// The first choice's text in a chat or legacy completion response.
+pub fn completion_text(response: &Value) -> Option<String> {
+    let choice = response.get("choices")?.as_array()?.first()?;
+    match choice.get("message") {
+        Some(message) => content_text(message.get("content")?),
+        None => choice.get("text")?.as_str().map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_upstream_url() {
+        let upstream = Upstream::parse("http://llm.internal:8080/openai/").unwrap();
+        assert_eq!(upstream.authority(), "llm.internal:8080");
+        assert_eq!(upstream.base_path, "/openai");
+        assert_eq!(Upstream::parse("http://llm").unwrap().authority(), "llm:80");
+        assert!(Upstream::parse("https://api.example.com").is_err());
+    }
+
+    #[test]
+    fn test_strip_control_headers() {
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            ("x-api-key", "wordmath-secret"),
+            ("x-wordmath-topic", "rust"),
+            ("x-wordmath-score", "1.0"),
+            ("accept-encoding", "gzip, br"),
+            ("authorization", "Bearer upstream"),
+            ("content-type", "application/json"),
+        ] {
+            headers.insert(name, HeaderValue::from_static(value));
+        }
+        strip_control_headers(&mut headers);
+        let mut names: Vec<&str> = headers.keys().map(HeaderName::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["authorization", "content-type"]);
+    }
+
+    #[test]
+    fn test_content_encoding() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(content_encoding(&headers), None);
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("identity"));
+        assert_eq!(content_encoding(&headers), None);
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        assert_eq!(content_encoding(&headers), Some("gzip"));
+    }
+
+    #[tokio::test]
+    async fn test_upstream_is_asked_for_identity_encoding() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await;
+            String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase()
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+        let upstream = Upstream::parse(&url).unwrap();
+        upstream
+            .send(&Method::POST, "/v1/completions", &headers, b"{}")
+            .await
+            .unwrap();
+        let request = server.await.unwrap();
+        assert!(
+            request.contains("accept-encoding: identity\r\n"),
+            "{}",
+            request
+        );
+        assert!(!request.contains("gzip"), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn test_response_size_limit() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                let body = "x".repeat(1000);
+                let response = format!("HTTP/1.1 200 OK\r\ncontent-length: 1000\r\n\r\n{}", body);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        let headers = HeaderMap::new();
+        let upstream = Upstream::parse(&url).unwrap();
+        let response = upstream
+            .send(&Method::GET, "/", &headers, &[])
+            .await
+            .unwrap();
+        assert_eq!(response.body.len(), 1000);
+        let small = upstream.with_max_response_bytes(500);
+        let err = small
+            .send(&Method::GET, "/", &headers, &[])
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("exceeds 500 bytes"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_chunked_response() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                    Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+                    4\r\n{\"a\"\r\n3;ext=1\r\n:1}\r\n0\r\n\r\n";
+        let response = parse_response(raw).unwrap();
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body, b"{\"a\":1}");
+        assert_eq!(response.headers["content-type"], "application/json");
+        assert!(!response.headers.contains_key("connection"));
+    }
+
+    #[test]
+    fn test_annotations() {
+        let body = br#"{"id":"c1","choices":[]}"#;
+        let analysis = json!({ "completion": { "score": 0.9 } });
+        assert!(Annotation::None
+            .apply(body, analysis.clone(), "t1")
+            .is_none());
+        assert!(Annotation::Envelope
+            .apply(b"not json", analysis.clone(), "t1")
+            .is_none());
+
+        let envelope = Annotation::Envelope
+            .apply(body, analysis.clone(), "t1")
+            .unwrap();
+        let envelope: Value = serde_json::from_slice(&envelope).unwrap();
+        assert_eq!(envelope["completion"]["id"], "c1");
+        assert_eq!(envelope["analysis"], analysis);
+        assert_eq!(envelope["trace_id"], "t1");
+
+        let merged = Annotation::Metadata
+            .apply(body, analysis.clone(), "t1")
+            .unwrap();
+        let merged: Value = serde_json::from_slice(&merged).unwrap();
+        assert_eq!(merged["id"], "c1");
+        assert_eq!(merged["metadata"]["wordmath"], analysis);
+        assert_eq!(Annotation::parse(" Envelope "), Some(Annotation::Envelope));
+        assert_eq!(Annotation::parse("xml"), None);
+    }
+
+    #[test]
+    fn test_extracts_prompt_and_completion() {
+        let request = json!({
+            "messages": [
+                { "role": "system", "content": "You help with rust web servers." },
+                { "role": "user", "content": "first question" },
+                { "role": "assistant", "content": "an answer" },
+                { "role": "user", "content": [{ "type": "text", "text": "how do I deploy?" }] },
+            ]
+        });
+        assert_eq!(prompt_text(&request).unwrap(), "how do I deploy?");
+        assert_eq!(
+            system_text(&request).unwrap(),
+            "You help with rust web servers."
+        );
+        assert_eq!(
+            prompt_text(&json!({ "prompt": "complete this" })).unwrap(),
+            "complete this"
+        );
+        let response = json!({ "choices": [{ "message": { "content": "use cargo" } }] });
+        assert_eq!(completion_text(&response).unwrap(), "use cargo");
+        assert_eq!(
+            completion_text(&json!({ "choices": [{ "text": "legacy" }] })).unwrap(),
+            "legacy"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Per-client replay detection across sessions.
+//!
+//! Each API key keeps MinHash signatures of its most recent messages. A
+//! message that near-duplicates one the same client sent in a *different*
+//! session counts as a replay; the share of replays in the window is the
+//! client's `client_replay_ratio`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Instant;
+use word_math_guard::minhash::MinHashSignature;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+const WINDOW: usize = 64;
+const REPLAY_SIMILARITY: f64 = 0.8;
+const MAX_CLIENTS: usize = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Recent {
+    session: Option<String>,
+    signature: MinHashSignature,
+    replay: bool,
+}
+
+struct ClientState {
+    recent: VecDeque<Recent>,
+    total: u64,
+    replays_total: u64,
+    last_seen: Instant,
+}
+
+impl ClientState {
+    fn replay_ratio(&self) -> f64 {
+        if self.recent.is_empty() {
+            return 0.0;
+        }
+        let replays = self.recent.iter().filter(|r| r.replay).count();
+        replays as f64 / self.recent.len() as f64
+    }
+}
+
+/// A client's replay window, handed from one instance to the next.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientSnapshot {
+    api_key: String,
+    recent: Vec<Recent>,
+    total: u64,
+    replays_total: u64,
+}
+
+/// One row of the offender listing.
+#[derive(Debug, Clone, Serialize)]
+pub struct Offender {
+    pub api_key: String,
+    pub client_replay_ratio: f64,
+    pub messages_total: u64,
+    pub replays_total: u64,
+}
+
+#[derive(Default)]
+pub struct ReplayTracker {
+    clients: Mutex<HashMap<String, ClientState>>,
+}
+
+impl ReplayTracker {
+    /// Record a message from `api_key` and return the client's replay ratio
+    /// over its recent window, including this message.
+    pub fn observe(&self, api_key: &str, session: Option<&str>, message: &str) -> f64 {
+        let signature = MinHashSignature::of(message);
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+
+        if !clients.contains_key(api_key) && clients.len() >= MAX_CLIENTS {
+            let stalest = clients
+                .iter()
+                .min_by_key(|(_, state)| state.last_seen)
+                .map(|(key, _)| key.clone());
+            if let Some(stalest) = stalest {
+                clients.remove(&stalest);
+            }
+        }
+
+        let state = clients
+            .entry(api_key.to_string())
+            .or_insert_with(|| ClientState {
+                recent: VecDeque::with_capacity(WINDOW),
+                total: 0,
+                replays_total: 0,
+                last_seen: Instant::now(),
+            });
+
+        let replay = state.recent.iter().any(|prev| {
+            prev.session.as_deref() != session
+                && prev.signature.similarity(&signature) >= REPLAY_SIMILARITY
+        });
+        if state.recent.len() == WINDOW {
+            state.recent.pop_front();
+        }
+        state.recent.push_back(Recent {
+            session: session.map(str::to_string),
+            signature,
+            replay,
+        });
+        state.total += 1;
+        state.replays_total += u64::from(replay);
+        state.last_seen = Instant::now();
+        state.replay_ratio()
+    }
+
+    /// Every client's window, for a state handoff.
+    pub fn export(&self) -> Vec<ClientSnapshot> {
+        let clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients
+            .iter()
+            .map(|(key, state)| ClientSnapshot {
+                api_key: key.clone(),
+                recent: state.recent.iter().cloned().collect(),
+                total: state.total,
+                replays_total: state.replays_total,
+            })
+            .collect()
+    }
+
+    /// Adopt a handed-off client window unless the client has already been
+    /// seen here; returns whether it was adopted.
+    pub fn import(&self, snapshot: ClientSnapshot) -> bool {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if clients.contains_key(&snapshot.api_key) || clients.len() >= MAX_CLIENTS {
+            return false;
+        }
+        let mut recent: VecDeque<Recent> = snapshot.recent.into();
+        while recent.len() > WINDOW {
+            recent.pop_front();
+        }
+        clients.insert(
+            snapshot.api_key,
+            ClientState {
+                recent,
+                total: snapshot.total,
+                replays_total: snapshot.replays_total,
+                last_seen: Instant::now(),
+            },
+        );
+        true
+    }
+
+    /// Clients with the highest current replay ratio, worst first.
+    pub fn worst_offenders(&self, limit: usize) -> Vec<Offender> {
+        let clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let mut rows: Vec<Offender> = clients
+            .iter()
+            .filter(|(_, state)| state.replays_total > 0)
+            .map(|(key, state)| Offender {
+                api_key: key.clone(),
+                client_replay_ratio: state.replay_ratio(),
+                messages_total: state.total,
+                replays_total: state.replays_total,
+            })
+            .collect();
+        rows.sort_by(|a, b| {
+            b.client_replay_ratio
+                .total_cmp(&a.client_replay_ratio)
+                .then(b.replays_total.cmp(&a.replays_total))
+        });
+        rows.truncate(limit);
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_across_sessions_only() {
+        let tracker = ReplayTracker::default();
+        let msg = "click this link to claim your free prize now";
+        assert_eq!(tracker.observe("k1", Some("s1"), msg), 0.0);
+        // Same session repeating itself is the repetition metric's job.
+        assert_eq!(tracker.observe("k1", Some("s1"), msg), 0.0);
+        let ratio = tracker.observe("k1", Some("s2"), msg);
+        assert!((ratio - 1.0 / 3.0).abs() < 1e-9);
+
+        tracker.observe("k2", Some("s9"), "an ordinary question about rust");
+        let offenders = tracker.worst_offenders(10);
+        assert_eq!(offenders.len(), 1);
+        assert_eq!(offenders[0].api_key, "k1");
+        assert_eq!(offenders[0].replays_total, 1);
+    }
+}
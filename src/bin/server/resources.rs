@@ -0,0 +1,236 @@
+//! File-backed scoring resources, validated and swapped as one unit.
+//!
+//! Profiles, registry topics, corpora, calibration tables and policy rules
+//! come from the files named by WORD_MATH_PROFILES, WORD_MATH_TOPICS,
+//! WORD_MATH_CORPORA, WORD_MATH_CALIBRATION and WORD_MATH_RULES.
+//! `ResourceSet::load` reads and validates all of them; `Resources::swap`
+//! then replaces the serving set in one step. A reload that fails anywhere,
+//! e.g. on a half-written file, leaves every resource at its old version,
+//! and requests always see one consistent set. A resource's version is a
+//! hash of its file contents (for corpora, including the documents).
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use word_math_guard::corpus::CorpusRegistry;
+use word_math_guard::hash::FxHasher;
+use word_math_guard::{
+    generate_hex_id, CalibrationTable, EmojiMode, ProfileSet, RuleSet, TopicRegistry, WordMathError,
+};
+
+/// Version reported for resources whose variable is unset.
+const UNSET: &str = "unset";
+
+/// One loaded resource, as reported by `/version`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceVersion {
+    pub name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Hex hash of the file contents, or "unset".
+    pub version: String,
+    pub entries: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ResourceSet {
+    pub profiles: ProfileSet,
+    /// Registry topics, compiled once per load.
+    pub topics: TopicRegistry,
+    /// Reference-document corpora, for drift against a knowledge base.
+    pub corpora: CorpusRegistry,
+    /// Per-language affine score corrections.
+    pub calibration: CalibrationTable,
+    /// Policy rules, applied after the thresholds.
+    pub rules: RuleSet,
+    pub versions: Vec<ResourceVersion>,
+    /// Hex ID stamped when the set was loaded.
+    pub loaded_at: String,
+}
+
+fn fingerprint(hasher: &mut FxHasher, bytes: &[u8]) {
+    hasher.write(bytes);
+    hasher.write_usize(bytes.len());
+}
+
+fn read(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Parse the file named by `var` with `parse`; the default when unset.
+fn load_one<T: Default>(
+    var: &'static str,
+    name: &'static str,
+    parse: impl FnOnce(&str) -> Result<T, WordMathError>,
+    entries: impl FnOnce(&T) -> usize,
+    versions: &mut Vec<ResourceVersion>,
+) -> Result<T, String> {
+    let Ok(path) = std::env::var(var) else {
+        versions.push(ResourceVersion {
+            name,
+            path: None,
+            version: UNSET.to_string(),
+            entries: 0,
+        });
+        return Ok(T::default());
+    };
+    let json = read(&path)?;
+    let value = parse(&json).map_err(|e| format!("{} ({}): {}", var, path, e))?;
+    let mut hasher = FxHasher::default();
+    fingerprint(&mut hasher, json.as_bytes());
+    versions.push(ResourceVersion {
+        name,
+        entries: entries(&value),
+        path: Some(path),
+        version: format!("{:016x}", hasher.finish()),
+    });
+    Ok(value)
+}
+
+/// Hash of a corpus registry file and every document it lists.
+fn corpora_version(path: &str) -> Result<String, String> {
+    let json = read(path)?;
+    let raw: HashMap<String, Vec<String>> =
+        serde_json::from_str(&json).map_err(|e| format!("{}: {}", path, e))?;
+    let base = Path::new(path).parent().unwrap_or(Path::new(""));
+    let mut files: Vec<&String> = raw.values().flatten().collect();
+    files.sort();
+    let mut hasher = FxHasher::default();
+    fingerprint(&mut hasher, json.as_bytes());
+    for file in files {
+        let full = base.join(file);
+        let text = std::fs::read(&full).map_err(|e| format!("{}: {}", full.display(), e))?;
+        fingerprint(&mut hasher, &text);
+    }
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+impl ResourceSet {
+    /// Read and validate every configured resource file.
+    pub fn load(emoji_mode: EmojiMode) -> Result<Self, String> {
+        let mut versions = Vec::new();
+        let profiles = load_one(
+            "WORD_MATH_PROFILES",
+            "profiles",
+            ProfileSet::from_json,
+            ProfileSet::len,
+            &mut versions,
+        )?;
+        let topics = load_one(
+            "WORD_MATH_TOPICS",
+            "topics",
+            |json| TopicRegistry::from_json(json, emoji_mode),
+            TopicRegistry::len,
+            &mut versions,
+        )?;
+        let calibration = load_one(
+            "WORD_MATH_CALIBRATION",
+            "calibration",
+            CalibrationTable::from_json,
+            CalibrationTable::len,
+            &mut versions,
+        )?;
+        let rules = load_one(
+            "WORD_MATH_RULES",
+            "rules",
+            RuleSet::from_json,
+            RuleSet::len,
+            &mut versions,
+        )?;
+        let corpora = match std::env::var("WORD_MATH_CORPORA") {
+            Ok(path) => {
+                let corpora = CorpusRegistry::load(&path, emoji_mode)
+                    .map_err(|e| format!("WORD_MATH_CORPORA ({}): {}", path, e))?;
+                versions.push(ResourceVersion {
+                    name: "corpora",
+                    version: corpora_version(&path)?,
+                    entries: corpora.len(),
+                    path: Some(path),
+                });
+                corpora
+            }
+            Err(_) => {
+                versions.push(ResourceVersion {
+                    name: "corpora",
+                    path: None,
+                    version: UNSET.to_string(),
+                    entries: 0,
+                });
+                CorpusRegistry::default()
+            }
+        };
+        Ok(Self {
+            profiles,
+            topics,
+            corpora,
+            calibration,
+            rules,
+            versions,
+            loaded_at: generate_hex_id(),
+        })
+    }
+}
+
+/// The serving resource set, replaced atomically on reload.
+pub struct Resources {
+    current: RwLock<Arc<ResourceSet>>,
+}
+
+impl Resources {
+    pub fn new(set: ResourceSet) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(set)),
+        }
+    }
+
+    /// The set serving requests now; hold it for a whole request.
+    pub fn current(&self) -> Arc<ResourceSet> {
+        Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Make `set` the serving set.
+    pub fn swap(&self, set: ResourceSet) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(set);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corpora_version_covers_documents() {
+        let dir = std::env::temp_dir().join(format!("wordmath-resources-{}", generate_hex_id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let registry = dir.join("corpora.json");
+        std::fs::write(&registry, r#"{ "help": ["a.md"] }"#).unwrap();
+        std::fs::write(dir.join("a.md"), "reset your password").unwrap();
+        let path = registry.to_str().unwrap();
+
+        let first = corpora_version(path).unwrap();
+        assert_eq!(first, corpora_version(path).unwrap());
+        std::fs::write(dir.join("a.md"), "reset your password twice").unwrap();
+        assert_ne!(first, corpora_version(path).unwrap());
+        std::fs::remove_file(dir.join("a.md")).unwrap();
+        assert!(corpora_version(path).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_swap_replaces_the_whole_set() {
+        let resources = Resources::new(ResourceSet::default());
+        let before = resources.current();
+        let next = ResourceSet {
+            rules: RuleSet::from_json(
+                r#"[{ "id": "loop", "when": "repetition > 0.5", "then": "block" }]"#,
+            )
+            .unwrap(),
+            ..ResourceSet::default()
+        };
+        resources.swap(next);
+        assert_eq!(before.rules.len(), 0);
+        assert_eq!(resources.current().rules.len(), 1);
+    }
+}
@@ -0,0 +1,297 @@
+//! Hourly and daily score rollups, maintained as traces are written.
+//!
+//! Each bucket holds the count, score sum and block count for one
+//! (period, profile, topic) triple, so dashboards can chart means and block
+//! rates without scanning raw traces. The bucket time comes from the trace
+//! hex ID (nanoseconds since the epoch). With an embedded `KvStore` each
+//! touched bucket is written under `rollup/<period>/<start>/<profile>/<topic>`.
+
+use crate::kv::KvStore;
+use crate::traces::TraceRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+use word_math_guard::Verdict;
+
+const KV_PREFIX: &str = "rollup/";
+/// Profile or topic label when the trace has none.
+const NONE_LABEL: &str = "-";
+const HOURLY_RETENTION_SECS: u64 = 14 * 86_400;
+const DAILY_RETENTION_SECS: u64 = 400 * 86_400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Hour,
+    Day,
+}
+
+impl Period {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "hour" | "hourly" => Some(Period::Hour),
+            "day" | "daily" => Some(Period::Day),
+            _ => None,
+        }
+    }
+
+    fn secs(self) -> u64 {
+        match self {
+            Period::Hour => 3_600,
+            Period::Day => 86_400,
+        }
+    }
+
+    fn retention_secs(self) -> u64 {
+        match self {
+            Period::Hour => HOURLY_RETENTION_SECS,
+            Period::Day => DAILY_RETENTION_SECS,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Period::Hour => "hour",
+            Period::Day => "day",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct BucketKey {
+    period: Period,
+    start: u64,
+    profile: String,
+    topic: String,
+}
+
+impl BucketKey {
+    fn kv_key(&self) -> String {
+        format!(
+            "{}{}/{:010}/{}/{}",
+            KV_PREFIX,
+            self.period.label(),
+            self.start,
+            self.profile,
+            self.topic
+        )
+    }
+
+    fn from_kv_key(key: &str) -> Option<Self> {
+        let mut parts = key.strip_prefix(KV_PREFIX)?.splitn(4, '/');
+        Some(Self {
+            period: Period::parse(parts.next()?)?,
+            start: parts.next()?.parse().ok()?,
+            profile: parts.next()?.to_string(),
+            topic: parts.next()?.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Totals {
+    count: u64,
+    score_sum: f64,
+    blocks: u64,
+}
+
+/// One rollup bucket, as served to dashboards.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RollupRow {
+    pub period: Period,
+    /// Bucket start, in seconds since the epoch.
+    pub start: u64,
+    pub profile: String,
+    pub topic: String,
+    pub count: u64,
+    pub mean_score: f64,
+    pub block_rate: f64,
+}
+
+/// Filters for `Rollups::query`.
+#[derive(Debug, Default, Deserialize)]
+pub struct RollupQuery {
+    pub period: Option<String>,
+    pub profile: Option<String>,
+    pub topic: Option<String>,
+    /// Only buckets starting at or after this time (epoch seconds).
+    pub since: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct Rollups {
+    kv: Option<Arc<KvStore>>,
+    buckets: Mutex<BTreeMap<BucketKey, Totals>>,
+}
+
+fn trace_secs(hex_id: &str) -> Option<u64> {
+    u64::from_str_radix(hex_id, 16)
+        .ok()
+        .map(|nanos| nanos / 1_000_000_000)
+}
+
+impl Rollups {
+    pub fn set_persistence(&mut self, kv: Arc<KvStore>) {
+        self.kv = Some(kv);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<BucketKey, Totals>> {
+        self.buckets.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replace the in-memory buckets with the persisted ones.
+    pub fn reload(&self) -> usize {
+        let Some(kv) = &self.kv else { return 0 };
+        let mut buckets = self.lock();
+        buckets.clear();
+        for (key, value) in kv.scan_prefix(KV_PREFIX) {
+            match (
+                BucketKey::from_kv_key(&key),
+                serde_json::from_str::<Totals>(&value),
+            ) {
+                (Some(bucket), Ok(totals)) => {
+                    buckets.insert(bucket, totals);
+                }
+                _ => warn!("skipping unreadable stored rollup {}", key),
+            }
+        }
+        buckets.len()
+    }
+
+    /// Add one trace to its hourly and daily buckets.
+    pub fn observe(&self, record: &TraceRecord) {
+        let Some(secs) = trace_secs(&record.hex_id) else {
+            return;
+        };
+        let mut buckets = self.lock();
+        let mut opened_bucket = false;
+        for period in [Period::Hour, Period::Day] {
+            let key = BucketKey {
+                period,
+                start: secs - secs % period.secs(),
+                profile: record.profile.as_deref().unwrap_or(NONE_LABEL).to_string(),
+                topic: record.topic_id.as_deref().unwrap_or(NONE_LABEL).to_string(),
+            };
+            opened_bucket |= !buckets.contains_key(&key);
+            let totals = buckets.entry(key.clone()).or_default();
+            totals.count += 1;
+            totals.score_sum += record.score;
+            totals.blocks += u64::from(record.verdict == Verdict::Block);
+            if let Some(kv) = &self.kv {
+                let result = serde_json::to_string(totals)
+                    .map_err(std::io::Error::other)
+                    .and_then(|json| kv.put(&key.kv_key(), json));
+                if let Err(e) = result {
+                    warn!("persisting rollup {} failed: {}", key.kv_key(), e);
+                }
+            }
+        }
+        // Buckets only expire as time moves into new ones.
+        if opened_bucket {
+            self.prune(&mut buckets, secs);
+        }
+    }
+
+    fn prune(&self, buckets: &mut BTreeMap<BucketKey, Totals>, now: u64) {
+        let expired: Vec<BucketKey> = buckets
+            .keys()
+            .filter(|key| key.start + key.period.retention_secs() < now)
+            .cloned()
+            .collect();
+        for key in expired {
+            buckets.remove(&key);
+            if let Some(kv) = &self.kv {
+                let _ = kv.delete(&key.kv_key());
+            }
+        }
+    }
+
+    /// Buckets matching `query`, oldest first.
+    pub fn query(&self, query: &RollupQuery) -> Result<Vec<RollupRow>, String> {
+        let period = match query.period.as_deref() {
+            Some(name) => {
+                Some(Period::parse(name).ok_or_else(|| format!("unknown period: {}", name))?)
+            }
+            None => None,
+        };
+        Ok(self
+            .lock()
+            .iter()
+            .filter(|(key, _)| period.is_none_or(|p| key.period == p))
+            .filter(|(key, _)| query.profile.as_deref().is_none_or(|p| key.profile == p))
+            .filter(|(key, _)| query.topic.as_deref().is_none_or(|t| key.topic == t))
+            .filter(|(key, _)| query.since.is_none_or(|since| key.start >= since))
+            .map(|(key, totals)| RollupRow {
+                period: key.period,
+                start: key.start,
+                profile: key.profile.clone(),
+                topic: key.topic.clone(),
+                count: totals.count,
+                mean_score: totals.score_sum / totals.count.max(1) as f64,
+                block_rate: totals.blocks as f64 / totals.count.max(1) as f64,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labels::Labels;
+
+    fn record(secs: u64, score: f64, verdict: Verdict, topic: Option<&str>) -> TraceRecord {
+        TraceRecord {
+            hex_id: format!("{:016x}", secs * 1_000_000_000),
+            y_repetition: 0.0,
+            z_drift: 0.0,
+            raw_score: score,
+            score,
+            verdict,
+            profile: None,
+            session_id: None,
+            topic_id: topic.map(str::to_string),
+            rule_hits: Vec::new(),
+            experiment: None,
+            variant: None,
+            labels: Labels::default(),
+        }
+    }
+
+    #[test]
+    fn test_hourly_and_daily_buckets() {
+        let rollups = Rollups::default();
+        let day = 1_700_006_400; // a midnight
+        rollups.observe(&record(day + 10, 0.9, Verdict::Allow, Some("billing")));
+        rollups.observe(&record(day + 20, 0.1, Verdict::Block, Some("billing")));
+        rollups.observe(&record(day + 3_700, 0.5, Verdict::Warn, Some("billing")));
+        rollups.observe(&record(day + 30, 0.8, Verdict::Allow, None));
+
+        let query = |period: &str, topic: Option<&str>| {
+            rollups
+                .query(&RollupQuery {
+                    period: Some(period.to_string()),
+                    topic: topic.map(str::to_string),
+                    ..Default::default()
+                })
+                .unwrap()
+        };
+        let hourly = query("hour", Some("billing"));
+        assert_eq!(hourly.len(), 2);
+        assert_eq!(hourly[0].start, day);
+        assert_eq!(hourly[0].count, 2);
+        assert!((hourly[0].mean_score - 0.5).abs() < 1e-9);
+        assert!((hourly[0].block_rate - 0.5).abs() < 1e-9);
+
+        let daily = query("day", Some("billing"));
+        assert_eq!(daily.len(), 1);
+        assert_eq!(daily[0].count, 3);
+        assert_eq!(query("day", None).len(), 2);
+        assert!(rollups
+            .query(&RollupQuery {
+                period: Some("week".to_string()),
+                ..Default::default()
+            })
+            .is_err());
+    }
+}
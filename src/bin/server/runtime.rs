@@ -0,0 +1,382 @@
+//! Tokio runtime and concurrency settings.
+//!
+//! Async worker threads only do I/O; scoring is CPU-bound and runs on the
+//! blocking pool via `cpu`, so a burst of large messages cannot stall
+//! accept loops and tail latency stays predictable. Both pools, and the
+//! number of open connections, are sized from the environment. A
+//! connection holds its slot until it closes, idle keep-alive ones
+//! included; further connections wait in the listen backlog.
+//!
+//! Connections are served by hyper directly rather than `axum::serve`, so
+//! the protocol can be tuned: HTTP/1.1 and HTTP/2 (prior knowledge, as
+//! behind a load balancer) are both accepted, high-QPS callers reuse a few
+//! long-lived connections instead of opening one per request, and a
+//! connection with no request in flight for the keep-alive timeout is shut
+//! down gracefully. An HTTP/2 connection holds one slot however many
+//! streams it multiplexes, so its streams are capped separately.
+
+use axum::extract::Request;
+use axum::{http::StatusCode, Router};
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use tower::ServiceExt;
+use tracing::{debug, warn};
+
+const DEFAULT_BLOCKING_THREADS: usize = 64;
+const DEFAULT_MAX_CONNECTIONS: usize = 1024;
+const DEFAULT_KEEP_ALIVE_SECS: u64 = 30;
+const DEFAULT_HEADER_READ_SECS: u64 = 30;
+const DEFAULT_H2_MAX_STREAMS: u32 = 100;
+/// Largest HTTP/2 header block accepted, after decompression.
+const H2_MAX_HEADER_LIST_SIZE: u32 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RuntimeConfig {
+    /// Async worker threads; `None` uses one per CPU.
+    pub worker_threads: Option<usize>,
+    /// Upper bound on the blocking pool that runs scoring.
+    pub blocking_threads: usize,
+    /// Connections open at once; excess ones are not accepted until one
+    /// closes.
+    pub max_connections: usize,
+    /// Reuse HTTP/1.1 connections across requests.
+    pub keep_alive: bool,
+    /// How long a connection may go without a request in flight before it
+    /// is shut down, for both protocols.
+    pub keep_alive_timeout: Duration,
+    /// How long an HTTP/1.1 client may take to send a request head. hyper
+    /// starts the clock as soon as the connection waits for a request, so
+    /// this also bounds idle HTTP/1.1 connections.
+    pub header_read_timeout: Duration,
+    /// Streams one HTTP/2 connection may have open at once.
+    pub h2_max_concurrent_streams: u32,
+}
+
+fn env_usize(name: &str) -> Option<usize> {
+    std::env::var(name)
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|&n| n > 0)
+}
+
+impl RuntimeConfig {
+    /// Settings from WORD_MATH_WORKER_THREADS, WORD_MATH_BLOCKING_THREADS,
+    /// WORD_MATH_MAX_CONNECTIONS, WORD_MATH_KEEP_ALIVE (`false` or `0`
+    /// disables it), WORD_MATH_KEEP_ALIVE_TIMEOUT_SECS,
+    /// WORD_MATH_HEADER_READ_TIMEOUT_SECS and WORD_MATH_H2_MAX_STREAMS.
+    pub fn from_env() -> Self {
+        Self {
+            worker_threads: env_usize("WORD_MATH_WORKER_THREADS"),
+            blocking_threads: env_usize("WORD_MATH_BLOCKING_THREADS")
+                .unwrap_or(DEFAULT_BLOCKING_THREADS),
+            max_connections: env_usize("WORD_MATH_MAX_CONNECTIONS")
+                .unwrap_or(DEFAULT_MAX_CONNECTIONS),
+            keep_alive: !matches!(
+                std::env::var("WORD_MATH_KEEP_ALIVE").as_deref(),
+                Ok("0" | "false")
+            ),
+            keep_alive_timeout: Duration::from_secs(
+                env_usize("WORD_MATH_KEEP_ALIVE_TIMEOUT_SECS")
+                    .map_or(DEFAULT_KEEP_ALIVE_SECS, |secs| secs as u64),
+            ),
+            header_read_timeout: Duration::from_secs(
+                env_usize("WORD_MATH_HEADER_READ_TIMEOUT_SECS")
+                    .map_or(DEFAULT_HEADER_READ_SECS, |secs| secs as u64),
+            ),
+            h2_max_concurrent_streams: env_usize("WORD_MATH_H2_MAX_STREAMS")
+                .map_or(DEFAULT_H2_MAX_STREAMS, |n| {
+                    u32::try_from(n).unwrap_or(u32::MAX)
+                }),
+        }
+    }
+
+    pub fn build(&self) -> std::io::Result<tokio::runtime::Runtime> {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder
+            .enable_all()
+            .thread_name("wordmath-io")
+            .max_blocking_threads(self.blocking_threads);
+        if let Some(workers) = self.worker_threads {
+            builder.worker_threads(workers);
+        }
+        builder.build()
+    }
+}
+
+/// Requests in flight on one connection, and since when it has had none.
+struct Activity {
+    in_flight: AtomicUsize,
+    idle_since: Mutex<Instant>,
+}
+
+/// Marks a request in flight until dropped.
+struct Busy(Arc<Activity>);
+
+impl Activity {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            in_flight: AtomicUsize::new(0),
+            idle_since: Mutex::new(Instant::now()),
+        })
+    }
+
+    fn begin(self: &Arc<Self>) -> Busy {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        Busy(Arc::clone(self))
+    }
+
+    /// Resolves once no request has been in flight for `timeout`.
+    async fn idle_for(&self, timeout: Duration) {
+        loop {
+            let wait = if self.in_flight.load(Ordering::SeqCst) > 0 {
+                timeout
+            } else {
+                let since = *self.idle_since.lock().unwrap_or_else(|e| e.into_inner());
+                timeout.saturating_sub(since.elapsed())
+            };
+            if wait.is_zero() {
+                return;
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        let mut idle_since = self.0.idle_since.lock().unwrap_or_else(|e| e.into_inner());
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            *idle_since = Instant::now();
+        }
+    }
+}
+
+/// Accept connections forever, serving each with `app` over HTTP/1.1 or
+/// HTTP/2, at most `max_connections` at a time.
+pub async fn serve(listener: TcpListener, app: Router, cfg: RuntimeConfig) {
+    let mut http = auto::Builder::new(TokioExecutor::new());
+    http.http1()
+        .keep_alive(cfg.keep_alive)
+        .timer(TokioTimer::new())
+        .header_read_timeout(cfg.header_read_timeout);
+    http.http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(cfg.h2_max_concurrent_streams)
+        .max_header_list_size(H2_MAX_HEADER_LIST_SIZE);
+    let permits = Arc::new(Semaphore::new(cfg.max_connections));
+    loop {
+        let permit = Arc::clone(&permits)
+            .acquire_owned()
+            .await
+            .expect("connection semaphore is never closed");
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Usually fd exhaustion; back off instead of spinning.
+                warn!("accept failed: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let _ = stream.set_nodelay(true);
+        let activity = Activity::new();
+        let service = {
+            let (app, activity) = (app.clone(), Arc::clone(&activity));
+            service_fn(move |request: Request<Incoming>| {
+                let busy = activity.begin();
+                let app = app.clone();
+                async move {
+                    let response = app.oneshot(request).await;
+                    drop(busy);
+                    response
+                }
+            })
+        };
+        let connection = http
+            .serve_connection(TokioIo::new(stream), service)
+            .into_owned();
+        tokio::spawn(async move {
+            let mut connection = std::pin::pin!(connection);
+            let result = tokio::select! {
+                result = connection.as_mut() => result,
+                () = activity.idle_for(cfg.keep_alive_timeout) => {
+                    // Lets a response still streaming finish first.
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+            if let Err(e) = result {
+                debug!("connection from {} ended: {}", peer, e);
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// Run CPU-bound scoring on the blocking pool.
+pub async fn cpu<T, F>(f: F) -> Result<T, (StatusCode, String)>
+where
+    F: FnOnce() -> Result<T, (StatusCode, String)> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("scoring task failed: {}", e),
+        )
+    })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nhost: test\r\n\r\n";
+
+    async fn respond(stream: &mut TcpStream) -> bool {
+        let mut buf = [0u8; 256];
+        let read = tokio::time::timeout(Duration::from_millis(300), stream.read(&mut buf));
+        matches!(read.await, Ok(Ok(n)) if buf[..n].starts_with(b"HTTP/1.1 200"))
+    }
+
+    fn config() -> RuntimeConfig {
+        RuntimeConfig {
+            worker_threads: None,
+            blocking_threads: 1,
+            max_connections: 16,
+            keep_alive: true,
+            keep_alive_timeout: Duration::from_secs(30),
+            header_read_timeout: Duration::from_secs(30),
+            h2_max_concurrent_streams: 7,
+        }
+    }
+
+    async fn start(cfg: RuntimeConfig) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/", get(|| async { "ok" }));
+        tokio::spawn(serve(listener, app, cfg));
+        addr
+    }
+
+    /// Whether the server closes `stream` within `within`.
+    async fn closes(stream: &mut TcpStream, within: Duration) -> bool {
+        let mut buf = [0u8; 256];
+        matches!(
+            tokio::time::timeout(within, stream.read(&mut buf)).await,
+            Ok(Ok(0) | Err(_))
+        )
+    }
+
+    /// One HTTP/2 frame: (type, flags, stream id, payload).
+    async fn read_frame(stream: &mut TcpStream) -> (u8, u8, u32, Vec<u8>) {
+        let mut head = [0u8; 9];
+        stream.read_exact(&mut head).await.unwrap();
+        let len = u32::from_be_bytes([0, head[0], head[1], head[2]]) as usize;
+        let id = u32::from_be_bytes([head[5], head[6], head[7], head[8]]) & 0x7fff_ffff;
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).await.unwrap();
+        (head[3], head[4], id, payload)
+    }
+
+    fn frame(kind: u8, flags: u8, id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        out.extend([kind, flags]);
+        out.extend(id.to_be_bytes());
+        out.extend(payload);
+        out
+    }
+
+    #[tokio::test]
+    async fn test_idle_keep_alive_connections_count_against_the_limit() {
+        let addr = start(RuntimeConfig {
+            max_connections: 1,
+            ..config()
+        })
+        .await;
+
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        first.write_all(REQUEST).await.unwrap();
+        assert!(respond(&mut first).await);
+
+        // The first connection is idle but open, so the second waits.
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        second.write_all(REQUEST).await.unwrap();
+        assert!(!respond(&mut second).await);
+
+        drop(first);
+        assert!(respond(&mut second).await);
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_timeout_closes_idle_connections() {
+        let addr = start(RuntimeConfig {
+            keep_alive_timeout: Duration::from_millis(200),
+            ..config()
+        })
+        .await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(REQUEST).await.unwrap();
+        assert!(respond(&mut stream).await);
+        assert!(closes(&mut stream, Duration::from_secs(2)).await);
+
+        // With keep-alive off, the connection ends with the response.
+        let addr = start(RuntimeConfig {
+            keep_alive: false,
+            ..config()
+        })
+        .await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(REQUEST).await.unwrap();
+        let mut response = Vec::new();
+        tokio::time::timeout(Duration::from_secs(2), stream.read_to_end(&mut response))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 200"));
+    }
+
+    #[tokio::test]
+    async fn test_http2_prior_knowledge_with_stream_limit() {
+        let addr = start(config()).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n")
+            .await
+            .unwrap();
+        stream.write_all(&frame(0x4, 0, 0, &[])).await.unwrap();
+
+        // The server's SETTINGS advertise the stream cap.
+        let (kind, _, _, settings) = read_frame(&mut stream).await;
+        assert_eq!(kind, 0x4);
+        let max_streams = settings
+            .chunks(6)
+            .find(|entry| entry[..2] == [0, 0x3])
+            .map(|entry| u32::from_be_bytes([entry[2], entry[3], entry[4], entry[5]]));
+        assert_eq!(max_streams, Some(7));
+
+        // GET / on stream 1: indexed :method GET, :path /, :scheme http and
+        // a literal :authority.
+        let block = [0x82, 0x84, 0x86, 0x41, 0x04, b't', b'e', b's', b't'];
+        stream.write_all(&frame(0x4, 0x1, 0, &[])).await.unwrap();
+        stream.write_all(&frame(0x1, 0x5, 1, &block)).await.unwrap();
+        let status = loop {
+            let (kind, _, id, payload) = read_frame(&mut stream).await;
+            if kind == 0x1 && id == 1 {
+                break payload[0];
+            }
+        };
+        // Indexed `:status 200`.
+        assert_eq!(status, 0x88);
+    }
+}
@@ -0,0 +1,870 @@
+//! JSON Schema (draft 2020-12) of the server's wire format.
+//!
+//! `GET /schema` serves one document whose `$defs` describe the request
+//! and response bodies of the scoring endpoints, `/stats`, `/version`,
+//! `/feedback` and the audit records (`/traces`, `/rollups`,
+//! `/audit/merkle`), so clients in other languages can generate models
+//! from it. Admin endpoints are not part of the contract.
+//!
+//! The same document is checked in as `schema/wordmath.schema.json`. The
+//! tests fail when it differs from `document()`, when a serialized
+//! response has a field its definition lacks, or when a minimal request
+//! built from a definition no longer deserializes, so wire format changes
+//! show up in review. After an intentional change, regenerate the file
+//! with `WORD_MATH_BLESS=1 cargo test --bin server schema`.
+//!
+//! Response objects are closed (`additionalProperties: false`); request
+//! objects are open because unknown fields are ignored.
+
+use serde_json::{json, Map, Value};
+
+pub const ID: &str = "https://github.com/Doctor0Evil/WordMath/schema/wordmath.schema.json";
+
+fn string() -> Value {
+    json!({"type": "string"})
+}
+
+fn number() -> Value {
+    json!({"type": "number"})
+}
+
+fn count() -> Value {
+    json!({"type": "integer", "minimum": 0})
+}
+
+fn boolean() -> Value {
+    json!({"type": "boolean"})
+}
+
+fn array(items: Value) -> Value {
+    json!({"type": "array", "items": items})
+}
+
+fn nullable(kind: &str) -> Value {
+    json!({"type": [kind, "null"]})
+}
+
+fn one_of(values: &[&str]) -> Value {
+    json!({"type": "string", "enum": values})
+}
+
+fn reference(name: &str) -> Value {
+    json!({"$ref": format!("#/$defs/{}", name)})
+}
+
+fn properties(
+    description: &str,
+    required: &[(&str, Value)],
+    optional: &[(&str, Value)],
+) -> Map<String, Value> {
+    let mut schema = Map::new();
+    schema.insert("description".into(), description.into());
+    schema.insert("type".into(), "object".into());
+    let fields = required
+        .iter()
+        .chain(optional)
+        .map(|(name, field)| (name.to_string(), field.clone()))
+        .collect();
+    schema.insert("properties".into(), Value::Object(fields));
+    let names = required.iter().map(|(name, _)| json!(name)).collect();
+    schema.insert("required".into(), Value::Array(names));
+    schema
+}
+
+/// A request body or query: unknown fields are allowed.
+fn request(description: &str, required: &[(&str, Value)], optional: &[(&str, Value)]) -> Value {
+    Value::Object(properties(description, required, optional))
+}
+
+/// A response body or record: no fields beyond the listed ones.
+fn response(description: &str, required: &[(&str, Value)], optional: &[(&str, Value)]) -> Value {
+    let mut schema = properties(description, required, optional);
+    schema.insert("additionalProperties".into(), false.into());
+    Value::Object(schema)
+}
+
+fn definitions() -> Vec<(&'static str, Value)> {
+    let scoring = || {
+        [
+            ("topic", string()),
+            ("topic_id", string()),
+            ("profile", string()),
+        ]
+    };
+    vec![
+        ("Verdict", one_of(&["allow", "warn", "block"])),
+        (
+            "Severity",
+            one_of(&["clean", "notice", "warn", "severe", "critical"]),
+        ),
+        ("Metric", one_of(&["score", "repetition", "drift"])),
+        (
+            "AnalyzeParams",
+            request(
+                "Query string of GET /analyze.",
+                &[("message", string())],
+                &[
+                    ("topic", string()),
+                    ("topic_id", string()),
+                    ("topic_ids", string()),
+                    ("corpus_id", string()),
+                    ("domain_id", string()),
+                    ("profile", string()),
+                    ("session_id", string()),
+                    ("sanitize", boolean()),
+                ],
+            ),
+        ),
+        (
+            "AnalyzeResponse",
+            response(
+                "Response of GET /analyze.",
+                &[
+                    ("y_repetition", number()),
+                    ("z_drift", number()),
+                    ("score", number()),
+                    ("raw_score", number()),
+                    ("evasion_risk", number()),
+                    ("verdict", reference("Verdict")),
+                    ("severity", reference("Severity")),
+                    ("hex_id", string()),
+                ],
+                &[
+                    ("explanation", reference("Explanation")),
+                    ("session", reference("SessionInfo")),
+                    ("degraded", boolean()),
+                    ("client_replay_ratio", number()),
+                    ("sanitized", reference("SanitizedInfo")),
+                    ("language", string()),
+                    ("topic_match", reference("TopicMatchInfo")),
+                ],
+            ),
+        ),
+        (
+            "Explanation",
+            response(
+                "Why a message got Warn or Block.",
+                &[
+                    ("reason", string()),
+                    ("verdict", reference("Verdict")),
+                    ("severity", reference("Severity")),
+                    ("fired", array(reference("ThresholdHit"))),
+                    ("top_contributor", reference("Metric")),
+                ],
+                &[
+                    ("rule_hits", array(reference("RuleHit"))),
+                    ("drift_clusters", array(reference("TermCluster"))),
+                ],
+            ),
+        ),
+        (
+            "ThresholdHit",
+            response(
+                "A threshold the analysis crossed.",
+                &[
+                    (
+                        "threshold",
+                        one_of(&["block_max", "warn_max", "max_repetition", "max_drift"]),
+                    ),
+                    ("metric", reference("Metric")),
+                    ("value", number()),
+                    ("limit", number()),
+                    ("margin", number()),
+                    ("verdict", reference("Verdict")),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "RuleHit",
+            response(
+                "A policy rule that matched.",
+                &[("id", string()), ("verdict", reference("Verdict"))],
+                &[],
+            ),
+        ),
+        (
+            "TermCluster",
+            response(
+                "Off-topic terms that occur together.",
+                &[("terms", array(string())), ("occurrences", count())],
+                &[],
+            ),
+        ),
+        (
+            "SessionInfo",
+            response(
+                "The session a message was scored in.",
+                &[
+                    ("id", string()),
+                    ("turn", count()),
+                    ("session_score", number()),
+                    ("session_verdict", reference("Verdict")),
+                ],
+                &[("history", array(reference("TurnSummary")))],
+            ),
+        ),
+        (
+            "TurnSummary",
+            response(
+                "Metrics of one earlier turn.",
+                &[
+                    ("y_repetition", number()),
+                    ("z_drift", number()),
+                    ("score", number()),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "SanitizedInfo",
+            response(
+                "Repaired copy of a flagged message.",
+                &[
+                    ("text", string()),
+                    ("collapsed_sentences", count()),
+                    ("score_before", number()),
+                    ("score_after", number()),
+                    ("verdict_after", reference("Verdict")),
+                ],
+                &[("removed_tail", string())],
+            ),
+        ),
+        (
+            "TopicMatchInfo",
+            response(
+                "The closest of several candidate topics.",
+                &[("topic_id", string()), ("drift", number())],
+                &[("runner_up", string()), ("margin", number())],
+            ),
+        ),
+        (
+            "BatchRequest",
+            request(
+                "Body of POST /analyze/batch.",
+                &[("items", array(reference("BatchRequestItem")))],
+                &[("profile", string()), ("worst_k", count())],
+            ),
+        ),
+        (
+            "BatchRequestItem",
+            request(
+                "One message of a batch.",
+                &[("message", string())],
+                &scoring(),
+            ),
+        ),
+        (
+            "BatchResponse",
+            response(
+                "Response of POST /analyze/batch.",
+                &[
+                    ("items", array(reference("BatchResponseItem"))),
+                    ("summary", reference("BatchSummary")),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "BatchResponseItem",
+            response(
+                "Scores of one batch item.",
+                &[
+                    ("y_repetition", number()),
+                    ("z_drift", number()),
+                    ("score", number()),
+                    ("verdict", reference("Verdict")),
+                    ("hex_id", string()),
+                ],
+                &[("profile", string())],
+            ),
+        ),
+        (
+            "BatchSummary",
+            response(
+                "Score distribution of a batch.",
+                &[
+                    ("count", count()),
+                    ("mean_score", number()),
+                    ("p50", number()),
+                    ("p90", number()),
+                    ("p99", number()),
+                    ("verdicts", reference("VerdictCounts")),
+                    ("worst_indices", array(count())),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "VerdictCounts",
+            response(
+                "Messages per verdict.",
+                &[("allow", count()), ("warn", count()), ("block", count())],
+                &[],
+            ),
+        ),
+        (
+            "CompareRequest",
+            request(
+                "Body of POST /compare.",
+                &[("a", string()), ("b", string())],
+                &scoring(),
+            ),
+        ),
+        (
+            "CompareResponse",
+            response(
+                "Response of POST /compare.",
+                &[
+                    ("lexical_overlap", number()),
+                    ("similarity", number()),
+                    ("drift_a", number()),
+                    ("drift_b", number()),
+                    ("drift_delta", number()),
+                    ("hex_id", string()),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "TranscriptRequest",
+            request(
+                "Body of POST /analyze/transcript.",
+                &[("words", array(reference("WeightedWord")))],
+                &scoring(),
+            ),
+        ),
+        (
+            "WeightedWord",
+            request(
+                "A recognized word and its confidence in [0, 1].",
+                &[("text", string()), ("confidence", number())],
+                &[],
+            ),
+        ),
+        (
+            "TranscriptResponse",
+            response(
+                "Response of POST /analyze/transcript.",
+                &[
+                    ("y_repetition", number()),
+                    ("z_drift", number()),
+                    ("score", number()),
+                    ("verdict", reference("Verdict")),
+                    ("mean_confidence", number()),
+                    ("hex_id", string()),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "SimilarityRequest",
+            request(
+                "Body of POST /similarity; needs `message` or `messages`.",
+                &[],
+                &[
+                    ("message", string()),
+                    ("messages", array(string())),
+                    ("max_dim", count()),
+                    ("profile", string()),
+                ],
+            ),
+        ),
+        (
+            "SimilarityMatrix",
+            response(
+                "Response of POST /similarity.",
+                &[
+                    ("size", count()),
+                    ("units", count()),
+                    ("bucket_starts", array(count())),
+                    ("values", array(number())),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "TrimRequest",
+            request(
+                "Body of POST /rewrite/suggest.",
+                &[("message", string())],
+                &[("profile", string())],
+            ),
+        ),
+        (
+            "TrimSuggestion",
+            response(
+                "Response of POST /rewrite/suggest.",
+                &[
+                    ("source", string()),
+                    ("removals", array(reference("Removal"))),
+                    ("y_before", number()),
+                    ("y_after", number()),
+                    ("trimmed", string()),
+                    ("reaches_threshold", boolean()),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "Removal",
+            response(
+                "A byte range of `source` to remove.",
+                &[
+                    ("kind", one_of(&["sentence", "bigram"])),
+                    ("start", count()),
+                    ("end", count()),
+                    ("text", string()),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "FeedbackRequest",
+            request(
+                "Body of POST /feedback.",
+                &[("hex_id", string()), ("label", reference("Verdict"))],
+                &[
+                    ("reviewer", nullable("string")),
+                    ("note", nullable("string")),
+                ],
+            ),
+        ),
+        (
+            "Feedback",
+            response(
+                "Response of POST /feedback: the stored label.",
+                &[
+                    ("hex_id", string()),
+                    ("label", reference("Verdict")),
+                    ("served", reference("Verdict")),
+                ],
+                &[("reviewer", string()), ("note", string())],
+            ),
+        ),
+        (
+            "StatsResponse",
+            response(
+                "Response of GET /stats.",
+                &[
+                    ("last_minute", reference("WindowTotals")),
+                    ("last_hour", reference("WindowTotals")),
+                    ("verdicts", reference("VerdictCounts")),
+                    ("active_sessions", count()),
+                    ("degraded", array(string())),
+                    ("hex_id", string()),
+                ],
+                &[("noise_epsilon", number())],
+            ),
+        ),
+        (
+            "WindowTotals",
+            response(
+                "Requests and mean score over a window.",
+                &[("requests", count()), ("mean_score", nullable("number"))],
+                &[],
+            ),
+        ),
+        (
+            "VersionResponse",
+            response(
+                "Response of GET /version.",
+                &[
+                    ("version", string()),
+                    ("schema_version", count()),
+                    ("resources_loaded_at", string()),
+                    ("resources", array(reference("ResourceVersion"))),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "ResourceVersion",
+            response(
+                "A loaded resource file.",
+                &[
+                    ("name", string()),
+                    ("version", string()),
+                    ("entries", count()),
+                ],
+                &[("path", string())],
+            ),
+        ),
+        (
+            "Labels",
+            json!({
+                "description": "Deployment labels, such as environment and region.",
+                "type": "object",
+                "additionalProperties": {"type": "string"}
+            }),
+        ),
+        (
+            "TraceRecord",
+            response(
+                "An audit record of one scored message; GET /traces returns a list.",
+                &[
+                    ("hex_id", string()),
+                    ("y_repetition", number()),
+                    ("z_drift", number()),
+                    ("raw_score", number()),
+                    ("score", number()),
+                    ("verdict", reference("Verdict")),
+                ],
+                &[
+                    ("profile", string()),
+                    ("session_id", string()),
+                    ("topic_id", string()),
+                    ("rule_hits", array(string())),
+                    ("experiment", string()),
+                    ("variant", string()),
+                    ("labels", reference("Labels")),
+                ],
+            ),
+        ),
+        (
+            "RollupRow",
+            response(
+                "One rollup bucket; GET /rollups returns a list.",
+                &[
+                    ("period", one_of(&["hour", "day"])),
+                    ("start", count()),
+                    ("profile", string()),
+                    ("topic", string()),
+                    ("count", count()),
+                    ("mean_score", number()),
+                    ("block_rate", number()),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "MerkleDigest",
+            response(
+                "Response of GET /audit/merkle.",
+                &[
+                    ("digest_id", string()),
+                    ("root", string()),
+                    ("leaves", count()),
+                    ("first_hex_id", nullable("string")),
+                    ("last_hex_id", nullable("string")),
+                ],
+                &[("labels", reference("Labels"))],
+            ),
+        ),
+        (
+            "InclusionProof",
+            response(
+                "Response of GET /audit/merkle/proof/{hex_id}.",
+                &[
+                    ("hex_id", string()),
+                    ("leaf_index", count()),
+                    ("tree_size", count()),
+                    ("leaf", string()),
+                    ("root", string()),
+                    ("path", array(reference("ProofStep"))),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "ConsistencyProof",
+            response(
+                "Response of GET /audit/merkle/consistency.",
+                &[
+                    ("first", count()),
+                    ("second", count()),
+                    ("first_root", string()),
+                    ("second_root", string()),
+                    ("proof", array(string())),
+                ],
+                &[],
+            ),
+        ),
+        (
+            "ProofStep",
+            response(
+                "A sibling hash on the path to the root.",
+                &[("sibling", string()), ("side", one_of(&["left", "right"]))],
+                &[],
+            ),
+        ),
+    ]
+}
+
+/// The whole schema document.
+pub fn document() -> Value {
+    let defs: Map<String, Value> = definitions()
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect();
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": ID,
+        "title": "WordMath wire format",
+        "$defs": defs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditLog, MerkleDigest};
+    use crate::labels::Labels;
+    use crate::traces::TraceRecord;
+    use std::path::Path;
+    use word_math_guard::verdict::{RuleHit, ThresholdHit, ThresholdKind, VerdictExplanation};
+    use word_math_guard::{Severity, Verdict};
+
+    /// Errors of `value` against `schema`, with JSON-pointer-like paths.
+    fn validate(doc: &Value, schema: &Value, value: &Value, at: &str, errors: &mut Vec<String>) {
+        if let Some(target) = schema["$ref"].as_str() {
+            let name = target.trim_start_matches("#/$defs/");
+            return validate(doc, &doc["$defs"][name], value, at, errors);
+        }
+        let kind = match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(n) if n.is_u64() || n.is_i64() => "integer",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        let allowed = match &schema["type"] {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        let fits = |t: &&str| *t == kind || (*t == "number" && kind == "integer");
+        if !allowed.is_empty() && !allowed.iter().any(fits) {
+            errors.push(format!("{}: {} is not {:?}", at, kind, allowed));
+            return;
+        }
+        if let Some(options) = schema["enum"].as_array() {
+            if !options.contains(value) {
+                errors.push(format!("{}: {} not in enum", at, value));
+            }
+        }
+        match value {
+            Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    validate(
+                        doc,
+                        &schema["items"],
+                        item,
+                        &format!("{}/{}", at, i),
+                        errors,
+                    );
+                }
+            }
+            Value::Object(fields) => {
+                for name in schema["required"].as_array().into_iter().flatten() {
+                    if !fields.contains_key(name.as_str().unwrap()) {
+                        errors.push(format!("{}: missing {}", at, name));
+                    }
+                }
+                for (name, field) in fields {
+                    let path = format!("{}/{}", at, name);
+                    match (&schema["properties"][name], &schema["additionalProperties"]) {
+                        (Value::Null, Value::Bool(false)) => {
+                            errors.push(format!("{}: not in the schema", path));
+                        }
+                        (Value::Null, Value::Object(_)) => {
+                            validate(doc, &schema["additionalProperties"], field, &path, errors)
+                        }
+                        (Value::Null, _) => {}
+                        (property, _) => validate(doc, property, field, &path, errors),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The smallest value `schema` accepts: required fields only, first
+    /// enum values.
+    fn minimal(doc: &Value, schema: &Value) -> Value {
+        if let Some(target) = schema["$ref"].as_str() {
+            return minimal(doc, &doc["$defs"][target.trim_start_matches("#/$defs/")]);
+        }
+        if let Some(first) = schema["enum"].get(0) {
+            return first.clone();
+        }
+        let kind = match &schema["type"] {
+            Value::Array(ts) => ts[0].clone(),
+            kind => kind.clone(),
+        };
+        match kind.as_str() {
+            Some("string") => json!("text"),
+            Some("number") => json!(0.5),
+            Some("integer") => json!(1),
+            Some("boolean") => json!(false),
+            Some("array") => json!([minimal(doc, &schema["items"])]),
+            _ => Value::Object(
+                schema["required"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .map(|name| (name.to_string(), minimal(doc, &schema["properties"][name])))
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_checked_in_schema_is_current() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema/wordmath.schema.json");
+        let current = serde_json::to_string_pretty(&document()).unwrap() + "\n";
+        if std::env::var_os("WORD_MATH_BLESS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &current).unwrap();
+        }
+        let checked_in = std::fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            checked_in == current,
+            "{} is stale; regenerate it with WORD_MATH_BLESS=1 cargo test --bin server schema",
+            path.display()
+        );
+    }
+
+    #[test]
+    fn test_responses_match_their_definitions() {
+        let doc = document();
+        let explanation = crate::Explanation {
+            reason: "repetitive".to_string(),
+            detail: VerdictExplanation {
+                verdict: Verdict::Warn,
+                severity: Severity::Notice,
+                fired: vec![ThresholdHit {
+                    threshold: ThresholdKind::MaxRepetition,
+                    metric: word_math_guard::verdict::Metric::Repetition,
+                    value: 0.9,
+                    limit: 0.8,
+                    margin: 0.1,
+                    verdict: Verdict::Warn,
+                }],
+                top_contributor: word_math_guard::verdict::Metric::Repetition,
+                rule_hits: vec![RuleHit {
+                    id: "loops".to_string(),
+                    verdict: Verdict::Warn,
+                }],
+            },
+            drift_clusters: vec![word_math_guard::clusters::TermCluster {
+                terms: vec!["crypto".to_string()],
+                occurrences: 2,
+            }],
+        };
+        let trace = TraceRecord {
+            hex_id: "00ff".to_string(),
+            y_repetition: 0.9,
+            z_drift: 0.1,
+            raw_score: 0.4,
+            score: 0.4,
+            verdict: Verdict::Warn,
+            profile: Some("strict".to_string()),
+            session_id: Some("s1".to_string()),
+            topic_id: Some("billing".to_string()),
+            rule_hits: vec!["loops".to_string()],
+            experiment: Some("exp".to_string()),
+            variant: Some("b".to_string()),
+            labels: Labels::parse("environment=staging").unwrap(),
+        };
+        let log = AuditLog::default();
+        log.append(&trace);
+        let mut digest = MerkleDigest::compute(&log);
+        digest.labels = trace.labels.clone();
+        let analyze = crate::AnalyzeResponse {
+            y_repetition: 0.9,
+            z_drift: 0.1,
+            score: 0.4,
+            raw_score: 0.4,
+            evasion_risk: 0.0,
+            verdict: Verdict::Warn,
+            severity: Severity::Notice,
+            explanation: Some(explanation),
+            session: Some(crate::SessionInfo {
+                id: "s1".to_string(),
+                turn: 3,
+                session_score: 0.5,
+                session_verdict: Verdict::Warn,
+                history: vec![word_math_guard::conversation::TurnSummary {
+                    y_repetition: 0.1,
+                    z_drift: 0.2,
+                    score: 0.8,
+                }],
+            }),
+            degraded: true,
+            client_replay_ratio: Some(0.25),
+            sanitized: Some(crate::SanitizedInfo {
+                repair: word_math_guard::rewrite::Sanitized {
+                    text: "fixed".to_string(),
+                    removed_tail: Some("loop loop".to_string()),
+                    collapsed_sentences: 1,
+                },
+                score_before: 0.4,
+                score_after: 0.9,
+                verdict_after: Verdict::Allow,
+            }),
+            language: Some("en"),
+            topic_match: Some(crate::TopicMatchInfo {
+                topic_id: "billing".to_string(),
+                drift: 0.1,
+                runner_up: Some("refunds".to_string()),
+                margin: Some(0.3),
+            }),
+            hex_id: "00ff".to_string(),
+        };
+        let samples = [
+            ("AnalyzeResponse", serde_json::to_value(&analyze).unwrap()),
+            ("TraceRecord", serde_json::to_value(&trace).unwrap()),
+            ("MerkleDigest", serde_json::to_value(&digest).unwrap()),
+            (
+                "InclusionProof",
+                serde_json::to_value(log.proof("00ff", 1).unwrap()).unwrap(),
+            ),
+            (
+                "ConsistencyProof",
+                serde_json::to_value(log.consistency(0, 1).unwrap()).unwrap(),
+            ),
+            (
+                "StatsResponse",
+                serde_json::to_value(crate::StatsResponse {
+                    last_minute: Default::default(),
+                    last_hour: Default::default(),
+                    verdicts: Default::default(),
+                    active_sessions: 0,
+                    degraded: vec!["sessions"],
+                    noise_epsilon: Some(1.0),
+                    hex_id: "00ff".to_string(),
+                })
+                .unwrap(),
+            ),
+        ];
+        let mut errors = Vec::new();
+        for (name, value) in &samples {
+            validate(&doc, &doc["$defs"][name], value, name, &mut errors);
+        }
+        // A field the definition lacks is caught.
+        let mut extra = serde_json::to_value(&trace).unwrap();
+        extra["surprise"] = json!(1);
+        let mut caught = Vec::new();
+        validate(&doc, &doc["$defs"]["TraceRecord"], &extra, "", &mut caught);
+        assert_eq!(caught, ["/surprise: not in the schema"]);
+        assert!(errors.is_empty(), "{:#?}", errors);
+    }
+
+    #[test]
+    fn test_minimal_requests_deserialize() {
+        let doc = document();
+        let minimal = |name: &str| minimal(&doc, &doc["$defs"][name]);
+        serde_json::from_value::<crate::AnalyzeParams>(minimal("AnalyzeParams")).unwrap();
+        serde_json::from_value::<crate::BatchRequest>(minimal("BatchRequest")).unwrap();
+        serde_json::from_value::<crate::CompareRequest>(minimal("CompareRequest")).unwrap();
+        serde_json::from_value::<crate::TranscriptRequest>(minimal("TranscriptRequest")).unwrap();
+        serde_json::from_value::<crate::SimilarityRequest>(minimal("SimilarityRequest")).unwrap();
+        serde_json::from_value::<crate::TrimRequest>(minimal("TrimRequest")).unwrap();
+        serde_json::from_value::<crate::feedback::FeedbackRequest>(minimal("FeedbackRequest"))
+            .unwrap();
+    }
+}
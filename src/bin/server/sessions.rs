@@ -0,0 +1,553 @@
+//! In-memory conversation sessions keyed by client-supplied session ID.
+//!
+//! Session IDs come from clients, so the store is capped: when full, the
+//! least recently used session is evicted, and sessions idle longer than
+//! the TTL are swept on every write.
+//!
+//! With an embedded `KvStore`, every session is written through as a
+//! `ConversationSnapshot` under `session/<id>` and reloaded on startup;
+//! evicted sessions are deleted there too. A turn writes only its own
+//! session, after the store lock is released, so one session's disk write
+//! does not hold up turns of the others. Writes of the same session are
+//! ordered by a per-session version, so a slow write never overwrites a
+//! newer snapshot. Evictions are deleted from the store the same way, after
+//! the lock is released, and take part in that order: a session recreated
+//! under an evicted ID is never removed by the late delete.
+
+use crate::kv::KvStore;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use word_math_guard::conversation::{TurnResult, DEFAULT_TRACE_HISTORY};
+use word_math_guard::{
+    generate_hex_id, ConversationAnalyzer, ConversationSnapshot, WordMathConfig,
+};
+
+const DEFAULT_MAX_SESSIONS: usize = 10_000;
+const DEFAULT_IDLE_TTL: Duration = Duration::from_secs(3600);
+const KV_PREFIX: &str = "session/";
+
+/// Why a session was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionReason {
+    /// The store was full and this was the least recently used session.
+    Lru,
+    /// The session was idle for longer than the TTL.
+    Ttl,
+}
+
+impl EvictionReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lru => "lru",
+            Self::Ttl => "ttl",
+        }
+    }
+}
+
+struct Entry {
+    conv: ConversationAnalyzer,
+    last_used: Instant,
+    /// Position in `Inner::recency`.
+    seq: u64,
+    /// Version of the newest snapshot taken for persistence.
+    version: u64,
+    /// Version last written to the store; `u64::MAX` once deleted. Held
+    /// while writing, so writes of one session never interleave.
+    written: Arc<Mutex<u64>>,
+}
+
+/// A stored session to delete once the store lock is released.
+struct Eviction {
+    id: String,
+    reason: EvictionReason,
+    /// Version the delete takes in the session's write order.
+    version: u64,
+    written: Arc<Mutex<u64>>,
+}
+
+/// A session snapshot to write once the store lock is released.
+struct Write {
+    id: String,
+    snapshot: ConversationSnapshot,
+    version: u64,
+    written: Arc<Mutex<u64>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    sessions: HashMap<String, Entry>,
+    /// Recency order: lowest sequence number is least recently used.
+    recency: BTreeMap<u64, String>,
+    next_seq: u64,
+    /// Write order of evicted sessions whose delete is still pending, so a
+    /// session recreated meanwhile continues it.
+    evicted: HashMap<String, (u64, Arc<Mutex<u64>>)>,
+}
+
+impl Inner {
+    fn touch(&mut self, id: &str) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if let Some(entry) = self.sessions.get_mut(id) {
+            self.recency.remove(&entry.seq);
+            entry.seq = seq;
+            entry.last_used = Instant::now();
+            self.recency.insert(seq, id.to_string());
+        }
+    }
+
+    /// Insert or replace session `id`. A replaced session's write order
+    /// carries over, so its in-flight writes cannot land last.
+    fn insert(&mut self, id: &str, conv: ConversationAnalyzer) -> &mut Entry {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let (version, written) = match self.sessions.remove(id) {
+            Some(old) => {
+                self.recency.remove(&old.seq);
+                (old.version, old.written)
+            }
+            None => self.evicted.remove(id).unwrap_or_default(),
+        };
+        self.recency.insert(seq, id.to_string());
+        self.sessions.entry(id.to_string()).or_insert(Entry {
+            conv,
+            last_used: Instant::now(),
+            seq,
+            version,
+            written,
+        })
+    }
+
+    /// Pop the least recently used session if `pred` accepts it.
+    fn pop_oldest_if(&mut self, pred: impl Fn(&Entry) -> bool) -> Option<(String, Entry)> {
+        let (&seq, id) = self.recency.iter().next()?;
+        if !pred(&self.sessions[id]) {
+            return None;
+        }
+        let id = self.recency.remove(&seq)?;
+        let entry = self.sessions.remove(&id)?;
+        Some((id, entry))
+    }
+}
+
+pub struct SessionStore {
+    max_sessions: usize,
+    idle_ttl: Duration,
+    trace_history: usize,
+    kv: Option<Arc<KvStore>>,
+    inner: Mutex<Inner>,
+    evicted_lru: AtomicU64,
+    evicted_ttl: AtomicU64,
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_SESSIONS, DEFAULT_IDLE_TTL)
+    }
+}
+
+impl SessionStore {
+    pub fn new(max_sessions: usize, idle_ttl: Duration) -> Self {
+        Self {
+            max_sessions: max_sessions.max(1),
+            idle_ttl,
+            trace_history: DEFAULT_TRACE_HISTORY,
+            kv: None,
+            inner: Mutex::new(Inner::default()),
+            evicted_lru: AtomicU64::new(0),
+            evicted_ttl: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of recent turns attached to each session trace.
+    pub fn with_trace_history(mut self, turns: usize) -> Self {
+        self.trace_history = turns;
+        self
+    }
+
+    /// Limits from WORD_MATH_MAX_SESSIONS and WORD_MATH_SESSION_TTL_SECS,
+    /// trace history length from WORD_MATH_TRACE_HISTORY.
+    pub fn from_env() -> Self {
+        let max_sessions = std::env::var("WORD_MATH_MAX_SESSIONS")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_SESSIONS);
+        let idle_ttl = std::env::var("WORD_MATH_SESSION_TTL_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .map_or(DEFAULT_IDLE_TTL, Duration::from_secs);
+        let trace_history = std::env::var("WORD_MATH_TRACE_HISTORY")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_TRACE_HISTORY);
+        Self::new(max_sessions, idle_ttl).with_trace_history(trace_history)
+    }
+
+    /// Persist sessions in `kv`, first loading the ones already stored there.
+    pub fn with_persistence(mut self, kv: Arc<KvStore>) -> Self {
+        self.kv = Some(kv);
+        let loaded = self.reload();
+        info!("loaded {} persisted session(s)", loaded);
+        self
+    }
+
+    /// Replace the in-memory sessions with the persisted ones, e.g. after
+    /// the store was restored from a backup. Returns the number loaded.
+    pub fn reload(&self) -> usize {
+        let Some(kv) = &self.kv else { return 0 };
+        let mut inner = self.lock();
+        // Writes and deletes still in flight for the replaced sessions are
+        // dropped.
+        let pending = inner.evicted.values().map(|(_, written)| written);
+        for written in inner.sessions.values().map(|e| &e.written).chain(pending) {
+            *written.lock().unwrap_or_else(|e| e.into_inner()) = u64::MAX;
+        }
+        *inner = Inner::default();
+        let mut loaded = 0;
+        let mut evictions = Vec::new();
+        for (key, value) in kv.scan_prefix(KV_PREFIX) {
+            let id = &key[KV_PREFIX.len()..];
+            match serde_json::from_str::<ConversationSnapshot>(&value) {
+                Ok(snapshot) => {
+                    evictions.extend(self.make_room(&mut inner, id));
+                    let conv = ConversationAnalyzer::restore(snapshot)
+                        .with_trace_history(self.trace_history);
+                    inner.insert(id, conv);
+                    loaded += 1;
+                }
+                Err(e) => warn!("skipping unreadable stored session {}: {}", id, e),
+            }
+        }
+        drop(inner);
+        self.delete_evicted(evictions);
+        loaded
+    }
+
+    /// Snapshot `entry` for `persist`, when sessions are persisted.
+    fn prepare(&self, id: &str, entry: &mut Entry) -> Option<Write> {
+        self.kv.as_ref()?;
+        entry.version += 1;
+        Some(Write {
+            id: id.to_string(),
+            snapshot: entry.conv.snapshot(),
+            version: entry.version,
+            written: Arc::clone(&entry.written),
+        })
+    }
+
+    /// Write a prepared snapshot; call without holding the store lock.
+    fn persist(&self, write: Option<Write>) {
+        let (Some(kv), Some(write)) = (&self.kv, write) else {
+            return;
+        };
+        let mut written = write.written.lock().unwrap_or_else(|e| e.into_inner());
+        if *written >= write.version {
+            return;
+        }
+        let result = serde_json::to_string(&write.snapshot)
+            .map_err(std::io::Error::other)
+            .and_then(|json| kv.put(&format!("{}{}", KV_PREFIX, write.id), json));
+        match result {
+            Ok(()) => *written = write.version,
+            Err(e) => warn!("persisting session {} failed: {}", write.id, e),
+        }
+    }
+
+    pub fn max_sessions(&self) -> usize {
+        self.max_sessions
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn record_eviction(
+        &self,
+        inner: &mut Inner,
+        id: String,
+        entry: Entry,
+        reason: EvictionReason,
+    ) -> Eviction {
+        let counter = match reason {
+            EvictionReason::Lru => &self.evicted_lru,
+            EvictionReason::Ttl => &self.evicted_ttl,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        let version = entry.version + 1;
+        if self.kv.is_some() {
+            inner
+                .evicted
+                .insert(id.clone(), (version, Arc::clone(&entry.written)));
+        }
+        Eviction {
+            id,
+            reason,
+            version,
+            written: entry.written,
+        }
+    }
+
+    /// Drop expired sessions, then LRU sessions until there is room for
+    /// one more. The evicted sessions go to `delete_evicted` once the store
+    /// lock is released.
+    fn make_room(&self, inner: &mut Inner, incoming: &str) -> Vec<Eviction> {
+        let ttl = self.idle_ttl;
+        let mut evictions = Vec::new();
+        while let Some((id, entry)) = inner.pop_oldest_if(|entry| entry.last_used.elapsed() >= ttl)
+        {
+            evictions.push(self.record_eviction(inner, id, entry, EvictionReason::Ttl));
+        }
+        if inner.sessions.contains_key(incoming) {
+            return evictions;
+        }
+        while inner.sessions.len() >= self.max_sessions {
+            match inner.pop_oldest_if(|_| true) {
+                Some((id, entry)) => {
+                    evictions.push(self.record_eviction(inner, id, entry, EvictionReason::Lru))
+                }
+                None => break,
+            }
+        }
+        evictions
+    }
+
+    /// Delete evicted sessions from the store; call without holding the
+    /// store lock.
+    fn delete_evicted(&self, evictions: Vec<Eviction>) {
+        for eviction in evictions {
+            if let Some(kv) = &self.kv {
+                // Waits out a write in flight; later writes of the evicted
+                // session are older than the delete and are skipped, while
+                // a session recreated meanwhile has already written past it.
+                let mut written = eviction.written.lock().unwrap_or_else(|e| e.into_inner());
+                if *written < eviction.version {
+                    match kv.delete(&format!("{}{}", KV_PREFIX, eviction.id)) {
+                        Ok(()) => *written = eviction.version,
+                        Err(e) => warn!("deleting stored session {} failed: {}", eviction.id, e),
+                    }
+                }
+                drop(written);
+                let mut inner = self.lock();
+                if inner
+                    .evicted
+                    .get(&eviction.id)
+                    .is_some_and(|(version, written)| {
+                        *version == eviction.version && Arc::ptr_eq(written, &eviction.written)
+                    })
+                {
+                    inner.evicted.remove(&eviction.id);
+                }
+            }
+            info!(
+                "HEX[{}]: session {} evicted ({})",
+                generate_hex_id(),
+                eviction.id,
+                eviction.reason.as_str()
+            );
+        }
+    }
+
+    /// Score `message` as the next turn of session `id`, creating the
+    /// session with `topic` and `cfg` on first use. Existing sessions keep
+    /// the topic and config they were created with.
+    pub fn push(
+        &self,
+        id: &str,
+        topic: &str,
+        cfg: WordMathConfig,
+        message: &str,
+    ) -> (TurnResult, WordMathConfig) {
+        let (turn, cfg, write, evictions) = {
+            let mut inner = self.lock();
+            let evictions = self.make_room(&mut inner, id);
+            if inner.sessions.contains_key(id) {
+                inner.touch(id);
+            } else {
+                let conv =
+                    ConversationAnalyzer::new(topic, cfg).with_trace_history(self.trace_history);
+                inner.insert(id, conv);
+            }
+
+            let entry = inner.sessions.get_mut(id).expect("session just inserted");
+            let turn = entry.conv.push(message);
+            let cfg = *entry.conv.config();
+            (turn, cfg, self.prepare(id, entry), evictions)
+        };
+        self.delete_evicted(evictions);
+        self.persist(write);
+        (turn, cfg)
+    }
+
+    pub fn snapshot(&self, id: &str) -> Option<ConversationSnapshot> {
+        let inner = self.lock();
+        inner.sessions.get(id).map(|entry| entry.conv.snapshot())
+    }
+
+    /// Replace (or create) session `id` from a snapshot.
+    pub fn restore(&self, id: &str, snapshot: ConversationSnapshot) {
+        let (write, evictions) = {
+            let mut inner = self.lock();
+            let evictions = self.make_room(&mut inner, id);
+            let conv =
+                ConversationAnalyzer::restore(snapshot).with_trace_history(self.trace_history);
+            let entry = inner.insert(id, conv);
+            (self.prepare(id, entry), evictions)
+        };
+        self.delete_evicted(evictions);
+        self.persist(write);
+    }
+
+    /// Like `restore`, but keeps a session this instance already has;
+    /// returns whether the snapshot was adopted.
+    pub fn adopt(&self, id: &str, snapshot: ConversationSnapshot) -> bool {
+        let (write, evictions) = {
+            let mut inner = self.lock();
+            if inner.sessions.contains_key(id) {
+                return false;
+            }
+            let evictions = self.make_room(&mut inner, id);
+            let conv =
+                ConversationAnalyzer::restore(snapshot).with_trace_history(self.trace_history);
+            let entry = inner.insert(id, conv);
+            (self.prepare(id, entry), evictions)
+        };
+        self.delete_evicted(evictions);
+        self.persist(write);
+        true
+    }
+
+    /// Snapshots of every live session, least recently used first.
+    pub fn export(&self) -> Vec<(String, ConversationSnapshot)> {
+        let inner = self.lock();
+        inner
+            .recency
+            .values()
+            .filter_map(|id| {
+                let entry = inner.sessions.get(id)?;
+                Some((id.clone(), entry.conv.snapshot()))
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().sessions.len()
+    }
+
+    /// Total evictions as (lru, ttl).
+    pub fn evictions(&self) -> (u64, u64) {
+        (
+            self.evicted_lru.load(Ordering::Relaxed),
+            self.evicted_ttl.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push(store: &SessionStore, id: &str) {
+        store.push(id, "topic", WordMathConfig::default(), "topic words");
+    }
+
+    #[test]
+    fn test_lru_eviction_keeps_recent_sessions() {
+        let store = SessionStore::new(2, Duration::from_secs(60));
+        push(&store, "a");
+        push(&store, "b");
+        push(&store, "a");
+        push(&store, "c");
+
+        assert_eq!(store.len(), 2);
+        assert!(store.snapshot("a").is_some());
+        assert!(store.snapshot("b").is_none());
+        assert_eq!(store.evictions(), (1, 0));
+        assert_eq!(store.snapshot("a").unwrap().history.len(), 2);
+    }
+
+    #[test]
+    fn test_persisted_sessions_survive_restart() {
+        let dir = std::env::temp_dir().join(format!("wordmath-sessions-{}", generate_hex_id()));
+        let kv = Arc::new(KvStore::open(&dir).unwrap());
+        let store = SessionStore::new(1, Duration::from_secs(60)).with_persistence(Arc::clone(&kv));
+        push(&store, "a");
+        push(&store, "a");
+        push(&store, "b");
+        drop(store);
+
+        let reopened = Arc::new(KvStore::open(&dir).unwrap());
+        let store = SessionStore::new(10, Duration::from_secs(60)).with_persistence(reopened);
+        assert_eq!(store.len(), 1);
+        assert!(store.snapshot("a").is_none());
+        assert_eq!(store.snapshot("b").unwrap().history.len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_turns_persist_the_latest_snapshot() {
+        let dir = std::env::temp_dir().join(format!("wordmath-sessions-{}", generate_hex_id()));
+        let kv = Arc::new(KvStore::open(&dir).unwrap());
+        let store = Arc::new(
+            SessionStore::new(10, Duration::from_secs(60)).with_persistence(Arc::clone(&kv)),
+        );
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let store = Arc::clone(&store);
+                std::thread::spawn(move || {
+                    for _ in 0..25 {
+                        push(&store, "shared");
+                        push(&store, &format!("own-{}", t));
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let stored = |id: &str| -> ConversationSnapshot {
+            serde_json::from_str(&kv.get(&format!("{}{}", KV_PREFIX, id)).unwrap().unwrap())
+                .unwrap()
+        };
+        assert_eq!(stored("shared").turns, 100);
+        assert_eq!(stored("shared"), store.snapshot("shared").unwrap());
+        assert_eq!(stored("own-3").turns, 25);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_recreated_session_outlives_the_delayed_delete() {
+        let dir = std::env::temp_dir().join(format!("wordmath-sessions-{}", generate_hex_id()));
+        let kv = Arc::new(KvStore::open(&dir).unwrap());
+        let store = SessionStore::new(1, Duration::from_secs(60)).with_persistence(Arc::clone(&kv));
+        push(&store, "a");
+        // Evict "a" under the lock, as `push` would, but recreate and
+        // persist it before the delete runs.
+        let evictions = {
+            let mut inner = store.lock();
+            store.make_room(&mut inner, "b")
+        };
+        assert_eq!(evictions.len(), 1);
+        push(&store, "a");
+        store.delete_evicted(evictions);
+        assert!(kv.get(&format!("{}a", KV_PREFIX)).unwrap().is_some());
+        assert!(store.lock().evicted.is_empty());
+
+        // Without a recreation the delete goes through.
+        push(&store, "b");
+        assert!(kv.get(&format!("{}a", KV_PREFIX)).unwrap().is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_idle_sessions_expire() {
+        let store = SessionStore::new(10, Duration::ZERO);
+        push(&store, "a");
+        push(&store, "b");
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.evictions(), (0, 1));
+    }
+}
@@ -0,0 +1,95 @@
+//! Live introspection through Unix signals.
+//!
+//! * `SIGUSR1` writes a JSON state dump (config, sessions, verdict counters)
+//!   to WORD_MATH_DUMP_DIR (default: the system temp dir).
+//! * `SIGUSR2` toggles debug-level scoring logs.
+//!
+//! The handlers only set flags; a watcher thread does the actual work
+//! outside signal context.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{info, warn};
+use word_math_guard::generate_hex_id;
+
+static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+static DEBUG_TOGGLE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    match signal {
+        libc::SIGUSR1 => DUMP_REQUESTED.store(true, Ordering::SeqCst),
+        libc::SIGUSR2 => DEBUG_TOGGLE_REQUESTED.store(true, Ordering::SeqCst),
+        _ => {}
+    }
+}
+
+/// Directory for state dumps.
+pub fn dump_dir() -> PathBuf {
+    std::env::var_os("WORD_MATH_DUMP_DIR").map_or_else(std::env::temp_dir, PathBuf::from)
+}
+
+/// Write `state` as `wordmath-state-<hex>.json` under `dir`.
+pub fn write_dump(dir: &Path, state: &serde_json::Value) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("wordmath-state-{}.json", generate_hex_id()));
+    let pretty = serde_json::to_string_pretty(state).map_err(std::io::Error::other)?;
+    std::fs::write(&path, pretty + "\n")?;
+    Ok(path)
+}
+
+/// Install the handlers and start the watcher thread. `dump` renders the
+/// current state; `set_debug` switches scoring logs on or off.
+pub fn install<D, S>(dump: D, set_debug: S)
+where
+    D: Fn() -> serde_json::Value + Send + 'static,
+    S: Fn(bool) + Send + 'static,
+{
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: `on_signal` only stores to atomics, which is async-signal-safe.
+    unsafe {
+        libc::signal(libc::SIGUSR1, handler);
+        libc::signal(libc::SIGUSR2, handler);
+    }
+
+    std::thread::Builder::new()
+        .name("wordmath-signals".to_string())
+        .spawn(move || {
+            let mut debug_on = false;
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+                if DUMP_REQUESTED.swap(false, Ordering::SeqCst) {
+                    match write_dump(&dump_dir(), &dump()) {
+                        Ok(path) => info!("SIGUSR1: state dumped to {}", path.display()),
+                        Err(e) => warn!("SIGUSR1: state dump failed: {}", e),
+                    }
+                }
+                if DEBUG_TOGGLE_REQUESTED.swap(false, Ordering::SeqCst) {
+                    debug_on = !debug_on;
+                    set_debug(debug_on);
+                    info!(
+                        "SIGUSR2: debug scoring logs {}",
+                        if debug_on { "on" } else { "off" }
+                    );
+                }
+            }
+        })
+        .expect("spawning the signal watcher failed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_dump_creates_json_file() {
+        let dir = std::env::temp_dir().join(format!("wordmath-dump-test-{}", generate_hex_id()));
+        let path = write_dump(&dir, &serde_json::json!({"active_sessions": 3})).unwrap();
+        let raw = std::fs::read_to_string(&path).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&raw).unwrap();
+        assert_eq!(value["active_sessions"], 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,594 @@
+//! Verdict sinks: every scored message, delivered off the request path.
+//!
+//! A `VerdictSink` receives the trace record of each scored message (its
+//! metrics, hex trace ID and verdict) once it is stored, so SIEMs and data
+//! lakes can subscribe to decisions without touching the handlers.
+//! WORD_MATH_SINKS (or its `_FILE` variant; webhook URLs may embed a
+//! token, so it is read as a secret) lists the built-in sinks,
+//! comma-separated:
+//!
+//! - `stdout`: one JSON line per record on standard output;
+//! - `file:<path>`: JSON lines appended to `<path>`;
+//! - `http://host[:port]/path`: each record POSTed as JSON.
+//!
+//! Every sink runs on its own thread fed by a bounded queue, so a slow or
+//! failing sink holds up neither the request nor the other sinks. The
+//! thread delivers records in batches of up to WORD_MATH_SINK_BATCH
+//! (default 100), at the latest WORD_MATH_SINK_FLUSH_MS (default 1000)
+//! after the oldest one arrived. A failed batch is retried up to
+//! WORD_MATH_SINK_RETRIES times (default 5) with exponential backoff from
+//! WORD_MATH_SINK_BACKOFF_MS (default 100, doubling up to 30 s), then
+//! dropped; delivery is at least once, so a retried batch may repeat
+//! records. While it waits, records beyond WORD_MATH_SINK_QUEUE (default
+//! 10000) queued ones are dropped. Drops are counted per sink and reason
+//! in `wordmath_sink_dropped_total`.
+
+use crate::audit::post_webhook;
+use crate::traces::TraceRecord;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use word_math_guard::secret::{self, Secret};
+
+/// A subscriber to scored messages.
+pub trait VerdictSink: Send + 'static {
+    /// Name for logs; must not reveal credentials.
+    fn name(&self) -> String;
+
+    /// Deliver one record. Called on the sink's own thread, in order.
+    fn deliver(&mut self, record: &TraceRecord) -> io::Result<()>;
+
+    /// Deliver a batch of records, by default one at a time. On error the
+    /// whole batch is retried.
+    fn deliver_batch(&mut self, records: &[TraceRecord]) -> io::Result<()> {
+        records.iter().try_for_each(|record| self.deliver(record))
+    }
+}
+
+/// JSON lines on standard output.
+pub struct StdoutSink;
+
+impl VerdictSink for StdoutSink {
+    fn name(&self) -> String {
+        "stdout".to_string()
+    }
+
+    fn deliver(&mut self, record: &TraceRecord) -> io::Result<()> {
+        self.deliver_batch(std::slice::from_ref(record))
+    }
+
+    fn deliver_batch(&mut self, records: &[TraceRecord]) -> io::Result<()> {
+        io::stdout().lock().write_all(&json_lines(records)?)
+    }
+}
+
+/// `records` as JSON lines.
+fn json_lines(records: &[TraceRecord]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut out, record).map_err(io::Error::other)?;
+        out.push(b'\n');
+    }
+    Ok(out)
+}
+
+/// JSON lines appended to a file.
+pub struct JsonlFileSink {
+    path: String,
+    file: File,
+}
+
+impl JsonlFileSink {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_string(),
+            file,
+        })
+    }
+}
+
+impl VerdictSink for JsonlFileSink {
+    fn name(&self) -> String {
+        format!("file:{}", self.path)
+    }
+
+    fn deliver(&mut self, record: &TraceRecord) -> io::Result<()> {
+        self.deliver_batch(std::slice::from_ref(record))
+    }
+
+    /// One write per batch, so lines are never interleaved or torn by
+    /// another writer appending to the same file.
+    fn deliver_batch(&mut self, records: &[TraceRecord]) -> io::Result<()> {
+        self.file.write_all(&json_lines(records)?)
+    }
+}
+
+/// Each record POSTed as JSON to a plain-HTTP webhook.
+pub struct WebhookSink {
+    url: Secret,
+    authority: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| "webhook sinks must be http://".to_string())?;
+        let authority = rest.split('/').next().unwrap_or_default();
+        if authority.is_empty() {
+            return Err(format!("webhook sink without a host: {}", url));
+        }
+        Ok(Self {
+            authority: authority.to_string(),
+            url: Secret::new(url.to_string()),
+        })
+    }
+}
+
+impl VerdictSink for WebhookSink {
+    fn name(&self) -> String {
+        format!("http://{}", self.authority)
+    }
+
+    fn deliver(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let body = serde_json::to_string(record).map_err(io::Error::other)?;
+        post_webhook(self.url.expose(), &body)
+    }
+
+    /// A batch is POSTed as one JSON array.
+    fn deliver_batch(&mut self, records: &[TraceRecord]) -> io::Result<()> {
+        let body = serde_json::to_string(records).map_err(io::Error::other)?;
+        post_webhook(self.url.expose(), &body)
+    }
+}
+
+/// A built-in sink from its WORD_MATH_SINKS entry.
+pub fn parse(spec: &str) -> Result<Box<dyn VerdictSink>, String> {
+    let spec = spec.trim();
+    if spec == "stdout" {
+        Ok(Box::new(StdoutSink))
+    } else if let Some(path) = spec.strip_prefix("file:") {
+        JsonlFileSink::open(path)
+            .map(|sink| Box::new(sink) as Box<dyn VerdictSink>)
+            .map_err(|e| format!("{}: {}", path, e))
+    } else if spec.starts_with("http://") {
+        WebhookSink::new(spec).map(|sink| Box::new(sink) as Box<dyn VerdictSink>)
+    } else {
+        Err(format!(
+            "unknown sink (expected stdout, file:<path> or http://...): {}",
+            spec.split('/').next().unwrap_or_default()
+        ))
+    }
+}
+
+/// Queueing, batching and retry settings shared by all sinks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SinkPolicy {
+    /// Records queued per sink before new ones are dropped.
+    pub queue: usize,
+    pub batch: usize,
+    pub flush_interval: Duration,
+    pub retries: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for SinkPolicy {
+    fn default() -> Self {
+        Self {
+            queue: 10_000,
+            batch: 100,
+            flush_interval: Duration::from_millis(1_000),
+            retries: 5,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl SinkPolicy {
+    /// Defaults overridden by WORD_MATH_SINK_QUEUE, _BATCH, _FLUSH_MS,
+    /// _RETRIES and _BACKOFF_MS.
+    pub fn from_env() -> Self {
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|s| s.trim().parse::<u64>().ok())
+        };
+        let default = Self::default();
+        Self {
+            queue: var("WORD_MATH_SINK_QUEUE").map_or(default.queue, |n| n.max(1) as usize),
+            batch: var("WORD_MATH_SINK_BATCH").map_or(default.batch, |n| n.max(1) as usize),
+            flush_interval: var("WORD_MATH_SINK_FLUSH_MS")
+                .map_or(default.flush_interval, Duration::from_millis),
+            retries: var("WORD_MATH_SINK_RETRIES").map_or(default.retries, |n| n as u32),
+            backoff: var("WORD_MATH_SINK_BACKOFF_MS")
+                .map_or(default.backoff, Duration::from_millis),
+            max_backoff: default.max_backoff,
+        }
+    }
+
+    /// Wait before retry number `attempt` (from 1).
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(1 << (attempt - 1).min(20))
+            .min(self.max_backoff)
+    }
+}
+
+/// Delivery counters of one sink.
+#[derive(Debug, Default)]
+struct SinkStats {
+    delivered: AtomicU64,
+    /// Dropped because the queue was full.
+    overflow: AtomicU64,
+    /// Dropped after the last retry failed.
+    failed: AtomicU64,
+}
+
+struct Worker {
+    name: String,
+    records: mpsc::SyncSender<TraceRecord>,
+    stats: Arc<SinkStats>,
+}
+
+/// Deliver `batch` with retries; returns whether it got through.
+fn deliver_with_retry(
+    sink: &mut dyn VerdictSink,
+    batch: &[TraceRecord],
+    policy: &SinkPolicy,
+    name: &str,
+) -> bool {
+    let mut attempt = 0;
+    loop {
+        match sink.deliver_batch(batch) {
+            Ok(()) => return true,
+            Err(e) if attempt < policy.retries => {
+                attempt += 1;
+                let wait = policy.backoff(attempt);
+                warn!(
+                    "sink {} failed on {} record(s), retry {} in {:?}: {}",
+                    name,
+                    batch.len(),
+                    attempt,
+                    wait,
+                    e
+                );
+                std::thread::sleep(wait);
+            }
+            Err(e) => {
+                warn!(
+                    "sink {} dropped {} record(s) after {} retries: {}",
+                    name,
+                    batch.len(),
+                    attempt,
+                    e
+                );
+                return false;
+            }
+        }
+    }
+}
+
+/// The sink thread: collect a batch, deliver it, repeat until every
+/// sender is gone, then deliver what is left.
+fn run(
+    mut sink: Box<dyn VerdictSink>,
+    inbox: mpsc::Receiver<TraceRecord>,
+    policy: SinkPolicy,
+    name: String,
+    stats: Arc<SinkStats>,
+) {
+    let mut batch = Vec::with_capacity(policy.batch);
+    let mut open = true;
+    while open || !batch.is_empty() {
+        if open {
+            match inbox.recv() {
+                Ok(record) => batch.push(record),
+                Err(_) => open = false,
+            }
+            let deadline = Instant::now() + policy.flush_interval;
+            while open && batch.len() < policy.batch {
+                let wait = deadline.saturating_duration_since(Instant::now());
+                match inbox.recv_timeout(wait) {
+                    Ok(record) => batch.push(record),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => open = false,
+                }
+            }
+        }
+        if batch.is_empty() {
+            continue;
+        }
+        let counter = if deliver_with_retry(sink.as_mut(), &batch, &policy, &name) {
+            &stats.delivered
+        } else {
+            &stats.failed
+        };
+        counter.fetch_add(batch.len() as u64, Ordering::Relaxed);
+        batch.clear();
+    }
+}
+
+/// The running sinks.
+#[derive(Default)]
+pub struct Sinks {
+    workers: Vec<Worker>,
+}
+
+impl Sinks {
+    /// Start one delivery thread per sink.
+    pub fn spawn(sinks: Vec<Box<dyn VerdictSink>>, policy: SinkPolicy) -> Self {
+        let workers = sinks
+            .into_iter()
+            .map(|sink| {
+                let name = sink.name();
+                let (records, inbox) = mpsc::sync_channel::<TraceRecord>(policy.queue);
+                let stats = Arc::new(SinkStats::default());
+                let (thread_name, thread_stats) = (name.clone(), Arc::clone(&stats));
+                std::thread::Builder::new()
+                    .name("wordmath-sink".to_string())
+                    .spawn(move || run(sink, inbox, policy, thread_name, thread_stats))
+                    .expect("spawning a sink thread failed");
+                Worker {
+                    name,
+                    records,
+                    stats,
+                }
+            })
+            .collect();
+        Self { workers }
+    }
+
+    /// Sinks listed in WORD_MATH_SINKS; none when unset.
+    pub fn from_env() -> Result<Self, String> {
+        let Some(specs) = secret::from_env("WORD_MATH_SINKS").map_err(|e| e.to_string())? else {
+            return Ok(Self::default());
+        };
+        let sinks = specs
+            .expose()
+            .split(',')
+            .filter(|spec| !spec.trim().is_empty())
+            .map(parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        let sinks = Self::spawn(sinks, SinkPolicy::from_env());
+        for worker in &sinks.workers {
+            info!("publishing verdicts to {}", worker.name);
+        }
+        Ok(sinks)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.workers.iter().map(|w| w.name.as_str()).collect()
+    }
+
+    /// Queue `record` for every sink; never blocks. A sink whose queue is
+    /// full drops it.
+    pub fn publish(&self, record: &TraceRecord) {
+        for worker in &self.workers {
+            match worker.records.try_send(record.clone()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    worker.stats.overflow.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    warn!("sink {} has stopped", worker.name);
+                }
+            }
+        }
+    }
+
+    /// Delivery counters in the Prometheus text format.
+    pub fn render(&self, out: &mut String) {
+        if self.workers.is_empty() {
+            return;
+        }
+        let _ = writeln!(
+            out,
+            "# HELP wordmath_sink_delivered_total Records delivered, by sink.\n\
+             # TYPE wordmath_sink_delivered_total counter"
+        );
+        for worker in &self.workers {
+            let _ = writeln!(
+                out,
+                "wordmath_sink_delivered_total{{sink=\"{}\"}} {}",
+                worker.name,
+                worker.stats.delivered.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP wordmath_sink_dropped_total Records dropped, by sink and reason.\n\
+             # TYPE wordmath_sink_dropped_total counter"
+        );
+        for worker in &self.workers {
+            for (reason, count) in [
+                ("overflow", &worker.stats.overflow),
+                ("failed", &worker.stats.failed),
+            ] {
+                let _ = writeln!(
+                    out,
+                    "wordmath_sink_dropped_total{{sink=\"{}\",reason=\"{}\"}} {}",
+                    worker.name,
+                    reason,
+                    count.load(Ordering::Relaxed)
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labels::Labels;
+    use std::sync::{Arc, Mutex};
+    use word_math_guard::Verdict;
+
+    struct Collect(Arc<Mutex<Vec<String>>>);
+
+    impl VerdictSink for Collect {
+        fn name(&self) -> String {
+            "collect".to_string()
+        }
+
+        fn deliver(&mut self, record: &TraceRecord) -> io::Result<()> {
+            self.0.lock().unwrap().push(record.hex_id.clone());
+            Ok(())
+        }
+    }
+
+    fn record(hex_id: &str) -> TraceRecord {
+        TraceRecord {
+            hex_id: hex_id.to_string(),
+            y_repetition: 0.1,
+            z_drift: 0.2,
+            raw_score: 0.85,
+            score: 0.85,
+            verdict: Verdict::Allow,
+            profile: None,
+            session_id: None,
+            topic_id: None,
+            rule_hits: Vec::new(),
+            experiment: None,
+            variant: None,
+            labels: Labels::default(),
+        }
+    }
+
+    #[test]
+    fn test_records_reach_custom_and_file_sinks_in_order() {
+        let path = std::env::temp_dir().join(format!("wordmath-sink-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sinks = Sinks::spawn(
+            vec![
+                Box::new(Collect(Arc::clone(&seen))),
+                parse(&format!("file:{}", path.display())).unwrap(),
+            ],
+            SinkPolicy {
+                flush_interval: Duration::from_millis(20),
+                ..SinkPolicy::default()
+            },
+        );
+        for id in ["a1", "b2", "c3"] {
+            sinks.publish(&record(id));
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let lines = loop {
+            let written = std::fs::read_to_string(&path).unwrap_or_default();
+            let done = written.lines().count() == 3 && seen.lock().unwrap().len() == 3;
+            if done || Instant::now() > deadline {
+                break written;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(*seen.lock().unwrap(), ["a1", "b2", "c3"]);
+        let first: TraceRecord = serde_json::from_str(lines.lines().next().unwrap()).unwrap();
+        assert_eq!(first, record("a1"));
+        assert_eq!(
+            sinks.names(),
+            ["collect", format!("file:{}", path.display()).as_str()]
+        );
+        assert!(parse("kafka://broker").is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    /// Blocks until the gate opens, then fails the first `failures`
+    /// batches; records the sizes of the batches it accepts.
+    struct Flaky {
+        gate: Arc<Mutex<()>>,
+        failures: usize,
+        batches: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl VerdictSink for Flaky {
+        fn name(&self) -> String {
+            "flaky".to_string()
+        }
+
+        fn deliver(&mut self, record: &TraceRecord) -> io::Result<()> {
+            self.deliver_batch(std::slice::from_ref(record))
+        }
+
+        fn deliver_batch(&mut self, records: &[TraceRecord]) -> io::Result<()> {
+            drop(self.gate.lock().unwrap());
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::Error::other("unavailable"));
+            }
+            self.batches.lock().unwrap().push(records.len());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_full_queue_drops_and_failed_batches_are_retried() {
+        let gate = Arc::new(Mutex::new(()));
+        let held = gate.lock().unwrap();
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sinks = Sinks::spawn(
+            vec![Box::new(Flaky {
+                gate: Arc::clone(&gate),
+                failures: 2,
+                batches: Arc::clone(&batches),
+            })],
+            SinkPolicy {
+                queue: 4,
+                batch: 3,
+                flush_interval: Duration::from_millis(20),
+                retries: 3,
+                backoff: Duration::from_millis(1),
+                ..SinkPolicy::default()
+            },
+        );
+        // The worker takes up to one batch, the queue holds 4 more, and
+        // publishing never blocks on the stuck sink.
+        let started = Instant::now();
+        for i in 0..20 {
+            sinks.publish(&record(&format!("r{}", i)));
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+        std::thread::sleep(Duration::from_millis(50));
+        drop(held);
+
+        let stats = &sinks.workers[0].stats;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while stats.delivered.load(Ordering::Relaxed) + stats.overflow.load(Ordering::Relaxed) < 20
+            && Instant::now() < deadline
+        {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let delivered = stats.delivered.load(Ordering::Relaxed);
+        assert!((4..=7).contains(&delivered), "{}", delivered);
+        assert_eq!(stats.overflow.load(Ordering::Relaxed), 20 - delivered);
+        assert_eq!(stats.failed.load(Ordering::Relaxed), 0);
+        assert!(batches.lock().unwrap().iter().all(|&n| n <= 3));
+
+        let mut body = String::new();
+        sinks.render(&mut body);
+        assert!(body.contains(&format!(
+            "wordmath_sink_delivered_total{{sink=\"flaky\"}} {}",
+            delivered
+        )));
+        assert!(body.contains("wordmath_sink_dropped_total{sink=\"flaky\",reason=\"failed\"} 0"));
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let policy = SinkPolicy {
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+            ..SinkPolicy::default()
+        };
+        let waits: Vec<_> = (1..=4).map(|n| policy.backoff(n).as_millis()).collect();
+        assert_eq!(waits, [100, 200, 350, 350]);
+    }
+}
@@ -0,0 +1,259 @@
+//! Runtime kill switches for individual metrics and policy rules.
+//!
+//! Admins flip switches through `/admin/switches` to silence a misfiring
+//! detector without a deploy. A disabled metric contributes nothing to the
+//! score (its value is reported as 0); a disabled rule never matches. With
+//! an embedded `KvStore`, disabled switches are stored under
+//! `switch/<kind>/<name>` and every change is appended under
+//! `audit/switch/<hex_id>`, so both survive restarts.
+
+use crate::kv::KvStore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+use word_math_guard::{generate_hex_id, score_linear, WordMathAnalysis, WordMathConfig};
+
+const SWITCH_PREFIX: &str = "switch/";
+const AUDIT_PREFIX: &str = "audit/switch/";
+/// Changes kept in memory for `/admin/switches`.
+const RECENT_CHANGES: usize = 100;
+/// Metrics that can be switched off.
+pub const METRICS: &[&str] = &["repetition", "drift"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwitchKind {
+    Metric,
+    Rule,
+}
+
+impl SwitchKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            SwitchKind::Metric => "metric",
+            SwitchKind::Rule => "rule",
+        }
+    }
+
+    fn parse(label: &str) -> Option<Self> {
+        match label {
+            "metric" => Some(SwitchKind::Metric),
+            "rule" => Some(SwitchKind::Rule),
+            _ => None,
+        }
+    }
+}
+
+/// Body of `POST /admin/switches`, and one entry of the change log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SwitchChange {
+    pub kind: SwitchKind,
+    pub name: String,
+    pub enabled: bool,
+    #[serde(default)]
+    pub hex_id: String,
+}
+
+/// Currently disabled switches plus recent changes.
+#[derive(Debug, Clone, Serialize)]
+pub struct SwitchReport {
+    pub disabled_metrics: Vec<String>,
+    pub disabled_rules: Vec<String>,
+    pub recent_changes: Vec<SwitchChange>,
+}
+
+/// Zero the given metrics and rescore; a no-op when neither is dropped.
+pub fn drop_metrics(
+    analysis: &mut WordMathAnalysis,
+    cfg: &WordMathConfig,
+    repetition: bool,
+    drift: bool,
+) {
+    if !repetition && !drift {
+        return;
+    }
+    if repetition {
+        analysis.y_repetition = 0.0;
+    }
+    if drift {
+        analysis.z_drift = 0.0;
+    }
+    analysis.score =
+        cfg.transform
+            .apply(score_linear(analysis.y_repetition, analysis.z_drift, *cfg));
+}
+
+#[derive(Default)]
+struct Board {
+    disabled: BTreeSet<(SwitchKind, String)>,
+    changes: VecDeque<SwitchChange>,
+}
+
+#[derive(Default)]
+pub struct Switches {
+    kv: Option<Arc<KvStore>>,
+    board: Mutex<Board>,
+}
+
+impl Switches {
+    /// Persist switches in `kv`, first loading the stored ones.
+    pub fn with_persistence(mut self, kv: Arc<KvStore>) -> Self {
+        {
+            let mut board = self.lock();
+            for (key, _) in kv.scan_prefix(SWITCH_PREFIX) {
+                let parsed = key
+                    .strip_prefix(SWITCH_PREFIX)
+                    .and_then(|rest| rest.split_once('/'))
+                    .and_then(|(kind, name)| Some((SwitchKind::parse(kind)?, name.to_string())));
+                match parsed {
+                    Some(switch) => {
+                        board.disabled.insert(switch);
+                    }
+                    None => warn!("skipping unreadable stored switch {}", key),
+                }
+            }
+            let stored = kv.scan_prefix(AUDIT_PREFIX);
+            let skip = stored.len().saturating_sub(RECENT_CHANGES);
+            for (_, value) in stored.into_iter().skip(skip) {
+                if let Ok(change) = serde_json::from_str(&value) {
+                    board.changes.push_back(change);
+                }
+            }
+        }
+        self.kv = Some(kv);
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Board> {
+        self.board.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_disabled(&self, kind: SwitchKind, name: &str) -> bool {
+        self.lock().disabled.contains(&(kind, name.to_string()))
+    }
+
+    pub fn rule_enabled(&self, id: &str) -> bool {
+        !self.is_disabled(SwitchKind::Rule, id)
+    }
+
+    /// Zero disabled metrics and rescore the analysis without them.
+    pub fn apply_metrics(&self, analysis: &mut WordMathAnalysis, cfg: &WordMathConfig) {
+        let (repetition_off, drift_off) = {
+            let board = self.lock();
+            if board.disabled.is_empty() {
+                return;
+            }
+            let off = |name: &str| {
+                board
+                    .disabled
+                    .contains(&(SwitchKind::Metric, name.to_string()))
+            };
+            (off("repetition"), off("drift"))
+        };
+        drop_metrics(analysis, cfg, repetition_off, drift_off);
+    }
+
+    /// Record a change (persisting it when enabled) and return it stamped.
+    pub fn set(&self, mut change: SwitchChange) -> std::io::Result<SwitchChange> {
+        change.hex_id = generate_hex_id();
+        let key = format!("{}{}/{}", SWITCH_PREFIX, change.kind.label(), change.name);
+        if let Some(kv) = &self.kv {
+            if change.enabled {
+                kv.delete(&key)?;
+            } else {
+                kv.put(&key, "off".to_string())?;
+            }
+            let json = serde_json::to_string(&change).map_err(std::io::Error::other)?;
+            kv.put(&format!("{}{}", AUDIT_PREFIX, change.hex_id), json)?;
+        }
+
+        let mut board = self.lock();
+        let switch = (change.kind, change.name.clone());
+        if change.enabled {
+            board.disabled.remove(&switch);
+        } else {
+            board.disabled.insert(switch);
+        }
+        board.changes.push_back(change.clone());
+        while board.changes.len() > RECENT_CHANGES {
+            board.changes.pop_front();
+        }
+        info!(
+            "HEX[{}]: audit switch {} {} {}",
+            change.hex_id,
+            change.kind.label(),
+            change.name,
+            if change.enabled {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        Ok(change)
+    }
+
+    pub fn report(&self) -> SwitchReport {
+        let board = self.lock();
+        let names = |kind: SwitchKind| {
+            board
+                .disabled
+                .iter()
+                .filter(|(k, _)| *k == kind)
+                .map(|(_, name)| name.clone())
+                .collect()
+        };
+        SwitchReport {
+            disabled_metrics: names(SwitchKind::Metric),
+            disabled_rules: names(SwitchKind::Rule),
+            recent_changes: board.changes.iter().rev().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use word_math_guard::analyze_message_with_trace;
+
+    fn change(kind: SwitchKind, name: &str, enabled: bool) -> SwitchChange {
+        SwitchChange {
+            kind,
+            name: name.to_string(),
+            enabled,
+            hex_id: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_switches_rescore_and_persist() {
+        let dir = std::env::temp_dir().join(format!("wordmath-switches-{}", generate_hex_id()));
+        let kv = Arc::new(KvStore::open(&dir).unwrap());
+        let switches = Switches::default().with_persistence(Arc::clone(&kv));
+        let cfg = WordMathConfig::default();
+
+        switches
+            .set(change(SwitchKind::Metric, "repetition", false))
+            .unwrap();
+        switches
+            .set(change(SwitchKind::Rule, "loop", false))
+            .unwrap();
+        let (mut analysis, _) = analyze_message_with_trace("spam spam spam", "spam", cfg);
+        switches.apply_metrics(&mut analysis, &cfg);
+        assert_eq!(analysis.y_repetition, 0.0);
+        assert_eq!(analysis.score, 1.0);
+        assert!(!switches.rule_enabled("loop"));
+
+        switches
+            .set(change(SwitchKind::Rule, "loop", true))
+            .unwrap();
+        let reloaded = Switches::default().with_persistence(kv);
+        assert!(reloaded.rule_enabled("loop"));
+        let report = reloaded.report();
+        assert_eq!(report.disabled_metrics, ["repetition"]);
+        assert!(report.disabled_rules.is_empty());
+        assert_eq!(report.recent_changes.len(), 3);
+        assert!(report.recent_changes[0].enabled);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,315 @@
+//! Queryable store of recent analysis records, keyed by hex trace ID.
+//!
+//! The newest `capacity` records are kept in memory; with an embedded
+//! `KvStore` they are also written under `trace/<hex_id>` (hex IDs are
+//! fixed-width, so key order is time order) and reloaded on startup.
+//! Every record also feeds the hourly/daily rollups (see rollups.rs), is
+//! appended to the audit log (see audit.rs) and is published to the
+//! verdict sinks (see sinks.rs), stamped with the deployment labels (see
+//! labels.rs).
+
+use crate::audit::AuditLog;
+use crate::kv::KvStore;
+use crate::labels::Labels;
+use crate::precision::Precision;
+use crate::rollups::Rollups;
+use crate::sinks::Sinks;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+use word_math_guard::Verdict;
+
+const DEFAULT_CAPACITY: usize = 10_000;
+const KV_PREFIX: &str = "trace/";
+
+/// One scored message, as kept for auditing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceRecord {
+    pub hex_id: String,
+    pub y_repetition: f64,
+    pub z_drift: f64,
+    pub raw_score: f64,
+    pub score: f64,
+    pub verdict: Verdict,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Registry topic ID, when the message was scored against one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic_id: Option<String>,
+    /// IDs of the policy rules that matched.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rule_hits: Vec<String>,
+    /// Experiment and variant the message was enrolled in, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experiment: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    /// Deployment labels of the instance that scored the message.
+    #[serde(default, skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
+}
+
+impl TraceRecord {
+    /// Round the metrics to the configured output precision.
+    pub fn rounded(mut self, precision: Precision) -> Self {
+        for value in [
+            &mut self.y_repetition,
+            &mut self.z_drift,
+            &mut self.raw_score,
+            &mut self.score,
+        ] {
+            *value = precision.round(*value);
+        }
+        self
+    }
+}
+
+pub struct TraceStore {
+    capacity: usize,
+    kv: Option<Arc<KvStore>>,
+    records: Mutex<VecDeque<TraceRecord>>,
+    rollups: Rollups,
+    audit: AuditLog,
+    sinks: Sinks,
+    labels: Labels,
+}
+
+impl Default for TraceStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl TraceStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            kv: None,
+            records: Mutex::new(VecDeque::new()),
+            rollups: Rollups::default(),
+            audit: AuditLog::default(),
+            sinks: Sinks::default(),
+            labels: Labels::default(),
+        }
+    }
+
+    /// Capacity from WORD_MATH_TRACE_CAPACITY.
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("WORD_MATH_TRACE_CAPACITY")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_CAPACITY);
+        Self::new(capacity)
+    }
+
+    /// Persist records in `kv`, first loading the newest ones stored there.
+    pub fn with_persistence(mut self, kv: Arc<KvStore>) -> Self {
+        self.rollups.set_persistence(Arc::clone(&kv));
+        self.audit.set_persistence(Arc::clone(&kv));
+        self.kv = Some(kv);
+        self.reload();
+        // Stores written before the audit log existed start it from the
+        // records they still hold.
+        if self.audit.size() == 0 {
+            for record in self.lock().iter() {
+                self.audit.append(record);
+            }
+        }
+        self
+    }
+
+    /// Publish every new record to `sinks`.
+    pub fn with_sinks(mut self, sinks: Sinks) -> Self {
+        self.sinks = sinks;
+        self
+    }
+
+    pub fn sinks(&self) -> &Sinks {
+        &self.sinks
+    }
+
+    /// Stamp every new record with `labels`.
+    pub fn with_labels(mut self, labels: Labels) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn labels(&self) -> &Labels {
+        &self.labels
+    }
+
+    /// Replace the in-memory records (and rollups) with the persisted ones,
+    /// dropping records beyond capacity. Returns the number of records loaded.
+    pub fn reload(&self) -> usize {
+        let Some(kv) = &self.kv else { return 0 };
+        self.rollups.reload();
+        self.audit.reload();
+        let stored = kv.scan_prefix(KV_PREFIX);
+        let skip = stored.len().saturating_sub(self.capacity);
+        for (key, _) in stored.iter().take(skip) {
+            let _ = kv.delete(key);
+        }
+
+        let mut records = self.lock();
+        records.clear();
+        for (key, value) in stored.into_iter().skip(skip) {
+            match serde_json::from_str::<TraceRecord>(&value) {
+                Ok(record) => records.push_back(record),
+                Err(e) => warn!("skipping unreadable stored trace {}: {}", key, e),
+            }
+        }
+        records.len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<TraceRecord>> {
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn record(&self, mut record: TraceRecord) {
+        if !self.labels.is_empty() {
+            record.labels = self.labels.clone();
+        }
+        self.rollups.observe(&record);
+        self.sinks.publish(&record);
+        let mut records = self.lock();
+        self.audit.append(&record);
+        if let Some(kv) = &self.kv {
+            let result = serde_json::to_string(&record)
+                .map_err(std::io::Error::other)
+                .and_then(|json| kv.put(&format!("{}{}", KV_PREFIX, record.hex_id), json));
+            if let Err(e) = result {
+                warn!("persisting trace {} failed: {}", record.hex_id, e);
+            }
+        }
+        records.push_back(record);
+        while records.len() > self.capacity {
+            if let (Some(old), Some(kv)) = (records.pop_front(), &self.kv) {
+                let _ = kv.delete(&format!("{}{}", KV_PREFIX, old.hex_id));
+            }
+        }
+    }
+
+    pub fn get(&self, hex_id: &str) -> Option<TraceRecord> {
+        self.lock()
+            .iter()
+            .rev()
+            .find(|r| r.hex_id == hex_id)
+            .cloned()
+    }
+
+    /// Up to `limit` records accepted by `filter`, newest first.
+    pub fn recent_matching(
+        &self,
+        limit: usize,
+        filter: impl Fn(&TraceRecord) -> bool,
+    ) -> Vec<TraceRecord> {
+        self.lock()
+            .iter()
+            .rev()
+            .filter(|r| filter(r))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Every held record, oldest first.
+    pub fn all(&self) -> Vec<TraceRecord> {
+        self.lock().iter().cloned().collect()
+    }
+
+    pub fn rollups(&self) -> &Rollups {
+        &self.rollups
+    }
+
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use word_math_guard::generate_hex_id;
+
+    fn record(hex_id: &str) -> TraceRecord {
+        TraceRecord {
+            hex_id: hex_id.to_string(),
+            y_repetition: 0.2,
+            z_drift: 0.4,
+            raw_score: 0.7,
+            score: 0.7,
+            verdict: Verdict::Allow,
+            profile: None,
+            session_id: None,
+            topic_id: None,
+            rule_hits: Vec::new(),
+            experiment: None,
+            variant: None,
+            labels: Labels::default(),
+        }
+    }
+
+    #[test]
+    fn test_capacity_and_persistence() {
+        let dir = std::env::temp_dir().join(format!("wordmath-traces-{}", generate_hex_id()));
+        let kv = Arc::new(KvStore::open(&dir).unwrap());
+        let labels = Labels::parse("environment=staging").unwrap();
+        let store = TraceStore::new(2)
+            .with_persistence(Arc::clone(&kv))
+            .with_labels(labels.clone());
+        for id in ["0001", "0002", "0003"] {
+            store.record(record(id));
+        }
+        assert_eq!(store.len(), 2);
+        assert!(store.get("0001").is_none());
+        drop(store);
+
+        let reopened = TraceStore::new(10).with_persistence(kv);
+        let ids: Vec<String> = reopened
+            .recent_matching(10, |_| true)
+            .into_iter()
+            .map(|r| r.hex_id)
+            .collect();
+        assert_eq!(ids, vec!["0003", "0002"]);
+        assert_eq!(reopened.get("0003").unwrap().labels, labels);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rule_hits_filter_and_serde() {
+        let store = TraceStore::new(10);
+        for (id, hits) in [
+            ("0001", &["loop"][..]),
+            ("0002", &[]),
+            ("0003", &["loop", "padding"]),
+        ] {
+            let mut r = record(id);
+            r.rule_hits = hits.iter().map(|h| h.to_string()).collect();
+            store.record(r);
+        }
+        let ids = |rule: &str| -> Vec<String> {
+            store
+                .recent_matching(10, |r| r.rule_hits.iter().any(|h| h == rule))
+                .into_iter()
+                .map(|r| r.hex_id)
+                .collect()
+        };
+        assert_eq!(ids("loop"), ["0003", "0001"]);
+        assert_eq!(ids("padding"), ["0003"]);
+        assert_eq!(store.recent_matching(1, |_| true).len(), 1);
+
+        // Records without hits omit the field, and records written before
+        // it existed read back with none.
+        let json = serde_json::to_string(&record("0004")).unwrap();
+        assert!(!json.contains("rule_hits"));
+        let parsed: TraceRecord = serde_json::from_str(&json).unwrap();
+        assert!(parsed.rule_hits.is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+//! `wordmath experiments analyze`: offline comparison of experiment arms.
+//!
+//! Reads trace records as JSON lines (or `/traces` responses, one array per
+//! line), keeps those tagged with the requested experiment and compares
+//! every variant with the baseline: score distribution, verdict rates, and
+//! Welch / two-proportion z-tests with normal-approximation p-values.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use word_math_guard::batch::{percentile, VerdictCounts};
+use word_math_guard::Verdict;
+
+/// The fields of a server trace record this analysis needs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogRecord {
+    pub score: f64,
+    pub verdict: Verdict,
+    #[serde(default)]
+    pub experiment: Option<String>,
+    #[serde(default)]
+    pub variant: Option<String>,
+}
+
+/// Parse a JSON-lines log; blank lines are skipped.
+pub fn parse_log(log: &str) -> Result<Vec<LogRecord>, String> {
+    let mut records = Vec::new();
+    for (i, line) in log.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parsed = if line.starts_with('[') {
+            serde_json::from_str::<Vec<LogRecord>>(line)
+        } else {
+            serde_json::from_str::<LogRecord>(line).map(|record| vec![record])
+        };
+        records.extend(parsed.map_err(|e| format!("line {}: {}", i + 1, e))?);
+    }
+    Ok(records)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreDistribution {
+    pub mean: f64,
+    pub stddev: f64,
+    pub p10: f64,
+    pub p50: f64,
+    pub p90: f64,
+}
+
+/// A z-test result; `p_value` is two-sided.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ZTest {
+    pub z: f64,
+    pub p_value: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantDelta {
+    pub mean_delta: f64,
+    pub block_rate_delta: f64,
+    pub warn_rate_delta: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_test: Option<ZTest>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_rate_test: Option<ZTest>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantSummary {
+    pub id: String,
+    pub count: usize,
+    pub scores: ScoreDistribution,
+    pub verdicts: VerdictCounts,
+    pub block_rate: f64,
+    pub warn_rate: f64,
+    /// Absent for the baseline.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vs_baseline: Option<VariantDelta>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExperimentAnalysis {
+    pub experiment: String,
+    pub baseline: String,
+    pub records: usize,
+    pub variants: Vec<VariantSummary>,
+}
+
+struct Arm {
+    scores: Vec<f64>,
+    verdicts: VerdictCounts,
+}
+
+impl Arm {
+    fn n(&self) -> f64 {
+        self.scores.len() as f64
+    }
+
+    fn mean(&self) -> f64 {
+        self.scores.iter().sum::<f64>() / self.n()
+    }
+
+    fn variance(&self) -> f64 {
+        if self.scores.len() < 2 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        self.scores.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (self.n() - 1.0)
+    }
+
+    fn rate(&self, count: usize) -> f64 {
+        count as f64 / self.n()
+    }
+}
+
+/// Complementary error function (Abramowitz & Stegun 7.1.26, |error| < 1.5e-7).
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erfc = poly * (-x * x).exp();
+    if x >= 0.0 {
+        erfc
+    } else {
+        2.0 - erfc
+    }
+}
+
+fn z_test(delta: f64, se: f64) -> Option<ZTest> {
+    (se.is_finite() && se > 0.0).then(|| {
+        let z = delta / se;
+        ZTest {
+            z,
+            p_value: erfc(z.abs() / std::f64::consts::SQRT_2),
+        }
+    })
+}
+
+fn delta(arm: &Arm, base: &Arm) -> VariantDelta {
+    let mean_se = (arm.variance() / arm.n() + base.variance() / base.n()).sqrt();
+    let pooled = (arm.verdicts.block + base.verdicts.block) as f64 / (arm.n() + base.n());
+    let rate_se = (pooled * (1.0 - pooled) * (1.0 / arm.n() + 1.0 / base.n())).sqrt();
+    let mean_delta = arm.mean() - base.mean();
+    let block_rate_delta = arm.rate(arm.verdicts.block) - base.rate(base.verdicts.block);
+    VariantDelta {
+        mean_delta,
+        block_rate_delta,
+        warn_rate_delta: arm.rate(arm.verdicts.warn) - base.rate(base.verdicts.warn),
+        mean_test: z_test(mean_delta, mean_se),
+        block_rate_test: z_test(block_rate_delta, rate_se),
+    }
+}
+
+/// Compare the variants of `experiment`. The baseline is `baseline` when
+/// given, else a variant named "control", else the first variant by name.
+pub fn analyze(
+    records: &[LogRecord],
+    experiment: &str,
+    baseline: Option<&str>,
+) -> Result<ExperimentAnalysis, String> {
+    let mut arms: BTreeMap<&str, Arm> = BTreeMap::new();
+    for record in records {
+        if record.experiment.as_deref() != Some(experiment) {
+            continue;
+        }
+        let Some(variant) = record.variant.as_deref() else {
+            continue;
+        };
+        let arm = arms.entry(variant).or_insert_with(|| Arm {
+            scores: Vec::new(),
+            verdicts: VerdictCounts::default(),
+        });
+        arm.scores.push(record.score);
+        arm.verdicts.record(record.verdict);
+    }
+    if arms.is_empty() {
+        return Err(format!("no records for experiment {}", experiment));
+    }
+
+    let baseline = match baseline {
+        Some(id) if arms.contains_key(id) => id,
+        Some(id) => return Err(format!("no records for baseline variant {}", id)),
+        None if arms.contains_key("control") => "control",
+        None => arms.keys().next().copied().unwrap_or_default(),
+    };
+    let base = &arms[baseline];
+    let mut variants: Vec<VariantSummary> = arms
+        .iter()
+        .map(|(&id, arm)| {
+            let mut sorted = arm.scores.clone();
+            sorted.sort_by(f64::total_cmp);
+            VariantSummary {
+                id: id.to_string(),
+                count: arm.scores.len(),
+                scores: ScoreDistribution {
+                    mean: arm.mean(),
+                    stddev: arm.variance().sqrt(),
+                    p10: percentile(&sorted, 10.0),
+                    p50: percentile(&sorted, 50.0),
+                    p90: percentile(&sorted, 90.0),
+                },
+                verdicts: arm.verdicts,
+                block_rate: arm.rate(arm.verdicts.block),
+                warn_rate: arm.rate(arm.verdicts.warn),
+                vs_baseline: (id != baseline).then(|| delta(arm, base)),
+            }
+        })
+        .collect();
+    // Baseline first, then the other variants by name.
+    variants.sort_by_key(|v| v.id != baseline);
+
+    Ok(ExperimentAnalysis {
+        experiment: experiment.to_string(),
+        baseline: baseline.to_string(),
+        records: variants.iter().map(|v| v.count).sum(),
+        variants,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_erfc_matches_known_values() {
+        assert!((erfc(0.0) - 1.0).abs() < 1e-6);
+        // Two-sided p-value at z = 1.96 is 0.05.
+        assert!((erfc(1.96 / std::f64::consts::SQRT_2) - 0.05).abs() < 1e-4);
+        assert!((erfc(-1.0) - 1.842_700_79).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_analyze_compares_variants_with_control() {
+        let mut log = String::new();
+        for i in 0..50 {
+            let jitter = (i % 5) as f64 * 0.01;
+            log.push_str(&format!(
+                "{{\"score\":{},\"verdict\":\"{}\",\"experiment\":\"exp-42\",\"variant\":\"control\"}}\n",
+                0.3 + jitter,
+                if i % 2 == 0 { "block" } else { "warn" }
+            ));
+            log.push_str(&format!(
+                "{{\"score\":{},\"verdict\":\"allow\",\"experiment\":\"exp-42\",\"variant\":\"lenient\"}}\n",
+                0.8 + jitter
+            ));
+        }
+        log.push_str("{\"score\":1.0,\"verdict\":\"allow\"}\n\n");
+        log.push_str(
+            "[{\"score\":0.1,\"verdict\":\"block\",\"experiment\":\"exp-7\",\"variant\":\"a\"}]\n",
+        );
+
+        let records = parse_log(&log).unwrap();
+        assert_eq!(records.len(), 102);
+        let analysis = analyze(&records, "exp-42", None).unwrap();
+        assert_eq!(analysis.baseline, "control");
+        assert_eq!(analysis.records, 100);
+        let [control, lenient] = &analysis.variants[..] else {
+            panic!("expected two variants");
+        };
+        assert_eq!(control.id, "control");
+        assert_eq!(control.block_rate, 0.5);
+        let delta = lenient.vs_baseline.as_ref().unwrap();
+        assert!((delta.mean_delta - 0.5).abs() < 1e-9);
+        assert_eq!(delta.block_rate_delta, -0.5);
+        assert!(delta.mean_test.unwrap().p_value < 1e-6);
+        assert!(delta.block_rate_test.unwrap().p_value < 0.001);
+
+        assert!(analyze(&records, "exp-9", None).is_err());
+        assert!(analyze(&records, "exp-42", Some("missing")).is_err());
+        assert!(parse_log("not json").is_err());
+    }
+}
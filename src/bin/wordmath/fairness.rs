@@ -0,0 +1,186 @@
+//! `wordmath eval`: score distributions per language and writing style.
+//!
+//! Runs the scorer over a labelled set of benign, on-topic messages (by
+//! default the bundled `fairness.jsonl`: the same support request in 15
+//! languages plus formal, slang, code-mixed and terse English) and
+//! summarizes every group. Since every message is on topic, the groups
+//! should score alike; a group whose mean score, repetition, drift or
+//! non-allow rate sits more than `max_gap` away from the median over all
+//! groups is flagged, e.g. a script the tokenizer splits badly.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use word_math_guard::batch::{percentile, VerdictCounts};
+use word_math_guard::{analyze_message_with_trace, verdict, WordMathConfig};
+
+/// The bundled evaluation set.
+pub const BUNDLED: &str = include_str!("fairness.jsonl");
+
+/// Flag threshold used when `--max-gap` is not given.
+pub const DEFAULT_MAX_GAP: f64 = 0.2;
+
+/// One labelled message; `group` is e.g. "lang:de" or "style:slang".
+#[derive(Debug, Clone, Deserialize)]
+pub struct EvalCase {
+    pub group: String,
+    pub topic: String,
+    pub message: String,
+}
+
+/// Parse a JSON-lines evaluation set; blank lines are skipped.
+pub fn parse_set(set: &str) -> Result<Vec<EvalCase>, String> {
+    let mut cases = Vec::new();
+    for (i, line) in set.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        cases.push(serde_json::from_str(line).map_err(|e| format!("line {}: {}", i + 1, e))?);
+    }
+    Ok(cases)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupSummary {
+    pub group: String,
+    pub count: usize,
+    pub mean_score: f64,
+    pub p10: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub mean_y: f64,
+    pub mean_z: f64,
+    pub verdicts: VerdictCounts,
+    /// Share of warn and block verdicts.
+    pub flag_rate: f64,
+}
+
+impl GroupSummary {
+    /// The metrics compared across groups.
+    const METRICS: [&'static str; 4] = ["score", "y", "z", "flag_rate"];
+
+    fn metric(&self, name: &str) -> f64 {
+        match name {
+            "score" => self.mean_score,
+            "y" => self.mean_y,
+            "z" => self.mean_z,
+            _ => self.flag_rate,
+        }
+    }
+}
+
+/// A group metric too far from the median over all groups.
+#[derive(Debug, Clone, Serialize)]
+pub struct Divergence {
+    pub group: String,
+    pub metric: &'static str,
+    pub value: f64,
+    pub median: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalReport {
+    pub cases: usize,
+    pub max_gap: f64,
+    pub groups: Vec<GroupSummary>,
+    pub divergent: Vec<Divergence>,
+}
+
+#[derive(Default)]
+struct Group {
+    scores: Vec<f64>,
+    y: f64,
+    z: f64,
+    verdicts: VerdictCounts,
+}
+
+/// Score every case with `cfg` and compare the groups.
+pub fn evaluate(cases: &[EvalCase], cfg: WordMathConfig, max_gap: f64) -> EvalReport {
+    let mut groups: BTreeMap<&str, Group> = BTreeMap::new();
+    for case in cases {
+        let (analysis, _) = analyze_message_with_trace(&case.message, &case.topic, cfg);
+        let group = groups.entry(&case.group).or_default();
+        group.scores.push(analysis.score);
+        group.y += analysis.y_repetition;
+        group.z += analysis.z_drift;
+        group
+            .verdicts
+            .record(verdict::evaluate(&analysis, &cfg).verdict);
+    }
+
+    let groups: Vec<GroupSummary> = groups
+        .into_iter()
+        .map(|(id, mut group)| {
+            let n = group.scores.len() as f64;
+            group.scores.sort_by(f64::total_cmp);
+            GroupSummary {
+                group: id.to_string(),
+                count: group.scores.len(),
+                mean_score: group.scores.iter().sum::<f64>() / n,
+                p10: percentile(&group.scores, 10.0),
+                p50: percentile(&group.scores, 50.0),
+                p90: percentile(&group.scores, 90.0),
+                mean_y: group.y / n,
+                mean_z: group.z / n,
+                flag_rate: (group.verdicts.warn + group.verdicts.block) as f64 / n,
+                verdicts: group.verdicts,
+            }
+        })
+        .collect();
+
+    let mut divergent = Vec::new();
+    for metric in GroupSummary::METRICS {
+        let mut values: Vec<f64> = groups.iter().map(|g| g.metric(metric)).collect();
+        values.sort_by(f64::total_cmp);
+        let median = percentile(&values, 50.0);
+        for group in &groups {
+            let value = group.metric(metric);
+            if (value - median).abs() > max_gap {
+                divergent.push(Divergence {
+                    group: group.group.clone(),
+                    metric,
+                    value,
+                    median,
+                });
+            }
+        }
+    }
+
+    EvalReport {
+        cases: cases.len(),
+        max_gap,
+        groups,
+        divergent,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outlier_group_is_flagged() {
+        let case = |group: &str, message: &str| EvalCase {
+            group: group.to_string(),
+            topic: "rust web server".to_string(),
+            message: message.to_string(),
+        };
+        let cases = vec![
+            case("a", "rust web server"),
+            case("b", "web server, rust"),
+            case("c", "server: rust web"),
+            case("d", "buy buy buy cheap pills buy buy"),
+        ];
+        let report = evaluate(&cases, WordMathConfig::default(), DEFAULT_MAX_GAP);
+        assert_eq!(report.groups.len(), 4);
+        assert!(
+            report.divergent.iter().all(|d| d.group == "d"),
+            "{:?}",
+            report.divergent
+        );
+        assert!(report.divergent.iter().any(|d| d.metric == "score"));
+
+        let bundled = parse_set(BUNDLED).unwrap();
+        assert!(bundled.iter().any(|c| c.group == "style:code-mixed"));
+    }
+}
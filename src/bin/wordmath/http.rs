@@ -0,0 +1,111 @@
+//! Minimal blocking HTTP/1.1 client for talking to a running server.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use word_math_guard::secret;
+
+/// Parse `http://host[:port]`; plain HTTP only.
+pub fn parse_target(url: &str) -> Result<(String, u16), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("--url must start with http://: {}", url))?;
+    let authority = rest.split('/').next().unwrap_or(rest);
+    match authority.rsplit_once(':') {
+        Some((host, port)) => port
+            .parse::<u16>()
+            .map(|port| (host.to_string(), port))
+            .map_err(|e| format!("--url port: {}", e)),
+        None => Ok((authority.to_string(), 80)),
+    }
+}
+
+pub fn connect(host: &str, port: u16) -> io::Result<BufReader<TcpStream>> {
+    let stream = TcpStream::connect((host, port))?;
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    stream.set_nodelay(true)?;
+    Ok(BufReader::new(stream))
+}
+
+/// Send one keep-alive request and return (status, body). WORD_MATH_API_KEY
+/// (or WORD_MATH_API_KEY_FILE), when set, is sent as the server's `x-api-key`.
+pub fn request(
+    stream: &mut BufReader<TcpStream>,
+    host: &str,
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> io::Result<(u16, Vec<u8>)> {
+    let api_key = secret::from_env("WORD_MATH_API_KEY")
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?
+        .map(|key| format!("x-api-key: {}\r\n", key.expose()))
+        .unwrap_or_default();
+    let head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: keep-alive\r\n{}Content-Length: {}\r\n\r\n",
+        method,
+        path,
+        host,
+        api_key,
+        body.len()
+    );
+    let writer = stream.get_mut();
+    writer.write_all(head.as_bytes())?;
+    writer.write_all(body)?;
+
+    let mut status_line = String::new();
+    stream.read_line(&mut status_line)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| io::Error::other(format!("bad status line: {:?}", status_line)))?;
+
+    let mut content_length = 0usize;
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line)? == 0 || line == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut response = vec![0; content_length];
+    stream.read_exact(&mut response)?;
+    Ok((status, response))
+}
+
+/// One request on a fresh connection; non-2xx statuses become errors.
+pub fn call(url: &str, method: &str, path: &str, body: &[u8]) -> Result<Vec<u8>, String> {
+    let (host, port) = parse_target(url)?;
+    let mut stream = connect(&host, port).map_err(|e| format!("{}: {}", url, e))?;
+    let (status, response) =
+        request(&mut stream, &host, method, path, body).map_err(|e| e.to_string())?;
+    if !(200..300).contains(&status) {
+        return Err(format!(
+            "{} {} returned {}: {}",
+            method,
+            path,
+            status,
+            String::from_utf8_lossy(&response)
+        ));
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(
+            parse_target("http://localhost:3000/"),
+            Ok(("localhost".to_string(), 3000))
+        );
+        assert!(parse_target("https://example.com").is_err());
+    }
+}
@@ -0,0 +1,252 @@
+//! `wordmath loadtest`: synthetic traffic against a running server.
+//!
+//! Messages are generated to order: an on-topic head, a share of off-topic
+//! words (`--drift`) and a looping tail (`--repetition`), which is the kind
+//! of degenerate text generic HTTP load tools cannot produce.
+
+use crate::http;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use word_math_guard::batch::{percentile, VerdictCounts};
+use word_math_guard::{text, EmojiMode, Verdict};
+
+const OFF_TOPIC: &[&str] = &[
+    "weather",
+    "recipe",
+    "football",
+    "holiday",
+    "guitar",
+    "garden",
+    "movie",
+    "coffee",
+    "mountain",
+    "painting",
+    "lottery",
+    "discount",
+    "crypto",
+    "horoscope",
+    "celebrity",
+    "vacation",
+];
+const FILLER: &[&str] = &["the", "a", "with", "and", "for", "about", "using", "in"];
+const LOOP_PHRASES: &[&[&str]] = &[
+    &["i", "hope", "this", "helps"],
+    &["buy", "now"],
+    &["let", "me", "know"],
+    &["again"],
+];
+
+/// Load-test settings from the command line.
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+    pub host: String,
+    pub port: u16,
+    pub topic: String,
+    pub requests: usize,
+    pub concurrency: usize,
+    pub words: usize,
+    /// Share of each message taken up by the looping tail.
+    pub repetition: f64,
+    /// Share of the non-looping words drawn from off-topic vocabulary.
+    pub drift: f64,
+    pub seed: u64,
+}
+
+/// xorshift64*: deterministic per seed, good enough for picking words.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.next_u64() as usize % items.len()]
+    }
+}
+
+/// One synthetic message with the configured size and characteristics.
+pub fn generate_message(rng: &mut Rng, topic_words: &[String], cfg: &LoadConfig) -> String {
+    let tail = (cfg.words as f64 * cfg.repetition.clamp(0.0, 1.0)).round() as usize;
+    let head = cfg.words.saturating_sub(tail);
+    let mut words: Vec<&str> = Vec::with_capacity(cfg.words);
+
+    for _ in 0..head {
+        let word = if rng.unit() < cfg.drift {
+            rng.pick(OFF_TOPIC)
+        } else if topic_words.is_empty() || rng.unit() < 0.3 {
+            rng.pick(FILLER)
+        } else {
+            rng.pick(topic_words).as_str()
+        };
+        words.push(word);
+    }
+    let phrase = rng.pick(LOOP_PHRASES);
+    words.extend(phrase.iter().cycle().take(tail));
+    words.join(" ")
+}
+
+pub fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() * 3);
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+fn parse_verdict(body: &str) -> Option<Verdict> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    serde_json::from_value(value.get("verdict")?.clone()).ok()
+}
+
+#[derive(Default)]
+struct Tally {
+    latencies_ms: Vec<f64>,
+    errors: usize,
+    verdicts: VerdictCounts,
+}
+
+/// Aggregated load-test results.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LoadReport {
+    pub requests: usize,
+    pub errors: usize,
+    pub elapsed_secs: f64,
+    pub throughput_rps: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub verdicts: VerdictCounts,
+}
+
+pub fn run(cfg: LoadConfig) -> LoadReport {
+    let topic_words = text::tokenize(&cfg.topic, EmojiMode::Strip);
+    let next = Arc::new(AtomicUsize::new(0));
+    let tally = Arc::new(Mutex::new(Tally::default()));
+    let started = Instant::now();
+
+    let workers: Vec<_> = (0..cfg.concurrency.max(1))
+        .map(|worker| {
+            let cfg = cfg.clone();
+            let topic_words = topic_words.clone();
+            let next = Arc::clone(&next);
+            let tally = Arc::clone(&tally);
+            std::thread::spawn(move || {
+                let mut rng = Rng::new(cfg.seed.wrapping_add(worker as u64 * 0x9e37_79b9));
+                let mut local = Tally::default();
+                let mut conn = None;
+                while next.fetch_add(1, Ordering::Relaxed) < cfg.requests {
+                    let message = generate_message(&mut rng, &topic_words, &cfg);
+                    let path = format!(
+                        "/analyze?message={}&topic={}",
+                        percent_encode(&message),
+                        percent_encode(&cfg.topic)
+                    );
+                    let sent = Instant::now();
+                    let result = match conn.as_mut() {
+                        Some(stream) => http::request(stream, &cfg.host, "GET", &path, &[]),
+                        None => http::connect(&cfg.host, cfg.port).and_then(|mut stream| {
+                            let result = http::request(&mut stream, &cfg.host, "GET", &path, &[]);
+                            conn = Some(stream);
+                            result
+                        }),
+                    };
+                    match result {
+                        Ok((200, body)) => {
+                            let body = String::from_utf8_lossy(&body);
+                            local
+                                .latencies_ms
+                                .push(sent.elapsed().as_secs_f64() * 1000.0);
+                            if let Some(verdict) = parse_verdict(&body) {
+                                local.verdicts.record(verdict);
+                            }
+                        }
+                        Ok(_) => local.errors += 1,
+                        Err(_) => {
+                            local.errors += 1;
+                            conn = None;
+                        }
+                    }
+                }
+                let mut tally = tally.lock().unwrap_or_else(|e| e.into_inner());
+                tally.latencies_ms.append(&mut local.latencies_ms);
+                tally.errors += local.errors;
+                tally.verdicts.allow += local.verdicts.allow;
+                tally.verdicts.warn += local.verdicts.warn;
+                tally.verdicts.block += local.verdicts.block;
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    let elapsed = started.elapsed().as_secs_f64();
+    let mut tally = std::mem::take(&mut *tally.lock().unwrap_or_else(|e| e.into_inner()));
+    tally.latencies_ms.sort_by(f64::total_cmp);
+    let done = tally.latencies_ms.len();
+    LoadReport {
+        requests: done + tally.errors,
+        errors: tally.errors,
+        elapsed_secs: elapsed,
+        throughput_rps: if elapsed > 0.0 {
+            done as f64 / elapsed
+        } else {
+            0.0
+        },
+        p50_ms: percentile(&tally.latencies_ms, 50.0),
+        p90_ms: percentile(&tally.latencies_ms, 90.0),
+        p99_ms: percentile(&tally.latencies_ms, 99.0),
+        max_ms: tally.latencies_ms.last().copied().unwrap_or(0.0),
+        verdicts: tally.verdicts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use word_math_guard::find_degeneration_onset;
+
+    fn cfg(repetition: f64, drift: f64) -> LoadConfig {
+        LoadConfig {
+            host: "127.0.0.1".to_string(),
+            port: 3000,
+            topic: "rust web server".to_string(),
+            requests: 1,
+            concurrency: 1,
+            words: 40,
+            repetition,
+            drift,
+            seed: 7,
+        }
+    }
+
+    #[test]
+    fn test_generated_messages_follow_characteristics() {
+        let topic = text::tokenize("rust web server", EmojiMode::Strip);
+        let mut rng = Rng::new(7);
+        let clean = generate_message(&mut rng, &topic, &cfg(0.0, 0.0));
+        let looping = generate_message(&mut rng, &topic, &cfg(0.8, 0.0));
+        assert_eq!(clean.split(' ').count(), 40);
+        let onset = find_degeneration_onset(&looping).expect("looping tail");
+        assert_eq!(looping[..onset].split_whitespace().count(), 8);
+    }
+}
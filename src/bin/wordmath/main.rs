@@ -0,0 +1,741 @@
+//! Command-line front end for the Word-Math guard.
+//!
+//! ```text
+//! wordmath analyze --topic "<topic>" [--profile NAME] [--highlight] [--matrix] [--format json] [MESSAGE]
+//! wordmath session --topic "<topic>" [--profile NAME] [--smoothing 0.3] [--format json]
+//! wordmath loadtest --topic "<topic>" [--url http://127.0.0.1:3000] [--requests N] [--concurrency C]
+//! wordmath admin backup --out snapshot.jsonl [--url URL]
+//! wordmath admin restore --in snapshot.jsonl [--url URL]
+//! wordmath admin reencrypt [--url URL]
+//! wordmath topic build docs/*.md --out topic.json [--id ID] [--keywords N]
+//! wordmath experiments analyze --log traces.jsonl --experiment ID [--baseline VARIANT]
+//! wordmath eval [--set cases.jsonl] [--profile NAME] [--max-gap 0.2]
+//! wordmath soak [--hours 24] [--check-secs 60] [--report report.json]
+//! ```
+//!
+//! Exit codes are stable so the tool can gate CI jobs: 0 = Allow,
+//! 3 = Warn, 4 = Block, 5 = divergent eval groups, 2 = usage or runtime
+//! error.
+
+mod experiments;
+mod fairness;
+mod http;
+mod loadtest;
+mod soak;
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::process::ExitCode;
+use word_math_guard::{
+    analyze_message_with_trace, compare, text, topic, verdict, ConversationAnalyzer, ProfileSet,
+    TopicDefinition, Verdict, WordMathConfig,
+};
+
+const USAGE: &str = "usage:
+  wordmath analyze --topic TOPIC [--profile NAME] [--highlight] [--matrix] [--format text|json] [MESSAGE]
+      Score one message (read from stdin when MESSAGE is omitted).
+      --highlight colorizes repeated words and off-topic words.
+      --matrix adds the sentence-by-sentence similarity matrix.
+  wordmath session --topic TOPIC [--profile NAME] [--smoothing ALPHA] [--format text|json]
+      Read one message per line from stdin and print the rolling session score.
+  wordmath loadtest --topic TOPIC [--url URL] [--requests N] [--concurrency C] [--words W]
+                    [--repetition R] [--drift D] [--seed S] [--format text|json]
+      Send synthetic /analyze traffic to a running server and report throughput
+      and latency percentiles. R and D (0..1) set the looping-tail share and the
+      off-topic word share of each generated message.
+  wordmath admin backup --out FILE [--url URL]
+  wordmath admin restore --in FILE [--url URL]
+      Snapshot or replace a server's persisted sessions and traces
+      (needs WORD_MATH_DATA_DIR on the server). A backup is an uncompressed
+      JSON-lines file: a {\"format\":\"wordmath-backup\",\"version\":1,...}
+      header, then one {\"k\":KEY,\"v\":VALUE} record per entry. Archive
+      names (.tar, .zst, .gz, ...) are refused for FILE.
+  wordmath admin reencrypt [--url URL]
+      Re-seal all persisted values under the server's active key after
+      rotating WORD_MATH_AUDIT_KEYS.
+  wordmath topic build FILE... --out FILE [--id ID] [--keywords N]
+      Extract weighted keywords from reference documents into a topic
+      definition; the output is a WORD_MATH_TOPICS registry (id defaults to
+      the output file's stem) and can be merged into an existing one.
+  wordmath experiments analyze --log FILE --experiment ID [--baseline VARIANT]
+                               [--format text|json]
+      Compare the variants of an A/B experiment from a log of trace records
+      (JSON lines, e.g. from GET /traces): score distributions, verdict
+      deltas and z-tests against the baseline (default: \"control\").
+  wordmath eval [--set FILE] [--profile NAME] [--max-gap G] [--format text|json]
+      Score a labelled set of on-topic messages (JSON lines with group, topic
+      and message; default: the bundled language and style set) and report
+      score distributions per group. Exits with 5 when a group's mean score,
+      y, z or flag rate differs from the median group by more than G
+      (default 0.2).
+  wordmath soak [--hours H] [--check-secs N] [--concurrency C] [--max-rss-mb M]
+                [--topic TOPIC] [--words W] [--seed S] [--server PATH]
+                [--report FILE] [--format text|json]
+      Run an embedded server (the server binary next to this one, or PATH)
+      under synthetic traffic for H hours (default 24), checking every N
+      seconds (default 60) that it is alive, stays under M MiB resident
+      (default 512), serves valid Merkle proofs, keeps scores in [0, 1] and
+      a canary message's score stable, and fails no request. Stops at the
+      first failed check; the JSON report is also written to FILE.
+
+Requests to a server send WORD_MATH_API_KEY (or the contents of the file in
+WORD_MATH_API_KEY_FILE), when set, as the x-api-key header.
+
+exit codes:
+  0  allow (or no verdict)
+  2  usage or runtime error
+  3  warn
+  4  block (session: final session verdict)
+  5  eval: divergent groups";
+
+const EXIT_ERROR: u8 = 2;
+const EXIT_WARN: u8 = 3;
+const EXIT_BLOCK: u8 = 4;
+const EXIT_DIVERGENT: u8 = 5;
+
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_REPEATED: &str = "\x1b[1;31m";
+const ANSI_OFF_TOPIC: &str = "\x1b[33m";
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(EXIT_ERROR);
+    }
+
+    let command = args.remove(0);
+    let verdict_code = |verdict: Option<Verdict>| verdict.map_or(0, exit_code);
+    let result = match command.as_str() {
+        "analyze" => run_analyze(args).map(verdict_code),
+        "session" => run_session(args).map(verdict_code),
+        "loadtest" => run_loadtest(args).map(verdict_code),
+        "admin" => run_admin(args).map(verdict_code),
+        "topic" => run_topic(args).map(verdict_code),
+        "experiments" => run_experiments(args).map(verdict_code),
+        "eval" => run_eval(args),
+        "soak" => run_soak(args).map(verdict_code),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(0)
+        }
+        other => Err(format!("unknown command: {}\n{}", other, USAGE)),
+    };
+
+    match result {
+        Ok(code) => ExitCode::from(code),
+        Err(msg) => {
+            eprintln!("wordmath: {}", msg);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// Documented process exit code for a verdict.
+fn exit_code(verdict: Verdict) -> u8 {
+    match verdict {
+        Verdict::Allow => 0,
+        Verdict::Warn => EXIT_WARN,
+        Verdict::Block => EXIT_BLOCK,
+    }
+}
+
+/// Output style selected with `--format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+fn take_format(args: &mut Vec<String>) -> Result<OutputFormat, String> {
+    match take_opt(args, "format")?.as_deref() {
+        None | Some("text") => Ok(OutputFormat::Text),
+        Some("json") => Ok(OutputFormat::Json),
+        Some(other) => Err(format!(
+            "unknown --format: {} (expected text or json)",
+            other
+        )),
+    }
+}
+
+/// Remove `--name VALUE` or `--name=VALUE` from `args` and return the value.
+fn take_opt(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let flag = format!("--{}", name);
+    let prefix = format!("--{}=", name);
+    for i in 0..args.len() {
+        if args[i] == flag {
+            if i + 1 >= args.len() {
+                return Err(format!("{} needs a value", flag));
+            }
+            let value = args.remove(i + 1);
+            args.remove(i);
+            return Ok(Some(value));
+        }
+        if let Some(value) = args[i].strip_prefix(&prefix) {
+            let value = value.to_string();
+            args.remove(i);
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+/// Remove a boolean `--name` switch from `args`.
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let flag = format!("--{}", name);
+    match args.iter().position(|arg| *arg == flag) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Fail on any argument that no option consumed.
+/// The archive or compression extension `path` ends in, if any; backups
+/// are written uncompressed and such a name would mislabel them.
+fn archive_extension(path: &str) -> Option<&'static str> {
+    let name = path.to_ascii_lowercase();
+    ["tar.zst", "tar.gz", "tgz", "tar", "zst", "gz", "zip"]
+        .into_iter()
+        .find(|ext| name.ends_with(&format!(".{}", ext)))
+}
+
+fn reject_leftovers(args: &[String]) -> Result<(), String> {
+    match args.first() {
+        Some(arg) => Err(format!("unexpected argument: {}\n{}", arg, USAGE)),
+        None => Ok(()),
+    }
+}
+
+/// Env config, or the named profile from WORD_MATH_PROFILES.
+fn resolve_config(profile: Option<&str>) -> Result<WordMathConfig, String> {
+    match profile {
+        None => Ok(WordMathConfig::from_env()),
+        Some(name) => ProfileSet::from_env()
+            .map_err(|e| e.to_string())?
+            .get(name)
+            .ok_or_else(|| format!("unknown profile: {}", name)),
+    }
+}
+
+fn run_analyze(mut args: Vec<String>) -> Result<Option<Verdict>, String> {
+    let topic = take_opt(&mut args, "topic")?.ok_or("analyze needs --topic")?;
+    let profile = take_opt(&mut args, "profile")?;
+    let format = take_format(&mut args)?;
+    let highlight = take_flag(&mut args, "highlight");
+    let matrix = take_flag(&mut args, "matrix");
+    if let Some(arg) = args.iter().find(|arg| arg.starts_with("--")) {
+        return Err(format!("unexpected argument: {}\n{}", arg, USAGE));
+    }
+
+    let message = if args.is_empty() || args == ["-"] {
+        let mut buf = String::new();
+        io::stdin()
+            .read_to_string(&mut buf)
+            .map_err(|e| e.to_string())?;
+        buf
+    } else {
+        args.join(" ")
+    };
+
+    let cfg = resolve_config(profile.as_deref())?;
+    let (analysis, trace) = analyze_message_with_trace(&message, &topic, cfg);
+    let explanation = verdict::evaluate(&analysis, &cfg);
+    let matrix = matrix
+        .then(|| compare::message_similarity_matrix(&message, compare::DEFAULT_MATRIX_DIM, &cfg));
+
+    if format == OutputFormat::Json {
+        let mut record = serde_json::json!({
+            "verdict": explanation.verdict,
+            "exit_code": exit_code(explanation.verdict),
+            "score": analysis.score,
+            "raw_score": trace.raw_score,
+            "y_repetition": analysis.y_repetition,
+            "z_drift": analysis.z_drift,
+            "evasion_risk": analysis.evasion_risk,
+            "hex_id": trace.hex_id,
+        });
+        if !explanation.fired.is_empty() {
+            record["reason"] = explanation.summary().into();
+        }
+        if highlight {
+            record["highlight"] = render_highlight(&message, &topic, &cfg, false).into();
+        }
+        if let Some(matrix) = &matrix {
+            record["similarity_matrix"] =
+                serde_json::to_value(matrix).map_err(|e| e.to_string())?;
+        }
+        println!("{}", record);
+        return Ok(Some(explanation.verdict));
+    }
+
+    println!(
+        "score={:.4} verdict={} y={:.4} z={:.4} hex={}",
+        analysis.score,
+        explanation.verdict.as_str(),
+        analysis.y_repetition,
+        analysis.z_drift,
+        trace.hex_id
+    );
+    if !explanation.fired.is_empty() {
+        println!("reason: {}", explanation.summary());
+    }
+    if analysis.evasion_risk > 0.0 {
+        println!(
+            "evasion_risk={:.4} homoglyphs={} split_letters={} invisible_stripped={}",
+            analysis.evasion_risk,
+            analysis.homoglyphs,
+            analysis.split_letters,
+            analysis.invisible_stripped
+        );
+    }
+    if highlight {
+        let color = io::stdout().is_terminal() || std::env::var_os("FORCE_COLOR").is_some();
+        println!("{}", render_highlight(&message, &topic, &cfg, color));
+        if color {
+            println!(
+                "legend: {}repeated{} {}off-topic{}",
+                ANSI_REPEATED, ANSI_RESET, ANSI_OFF_TOPIC, ANSI_RESET
+            );
+        }
+    }
+    if let Some(matrix) = &matrix {
+        println!(
+            "similarity ({} sentence(s), {}x{}):",
+            matrix.units, matrix.size, matrix.size
+        );
+        for row in matrix.values.chunks(matrix.size.max(1)) {
+            let cells: Vec<String> = row.iter().map(|v| format!("{:.2}", v)).collect();
+            println!("  {}", cells.join(" "));
+        }
+    }
+
+    Ok(Some(explanation.verdict))
+}
+
+/// The (normalized) message with repeated tokens and tokens absent from
+/// the topic wrapped in ANSI colors. Without color, marks them as
+/// `[[repeated]]` and `<off-topic>` instead.
+fn render_highlight(message: &str, topic: &str, cfg: &WordMathConfig, color: bool) -> String {
+    let message = if cfg.normalize {
+        text::normalize(message).text
+    } else {
+        message.to_string()
+    };
+    let spans = text::token_spans(&message, cfg.emoji_mode);
+    let topic_tokens: HashSet<String> = text::tokenize(topic, cfg.emoji_mode).into_iter().collect();
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for span in &spans {
+        *counts.entry(span.token.as_str()).or_insert(0) += 1;
+    }
+
+    let mut out = String::with_capacity(message.len() * 2);
+    let mut cursor = 0;
+    for span in &spans {
+        out.push_str(&message[cursor..span.start]);
+        let word = &message[span.start..span.end];
+        let repeated = counts[span.token.as_str()] > 1;
+        let off_topic = !topic_tokens.contains(&span.token);
+        match (repeated, off_topic, color) {
+            (true, _, true) => out.push_str(&format!("{}{}{}", ANSI_REPEATED, word, ANSI_RESET)),
+            (false, true, true) => {
+                out.push_str(&format!("{}{}{}", ANSI_OFF_TOPIC, word, ANSI_RESET))
+            }
+            (true, _, false) => out.push_str(&format!("[[{}]]", word)),
+            (false, true, false) => out.push_str(&format!("<{}>", word)),
+            (false, false, _) => out.push_str(word),
+        }
+        cursor = span.end;
+    }
+    out.push_str(&message[cursor..]);
+    out
+}
+
+/// Parse an optional numeric option.
+fn take_num<T: std::str::FromStr>(args: &mut Vec<String>, name: &str) -> Result<Option<T>, String>
+where
+    T::Err: std::fmt::Display,
+{
+    take_opt(args, name)?
+        .map(|s| s.parse::<T>().map_err(|e| format!("--{}: {}", name, e)))
+        .transpose()
+}
+
+fn run_loadtest(mut args: Vec<String>) -> Result<Option<Verdict>, String> {
+    let topic = take_opt(&mut args, "topic")?.ok_or("loadtest needs --topic")?;
+    let url = take_opt(&mut args, "url")?.unwrap_or_else(|| DEFAULT_URL.to_string());
+    let (host, port) = http::parse_target(&url)?;
+    let format = take_format(&mut args)?;
+    let cfg = loadtest::LoadConfig {
+        host,
+        port,
+        topic,
+        requests: take_num(&mut args, "requests")?.unwrap_or(1000),
+        concurrency: take_num(&mut args, "concurrency")?.unwrap_or(8),
+        words: take_num(&mut args, "words")?.unwrap_or(40),
+        repetition: take_num(&mut args, "repetition")?.unwrap_or(0.2),
+        drift: take_num(&mut args, "drift")?.unwrap_or(0.2),
+        seed: take_num(&mut args, "seed")?.unwrap_or(1),
+    };
+    reject_leftovers(&args)?;
+
+    let report = loadtest::run(cfg);
+    match format {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string(&report).map_err(|e| e.to_string())?
+        ),
+        OutputFormat::Text => {
+            println!(
+                "requests={} errors={} elapsed={:.2}s throughput={:.1} req/s",
+                report.requests, report.errors, report.elapsed_secs, report.throughput_rps
+            );
+            println!(
+                "latency p50={:.2}ms p90={:.2}ms p99={:.2}ms max={:.2}ms",
+                report.p50_ms, report.p90_ms, report.p99_ms, report.max_ms
+            );
+            println!(
+                "verdicts allow={} warn={} block={}",
+                report.verdicts.allow, report.verdicts.warn, report.verdicts.block
+            );
+        }
+    }
+
+    if report.errors > 0 {
+        return Err(format!("{} request(s) failed", report.errors));
+    }
+    Ok(None)
+}
+
+const DEFAULT_URL: &str = "http://127.0.0.1:3000";
+
+fn run_admin(mut args: Vec<String>) -> Result<Option<Verdict>, String> {
+    if args.is_empty() {
+        return Err(format!("admin needs a subcommand\n{}", USAGE));
+    }
+    let action = args.remove(0);
+    let url = take_opt(&mut args, "url")?.unwrap_or_else(|| DEFAULT_URL.to_string());
+    match action.as_str() {
+        "backup" => {
+            let out = take_opt(&mut args, "out")?.ok_or("admin backup needs --out")?;
+            reject_leftovers(&args)?;
+            if let Some(ext) = archive_extension(&out) {
+                return Err(format!(
+                    "{}: backups are plain JSON lines, not .{} archives; \
+                     pick a name like snapshot.jsonl",
+                    out, ext
+                ));
+            }
+            let backup = http::call(&url, "GET", "/admin/backup", &[])?;
+            std::fs::write(&out, &backup).map_err(|e| format!("{}: {}", out, e))?;
+            eprintln!("wrote {} bytes to {}", backup.len(), out);
+        }
+        "restore" => {
+            let input = take_opt(&mut args, "in")?.ok_or("admin restore needs --in")?;
+            reject_leftovers(&args)?;
+            let backup = std::fs::read(&input).map_err(|e| format!("{}: {}", input, e))?;
+            let response = http::call(&url, "POST", "/admin/restore", &backup)?;
+            println!("{}", String::from_utf8_lossy(&response));
+        }
+        "reencrypt" => {
+            reject_leftovers(&args)?;
+            let response = http::call(&url, "POST", "/admin/reencrypt", &[])?;
+            println!("{}", String::from_utf8_lossy(&response));
+        }
+        other => return Err(format!("unknown admin command: {}\n{}", other, USAGE)),
+    }
+    Ok(None)
+}
+
+fn run_topic(mut args: Vec<String>) -> Result<Option<Verdict>, String> {
+    if args.first().map(String::as_str) != Some("build") {
+        return Err(format!("topic needs the build subcommand\n{}", USAGE));
+    }
+    args.remove(0);
+    let out = take_opt(&mut args, "out")?.ok_or("topic build needs --out")?;
+    let top_k = take_num(&mut args, "keywords")?.unwrap_or(topic::DEFAULT_TOPIC_KEYWORDS);
+    let id = match take_opt(&mut args, "id")? {
+        Some(id) => id,
+        None => std::path::Path::new(&out)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("topic")
+            .to_string(),
+    };
+    if let Some(flag) = args.iter().find(|arg| arg.starts_with("--")) {
+        return Err(format!("unexpected argument: {}", flag));
+    }
+    if args.is_empty() {
+        return Err("topic build needs at least one document".to_string());
+    }
+
+    let documents = args
+        .iter()
+        .map(|path| std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let cfg = WordMathConfig::from_env();
+    let definition = TopicDefinition::from_documents(
+        &documents.iter().map(String::as_str).collect::<Vec<_>>(),
+        top_k,
+        cfg.emoji_mode,
+    );
+    if definition.keywords.is_empty() {
+        return Err("no keywords found in the documents".to_string());
+    }
+    let registry = serde_json::json!({ id.as_str(): definition });
+    let json = serde_json::to_string_pretty(&registry).map_err(|e| e.to_string())?;
+    std::fs::write(&out, json + "\n").map_err(|e| format!("{}: {}", out, e))?;
+    eprintln!(
+        "wrote topic {:?} with {} keyword(s) from {} document(s) to {}",
+        id,
+        definition.keywords.len(),
+        documents.len(),
+        out
+    );
+    Ok(None)
+}
+
+fn run_experiments(mut args: Vec<String>) -> Result<Option<Verdict>, String> {
+    if args.first().map(String::as_str) != Some("analyze") {
+        return Err(format!(
+            "experiments needs the analyze subcommand\n{}",
+            USAGE
+        ));
+    }
+    args.remove(0);
+    let log = take_opt(&mut args, "log")?.ok_or("experiments analyze needs --log")?;
+    let experiment =
+        take_opt(&mut args, "experiment")?.ok_or("experiments analyze needs --experiment")?;
+    let baseline = take_opt(&mut args, "baseline")?;
+    let format = take_format(&mut args)?;
+    reject_leftovers(&args)?;
+
+    let text = std::fs::read_to_string(&log).map_err(|e| format!("{}: {}", log, e))?;
+    let records = experiments::parse_log(&text).map_err(|e| format!("{}: {}", log, e))?;
+    let analysis = experiments::analyze(&records, &experiment, baseline.as_deref())?;
+    if format == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string(&analysis).map_err(|e| e.to_string())?
+        );
+        return Ok(None);
+    }
+
+    println!(
+        "experiment={} baseline={} records={}",
+        analysis.experiment, analysis.baseline, analysis.records
+    );
+    for variant in &analysis.variants {
+        println!(
+            "variant={} n={} mean={:.4} sd={:.4} p10={:.4} p50={:.4} p90={:.4} allow={} warn={} block={}",
+            variant.id,
+            variant.count,
+            variant.scores.mean,
+            variant.scores.stddev,
+            variant.scores.p10,
+            variant.scores.p50,
+            variant.scores.p90,
+            variant.verdicts.allow,
+            variant.verdicts.warn,
+            variant.verdicts.block
+        );
+        if let Some(delta) = &variant.vs_baseline {
+            let test = |t: Option<experiments::ZTest>| {
+                t.map_or("z=- p=-".to_string(), |t| {
+                    format!("z={:.2} p={:.4}", t.z, t.p_value)
+                })
+            };
+            println!(
+                "  vs {}: mean {:+.4} ({}), block rate {:+.4} ({}), warn rate {:+.4}",
+                analysis.baseline,
+                delta.mean_delta,
+                test(delta.mean_test),
+                delta.block_rate_delta,
+                test(delta.block_rate_test),
+                delta.warn_rate_delta
+            );
+        }
+    }
+    Ok(None)
+}
+
+/// Exits with `EXIT_DIVERGENT` when any group diverges, so a fairness gate
+/// is not mistaken for a Warn verdict.
+fn run_eval(mut args: Vec<String>) -> Result<u8, String> {
+    let set = take_opt(&mut args, "set")?;
+    let profile = take_opt(&mut args, "profile")?;
+    let max_gap = take_num(&mut args, "max-gap")?.unwrap_or(fairness::DEFAULT_MAX_GAP);
+    let format = take_format(&mut args)?;
+    reject_leftovers(&args)?;
+
+    let cases = match &set {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            fairness::parse_set(&text).map_err(|e| format!("{}: {}", path, e))?
+        }
+        None => fairness::parse_set(fairness::BUNDLED)?,
+    };
+    if cases.is_empty() {
+        return Err("eval set is empty".to_string());
+    }
+    let cfg = resolve_config(profile.as_deref())?;
+    let report = fairness::evaluate(&cases, cfg, max_gap);
+    if format == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string(&report).map_err(|e| e.to_string())?
+        );
+        return Ok(eval_code(&report));
+    }
+
+    println!(
+        "cases={} groups={} max_gap={}",
+        report.cases,
+        report.groups.len(),
+        report.max_gap
+    );
+    for group in &report.groups {
+        println!(
+            "group={} n={} mean={:.4} p10={:.4} p50={:.4} p90={:.4} y={:.4} z={:.4} allow={} warn={} block={}",
+            group.group,
+            group.count,
+            group.mean_score,
+            group.p10,
+            group.p50,
+            group.p90,
+            group.mean_y,
+            group.mean_z,
+            group.verdicts.allow,
+            group.verdicts.warn,
+            group.verdicts.block
+        );
+    }
+    for d in &report.divergent {
+        println!(
+            "divergent: group={} {}={:.4} median={:.4}",
+            d.group, d.metric, d.value, d.median
+        );
+    }
+    Ok(eval_code(&report))
+}
+
+fn eval_code(report: &fairness::EvalReport) -> u8 {
+    if report.divergent.is_empty() {
+        0
+    } else {
+        EXIT_DIVERGENT
+    }
+}
+
+fn run_soak(mut args: Vec<String>) -> Result<Option<Verdict>, String> {
+    let cfg = soak::SoakConfig {
+        server: take_opt(&mut args, "server")?.map_or_else(soak::sibling_server, Into::into),
+        hours: take_num(&mut args, "hours")?.unwrap_or(24.0),
+        check_interval: std::time::Duration::from_secs(
+            take_num(&mut args, "check-secs")?.unwrap_or(60u64).max(1),
+        ),
+        concurrency: take_num(&mut args, "concurrency")?.unwrap_or(4),
+        max_rss_mb: take_num(&mut args, "max-rss-mb")?.unwrap_or(512.0),
+        topic: take_opt(&mut args, "topic")?.unwrap_or_else(|| "rust web server".to_string()),
+        words: take_num(&mut args, "words")?.unwrap_or(40),
+        seed: take_num(&mut args, "seed")?.unwrap_or(1),
+    };
+    let report_path = take_opt(&mut args, "report")?;
+    let format = take_format(&mut args)?;
+    reject_leftovers(&args)?;
+
+    let report = soak::run(cfg)?;
+    if let Some(path) = &report_path {
+        soak::write_report(std::path::Path::new(path), &report)?;
+    }
+    match format {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string(&report).map_err(|e| e.to_string())?
+        ),
+        OutputFormat::Text => {
+            println!(
+                "passed={} elapsed={:.0}s requests={} errors={} proofs={}",
+                report.passed,
+                report.elapsed_secs,
+                report.requests,
+                report.errors,
+                report.audit_proofs_verified
+            );
+            println!(
+                "verdicts allow={} warn={} block={}",
+                report.verdicts.allow, report.verdicts.warn, report.verdicts.block
+            );
+            if let Some(peak) = report.rss_peak_mb {
+                println!("rss peak={:.1}MiB", peak);
+            }
+        }
+    }
+
+    match (&report.failure, &report.server_dir) {
+        (Some(failure), Some(dir)) => Err(format!(
+            "soak failed: {} (server data and log kept in {})",
+            failure, dir
+        )),
+        (Some(failure), None) => Err(format!("soak failed: {}", failure)),
+        (None, _) => Ok(None),
+    }
+}
+
+fn run_session(mut args: Vec<String>) -> Result<Option<Verdict>, String> {
+    let topic = take_opt(&mut args, "topic")?.ok_or("session needs --topic")?;
+    let profile = take_opt(&mut args, "profile")?;
+    let format = take_format(&mut args)?;
+    let smoothing = take_num::<f64>(&mut args, "smoothing")?;
+    reject_leftovers(&args)?;
+
+    let cfg = resolve_config(profile.as_deref())?;
+    let mut conv = ConversationAnalyzer::new(topic, cfg);
+    if let Some(smoothing) = smoothing {
+        conv = conv.with_smoothing(smoothing);
+    }
+
+    let stdin = io::stdin();
+    let mut out = io::stdout().lock();
+    for line in stdin.lock().lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let turn = conv.push(&line);
+        let written = match format {
+            OutputFormat::Text => writeln!(
+                out,
+                "turn={} score={:.4} verdict={} session={:.4} session_verdict={} hex={}",
+                turn.turn,
+                turn.analysis.score,
+                turn.verdict.as_str(),
+                turn.session_score,
+                turn.session_verdict.as_str(),
+                turn.trace.hex_id
+            ),
+            OutputFormat::Json => writeln!(
+                out,
+                "{}",
+                serde_json::json!({
+                    "turn": turn.turn,
+                    "score": turn.analysis.score,
+                    "verdict": turn.verdict,
+                    "session_score": turn.session_score,
+                    "session_verdict": turn.session_verdict,
+                    "hex_id": turn.trace.hex_id,
+                })
+            ),
+        };
+        written
+            .and_then(|_| out.flush())
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok((conv.turns() > 0).then(|| conv.session_verdict()))
+}
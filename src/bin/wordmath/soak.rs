@@ -0,0 +1,539 @@
+//! `wordmath soak`: endurance run against an embedded server.
+//!
+//! Starts the `server` binary installed next to this one (or `--server
+//! PATH`) on a free local port, with a fresh data directory and none of
+//! the caller's WORD_MATH_* settings, and drives synthetic `/analyze`
+//! traffic at it for `--hours`. Messages come from the loadtest generator
+//! with the looping-tail and off-topic shares varied per request. Every
+//! `--check-secs` these invariants are verified:
+//!
+//! - alive: the server is still running and its log shows no panic;
+//! - memory: its resident set stays under `--max-rss-mb` (Linux only);
+//! - audit: inclusion proofs for the first and last record of the newest
+//!   Merkle digest fold to its published root, and a consistency proof
+//!   shows the root extends the one seen at the previous check;
+//! - scores: every response has y, z and score finite and in [0, 1], and
+//!   a fixed canary message keeps the score and verdict it first got;
+//! - errors: no request failed.
+//!
+//! The run stops at the first failed check. The report (stdout, and
+//! `--report FILE`) lists every check interval, so a release pipeline can
+//! gate on it and chart latency and memory over the run.
+
+use crate::http;
+use crate::loadtest::{self, LoadConfig, Rng};
+use serde::Serialize;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use word_math_guard::batch::{percentile, VerdictCounts};
+use word_math_guard::{generate_hex_id, merkle, text, EmojiMode, Verdict};
+
+const CANARY: &str = "The rust web server handles each request on the rust runtime and \
+                      the web server returns the response to the client.";
+/// Violations kept for the report; later ones are only counted.
+const MAX_VIOLATIONS: usize = 20;
+
+/// Soak settings from the command line.
+#[derive(Debug, Clone)]
+pub struct SoakConfig {
+    pub server: PathBuf,
+    pub hours: f64,
+    pub check_interval: Duration,
+    pub concurrency: usize,
+    pub max_rss_mb: f64,
+    pub topic: String,
+    pub words: usize,
+    pub seed: u64,
+}
+
+/// One check interval.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckRecord {
+    pub elapsed_secs: f64,
+    pub requests: u64,
+    pub errors: u64,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rss_mb: Option<f64>,
+    pub audit_leaves: u64,
+    /// Invariants that failed in this interval.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SoakReport {
+    pub passed: bool,
+    /// First failed invariant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+    pub hours: f64,
+    pub elapsed_secs: f64,
+    pub requests: u64,
+    pub errors: u64,
+    pub verdicts: VerdictCounts,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rss_peak_mb: Option<f64>,
+    pub audit_proofs_verified: u64,
+    /// Server data directory and log, kept when the run failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_dir: Option<String>,
+    pub checks: Vec<CheckRecord>,
+}
+
+/// What the traffic threads saw since the last check.
+#[derive(Default)]
+struct Window {
+    latencies_ms: Vec<f64>,
+    errors: u64,
+    verdicts: VerdictCounts,
+    violations: Vec<String>,
+}
+
+/// Scores of one `/analyze` response, or why it is out of range.
+pub fn check_response(body: &[u8]) -> Result<(f64, Verdict), String> {
+    let value: serde_json::Value =
+        serde_json::from_slice(body).map_err(|e| format!("unreadable response: {}", e))?;
+    for metric in ["y_repetition", "z_drift", "score"] {
+        match value[metric].as_f64() {
+            Some(x) if (0.0..=1.0).contains(&x) => {}
+            other => return Err(format!("{} out of range: {:?}", metric, other)),
+        }
+    }
+    let verdict = serde_json::from_value(value["verdict"].clone())
+        .map_err(|e| format!("bad verdict: {}", e))?;
+    Ok((value["score"].as_f64().unwrap_or_default(), verdict))
+}
+
+/// Resident set of `pid` in MiB, from /proc.
+fn rss_mb(pid: u32) -> Option<f64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let kb = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .split_whitespace()
+        .next()?
+        .parse::<f64>()
+        .ok()?;
+    Some(kb / 1024.0)
+}
+
+fn analyze_path(message: &str, topic: &str) -> String {
+    format!(
+        "/analyze?message={}&topic={}",
+        loadtest::percent_encode(message),
+        loadtest::percent_encode(topic)
+    )
+}
+
+/// The embedded server and its scratch directory.
+struct Embedded {
+    child: Child,
+    dir: PathBuf,
+    log: PathBuf,
+    port: u16,
+    log_offset: u64,
+}
+
+impl Embedded {
+    fn start(cfg: &SoakConfig) -> Result<Self, String> {
+        if !cfg.server.exists() {
+            return Err(format!(
+                "server binary not found at {}; pass --server PATH",
+                cfg.server.display()
+            ));
+        }
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .map_err(|e| format!("finding a free port: {}", e))?
+            .port();
+        let dir = std::env::temp_dir().join(format!("wordmath-soak-{}", generate_hex_id()));
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let log = dir.join("server.log");
+        let out = std::fs::File::create(&log).map_err(|e| format!("{}: {}", log.display(), e))?;
+        let err = out.try_clone().map_err(|e| e.to_string())?;
+
+        let mut command = Command::new(&cfg.server);
+        for (name, _) in std::env::vars_os() {
+            if name.to_string_lossy().starts_with("WORD_MATH_") {
+                command.env_remove(name);
+            }
+        }
+        let merkle_secs = (cfg.check_interval.as_secs() / 2).max(1);
+        let child = command
+            .env("WORD_MATH_PORT", port.to_string())
+            .env("WORD_MATH_DATA_DIR", dir.join("data"))
+            .env("WORD_MATH_MERKLE_INTERVAL_SECS", merkle_secs.to_string())
+            .stdin(Stdio::null())
+            .stdout(out)
+            .stderr(err)
+            .spawn()
+            .map_err(|e| format!("starting {}: {}", cfg.server.display(), e))?;
+        let mut server = Self {
+            child,
+            dir,
+            log,
+            port,
+            log_offset: 0,
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(15);
+        while http::call(&server.url(), "GET", "/version", &[]).is_err() {
+            if let Some(reason) = server.dead() {
+                return Err(reason);
+            }
+            if Instant::now() > deadline {
+                return Err(format!(
+                    "server did not come up; see {}",
+                    server.log.display()
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Ok(server)
+    }
+
+    fn url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    /// Why the server is gone or broken, if it is.
+    fn dead(&mut self) -> Option<String> {
+        if let Ok(Some(status)) = self.child.try_wait() {
+            return Some(format!("server exited with {}", status));
+        }
+        let mut file = std::fs::File::open(&self.log).ok()?;
+        file.seek(SeekFrom::Start(self.log_offset)).ok()?;
+        let mut fresh = String::new();
+        file.read_to_string(&mut fresh).ok()?;
+        self.log_offset += fresh.len() as u64;
+        fresh
+            .lines()
+            .find(|line| line.contains("panicked at"))
+            .map(|line| format!("server panicked: {}", line.trim()))
+    }
+
+    /// Stop the server; remove its directory unless `keep`.
+    fn stop(mut self, keep: bool) -> Option<String> {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        if keep {
+            return Some(self.dir.display().to_string());
+        }
+        let _ = std::fs::remove_dir_all(&self.dir);
+        None
+    }
+}
+
+impl Drop for Embedded {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Verify inclusion proofs of the newest digest's first and last record,
+/// and its consistency with `previous` (tree size and root), which is then
+/// advanced to it; returns (leaves, proofs verified).
+fn check_audit(url: &str, previous: &mut Option<(u64, String)>) -> Result<(u64, u64), String> {
+    let get = |path: &str| -> Result<serde_json::Value, String> {
+        let body = http::call(url, "GET", path, &[])?;
+        serde_json::from_slice(&body).map_err(|e| format!("{}: {}", path, e))
+    };
+    // A refresh between reading the digest and its proofs changes the
+    // root, so retry a few times before calling it a mismatch.
+    let mut last_error = String::new();
+    for _ in 0..3 {
+        let digest = get("/audit/merkle")?;
+        let root = digest["root"].as_str().unwrap_or_default().to_string();
+        let leaves = digest["leaves"].as_u64().unwrap_or(0);
+        let mut ids: Vec<&str> = ["first_hex_id", "last_hex_id"]
+            .iter()
+            .filter_map(|field| digest[field].as_str())
+            .collect();
+        ids.dedup();
+        let mut verified = 0;
+        for id in &ids {
+            let proof = get(&format!("/audit/merkle/proof/{}", id))?;
+            let path = proof["path"].as_array().cloned().unwrap_or_default();
+            let steps = path.iter().map(|step| {
+                (
+                    step["sibling"].as_str().unwrap_or_default(),
+                    step["side"].as_str().unwrap_or_default(),
+                )
+            });
+            let folded = merkle::fold_path(proof["leaf"].as_str().unwrap_or_default(), steps);
+            let proof_root = proof["root"].as_str().unwrap_or_default();
+            if folded.as_deref() != Some(proof_root) {
+                return Err(format!("proof for {} does not fold to its root", id));
+            }
+            if proof_root != root {
+                last_error = format!("proof for {} is against another root", id);
+                break;
+            }
+            verified += 1;
+        }
+        if verified == ids.len() as u64 {
+            if let Some((first, first_root)) = previous.as_ref() {
+                let path = format!(
+                    "/audit/merkle/consistency?first={}&second={}",
+                    first, leaves
+                );
+                let proof = get(&path)?;
+                let hashes: Option<Vec<merkle::Hash>> = proof["proof"]
+                    .as_array()
+                    .map(|hashes| {
+                        hashes
+                            .iter()
+                            .map(|h| merkle::from_hex(h.as_str()?))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let consistent = match (
+                    hashes,
+                    merkle::from_hex(first_root),
+                    merkle::from_hex(&root),
+                ) {
+                    (Some(hashes), Some(first_root), Some(second_root)) => {
+                        merkle::verify_consistency(
+                            *first,
+                            leaves,
+                            &first_root,
+                            &second_root,
+                            &hashes,
+                        )
+                    }
+                    _ => false,
+                };
+                if !consistent {
+                    return Err(format!(
+                        "root over {} leaves does not extend the root over {}",
+                        leaves, first
+                    ));
+                }
+                verified += 1;
+            }
+            *previous = Some((leaves, root));
+            return Ok((leaves, verified));
+        }
+    }
+    Err(last_error)
+}
+
+/// One traffic thread: send generated messages until `stop` is set.
+fn drive(
+    port: u16,
+    cfg: SoakConfig,
+    worker: u64,
+    stop: Arc<AtomicBool>,
+    window: Arc<Mutex<Window>>,
+) {
+    let topic_words = text::tokenize(&cfg.topic, EmojiMode::Strip);
+    let mut rng = Rng::new(cfg.seed.wrapping_add(worker * 0x9e37_79b9));
+    let mut conn: Option<BufReader<TcpStream>> = None;
+    while !stop.load(Ordering::Relaxed) {
+        let shape = LoadConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            topic: cfg.topic.clone(),
+            requests: 0,
+            concurrency: 1,
+            words: cfg.words,
+            repetition: *rng.pick(&[0.0, 0.3, 0.8]),
+            drift: *rng.pick(&[0.0, 0.3, 0.7]),
+            seed: 0,
+        };
+        let message = loadtest::generate_message(&mut rng, &topic_words, &shape);
+        let path = analyze_path(&message, &cfg.topic);
+        let sent = Instant::now();
+        let result = match conn.as_mut() {
+            Some(stream) => http::request(stream, "127.0.0.1", "GET", &path, &[]),
+            None => http::connect("127.0.0.1", port).and_then(|mut stream| {
+                let result = http::request(&mut stream, "127.0.0.1", "GET", &path, &[]);
+                conn = Some(stream);
+                result
+            }),
+        };
+        let elapsed_ms = sent.elapsed().as_secs_f64() * 1000.0;
+        let mut window = window.lock().unwrap_or_else(|e| e.into_inner());
+        match result {
+            Ok((200, body)) => {
+                window.latencies_ms.push(elapsed_ms);
+                match check_response(&body) {
+                    Ok((_, verdict)) => window.verdicts.record(verdict),
+                    Err(e) if window.violations.len() < MAX_VIOLATIONS => {
+                        window.violations.push(format!("{}: {}", e, message));
+                    }
+                    Err(_) => {}
+                }
+            }
+            Ok((status, _)) => {
+                window.errors += 1;
+                if window.violations.len() < MAX_VIOLATIONS {
+                    window.violations.push(format!("status {}", status));
+                }
+            }
+            Err(_) => {
+                window.errors += 1;
+                conn = None;
+            }
+        }
+    }
+}
+
+pub fn run(cfg: SoakConfig) -> Result<SoakReport, String> {
+    let mut server = Embedded::start(&cfg)?;
+    let url = server.url();
+    let canary = http::call(&url, "GET", &analyze_path(CANARY, &cfg.topic), &[])
+        .and_then(|body| check_response(&body))?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let window = Arc::new(Mutex::new(Window::default()));
+    let workers: Vec<_> = (0..cfg.concurrency.max(1) as u64)
+        .map(|worker| {
+            let (cfg, stop, window) = (cfg.clone(), Arc::clone(&stop), Arc::clone(&window));
+            let port = server.port;
+            std::thread::spawn(move || drive(port, cfg, worker, stop, window))
+        })
+        .collect();
+
+    let started = Instant::now();
+    let duration = Duration::from_secs_f64(cfg.hours.max(0.0) * 3600.0);
+    let mut report = SoakReport {
+        passed: true,
+        failure: None,
+        hours: cfg.hours,
+        elapsed_secs: 0.0,
+        requests: 0,
+        errors: 0,
+        verdicts: VerdictCounts::default(),
+        rss_peak_mb: None,
+        audit_proofs_verified: 0,
+        server_dir: None,
+        checks: Vec::new(),
+    };
+    let mut previous_root = None;
+    while report.passed && started.elapsed() < duration {
+        let remaining = duration.saturating_sub(started.elapsed());
+        std::thread::sleep(cfg.check_interval.min(remaining));
+        let mut seen = std::mem::take(&mut *window.lock().unwrap_or_else(|e| e.into_inner()));
+        seen.latencies_ms.sort_by(f64::total_cmp);
+
+        let mut failed = Vec::new();
+        if let Some(reason) = server.dead() {
+            failed.push(format!("alive: {}", reason));
+        }
+        let rss = rss_mb(server.child.id());
+        if let Some(rss) = rss {
+            report.rss_peak_mb = Some(report.rss_peak_mb.unwrap_or(0.0).max(rss));
+            if rss > cfg.max_rss_mb {
+                failed.push(format!(
+                    "memory: {:.1} MiB over the {} MiB limit",
+                    rss, cfg.max_rss_mb
+                ));
+            }
+        }
+        let mut audit_leaves = 0;
+        match check_audit(&url, &mut previous_root) {
+            Ok((leaves, verified)) => {
+                audit_leaves = leaves;
+                report.audit_proofs_verified += verified;
+            }
+            Err(e) => failed.push(format!("audit: {}", e)),
+        }
+        failed.extend(seen.violations.iter().map(|v| format!("scores: {}", v)));
+        match http::call(&url, "GET", &analyze_path(CANARY, &cfg.topic), &[])
+            .and_then(|body| check_response(&body))
+        {
+            Ok(result) if result == canary => {}
+            Ok(result) => failed.push(format!(
+                "scores: canary moved from {:?} to {:?}",
+                canary, result
+            )),
+            Err(e) => failed.push(format!("scores: canary: {}", e)),
+        }
+        if seen.errors > 0 {
+            failed.push(format!("errors: {} request(s) failed", seen.errors));
+        }
+
+        let check = CheckRecord {
+            elapsed_secs: started.elapsed().as_secs_f64(),
+            requests: seen.latencies_ms.len() as u64 + seen.errors,
+            errors: seen.errors,
+            p50_ms: percentile(&seen.latencies_ms, 50.0),
+            p99_ms: percentile(&seen.latencies_ms, 99.0),
+            rss_mb: rss,
+            audit_leaves,
+            failed,
+        };
+        eprintln!(
+            "soak t={:.0}s requests={} errors={} p99={:.2}ms rss={} leaves={}{}",
+            check.elapsed_secs,
+            check.requests,
+            check.errors,
+            check.p99_ms,
+            rss.map_or_else(|| "n/a".to_string(), |mb| format!("{:.1}MiB", mb)),
+            check.audit_leaves,
+            if check.failed.is_empty() {
+                ""
+            } else {
+                " FAILED"
+            }
+        );
+        report.requests += check.requests;
+        report.errors += check.errors;
+        report.verdicts.allow += seen.verdicts.allow;
+        report.verdicts.warn += seen.verdicts.warn;
+        report.verdicts.block += seen.verdicts.block;
+        if let Some(first) = check.failed.first() {
+            report.passed = false;
+            report.failure = Some(first.clone());
+        }
+        report.checks.push(check);
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    for worker in workers {
+        let _ = worker.join();
+    }
+    report.elapsed_secs = started.elapsed().as_secs_f64();
+    report.server_dir = server.stop(!report.passed);
+    Ok(report)
+}
+
+/// The `server` binary next to the running executable.
+pub fn sibling_server() -> PathBuf {
+    let exe = std::env::current_exe().unwrap_or_default();
+    exe.with_file_name(format!("server{}", std::env::consts::EXE_SUFFIX))
+}
+
+/// Write `report` as pretty JSON to `path`.
+pub fn write_report(path: &Path, report: &SoakReport) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(report).map_err(|e| e.to_string())?;
+    std::fs::write(path, json + "\n").map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_responses_outside_the_unit_range_are_violations() {
+        let ok = br#"{"y_repetition":0.2,"z_drift":0.1,"score":0.7,"verdict":"allow"}"#;
+        assert_eq!(check_response(ok), Ok((0.7, Verdict::Allow)));
+        let high = br#"{"y_repetition":1.2,"z_drift":0.1,"score":0.7,"verdict":"allow"}"#;
+        assert!(check_response(high)
+            .unwrap_err()
+            .starts_with("y_repetition"));
+        let missing = br#"{"y_repetition":0.2,"z_drift":0.1,"verdict":"warn"}"#;
+        assert!(check_response(missing).unwrap_err().starts_with("score"));
+        assert!(check_response(b"{").is_err());
+        assert!(rss_mb(std::process::id()).is_none_or(|mb| mb > 0.0));
+    }
+}
@@ -0,0 +1,130 @@
+//! Per-language score calibration.
+//!
+//! Scores skew by language: agglutinative languages such as Finnish or
+//! Turkish repeat fewer surface forms, so the same degeneration scores
+//! higher than in English. A calibration table maps language codes (as
+//! returned by `lang::detect`) to an affine correction applied after
+//! scoring, so one set of thresholds works across locales:
+//!
+//! ```json
+//! { "fi": { "scale": 0.9, "offset": 0.0 }, "tr": { "scale": 0.92, "offset": 0.01 } }
+//! ```
+
+use crate::{lang, WordMathAnalysis, WordMathError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Affine correction: clamp(scale * score + offset, 0, 1).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Affine {
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl Default for Affine {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl Affine {
+    pub fn apply(&self, score: f64) -> f64 {
+        (self.scale * score + self.offset).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct CalibrationTable {
+    languages: HashMap<String, Affine>,
+}
+
+impl CalibrationTable {
+    pub fn from_json(json: &str) -> Result<Self, WordMathError> {
+        serde_json::from_str(json).map_err(|e| WordMathError::Config(e.to_string()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WordMathError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| WordMathError::Config(format!("{}: {}", path.display(), e)))?;
+        Self::from_json(&json)
+    }
+
+    /// Load the file named by WORD_MATH_CALIBRATION, or an empty table if unset.
+    pub fn from_env() -> Result<Self, WordMathError> {
+        match std::env::var("WORD_MATH_CALIBRATION") {
+            Ok(path) => Self::load(path),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.languages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.languages.is_empty()
+    }
+
+    pub fn get(&self, language: &str) -> Option<Affine> {
+        self.languages.get(language).copied()
+    }
+
+    /// Detect the message language and, if the table has an entry for it,
+    /// correct `analysis.score` in place. Returns the detected language.
+    pub fn calibrate(
+        &self,
+        message: &str,
+        analysis: &mut WordMathAnalysis,
+    ) -> Option<&'static str> {
+        if self.is_empty() {
+            return None;
+        }
+        let language = lang::detect(message)?;
+        if let Some(affine) = self.get(language) {
+            analysis.score = affine.apply(analysis.score);
+        }
+        Some(language)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyze_message_with_trace, WordMathConfig};
+
+    #[test]
+    fn test_calibrates_detected_language_only() {
+        let table =
+            CalibrationTable::from_json(r#"{ "fi": { "scale": 0.5 }, "de": { "offset": 0.1 } }"#)
+                .unwrap();
+        let cfg = WordMathConfig::default();
+
+        let message = "Palvelin on hidas ja se ei vastaa";
+        let (mut analysis, _) = analyze_message_with_trace(message, "palvelin", cfg);
+        let before = analysis.score;
+        assert_eq!(table.calibrate(message, &mut analysis), Some("fi"));
+        assert!((analysis.score - before * 0.5).abs() < 1e-12);
+
+        let message = "The server is down and the logs are empty";
+        let (mut analysis, _) = analyze_message_with_trace(message, "server", cfg);
+        let before = analysis.score;
+        assert_eq!(table.calibrate(message, &mut analysis), Some("en"));
+        assert_eq!(analysis.score, before);
+
+        assert_eq!(
+            Affine {
+                scale: 2.0,
+                offset: 0.0
+            }
+            .apply(0.8),
+            1.0
+        );
+    }
+}
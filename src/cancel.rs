@@ -0,0 +1,145 @@
+//! Cooperative cancellation for analyses of very large inputs.
+//!
+//! `analyze_async` feeds the message through the incremental counters of
+//! `StreamGuard` in `CHUNK_BYTES` pieces, yielding to the executor and
+//! checking its `CancellationToken` between pieces. A caller enforcing a
+//! deadline cancels the token and gets back the metrics of the prefix
+//! processed so far, flagged as incomplete, instead of waiting for the
+//! whole input. The token is `tokio_util`'s, so one token can also stop the
+//! caller's other tasks; the future itself is runtime-agnostic. Only
+//! compiled with the `cancel` feature.
+
+use crate::stream::StreamGuard;
+use crate::{
+    generate_hex_id, score_linear, text, CompiledTopic, WordMathAnalysis, WordMathConfig,
+    WordMathTrace,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Bytes scored between cancellation checks.
+pub const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Re-exported so callers needn't depend on `tokio-util` themselves.
+pub use tokio_util::sync::CancellationToken;
+
+/// Result of `analyze_async`.
+#[derive(Debug, Clone)]
+pub struct AsyncAnalysis {
+    pub analysis: WordMathAnalysis,
+    pub trace: WordMathTrace,
+    /// False when the analysis was cancelled; the metrics then describe
+    /// only the first `processed_bytes` of the message.
+    pub complete: bool,
+    pub processed_bytes: usize,
+}
+
+/// Yields once, so other tasks (and the canceller) get to run.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Like `analyze_message_with_trace`, processing the message in chunks and
+/// stopping early, with a partial result, once `cancel` is cancelled.
+pub async fn analyze_async(
+    message: &str,
+    topic: &str,
+    cfg: WordMathConfig,
+    cancel: &CancellationToken,
+) -> AsyncAnalysis {
+    let topic = CompiledTopic::compile(topic, cfg.emoji_mode);
+    analyze_chunked(message, &topic, cfg, cancel, CHUNK_BYTES).await
+}
+
+async fn analyze_chunked(
+    message: &str,
+    topic: &CompiledTopic,
+    cfg: WordMathConfig,
+    cancel: &CancellationToken,
+    chunk_bytes: usize,
+) -> AsyncAnalysis {
+    let mut guard = StreamGuard::with_topic(topic, cfg)
+        .with_cadence(usize::MAX)
+        .with_min_words(usize::MAX);
+    let mut processed = 0;
+    while processed < message.len() && !cancel.is_cancelled() {
+        let mut end = (processed + chunk_bytes).min(message.len());
+        while !message.is_char_boundary(end) {
+            end += 1;
+        }
+        guard.check(&message[processed..end]);
+        processed = end;
+        if processed < message.len() {
+            YieldNow(false).await;
+        }
+    }
+    guard.finish();
+
+    let analysis = guard.analysis();
+    let raw_score = score_linear(analysis.y_repetition, analysis.z_drift, cfg);
+    AsyncAnalysis {
+        trace: WordMathTrace {
+            hex_id: generate_hex_id(),
+            message_len: text::grapheme_len(&message[..processed]),
+            topic_len: topic.grapheme_len(),
+            raw_score,
+            adjusted_score: analysis.score,
+        },
+        analysis,
+        complete: processed == message.len(),
+        processed_bytes: processed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze_message_with_trace;
+    use std::task::Waker;
+
+    fn poll<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+        future.poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn test_chunked_analysis_matches_and_can_be_cancelled() {
+        let cfg = WordMathConfig::default();
+        let topic = CompiledTopic::compile("rust web server", cfg.emoji_mode);
+        let message = "the rust web server ünïcode handles requests ".repeat(50);
+        let cancel = CancellationToken::new();
+
+        let mut whole = Box::pin(analyze_chunked(&message, &topic, cfg, &cancel, 7));
+        let result = loop {
+            if let Poll::Ready(result) = poll(whole.as_mut()) {
+                break result;
+            }
+        };
+        let (expected, trace) = analyze_message_with_trace(&message, "rust web server", cfg);
+        assert!(result.complete);
+        assert_eq!(result.analysis.y_repetition, expected.y_repetition);
+        assert_eq!(result.analysis.z_drift, expected.z_drift);
+        assert_eq!(result.trace.message_len, trace.message_len);
+
+        let mut partial = Box::pin(analyze_chunked(&message, &topic, cfg, &cancel, 100));
+        assert!(poll(partial.as_mut()).is_pending());
+        cancel.cancel();
+        let Poll::Ready(result) = poll(partial.as_mut()) else {
+            panic!("a cancelled analysis finishes at its next check");
+        };
+        assert!(!result.complete);
+        assert_eq!(result.processed_bytes, 100);
+        assert!(result.trace.message_len < trace.message_len);
+    }
+}
@@ -0,0 +1,158 @@
+//! Grouping of a message's off-topic terms, to say what drift is about.
+//!
+//! Terms missing from the topic are linked when they occur within
+//! `WINDOW` tokens of each other in the same sentence, and linked terms
+//! form one cluster, so "bitcoin price surged while ethereum price fell"
+//! reads as one cluster rather than six loose words. Very short tokens
+//! ("is", "my") are left out; they would otherwise join every cluster
+//! they sit next to. So are the message language's stopwords, when its
+//! `stopwords` pack is enabled.
+
+use crate::matching::MatchForm;
+use crate::{stopwords, text, CompiledTopic, WordMathConfig};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Off-topic terms at most this many tokens apart are linked.
+const WINDOW: usize = 3;
+/// Shorter terms are not clustered.
+const MIN_TERM_CHARS: usize = 3;
+const MAX_CLUSTERS: usize = 5;
+const MAX_TERMS: usize = 8;
+
+/// Off-topic terms that occur together.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TermCluster {
+    /// Most frequent first, then in order of appearance.
+    pub terms: Vec<String>,
+    /// Occurrences of all the cluster's terms in the message.
+    pub occurrences: usize,
+}
+
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// Clusters of `message`'s off-topic terms, largest first; at most five,
+/// of at most eight terms each.
+pub fn drift_clusters(
+    message: &str,
+    topic: &CompiledTopic,
+    cfg: &WordMathConfig,
+) -> Vec<TermCluster> {
+    let normalized = if cfg.normalize {
+        text::normalize(message).text
+    } else {
+        message.to_string()
+    };
+    let form = MatchForm::of(cfg);
+    let topic_tokens: HashSet<&str> = topic.tokens_for(form).iter().map(String::as_str).collect();
+    let pack = stopwords::for_text(&normalized);
+
+    // Distinct off-topic terms in order of appearance, with their counts,
+    // and the token positions they occur at.
+    let mut ids: HashMap<&str, usize> = HashMap::new();
+    let mut terms: Vec<(&str, usize)> = Vec::new();
+    let mut positions: Vec<(usize, usize)> = Vec::new();
+    let sentences: Vec<Vec<String>> = text::sentences(&normalized)
+        .into_iter()
+        .map(|sentence| text::tokenize(sentence, cfg.emoji_mode))
+        .collect();
+    let mut offset = 0;
+    for tokens in &sentences {
+        for (pos, token) in tokens.iter().enumerate() {
+            if token.chars().count() < MIN_TERM_CHARS
+                || topic_tokens.contains(form.key(token).as_str())
+                || pack.is_some_and(|p| p.is_stopword(token))
+            {
+                continue;
+            }
+            let id = *ids.entry(token).or_insert_with(|| {
+                terms.push((token, 0));
+                terms.len() - 1
+            });
+            terms[id].1 += 1;
+            positions.push((offset + pos, id));
+        }
+        // Keep sentences further apart than the window.
+        offset += tokens.len() + WINDOW + 1;
+    }
+
+    let mut parent: Vec<usize> = (0..terms.len()).collect();
+    for (i, &(pos, id)) in positions.iter().enumerate() {
+        for &(next_pos, next_id) in &positions[i + 1..] {
+            if next_pos - pos > WINDOW {
+                break;
+            }
+            let (a, b) = (find(&mut parent, id), find(&mut parent, next_id));
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of: HashMap<usize, usize> = HashMap::new();
+    for id in 0..terms.len() {
+        let root = find(&mut parent, id);
+        let group = *group_of.entry(root).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(id);
+    }
+
+    let mut clusters: Vec<TermCluster> = groups
+        .into_iter()
+        .map(|mut ids| {
+            // Stable, so equally frequent terms keep their order.
+            ids.sort_by_key(|&id| std::cmp::Reverse(terms[id].1));
+            TermCluster {
+                occurrences: ids.iter().map(|&id| terms[id].1).sum(),
+                terms: ids
+                    .into_iter()
+                    .take(MAX_TERMS)
+                    .map(|id| terms[id].0.to_string())
+                    .collect(),
+            }
+        })
+        .collect();
+    clusters.sort_by_key(|c| std::cmp::Reverse(c.occurrences));
+    clusters.truncate(MAX_CLUSTERS);
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearby_off_topic_terms_cluster() {
+        let cfg = WordMathConfig::default();
+        let topic = CompiledTopic::compile("rust web server deployment", cfg.emoji_mode);
+        let message = "bitcoin price surged while ethereum price fell. \
+                       the rust web server handles deployment fine. \
+                       pizza toppings tonight";
+        let clusters = drift_clusters(message, &topic, &cfg);
+        let terms: Vec<&[String]> = clusters.iter().map(|c| &c.terms[..]).collect();
+        assert_eq!(
+            terms,
+            [
+                &["price", "bitcoin", "surged", "while", "ethereum", "fell"][..],
+                &["pizza", "toppings", "tonight"],
+                &["handles", "fine"],
+                &["the"],
+            ]
+        );
+        assert_eq!(clusters[0].occurrences, 7);
+    }
+
+    #[test]
+    fn test_on_topic_message_has_no_clusters() {
+        let cfg = WordMathConfig::default();
+        let topic = CompiledTopic::compile("rust web server", cfg.emoji_mode);
+        assert!(drift_clusters("rust web server", &topic, &cfg).is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+//! Reduced-precision scoring for embedded and browser-side pre-screening.
+//!
+//! Computes y, z and f(y, z) like `analyze_message_with_trace`, but in
+//! `Real`, which is `f32` when the crate is built with the `f32` feature
+//! (and `f64` otherwise), with `u32` counts and no trace record. The
+//! feature also shrinks the inline token buffers in `count`. Results agree
+//! with the full pipeline to within f32 rounding, which is far below any
+//! useful threshold resolution.
+
+use crate::{count, text, WordMathConfig};
+
+/// Floating-point type used by the compact scorer.
+#[cfg(feature = "f32")]
+pub type Real = f32;
+/// Floating-point type used by the compact scorer.
+#[cfg(not(feature = "f32"))]
+pub type Real = f64;
+
+/// Scoring weights and output transform, in `Real`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactConfig {
+    pub alpha: Real,
+    pub beta: Real,
+    pub floor: Real,
+    pub ceiling: Real,
+    pub scale: Real,
+    pub offset: Real,
+    pub emoji_mode: text::EmojiMode,
+    pub normalize: bool,
+}
+
+impl From<WordMathConfig> for CompactConfig {
+    fn from(cfg: WordMathConfig) -> Self {
+        Self {
+            alpha: cfg.alpha as Real,
+            beta: cfg.beta as Real,
+            floor: cfg.transform.floor as Real,
+            ceiling: cfg.transform.ceiling as Real,
+            scale: cfg.transform.scale as Real,
+            offset: cfg.transform.offset as Real,
+            emoji_mode: cfg.emoji_mode,
+            normalize: cfg.normalize,
+        }
+    }
+}
+
+impl Default for CompactConfig {
+    fn default() -> Self {
+        WordMathConfig::default().into()
+    }
+}
+
+/// y, z and the transformed score of one message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactAnalysis {
+    pub y_repetition: Real,
+    pub z_drift: Real,
+    pub score: Real,
+}
+
+fn ratio(numerator: usize, denominator: usize) -> Real {
+    numerator as u32 as Real / denominator as u32 as Real
+}
+
+/// Score `message` against `topic` in reduced precision.
+pub fn analyze(message: &str, topic: &str, cfg: CompactConfig) -> CompactAnalysis {
+    let message = if cfg.normalize {
+        text::normalize(message).text
+    } else {
+        message.to_string()
+    };
+    let msg_tokens = text::tokenize(&message, cfg.emoji_mode);
+    let topic_tokens = text::tokenize(topic, cfg.emoji_mode);
+
+    let y = if msg_tokens.is_empty() {
+        0.0
+    } else {
+        ratio(count::max_token_count(&msg_tokens), msg_tokens.len())
+    };
+    let z = match (msg_tokens.is_empty(), topic_tokens.is_empty()) {
+        (true, true) => 0.0,
+        (true, false) | (false, true) => 1.0,
+        (false, false) => {
+            let (common, union) = count::distinct_overlap(&msg_tokens, &topic_tokens);
+            1.0 - ratio(common, union.max(1))
+        }
+    };
+
+    let raw = (1.0 - cfg.alpha * y - cfg.beta * z).clamp(0.0, 1.0);
+    let floor = cfg.floor.clamp(0.0, 1.0);
+    let ceiling = cfg.ceiling.clamp(floor, 1.0);
+    CompactAnalysis {
+        y_repetition: y,
+        z_drift: z,
+        score: (cfg.scale * raw + cfg.offset).clamp(floor, ceiling),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze_message_with_trace;
+
+    #[test]
+    fn test_matches_full_pipeline() {
+        let cfg = WordMathConfig::default();
+        for (message, topic) in [
+            ("how do I reset my password", "password reset help"),
+            ("spam spam spam spam buy now", "password reset help"),
+            ("", "password reset help"),
+        ] {
+            let (full, _) = analyze_message_with_trace(message, topic, cfg);
+            let compact = analyze(message, topic, cfg.into());
+            assert!((compact.y_repetition - full.y_repetition as Real).abs() < 1e-6);
+            assert!((compact.z_drift - full.z_drift as Real).abs() < 1e-6);
+            assert!((compact.score - full.score as Real).abs() < 1e-6);
+        }
+    }
+}
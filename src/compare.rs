@@ -0,0 +1,212 @@
+//! Differential scoring of two messages against the same topic.
+
+use crate::minhash::{MinHashSignature, DEFAULT_NUM_HASHES};
+use crate::{count, text, topic_drift_of, CompiledTopic, WordMathConfig};
+use serde::Serialize;
+
+/// How two messages relate to each other and to a shared topic.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MessageComparison {
+    /// Jaccard overlap of the two messages' distinct tokens.
+    pub lexical_overlap: f64,
+    /// MinHash estimate of word-bigram similarity; near 1.0 means one is a
+    /// near-duplicate of the other.
+    pub similarity: f64,
+    /// Topic drift z of the first message.
+    pub drift_a: f64,
+    /// Topic drift z of the second message.
+    pub drift_b: f64,
+}
+
+impl MessageComparison {
+    /// `drift_b - drift_a`: positive when the second message moved further
+    /// off-topic.
+    pub fn drift_delta(&self) -> f64 {
+        self.drift_b - self.drift_a
+    }
+}
+
+fn tokens(message: &str, cfg: &WordMathConfig) -> Vec<String> {
+    if cfg.normalize {
+        text::tokenize(&text::normalize(message).text, cfg.emoji_mode)
+    } else {
+        text::tokenize(message, cfg.emoji_mode)
+    }
+}
+
+/// Compare messages `a` and `b`, e.g. to check whether a regenerated answer
+/// actually differs from the previous one.
+pub fn compare_messages(a: &str, b: &str, topic: &str, cfg: WordMathConfig) -> MessageComparison {
+    compare_with_topic(a, b, &CompiledTopic::compile(topic, cfg.emoji_mode), cfg)
+}
+
+/// Like `compare_messages`, against a precompiled topic.
+pub fn compare_with_topic(
+    a: &str,
+    b: &str,
+    topic: &CompiledTopic,
+    cfg: WordMathConfig,
+) -> MessageComparison {
+    let recompiled;
+    let topic = if topic.emoji_mode() == cfg.emoji_mode {
+        topic
+    } else {
+        recompiled = CompiledTopic::compile(topic.text(), cfg.emoji_mode);
+        &recompiled
+    };
+
+    let a_tokens = tokens(a, &cfg);
+    let b_tokens = tokens(b, &cfg);
+
+    let lexical_overlap = match (a_tokens.is_empty(), b_tokens.is_empty()) {
+        (true, true) => 1.0,
+        (true, false) | (false, true) => 0.0,
+        (false, false) => {
+            let (common, union) = count::distinct_overlap(&a_tokens, &b_tokens);
+            common as f64 / union as f64
+        }
+    };
+    let similarity = MinHashSignature::from_tokens(&a_tokens, DEFAULT_NUM_HASHES).similarity(
+        &MinHashSignature::from_tokens(&b_tokens, DEFAULT_NUM_HASHES),
+    );
+
+    MessageComparison {
+        lexical_overlap,
+        similarity,
+        drift_a: topic_drift_of(&a_tokens, topic.tokens()),
+        drift_b: topic_drift_of(&b_tokens, topic.tokens()),
+    }
+}
+
+/// Default largest side of a similarity matrix.
+pub const DEFAULT_MATRIX_DIM: usize = 64;
+
+/// Square unit-by-unit similarity matrix, flattened row-major for compact
+/// export to heat-map tools.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SimilarityMatrix {
+    /// Rows (and columns) after downsampling.
+    pub size: usize,
+    /// Number of sentences or turns before downsampling.
+    pub units: usize,
+    /// Index of the first unit in each row's bucket.
+    pub bucket_starts: Vec<usize>,
+    /// `size * size` token-Jaccard similarities rounded to 3 decimals.
+    pub values: Vec<f64>,
+}
+
+impl SimilarityMatrix {
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.values[row * self.size + col]
+    }
+}
+
+/// Pairwise similarity of `units` (sentences of one message, or the turns
+/// of a transcript). Past `max_dim` units, consecutive units are grouped
+/// into `max_dim` buckets and each cell averages its bucket pairs.
+pub fn similarity_matrix<S: AsRef<str>>(
+    units: &[S],
+    max_dim: usize,
+    cfg: &WordMathConfig,
+) -> SimilarityMatrix {
+    let tokens: Vec<Vec<String>> = units
+        .iter()
+        .map(|u| self::tokens(u.as_ref(), cfg))
+        .collect();
+    let n = tokens.len();
+    let size = n.min(max_dim.max(1));
+    let bucket_starts: Vec<usize> = (0..size).map(|b| b * n / size).collect();
+    let bucket = |b: usize| bucket_starts[b]..bucket_starts.get(b + 1).copied().unwrap_or(n);
+
+    let jaccard = |a: &[String], b: &[String]| {
+        if a.is_empty() || b.is_empty() {
+            return 0.0;
+        }
+        let (common, union) = count::distinct_overlap(a, b);
+        common as f64 / union as f64
+    };
+
+    let mut values = vec![0.0; size * size];
+    for row in 0..size {
+        for col in row..size {
+            let mut sum = 0.0;
+            let mut cells = 0usize;
+            for i in bucket(row) {
+                for j in bucket(col) {
+                    sum += if i == j {
+                        1.0
+                    } else {
+                        jaccard(&tokens[i], &tokens[j])
+                    };
+                    cells += 1;
+                }
+            }
+            let value = (sum / cells as f64 * 1000.0).round() / 1000.0;
+            values[row * size + col] = value;
+            values[col * size + row] = value;
+        }
+    }
+
+    SimilarityMatrix {
+        size,
+        units: n,
+        bucket_starts,
+        values,
+    }
+}
+
+/// `similarity_matrix` over the sentences of one message.
+pub fn message_similarity_matrix(
+    message: &str,
+    max_dim: usize,
+    cfg: &WordMathConfig,
+) -> SimilarityMatrix {
+    similarity_matrix(&text::sentences(message), max_dim, cfg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regenerated_answer_is_near_duplicate() {
+        let cfg = WordMathConfig::default();
+        let topic = "rust async runtime";
+        let same = compare_messages(
+            "tokio is the most common rust async runtime",
+            "Tokio is the most common Rust async runtime.",
+            topic,
+            cfg,
+        );
+        assert_eq!(same.lexical_overlap, 1.0);
+        assert_eq!(same.similarity, 1.0);
+        assert_eq!(same.drift_delta(), 0.0);
+
+        let different = compare_messages(
+            "tokio is the most common rust async runtime",
+            "my cat enjoys sleeping in the sun",
+            topic,
+            cfg,
+        );
+        assert!(different.lexical_overlap < 0.2);
+        assert!(different.similarity < 0.2);
+        assert!(different.drift_delta() > 0.0);
+    }
+
+    #[test]
+    fn test_similarity_matrix_symmetric_and_downsampled() {
+        let cfg = WordMathConfig::default();
+        let message = "Rust is fast. Buy now today. Rust is safe. Buy now today.";
+        let matrix = message_similarity_matrix(message, DEFAULT_MATRIX_DIM, &cfg);
+        assert_eq!(matrix.size, 4);
+        assert_eq!(matrix.get(1, 3), 1.0);
+        assert_eq!(matrix.get(0, 1), 0.0);
+        assert_eq!(matrix.get(0, 2), matrix.get(2, 0));
+
+        let small = message_similarity_matrix(message, 2, &cfg);
+        assert_eq!(small.size, 2);
+        assert_eq!(small.units, 4);
+        assert_eq!(small.bucket_starts, vec![0, 2]);
+        assert_eq!(small.values.len(), 4);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::verdict::{self, Verdict};
+use crate::{analyze_message_with_trace, WordMathAnalysis, WordMathConfig, WordMathTrace};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Default weight of the newest turn in the smoothed session score.
+pub const DEFAULT_SMOOTHING: f64 = 0.3;
+
+/// Default number of recent turns attached to each `TurnResult`.
+pub const DEFAULT_TRACE_HISTORY: usize = 5;
+
+/// Default number of turns kept in the session history. Older turns only
+/// live on in the smoothed score, so a long-lived session stays bounded.
+pub const DEFAULT_HISTORY_LIMIT: usize = 64;
+
+/// Per-turn metrics kept in the session history.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TurnSummary {
+    pub y_repetition: f64,
+    pub z_drift: f64,
+    pub score: f64,
+}
+
+/// Result of pushing one message into a conversation.
+#[derive(Debug, Clone)]
+pub struct TurnResult {
+    /// 1-based turn number.
+    pub turn: usize,
+    pub analysis: WordMathAnalysis,
+    pub trace: WordMathTrace,
+    /// Verdict for this message alone.
+    pub verdict: Verdict,
+    /// Exponentially smoothed score over all turns so far.
+    pub session_score: f64,
+    /// Verdict for the smoothed session score.
+    pub session_verdict: Verdict,
+    /// The last few turns (including this one), oldest first, so a single
+    /// trace record shows the trajectory that led to its verdict.
+    pub recent: Vec<TurnSummary>,
+}
+
+/// Serializable state of a `ConversationAnalyzer`, for migrating sessions
+/// between deployments or attaching them to bug reports.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationSnapshot {
+    pub topic: String,
+    pub config: WordMathConfig,
+    pub smoothing: f64,
+    pub session_score: Option<f64>,
+    /// The most recent turns, oldest first.
+    pub history: Vec<TurnSummary>,
+    /// Turns scored in total, including ones no longer in `history`.
+    /// Snapshots written before the history was bounded omit it.
+    #[serde(default)]
+    pub turns: usize,
+}
+
+/// Rolling scorer for a sequence of messages against one topic.
+///
+/// Each turn is scored on its own; the session score is an exponential
+/// moving average so that a single bad turn warns, but only a sustained
+/// run of bad turns drags the whole session into Block.
+#[derive(Debug, Clone)]
+pub struct ConversationAnalyzer {
+    topic: String,
+    cfg: WordMathConfig,
+    smoothing: f64,
+    session_score: Option<f64>,
+    history: VecDeque<TurnSummary>,
+    history_limit: usize,
+    turns: usize,
+    trace_history: usize,
+}
+
+impl ConversationAnalyzer {
+    pub fn new(topic: impl Into<String>, cfg: WordMathConfig) -> Self {
+        Self {
+            topic: topic.into(),
+            cfg,
+            smoothing: DEFAULT_SMOOTHING,
+            session_score: None,
+            history: VecDeque::new(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            turns: 0,
+            trace_history: DEFAULT_TRACE_HISTORY,
+        }
+    }
+
+    /// Set the EMA weight of the newest turn, clamped to (0, 1].
+    pub fn with_smoothing(mut self, smoothing: f64) -> Self {
+        self.smoothing = smoothing.clamp(f64::EPSILON, 1.0);
+        self
+    }
+
+    /// Number of recent turns copied into each `TurnResult`; 0 disables.
+    /// The history limit grows to match when it is smaller.
+    pub fn with_trace_history(mut self, turns: usize) -> Self {
+        self.trace_history = turns;
+        self.history_limit = self.history_limit.max(turns);
+        self
+    }
+
+    /// Number of turns kept in the history (at least 1); older ones are
+    /// dropped as new turns arrive.
+    pub fn with_history_limit(mut self, turns: usize) -> Self {
+        self.history_limit = turns.max(self.trace_history).max(1);
+        self.trim_history();
+        self
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn config(&self) -> &WordMathConfig {
+        &self.cfg
+    }
+
+    pub fn turns(&self) -> usize {
+        self.turns
+    }
+
+    /// The last turns, up to the history limit, oldest first.
+    pub fn history(&self) -> &VecDeque<TurnSummary> {
+        &self.history
+    }
+
+    /// The last `k` turns still in the history, oldest first.
+    pub fn recent(&self, k: usize) -> Vec<TurnSummary> {
+        let skip = self.history.len().saturating_sub(k);
+        self.history.iter().skip(skip).copied().collect()
+    }
+
+    fn trim_history(&mut self) {
+        while self.history.len() > self.history_limit {
+            self.history.pop_front();
+        }
+    }
+
+    /// Smoothed score so far; 1.0 before the first turn.
+    pub fn session_score(&self) -> f64 {
+        self.session_score.unwrap_or(1.0)
+    }
+
+    /// Verdict for a session score under this analyzer's thresholds.
+    pub fn session_verdict(&self) -> Verdict {
+        let t = self.cfg.thresholds;
+        let score = self.session_score();
+        if score <= t.block_max {
+            Verdict::Block
+        } else if score <= t.warn_max {
+            Verdict::Warn
+        } else {
+            Verdict::Allow
+        }
+    }
+
+    /// Capture the full state; `restore` of the result continues identically.
+    pub fn snapshot(&self) -> ConversationSnapshot {
+        ConversationSnapshot {
+            topic: self.topic.clone(),
+            config: self.cfg,
+            smoothing: self.smoothing,
+            session_score: self.session_score,
+            history: self.history.iter().copied().collect(),
+            turns: self.turns,
+        }
+    }
+
+    /// Rebuild an analyzer from a snapshot, keeping the newest turns of
+    /// its history up to the default limit.
+    pub fn restore(snapshot: ConversationSnapshot) -> Self {
+        let turns = snapshot.turns.max(snapshot.history.len());
+        let mut restored = Self {
+            topic: snapshot.topic,
+            cfg: snapshot.config,
+            smoothing: snapshot.smoothing.clamp(f64::EPSILON, 1.0),
+            session_score: snapshot.session_score,
+            history: snapshot.history.into(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            turns,
+            trace_history: DEFAULT_TRACE_HISTORY,
+        };
+        restored.trim_history();
+        restored
+    }
+
+    /// Score the next message and update the session state.
+    pub fn push(&mut self, message: &str) -> TurnResult {
+        let (analysis, trace) = analyze_message_with_trace(message, &self.topic, self.cfg);
+        let verdict = verdict::evaluate(&analysis, &self.cfg).verdict;
+
+        let session_score = match self.session_score {
+            None => analysis.score,
+            Some(prev) => self.smoothing * analysis.score + (1.0 - self.smoothing) * prev,
+        };
+        self.session_score = Some(session_score);
+        self.history.push_back(TurnSummary {
+            y_repetition: analysis.y_repetition,
+            z_drift: analysis.z_drift,
+            score: analysis.score,
+        });
+        self.trim_history();
+        self.turns += 1;
+
+        TurnResult {
+            turn: self.turns,
+            analysis,
+            trace,
+            verdict,
+            session_score,
+            session_verdict: self.session_verdict(),
+            recent: self.recent(self.trace_history),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_score_smooths_single_bad_turn() {
+        let mut conv = ConversationAnalyzer::new("rust web server", WordMathConfig::default());
+        assert_eq!(conv.session_score(), 1.0);
+
+        for _ in 0..3 {
+            conv.push("rust web server");
+        }
+        let bad = conv.push("buy buy buy buy now");
+        assert_eq!(bad.turn, 4);
+        assert_eq!(bad.verdict, Verdict::Block);
+        assert_ne!(bad.session_verdict, Verdict::Block);
+        assert_eq!(conv.history().len(), 4);
+        assert_eq!(bad.recent.len(), 4);
+        assert_eq!(bad.recent.last().unwrap().score, bad.analysis.score);
+    }
+
+    #[test]
+    fn test_trace_history_is_bounded() {
+        let mut conv =
+            ConversationAnalyzer::new("rust", WordMathConfig::default()).with_trace_history(2);
+        for _ in 0..5 {
+            conv.push("rust");
+        }
+        assert_eq!(conv.push("rust").recent.len(), 2);
+
+        let mut off =
+            ConversationAnalyzer::new("rust", WordMathConfig::default()).with_trace_history(0);
+        assert!(off.push("rust").recent.is_empty());
+    }
+
+    #[test]
+    fn test_history_keeps_only_the_last_turns() {
+        let mut conv = ConversationAnalyzer::new("rust", WordMathConfig::default())
+            .with_trace_history(3)
+            .with_history_limit(4);
+        for i in 0..10 {
+            conv.push(if i % 2 == 0 { "rust" } else { "buy buy now" });
+        }
+        let last = conv.push("rust rust");
+        assert_eq!(last.turn, 11);
+        assert_eq!(conv.turns(), 11);
+        assert_eq!(conv.history().len(), 4);
+        assert_eq!(conv.history().back().unwrap().score, last.analysis.score);
+        assert_eq!(last.recent.len(), 3);
+
+        let snapshot = conv.snapshot();
+        assert_eq!((snapshot.turns, snapshot.history.len()), (11, 4));
+        assert_eq!(
+            ConversationAnalyzer::restore(snapshot).push("rust").turn,
+            12
+        );
+    }
+
+    #[test]
+    fn test_sustained_bad_turns_block_session() {
+        let mut conv = ConversationAnalyzer::new("rust web server", WordMathConfig::default())
+            .with_smoothing(0.5);
+        let mut last = None;
+        for _ in 0..6 {
+            last = Some(conv.push("buy buy buy buy now"));
+        }
+        assert_eq!(last.unwrap().session_verdict, Verdict::Block);
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip_continues_identically() {
+        let mut original = ConversationAnalyzer::new("rust web server", WordMathConfig::default());
+        original.push("rust web server");
+        original.push("buy buy buy now");
+
+        let json = serde_json::to_string(&original.snapshot()).unwrap();
+        let mut restored = ConversationAnalyzer::restore(serde_json::from_str(&json).unwrap());
+        assert_eq!(restored.snapshot(), original.snapshot());
+
+        let a = original.push("rust axum");
+        let b = restored.push("rust axum");
+        assert_eq!(a.session_score, b.session_score);
+        assert_eq!(a.turn, b.turn);
+    }
+}
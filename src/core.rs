@@ -0,0 +1,166 @@
+//! Pure lexical metrics over pre-tokenized input, usable without `std`.
+//!
+//! Everything here depends only on `core` and `alloc` (no hashing, no I/O,
+//! no float intrinsics from `std`), so the module can be copied into a
+//! `#![no_std]` crate for embedded gateways or sandboxed agents that bring
+//! their own tokenizer. The crate-level pipeline uses the faster hashed
+//! counters in `count`; results are identical.
+
+use ::core::cmp::Ordering;
+use alloc::vec::Vec;
+
+fn sorted<S: AsRef<str>>(tokens: &[S]) -> Vec<&str> {
+    let mut sorted: Vec<&str> = tokens.iter().map(AsRef::as_ref).collect();
+    sorted.sort_unstable();
+    sorted
+}
+
+/// Occurrence counts of the distinct tokens, in token order.
+fn run_lengths(sorted: &[&str]) -> Vec<usize> {
+    let mut runs = Vec::new();
+    let mut prev: Option<&str> = None;
+    for &token in sorted {
+        match runs.last_mut() {
+            Some(run) if prev == Some(token) => *run += 1,
+            _ => runs.push(1),
+        }
+        prev = Some(token);
+    }
+    runs
+}
+
+/// Repetition density y = max_w c(w) / n; 0 for empty input.
+pub fn repetition_density<S: AsRef<str>>(tokens: &[S]) -> f64 {
+    if tokens.is_empty() {
+        return 0.0;
+    }
+    let max = run_lengths(&sorted(tokens)).into_iter().max().unwrap_or(0);
+    max as f64 / tokens.len() as f64
+}
+
+/// Base-2 logarithm for x > 0, without `std`.
+///
+/// Splits x into 2^e * m with m in [1, 2) and sums the atanh series for
+/// ln(m), which converges quickly since (m - 1) / (m + 1) <= 1/3.
+pub(crate) fn log2(x: f64) -> f64 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let mantissa = f64::from_bits((bits & 0x000f_ffff_ffff_ffff) | 0x3ff0_0000_0000_0000);
+    let s = (mantissa - 1.0) / (mantissa + 1.0);
+    let s2 = s * s;
+    let mut term = s;
+    let mut ln = 0.0;
+    for k in 0..16 {
+        ln += term / (2 * k + 1) as f64;
+        term *= s2;
+    }
+    exponent as f64 + 2.0 * ln / ::core::f64::consts::LN_2
+}
+
+/// Shannon entropy of the token distribution, in bits; 0 for empty input.
+pub fn token_entropy<S: AsRef<str>>(tokens: &[S]) -> f64 {
+    let n = tokens.len() as f64;
+    run_lengths(&sorted(tokens))
+        .into_iter()
+        .map(|count| {
+            let p = count as f64 / n;
+            -p * log2(p)
+        })
+        .sum()
+}
+
+/// Share of the message's word n-grams that also occur in `reference`.
+///
+/// Messages shorter than `n` tokens have no n-grams and score 0.
+pub fn ngram_coverage<S: AsRef<str>, R: AsRef<str>>(
+    message: &[S],
+    reference: &[R],
+    n: usize,
+) -> f64 {
+    if n == 0 || message.len() < n {
+        return 0.0;
+    }
+    let message: Vec<&str> = message.iter().map(AsRef::as_ref).collect();
+    let reference: Vec<&str> = reference.iter().map(AsRef::as_ref).collect();
+    let mut known: Vec<&[&str]> = reference.windows(n).collect();
+    known.sort_unstable();
+    let grams = message.len() - n + 1;
+    let covered = message
+        .windows(n)
+        .filter(|gram| known.binary_search(gram).is_ok())
+        .count();
+    covered as f64 / grams as f64
+}
+
+/// Jaccard similarity of the distinct tokens of `a` and `b`.
+///
+/// Two empty inputs are identical (1.0); one empty input shares nothing.
+pub fn jaccard<S: AsRef<str>, R: AsRef<str>>(a: &[S], b: &[R]) -> f64 {
+    let mut a = sorted(a);
+    let mut b = sorted(b);
+    a.dedup();
+    b.dedup();
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let (mut i, mut j, mut common) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(b[j]) {
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                common += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    common as f64 / (a.len() + b.len() - common) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let tokens = ["a", "b", "a", "c", "a", "b"];
+        assert_eq!(repetition_density(&tokens), 0.5);
+        assert_eq!(repetition_density::<&str>(&[]), 0.0);
+
+        assert!((token_entropy(&["x", "y", "x", "y"]) - 1.0).abs() < 1e-12);
+        let expected = -(0.5 * 0.5f64.log2() + 2.0 * (0.25 * 0.25f64.log2()));
+        assert!((token_entropy(&["a", "a", "b", "c"]) - expected).abs() < 1e-12);
+        for x in [1e-9, 0.3, 1.0, 7.5, 1e12] {
+            assert!((log2(x) - x.log2()).abs() < 1e-12, "log2({})", x);
+        }
+
+        let reference = ["reset", "your", "password", "here"];
+        assert_eq!(
+            ngram_coverage(&["reset", "your", "password"], &reference, 2),
+            1.0
+        );
+        assert_eq!(
+            ngram_coverage(&["your", "password", "now"], &reference, 2),
+            0.5
+        );
+        assert_eq!(ngram_coverage(&["reset"], &reference, 2), 0.0);
+
+        assert_eq!(jaccard(&["a", "b", "b", "c"], &["b", "c", "d"]), 0.5);
+        assert_eq!(jaccard::<&str, &str>(&[], &[]), 1.0);
+    }
+
+    #[test]
+    fn test_matches_crate_pipeline() {
+        let message = crate::text::tokenize("spam spam buy now spam", crate::EmojiMode::Strip);
+        let topic = crate::text::tokenize("buy groceries now", crate::EmojiMode::Strip);
+        assert_eq!(
+            repetition_density(&message),
+            crate::repetition_density_of(&message)
+        );
+        assert_eq!(
+            1.0 - jaccard(&message, &topic),
+            crate::topic_drift_of(&message, &topic)
+        );
+    }
+}
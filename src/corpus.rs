@@ -0,0 +1,336 @@
+//! Topic drift against a collection of reference documents.
+//!
+//! For RAG-style deployments the "topic" is a knowledge base rather than a
+//! sentence. A `Corpus` keeps the documents in a BM25 index (see `index`),
+//! and the drift of a message is one minus its best normalized relevance to
+//! any one document, so a message squarely about one reference document is
+//! on topic even if it shares little with the rest.
+//!
+//! A corpus can also serve as a domain for a plain topic:
+//! `domain_drift_of` keeps the topic but weighs every word by its IDF in
+//! the corpus, so words common across the domain ("please", "account" in
+//! a help center) count for little and its rare, specific terms for a lot.
+//! That adapts lexical drift to a domain without any model.
+
+use crate::index::Bm25Index;
+use crate::matching::MatchForm;
+use crate::repro::Arithmetic;
+use crate::text::{self, EmojiMode};
+use crate::WordMathError;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Reference documents indexed for BM25.
+#[derive(Debug, Clone)]
+pub struct Corpus {
+    index: Bm25Index,
+    grapheme_len: usize,
+}
+
+impl Corpus {
+    pub fn build(documents: &[&str], emoji_mode: EmojiMode) -> Self {
+        let mut index = Bm25Index::new(emoji_mode);
+        for (i, document) in documents.iter().enumerate() {
+            index.add(&format!("{:06}", i), document);
+        }
+        Self::from_index(index)
+    }
+
+    /// Wrap an existing index, e.g. one loaded with `Bm25Index::load`.
+    pub fn from_index(index: Bm25Index) -> Self {
+        let grapheme_len = index
+            .ids()
+            .filter_map(|id| index.text(id))
+            .map(text::grapheme_len)
+            .sum();
+        Self {
+            index,
+            grapheme_len,
+        }
+    }
+
+    pub fn index(&self) -> &Bm25Index {
+        &self.index
+    }
+
+    pub fn emoji_mode(&self) -> EmojiMode {
+        self.index.emoji_mode()
+    }
+
+    /// The same documents indexed with another emoji mode.
+    pub fn with_emoji_mode(&self, emoji_mode: EmojiMode) -> Self {
+        let mut index = Bm25Index::with_params(emoji_mode, self.index.params());
+        for id in self.index.ids() {
+            index.add(id, self.index.text(id).unwrap_or_default());
+        }
+        Self::from_index(index)
+    }
+
+    /// Combined length of the documents in grapheme clusters.
+    pub fn grapheme_len(&self) -> usize {
+        self.grapheme_len
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Best relevance of the message tokens to any document, in [0, 1].
+    pub fn relevance_of(&self, tokens: &[String]) -> f64 {
+        self.index.best_relevance(tokens)
+    }
+
+    /// Like `relevance_of`, computed with `math`.
+    pub fn relevance_with(&self, tokens: &[String], math: Arithmetic) -> f64 {
+        self.index.best_relevance_with(tokens, math)
+    }
+}
+
+/// Corpus drift z = 1 - best normalized BM25 relevance to any document.
+pub fn corpus_drift_of(tokens: &[String], corpus: &Corpus) -> f64 {
+    corpus_drift_with(tokens, corpus, Arithmetic::Native)
+}
+
+/// Like `corpus_drift_of`, computed with `math`.
+pub fn corpus_drift_with(tokens: &[String], corpus: &Corpus, math: Arithmetic) -> f64 {
+    match (tokens.is_empty(), corpus.is_empty()) {
+        (true, true) => 0.0,
+        (true, false) | (false, true) => 1.0,
+        (false, false) => 1.0 - corpus.relevance_with(tokens, math),
+    }
+}
+
+/// Topic drift with words weighted by their IDF in `domain`:
+/// z = 1 - w(M ∩ T) / w(M ∪ T) over the distinct keys of the message (M)
+/// and topic (T) tokens in `form`. A key weighs the highest IDF among the
+/// tokens it stands for; words the domain never saw weigh the most.
+pub fn domain_drift_of(
+    tokens: &[String],
+    topic: &[String],
+    domain: &Corpus,
+    form: MatchForm,
+) -> f64 {
+    domain_drift_with(tokens, topic, domain, form, Arithmetic::Native)
+}
+
+/// Like `domain_drift_of`, computed with `math`.
+pub fn domain_drift_with(
+    tokens: &[String],
+    topic: &[String],
+    domain: &Corpus,
+    form: MatchForm,
+    math: Arithmetic,
+) -> f64 {
+    match (tokens.is_empty(), topic.is_empty()) {
+        (true, true) => return 0.0,
+        (true, false) | (false, true) => return 1.0,
+        (false, false) => {}
+    }
+    let weigh = |tokens: &[String]| {
+        let mut keys: HashMap<String, f64> = HashMap::new();
+        for token in tokens {
+            let weight = keys.entry(form.key(token)).or_insert(0.0);
+            *weight = weight.max(domain.index.idf_with(token, math));
+        }
+        keys
+    };
+    let (message, topic) = (weigh(tokens), weigh(topic));
+    let mut union: Vec<(f64, bool)> = message
+        .iter()
+        .map(|(key, &w)| match topic.get(key) {
+            Some(&t) => (w.max(t), true),
+            None => (w, false),
+        })
+        .chain(
+            topic
+                .iter()
+                .filter(|(key, _)| !message.contains_key(*key))
+                .map(|(_, &t)| (t, false)),
+        )
+        .collect();
+    // Sum in a fixed order so the result doesn't depend on hashing.
+    union.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let total = math.sum(union.iter().map(|&(w, _)| w));
+    let shared = math.sum(union.iter().filter(|&&(_, s)| s).map(|&(w, _)| w));
+    if total > 0.0 {
+        1.0 - shared / total
+    } else {
+        1.0
+    }
+}
+
+/// Named corpora indexed once at startup.
+///
+/// Loaded from a JSON object mapping corpus IDs to document files; relative
+/// paths are resolved against the JSON file's directory:
+///
+/// ```json
+/// { "help-center": ["docs/refunds.md", "docs/passwords.md"] }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CorpusRegistry {
+    corpora: HashMap<String, Arc<Corpus>>,
+}
+
+impl CorpusRegistry {
+    /// Read the registry file and index every listed document.
+    pub fn load(path: impl AsRef<Path>, emoji_mode: EmojiMode) -> Result<Self, WordMathError> {
+        let path = path.as_ref();
+        let read = |path: &Path| {
+            std::fs::read_to_string(path)
+                .map_err(|e| WordMathError::Config(format!("{}: {}", path.display(), e)))
+        };
+        let raw: HashMap<String, Vec<String>> = serde_json::from_str(&read(path)?)
+            .map_err(|e| WordMathError::Config(format!("{}: {}", path.display(), e)))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut corpora = HashMap::new();
+        for (id, files) in raw {
+            let mut index = Bm25Index::new(emoji_mode);
+            for file in &files {
+                index.add(file, &read(&base.join(file))?);
+            }
+            corpora.insert(id, Arc::new(Corpus::from_index(index)));
+        }
+        Ok(Self { corpora })
+    }
+
+    /// Load the file named by WORD_MATH_CORPORA, or an empty registry if unset.
+    pub fn from_env(emoji_mode: EmojiMode) -> Result<Self, WordMathError> {
+        match std::env::var("WORD_MATH_CORPORA") {
+            Ok(path) => Self::load(path, emoji_mode),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Corpus>> {
+        self.corpora.get(id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.corpora.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.corpora.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(s: &str) -> Vec<String> {
+        text::tokenize(s, EmojiMode::Strip)
+    }
+
+    #[test]
+    fn test_drift_follows_best_document() {
+        let corpus = Corpus::build(
+            &[
+                "refunds are issued to the original payment method within five days",
+                "kubernetes deployments roll out new pods gradually",
+                "reset your password from the account settings page",
+            ],
+            EmojiMode::Strip,
+        );
+        let on_topic = corpus_drift_of(
+            &tokens("how do kubernetes deployments roll out pods"),
+            &corpus,
+        );
+        let mixed = corpus_drift_of(
+            &tokens("kubernetes pods and my favourite pizza toppings"),
+            &corpus,
+        );
+        let off_topic = corpus_drift_of(&tokens("best pizza toppings tonight"), &corpus);
+
+        assert!(on_topic < 0.5, "on topic drift {}", on_topic);
+        assert!(on_topic < mixed && mixed < off_topic);
+        assert_eq!(off_topic, 1.0);
+    }
+
+    #[test]
+    fn test_reproducible_drift_agrees_with_native() {
+        let corpus = Corpus::build(
+            &[
+                "refunds are issued to the original payment method",
+                "kubernetes deployments roll out new pods gradually",
+            ],
+            EmojiMode::Strip,
+        );
+        let message = tokens("kubernetes refunds roll out to the payment method");
+        let native = corpus_drift_with(&message, &corpus, Arithmetic::Native);
+        let reproducible = corpus_drift_with(&message, &corpus, Arithmetic::Reproducible);
+        assert!((native - reproducible).abs() < 1e-12);
+        assert_eq!(
+            reproducible.to_bits(),
+            corpus_drift_with(&message, &corpus.clone(), Arithmetic::Reproducible).to_bits()
+        );
+    }
+
+    #[test]
+    fn test_domain_weights_discount_common_words() {
+        let domain = Corpus::build(
+            &[
+                "please sign in to your account to see your orders",
+                "please update your account email address",
+                "your account password can be reset from settings",
+                "please contact support about your account",
+            ],
+            EmojiMode::Strip,
+        );
+        let topic = tokens("account password reset");
+        let generic = tokens("please check my account");
+        let specific = tokens("my password reset failed");
+
+        let weighted =
+            |message: &[String]| domain_drift_of(message, &topic, &domain, MatchForm::Exact);
+        // "account" is everywhere in the domain, "password" and "reset"
+        // are not.
+        assert!(weighted(&generic) > crate::topic_drift_of(&generic, &topic));
+        assert!(weighted(&specific) < weighted(&generic) - 0.2);
+        assert_eq!(domain_drift_of(&[], &topic, &domain, MatchForm::Exact), 1.0);
+    }
+
+    #[test]
+    fn test_reproducible_domain_drift_agrees_with_native() {
+        let domain = Corpus::build(
+            &[
+                "please sign in to your account to see your orders",
+                "your account password can be reset from settings",
+                "kubernetes deployments roll out new pods gradually",
+            ],
+            EmojiMode::Strip,
+        );
+        let topic = tokens("account password reset");
+        let message = tokens("my kubernetes password reset rolled out to the account");
+        let drift =
+            |math: Arithmetic| domain_drift_with(&message, &topic, &domain, MatchForm::Exact, math);
+        let (native, reproducible) = (drift(Arithmetic::Native), drift(Arithmetic::Reproducible));
+        assert!((native - reproducible).abs() < 1e-12);
+        assert_eq!(
+            reproducible.to_bits(),
+            domain_drift_with(
+                &message,
+                &topic,
+                &domain.clone(),
+                MatchForm::Exact,
+                Arithmetic::Reproducible
+            )
+            .to_bits()
+        );
+    }
+
+    #[test]
+    fn test_empty_inputs() {
+        let empty = Corpus::build(&[], EmojiMode::Strip);
+        assert_eq!(corpus_drift_of(&[], &empty), 0.0);
+        assert_eq!(corpus_drift_of(&tokens("hello"), &empty), 1.0);
+        let corpus = Corpus::build(&["hello world"], EmojiMode::Strip);
+        assert_eq!(corpus_drift_of(&[], &corpus), 1.0);
+    }
+}
@@ -0,0 +1,101 @@
+//! Token counting primitives behind the lexical metrics.
+//!
+//! Most server traffic is short chat messages, so inputs of up to
+//! `SMALL_TOKENS` tokens are counted by sorting borrowed slices in a
+//! stack-allocated `SmallVec` (no hashing, no heap). Longer inputs fall
+//! back to `FxHashMap` / `FxHashSet`.
+
+use crate::hash::{FxHashMap, FxHashSet};
+use smallvec::SmallVec;
+
+/// Inputs up to this many tokens take the allocation-free path.
+#[cfg(not(feature = "f32"))]
+pub const SMALL_TOKENS: usize = 64;
+/// With the `f32` feature the inline buffer shrinks to keep stack and code
+/// size down on embedded / WASM targets.
+#[cfg(feature = "f32")]
+pub const SMALL_TOKENS: usize = 16;
+
+type SmallTokens<'a> = SmallVec<[&'a str; SMALL_TOKENS]>;
+
+fn sorted_small(tokens: &[String]) -> SmallTokens<'_> {
+    let mut sorted: SmallTokens<'_> = tokens.iter().map(String::as_str).collect();
+    sorted.sort_unstable();
+    sorted
+}
+
+/// Highest occurrence count of any single token.
+pub fn max_token_count(tokens: &[String]) -> usize {
+    if tokens.len() <= SMALL_TOKENS {
+        let sorted = sorted_small(tokens);
+        let mut best = 0;
+        let mut run = 0;
+        let mut prev: Option<&str> = None;
+        for &token in &sorted {
+            run = if prev == Some(token) { run + 1 } else { 1 };
+            best = best.max(run);
+            prev = Some(token);
+        }
+        return best;
+    }
+
+    let mut counts: FxHashMap<&str, usize> = FxHashMap::default();
+    counts.reserve(tokens.len());
+    for token in tokens {
+        *counts.entry(token.as_str()).or_insert(0) += 1;
+    }
+    counts.values().copied().max().unwrap_or(0)
+}
+
+/// Sizes of the intersection and union of the distinct tokens of `a` and `b`.
+pub fn distinct_overlap(a: &[String], b: &[String]) -> (usize, usize) {
+    if a.len() <= SMALL_TOKENS && b.len() <= SMALL_TOKENS {
+        let mut a = sorted_small(a);
+        let mut b = sorted_small(b);
+        a.dedup();
+        b.dedup();
+
+        let (mut i, mut j, mut common) = (0, 0, 0);
+        while i < a.len() && j < b.len() {
+            match a[i].cmp(b[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    common += 1;
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        return (common, a.len() + b.len() - common);
+    }
+
+    let a: FxHashSet<&str> = a.iter().map(String::as_str).collect();
+    let b: FxHashSet<&str> = b.iter().map(String::as_str).collect();
+    let common = a.intersection(&b).count();
+    (common, a.len() + b.len() - common)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toks(words: &str) -> Vec<String> {
+        words.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_small_and_large_paths_agree() {
+        let short = toks("a b a c a b");
+        assert_eq!(max_token_count(&short), 3);
+
+        let long: Vec<String> = (0..200).map(|i| format!("w{}", i % 7)).collect();
+        assert!(long.len() > SMALL_TOKENS);
+        assert_eq!(max_token_count(&long), 29);
+
+        assert_eq!(distinct_overlap(&toks("a b b c"), &toks("b c d")), (2, 4));
+        let other: Vec<String> = (0..100).map(|i| format!("w{}", i % 3)).collect();
+        assert_eq!(distinct_overlap(&long, &other), (3, 7));
+        assert_eq!(distinct_overlap(&[], &[]), (0, 0));
+    }
+}
@@ -0,0 +1,177 @@
+//! Detection of tricks that disguise spam from the metrics.
+//!
+//! Zero-width padding is stripped by `text::normalize` and reported as
+//! `obfuscation`. Two more tricks are handled here:
+//!
+//! - homoglyphs: Latin words spelled with Cyrillic or Greek lookalikes
+//!   ("frее" with Cyrillic e), fullwidth letters ("ｆｒｅｅ") or math
+//!   alphanumerics ("𝐟𝐫𝐞𝐞"). With normalization on, such tokens are folded
+//!   back to plain Latin before scoring, so repetition and drift see the
+//!   word it imitates. A lookalike only counts inside a token that also
+//!   has Latin letters, so Russian or Greek words are left alone.
+//! - split tokens: words spelled out letter by letter ("f r e e"), which
+//!   turn one repeated word into a handful of unrelated letters. Runs of
+//!   `SPLIT_RUN` or more single Latin letters are counted.
+//!
+//! `risk` combines the three shares into `WordMathAnalysis::evasion_risk`.
+//! Synonym cycling is left to the existing metrics: rotating synonyms
+//! keeps repetition low but drifts off the topic.
+
+/// Shortest run of single-letter tokens counted as a split word.
+pub const SPLIT_RUN: usize = 3;
+
+/// `evasion_risk` from which a message is considered disguised; e.g. a
+/// rule `evasion_risk >= 0.1`.
+pub const RISK_FLAG: f64 = 0.1;
+
+/// The Latin letter a Cyrillic or Greek lowercase lookalike imitates.
+fn lookalike(c: char) -> Option<char> {
+    Some(match c {
+        'а' | 'α' => 'a',
+        'с' | 'ϲ' => 'c',
+        'ԁ' => 'd',
+        'е' => 'e',
+        'һ' => 'h',
+        'і' | 'ι' => 'i',
+        'ј' => 'j',
+        'к' | 'κ' => 'k',
+        'о' | 'ο' => 'o',
+        'р' | 'ρ' => 'p',
+        'ԛ' => 'q',
+        'ѕ' => 's',
+        'υ' => 'u',
+        'ν' => 'v',
+        'ԝ' => 'w',
+        'х' | 'χ' => 'x',
+        'у' => 'y',
+        _ => return None,
+    })
+}
+
+/// The ASCII letter or digit a fullwidth or mathematical alphanumeric
+/// char stands for, lowercased.
+fn styled(c: char) -> Option<char> {
+    let code = c as u32;
+    let ascii = match code {
+        0xFF10..=0xFF19 => b'0' + (code - 0xFF10) as u8,
+        0xFF21..=0xFF3A => b'a' + (code - 0xFF21) as u8,
+        0xFF41..=0xFF5A => b'a' + (code - 0xFF41) as u8,
+        // Bold, italic, script, ... : 13 styles of A-Z followed by a-z.
+        0x1D400..=0x1D6A3 => b'a' + ((code - 0x1D400) % 52 % 26) as u8,
+        0x1D7CE..=0x1D7FF => b'0' + ((code - 0x1D7CE) % 10) as u8,
+        _ => return None,
+    };
+    Some(ascii as char)
+}
+
+/// `token` with homoglyphs replaced by the Latin letters they imitate, or
+/// None when it has none.
+pub fn fold_homoglyphs(token: &str) -> Option<String> {
+    let latin = token.chars().any(|c| c.is_ascii_alphabetic());
+    let mut changed = false;
+    let folded: String = token
+        .chars()
+        .map(|c| {
+            let plain = styled(c).or_else(|| lookalike(c).filter(|_| latin));
+            changed |= plain.is_some();
+            plain.unwrap_or(c)
+        })
+        .collect();
+    changed.then_some(folded)
+}
+
+/// Fold the homoglyphs of every token in place; returns how many tokens
+/// had any.
+pub fn fold_tokens(tokens: &mut [String]) -> usize {
+    let mut folded = 0;
+    for token in tokens {
+        if let Some(plain) = fold_homoglyphs(token) {
+            *token = plain;
+            folded += 1;
+        }
+    }
+    folded
+}
+
+/// True for a token that is one Latin letter.
+pub fn is_split_letter(token: &str) -> bool {
+    let mut chars = token.chars();
+    matches!((chars.next(), chars.next()), (Some(c), None) if c.is_ascii_alphabetic())
+}
+
+/// Running count of split letters, fed one token at a time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SplitCounter {
+    run: usize,
+    /// Tokens in runs of at least `SPLIT_RUN` single letters.
+    pub letters: usize,
+}
+
+impl SplitCounter {
+    pub fn push(&mut self, token: &str) {
+        if !is_split_letter(token) {
+            self.run = 0;
+            return;
+        }
+        self.run += 1;
+        match self.run.cmp(&SPLIT_RUN) {
+            std::cmp::Ordering::Less => {}
+            std::cmp::Ordering::Equal => self.letters += SPLIT_RUN,
+            std::cmp::Ordering::Greater => self.letters += 1,
+        }
+    }
+}
+
+/// Tokens of `tokens` in runs of at least `SPLIT_RUN` single letters.
+pub fn split_letters(tokens: &[String]) -> usize {
+    let mut counter = SplitCounter::default();
+    for token in tokens {
+        counter.push(token);
+    }
+    counter.letters
+}
+
+/// Combined evasion risk in [0, 1]: the chance that at least one trick
+/// is in play, treating the padded char share, the homoglyph token share
+/// and the split letter share as independent.
+pub fn risk(obfuscation: f64, homoglyphs: usize, split_letters: usize, tokens: usize) -> f64 {
+    let share = |count: usize| {
+        if tokens == 0 {
+            0.0
+        } else {
+            (count as f64 / tokens as f64).min(1.0)
+        }
+    };
+    let clean = (1.0 - obfuscation.clamp(0.0, 1.0))
+        * (1.0 - share(homoglyphs))
+        * (1.0 - share(split_letters));
+    1.0 - clean
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_homoglyphs_fold_to_latin_only_in_latin_tokens() {
+        assert_eq!(fold_homoglyphs("frее").as_deref(), Some("free"));
+        assert_eq!(fold_homoglyphs("ｆｒｅｅ").as_deref(), Some("free"));
+        assert_eq!(fold_homoglyphs("𝐟𝐫𝐞𝐞").as_deref(), Some("free"));
+        assert_eq!(fold_homoglyphs("𝑭𝒓𝒆𝒆").as_deref(), Some("free"));
+        assert_eq!(fold_homoglyphs("free"), None);
+        assert_eq!(fold_homoglyphs("привет"), None);
+        assert_eq!(fold_homoglyphs("сор"), None);
+    }
+
+    #[test]
+    fn test_split_letter_runs() {
+        let tokens: Vec<String> = "buy f r e e pills a b or x y z"
+            .split(' ')
+            .map(String::from)
+            .collect();
+        assert_eq!(split_letters(&tokens), 7);
+        assert_eq!(risk(0.0, 0, 0, 10), 0.0);
+        assert!((risk(0.0, 0, 5, 10) - 0.5).abs() < 1e-12);
+        assert!((risk(0.5, 0, 5, 10) - 0.75).abs() < 1e-12);
+    }
+}
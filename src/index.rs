@@ -0,0 +1,330 @@
+//! Incremental BM25 index over named documents.
+//!
+//! Used by `corpus` for drift against reference documents, and public so
+//! embedders can query relevance directly. Documents can be added, replaced
+//! and removed at any time; term statistics are updated in place. An index
+//! is saved as JSON holding the document texts and parameters, and
+//! re-tokenized on load.
+
+use crate::repro::Arithmetic;
+use crate::text::{self, EmojiMode};
+use crate::WordMathError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+const FILE_FORMAT: &str = "wordmath-bm25";
+const FILE_VERSION: u32 = 1;
+
+/// BM25 tuning constants.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bm25Params {
+    /// Term-frequency saturation.
+    pub k1: f64,
+    /// Document-length normalization strength, 0..=1.
+    pub b: f64,
+}
+
+impl Default for Bm25Params {
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IndexedDocument {
+    text: String,
+    len: usize,
+    term_counts: HashMap<String, u32>,
+}
+
+/// A scored search hit.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    pub id: String,
+    pub score: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredIndex {
+    format: String,
+    version: u32,
+    emoji_mode: EmojiMode,
+    params: Bm25Params,
+    documents: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bm25Index {
+    params: Bm25Params,
+    emoji_mode: EmojiMode,
+    documents: BTreeMap<String, IndexedDocument>,
+    doc_freq: HashMap<String, u32>,
+    total_len: usize,
+}
+
+impl Bm25Index {
+    pub fn new(emoji_mode: EmojiMode) -> Self {
+        Self::with_params(emoji_mode, Bm25Params::default())
+    }
+
+    pub fn with_params(emoji_mode: EmojiMode, params: Bm25Params) -> Self {
+        Self {
+            params,
+            emoji_mode,
+            documents: BTreeMap::new(),
+            doc_freq: HashMap::new(),
+            total_len: 0,
+        }
+    }
+
+    pub fn emoji_mode(&self) -> EmojiMode {
+        self.emoji_mode
+    }
+
+    pub fn params(&self) -> Bm25Params {
+        self.params
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.documents.contains_key(id)
+    }
+
+    /// Document IDs in sorted order.
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.documents.keys().map(String::as_str)
+    }
+
+    /// Original text of a document.
+    pub fn text(&self, id: &str) -> Option<&str> {
+        self.documents.get(id).map(|d| d.text.as_str())
+    }
+
+    /// Add a document, replacing any existing one with the same ID.
+    pub fn add(&mut self, id: &str, text: &str) {
+        self.remove(id);
+        let tokens = text::tokenize(text, self.emoji_mode);
+        let mut term_counts: HashMap<String, u32> = HashMap::new();
+        for token in &tokens {
+            *term_counts.entry(token.clone()).or_default() += 1;
+        }
+        for term in term_counts.keys() {
+            *self.doc_freq.entry(term.clone()).or_default() += 1;
+        }
+        self.total_len += tokens.len();
+        self.documents.insert(
+            id.to_string(),
+            IndexedDocument {
+                text: text.to_string(),
+                len: tokens.len(),
+                term_counts,
+            },
+        );
+    }
+
+    /// Remove a document; returns whether it was present.
+    pub fn remove(&mut self, id: &str) -> bool {
+        let Some(document) = self.documents.remove(id) else {
+            return false;
+        };
+        for term in document.term_counts.keys() {
+            if let Some(df) = self.doc_freq.get_mut(term) {
+                *df -= 1;
+                if *df == 0 {
+                    self.doc_freq.remove(term);
+                }
+            }
+        }
+        self.total_len -= document.len;
+        true
+    }
+
+    /// Inverse document frequency (the non-negative BM25+ variant).
+    pub fn idf(&self, term: &str) -> f64 {
+        self.idf_with(term, Arithmetic::Native)
+    }
+
+    pub(crate) fn idf_with(&self, term: &str, math: Arithmetic) -> f64 {
+        let n = self.documents.len() as f64;
+        let df = self.doc_freq.get(term).copied().unwrap_or(0) as f64;
+        math.ln(1.0 + (n - df + 0.5) / (df + 0.5))
+    }
+
+    fn score_document(
+        &self,
+        terms: &[&str],
+        idfs: &[f64],
+        document: &IndexedDocument,
+        math: Arithmetic,
+    ) -> f64 {
+        let Bm25Params { k1, b } = self.params;
+        let avg_len = self.total_len as f64 / self.documents.len().max(1) as f64;
+        let norm = 1.0 - b + b * document.len as f64 / avg_len.max(1.0);
+        math.sum(terms.iter().zip(idfs).map(|(term, idf)| {
+            let tf = document.term_counts.get(*term).copied().unwrap_or(0) as f64;
+            idf * tf * (k1 + 1.0) / (tf + k1 * norm)
+        }))
+    }
+
+    /// Distinct query terms, sorted so sums run in a fixed order, and
+    /// their IDFs.
+    fn query_terms<'a>(&self, tokens: &'a [String], math: Arithmetic) -> (Vec<&'a str>, Vec<f64>) {
+        let mut terms: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        terms.sort_unstable();
+        terms.dedup();
+        let idfs = terms.iter().map(|t| self.idf_with(t, math)).collect();
+        (terms, idfs)
+    }
+
+    /// BM25 score of the (distinct) query tokens against one document.
+    pub fn score(&self, tokens: &[String], id: &str) -> Option<f64> {
+        let document = self.documents.get(id)?;
+        let (terms, idfs) = self.query_terms(tokens, Arithmetic::Native);
+        Some(self.score_document(&terms, &idfs, document, Arithmetic::Native))
+    }
+
+    /// The `limit` best-scoring documents for a free-text query.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let tokens = text::tokenize(query, self.emoji_mode);
+        let (terms, idfs) = self.query_terms(&tokens, Arithmetic::Native);
+        let mut hits: Vec<SearchHit> = self
+            .documents
+            .iter()
+            .map(|(id, document)| SearchHit {
+                id: id.clone(),
+                score: self.score_document(&terms, &idfs, document, Arithmetic::Native),
+            })
+            .filter(|hit| hit.score > 0.0)
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+        hits.truncate(limit);
+        hits
+    }
+
+    /// Best relevance of the tokens to any document, in [0, 1].
+    ///
+    /// BM25 is normalized by the sum of the query terms' IDFs: the score a
+    /// document of average length reaches when it contains every query term
+    /// once. Query terms the index never saw carry the highest IDF, so
+    /// unknown words pull relevance down.
+    pub fn best_relevance(&self, tokens: &[String]) -> f64 {
+        self.best_relevance_with(tokens, Arithmetic::Native)
+    }
+
+    /// Like `best_relevance`, computed with `math`.
+    pub fn best_relevance_with(&self, tokens: &[String], math: Arithmetic) -> f64 {
+        let (terms, idfs) = self.query_terms(tokens, math);
+        let ideal = math.sum(idfs.iter().copied());
+        if ideal <= 0.0 {
+            return 0.0;
+        }
+        self.documents
+            .values()
+            .map(|document| self.score_document(&terms, &idfs, document, math) / ideal)
+            .fold(0.0, f64::max)
+            .min(1.0)
+    }
+
+    /// Write the index (document texts and parameters) as JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), WordMathError> {
+        let path = path.as_ref();
+        let stored = StoredIndex {
+            format: FILE_FORMAT.to_string(),
+            version: FILE_VERSION,
+            emoji_mode: self.emoji_mode,
+            params: self.params,
+            documents: self
+                .documents
+                .iter()
+                .map(|(id, d)| (id.clone(), d.text.clone()))
+                .collect(),
+        };
+        let json =
+            serde_json::to_string(&stored).map_err(|e| WordMathError::Config(e.to_string()))?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, json)
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .map_err(|e| WordMathError::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Read an index written by `save`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WordMathError> {
+        let path = path.as_ref();
+        let config_err = |e: String| WordMathError::Config(format!("{}: {}", path.display(), e));
+        let json = std::fs::read_to_string(path).map_err(|e| config_err(e.to_string()))?;
+        let stored: StoredIndex =
+            serde_json::from_str(&json).map_err(|e| config_err(e.to_string()))?;
+        if stored.format != FILE_FORMAT || stored.version != FILE_VERSION {
+            return Err(config_err(format!(
+                "unsupported index {} v{}",
+                stored.format, stored.version
+            )));
+        }
+        let mut index = Self::with_params(stored.emoji_mode, stored.params);
+        for (id, text) in &stored.documents {
+            index.add(id, text);
+        }
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> Bm25Index {
+        let mut index = Bm25Index::new(EmojiMode::Strip);
+        index.add("refunds", "refunds go back to the original payment method");
+        index.add("k8s", "kubernetes deployments roll out pods");
+        index.add("passwords", "reset your password in account settings");
+        index
+    }
+
+    #[test]
+    fn test_search_and_incremental_updates() {
+        let mut index = index();
+        let hits = index.search("kubernetes pods", 2);
+        assert_eq!(hits[0].id, "k8s");
+        assert_eq!(hits.len(), 1);
+
+        let before = index.idf("kubernetes");
+        index.add("helm", "helm charts template kubernetes manifests");
+        assert!(index.idf("kubernetes") < before);
+        assert_eq!(index.search("kubernetes", 5).len(), 2);
+
+        assert!(index.remove("helm"));
+        assert!(!index.remove("helm"));
+        assert!((index.idf("kubernetes") - before).abs() < 1e-12);
+
+        // Replacing a document keeps statistics consistent.
+        index.add("k8s", "pizza toppings");
+        assert!(index.search("kubernetes", 5).is_empty());
+        assert_eq!(index.len(), 3);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let index = index();
+        let path =
+            std::env::temp_dir().join(format!("wordmath-bm25-{}.json", crate::generate_hex_id()));
+        index.save(&path).unwrap();
+        let loaded = Bm25Index::load(&path).unwrap();
+        assert_eq!(
+            loaded.ids().collect::<Vec<_>>(),
+            ["k8s", "passwords", "refunds"]
+        );
+        assert_eq!(
+            loaded.search("reset password", 1),
+            index.search("reset password", 1)
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+//! Lightweight language identification.
+//!
+//! Non-Latin scripts are identified by their Unicode blocks; Latin-script
+//! text is matched against short lists of very frequent function words.
+//! This is enough to pick a per-language calibration, not a general-purpose
+//! detector: short or mixed messages may come back as `None`.
+
+/// Frequent function words per Latin-script language (ISO 639-1 codes).
+const FUNCTION_WORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "is", "of", "to", "in", "it", "you", "that", "this", "with", "for",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "und", "das", "ist", "nicht", "ich", "mit", "ein", "zu", "auf", "sie",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "et", "est", "des", "une", "pas", "que", "pour", "dans", "je",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "los", "las", "y", "es", "que", "una", "por", "para", "con", "del", "no",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "di", "che", "è", "per", "non", "una", "sono", "della", "gli", "con", "ho",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "os", "que", "não", "uma", "para", "com", "do", "da", "em", "é", "você",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "van", "is", "niet", "dat", "ik", "op", "te", "zijn",
+        ],
+    ),
+    (
+        "tr",
+        &[
+            "ve", "bir", "bu", "için", "ile", "da", "de", "ne", "çok", "gibi", "daha", "olarak",
+        ],
+    ),
+    (
+        "fi",
+        &[
+            "ja", "on", "ei", "että", "se", "oli", "kun", "mutta", "niin", "ovat", "myös", "tai",
+        ],
+    ),
+];
+
+/// Share of letters a script must reach to decide the language.
+const SCRIPT_SHARE: f64 = 0.5;
+/// Function-word hits needed before trusting a Latin-script guess.
+const MIN_WORD_HITS: usize = 2;
+
+fn script_language(c: char) -> Option<&'static str> {
+    Some(match c as u32 {
+        0x0370..=0x03FF => "el",
+        0x0400..=0x04FF => "ru",
+        0x0590..=0x05FF => "he",
+        0x0600..=0x06FF => "ar",
+        0x0900..=0x097F => "hi",
+        0x0E00..=0x0E7F => "th",
+        0x3040..=0x30FF => "ja",
+        0xAC00..=0xD7AF | 0x1100..=0x11FF => "ko",
+        0x4E00..=0x9FFF => "zh",
+        _ => return None,
+    })
+}
+
+/// Best-guess ISO 639-1 code for `text`, or `None` when unsure.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let mut letters = 0usize;
+    let mut scripts: Vec<(&'static str, usize)> = Vec::new();
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        if let Some(lang) = script_language(c) {
+            match scripts.iter_mut().find(|(l, _)| *l == lang) {
+                Some((_, n)) => *n += 1,
+                None => scripts.push((lang, 1)),
+            }
+        }
+    }
+    if letters == 0 {
+        return None;
+    }
+    // Japanese mixes kana with Han characters, so any kana decides it.
+    let kana = scripts.iter().find(|(l, _)| *l == "ja").map_or(0, |s| s.1);
+    let han = scripts.iter().find(|(l, _)| *l == "zh").map_or(0, |s| s.1);
+    if kana > 0 && (kana + han) as f64 / letters as f64 >= SCRIPT_SHARE {
+        return Some("ja");
+    }
+    if let Some((lang, n)) = scripts.iter().max_by_key(|(_, n)| *n) {
+        if *n as f64 / letters as f64 >= SCRIPT_SHARE {
+            return Some(lang);
+        }
+    }
+
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut best: Option<(&'static str, usize)> = None;
+    for (lang, list) in FUNCTION_WORDS {
+        let hits = words.iter().filter(|w| list.contains(&w.as_str())).count();
+        if hits >= MIN_WORD_HITS && best.is_none_or(|(_, b)| hits > b) {
+            best = Some((lang, hits));
+        }
+    }
+    best.map(|(lang, _)| lang)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_scripts_and_function_words() {
+        assert_eq!(detect("Привет, как дела?"), Some("ru"));
+        assert_eq!(detect("これは日本語の文章です"), Some("ja"));
+        assert_eq!(detect("这是中文"), Some("zh"));
+        assert_eq!(
+            detect("The server is down and the logs are empty"),
+            Some("en")
+        );
+        assert_eq!(
+            detect("Der Server ist nicht erreichbar und die Logs sind leer"),
+            Some("de")
+        );
+        assert_eq!(
+            detect("Sunucu çok yavaş ve bu sorun için destek gerekiyor"),
+            Some("tr")
+        );
+        assert_eq!(detect("Palvelin on hidas ja se ei vastaa"), Some("fi"));
+        assert_eq!(detect("kubernetes"), None);
+        assert_eq!(detect("1234 !!"), None);
+    }
+}
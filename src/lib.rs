@@ -1,26 +1,185 @@
-use std::collections::{HashMap, HashSet};
-use unicode_segmentation::UnicodeSegmentation;
+extern crate alloc;
+
+/// Evaluate `$body` inside a debug span named `$name` and record the result
+/// as `$field`, when the `spans` feature is enabled.
+macro_rules! stage {
+    ($name:literal, $field:ident, $body:expr) => {{
+        #[cfg(feature = "spans")]
+        let _span = tracing::debug_span!($name, $field = tracing::field::Empty).entered();
+        let value = $body;
+        #[cfg(feature = "spans")]
+        _span.record(stringify!($field), value);
+        value
+    }};
+}
+
+use matching::MatchForm;
+use repro::Arithmetic;
+use serde::{Deserialize, Serialize};
+
+pub mod analyzer;
+pub mod asr;
+pub mod batch;
+pub mod calibration;
+#[cfg(feature = "cancel")]
+pub mod cancel;
+pub mod clusters;
+pub mod compact;
+pub mod compare;
+pub mod conversation;
+pub mod core;
+pub mod corpus;
+pub mod count;
+pub mod evasion;
+pub mod hash;
+pub mod index;
+pub mod lang;
+pub mod matching;
+pub mod merkle;
+pub mod minhash;
+pub mod phonetic;
+pub mod profile;
+pub mod repro;
+pub mod rewrite;
+pub mod rules;
+pub mod secret;
+pub mod stopwords;
+pub mod stream;
+#[cfg(feature = "metrics")]
+pub mod telemetry;
+pub mod text;
+pub mod topic;
+pub mod translit;
+pub mod tuning;
+pub mod verdict;
+
+pub use analyzer::{global, init_global, Analyzer};
+pub use calibration::CalibrationTable;
+#[cfg(feature = "cancel")]
+pub use cancel::{analyze_async, CancellationToken};
+pub use compare::{compare_messages, MessageComparison};
+pub use conversation::{ConversationAnalyzer, ConversationSnapshot};
+pub use corpus::Corpus;
+pub use index::Bm25Index;
+pub use profile::ProfileSet;
+pub use rewrite::find_degeneration_onset;
+pub use rules::RuleSet;
+pub use text::EmojiMode;
+pub use topic::{CompiledTopic, TopicCache, TopicDefinition, TopicRegistry};
+pub use verdict::{Severity, SeverityBands, Thresholds, Verdict, VerdictExplanation};
+
+/// How byte inputs that are not valid UTF-8 are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Utf8Policy {
+    /// Replace invalid sequences with U+FFFD and keep scoring.
+    #[default]
+    Lossy,
+    /// Refuse to score the input.
+    Reject,
+}
+
+impl Utf8Policy {
+    /// Parse a policy name ("lossy" or "reject"), case-insensitively.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "lossy" => Some(Self::Lossy),
+            "reject" => Some(Self::Reject),
+            _ => None,
+        }
+    }
+}
+
+/// Output transformation applied after the scoring function:
+/// clamp(scale * f + offset, floor, ceiling).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoreTransform {
+    pub floor: f64,
+    pub ceiling: f64,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl Default for ScoreTransform {
+    fn default() -> Self {
+        Self {
+            floor: 0.0,
+            ceiling: 1.0,
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl ScoreTransform {
+    /// Apply the transform to a raw score.
+    pub fn apply(&self, raw: f64) -> f64 {
+        let floor = self.floor.clamp(0.0, 1.0);
+        let ceiling = self.ceiling.clamp(floor, 1.0);
+        (self.scale * raw + self.offset).clamp(floor, ceiling)
+    }
+
+    /// True if this transform never changes a score in [0, 1].
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+}
 
 /// Configuration for the Word-Math scoring function f(y, z).
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct WordMathConfig {
     /// Weight for repetition / contamination y
     pub alpha: f64,
     /// Weight for topic drift z
     pub beta: f64,
+    /// Handling of invalid UTF-8 in `analyze_bytes`
+    pub utf8_policy: Utf8Policy,
+    /// Whether emoji sequences are scored as tokens or stripped
+    pub emoji_mode: EmojiMode,
+    /// Run the whitespace / invisible-character normalization pre-pass
+    pub normalize: bool,
+    /// Floor / ceiling / affine rescale applied to f(y, z)
+    pub transform: ScoreTransform,
+    /// Allow / Warn / Block thresholds
+    pub thresholds: Thresholds,
+    /// Bit-identical scores across platforms (see `repro`)
+    pub reproducible: bool,
+    /// Measure drift on script-folded tokens (see `translit`)
+    pub transliterate: bool,
+    /// Measure drift on phonetic keys, for ASR transcripts (see `phonetic`)
+    pub phonetic: bool,
 }
 
 impl Default for WordMathConfig {
     fn default() -> Self {
         // Example values: repetition and drift weighted equally.
         // alpha + beta should be <= 1.0 for the linear form below.
-        Self { alpha: 0.5, beta: 0.5 }
+        Self {
+            alpha: 0.5,
+            beta: 0.5,
+            utf8_policy: Utf8Policy::Lossy,
+            emoji_mode: EmojiMode::Strip,
+            normalize: true,
+            transform: ScoreTransform::default(),
+            thresholds: Thresholds::default(),
+            reproducible: false,
+            transliterate: false,
+            phonetic: false,
+        }
     }
 }
 
 impl WordMathConfig {
     /// Load config from environment variables:
-    /// WORD_MATH_ALPHA, WORD_MATH_BETA.
+    /// WORD_MATH_ALPHA, WORD_MATH_BETA, WORD_MATH_UTF8_POLICY,
+    /// WORD_MATH_EMOJI_MODE, WORD_MATH_NORMALIZE, WORD_MATH_REPRODUCIBLE,
+    /// WORD_MATH_TRANSLITERATE, WORD_MATH_PHONETIC,
+    /// WORD_MATH_SCORE_FLOOR,
+    /// WORD_MATH_SCORE_CEILING, WORD_MATH_SCORE_SCALE, WORD_MATH_SCORE_OFFSET,
+    /// WORD_MATH_BLOCK_MAX, WORD_MATH_WARN_MAX, WORD_MATH_MAX_REPETITION,
+    /// WORD_MATH_MAX_DRIFT, WORD_MATH_SEVERITY_{CRITICAL,SEVERE,WARN,NOTICE}_MAX.
     /// Falls back to Default if parsing fails or vars are missing.
     pub fn from_env() -> Self {
         let mut cfg = Self::default();
@@ -37,9 +196,82 @@ impl WordMathConfig {
             }
         }
 
+        if let Ok(policy_str) = std::env::var("WORD_MATH_UTF8_POLICY") {
+            if let Some(policy) = Utf8Policy::parse(&policy_str) {
+                cfg.utf8_policy = policy;
+            }
+        }
+
+        if let Ok(mode_str) = std::env::var("WORD_MATH_EMOJI_MODE") {
+            if let Some(mode) = EmojiMode::parse(&mode_str) {
+                cfg.emoji_mode = mode;
+            }
+        }
+
+        if let Ok(normalize_str) = std::env::var("WORD_MATH_NORMALIZE") {
+            if let Ok(normalize) = normalize_str.parse::<bool>() {
+                cfg.normalize = normalize;
+            }
+        }
+
+        if let Ok(reproducible_str) = std::env::var("WORD_MATH_REPRODUCIBLE") {
+            if let Ok(reproducible) = reproducible_str.parse::<bool>() {
+                cfg.reproducible = reproducible;
+            }
+        }
+
+        if let Ok(transliterate_str) = std::env::var("WORD_MATH_TRANSLITERATE") {
+            if let Ok(transliterate) = transliterate_str.parse::<bool>() {
+                cfg.transliterate = transliterate;
+            }
+        }
+
+        if let Ok(phonetic_str) = std::env::var("WORD_MATH_PHONETIC") {
+            if let Ok(phonetic) = phonetic_str.parse::<bool>() {
+                cfg.phonetic = phonetic;
+            }
+        }
+
+        let float_vars = [
+            ("WORD_MATH_SCORE_FLOOR", &mut cfg.transform.floor),
+            ("WORD_MATH_SCORE_CEILING", &mut cfg.transform.ceiling),
+            ("WORD_MATH_SCORE_SCALE", &mut cfg.transform.scale),
+            ("WORD_MATH_SCORE_OFFSET", &mut cfg.transform.offset),
+            ("WORD_MATH_BLOCK_MAX", &mut cfg.thresholds.block_max),
+            ("WORD_MATH_WARN_MAX", &mut cfg.thresholds.warn_max),
+            (
+                "WORD_MATH_MAX_REPETITION",
+                &mut cfg.thresholds.max_repetition,
+            ),
+            ("WORD_MATH_MAX_DRIFT", &mut cfg.thresholds.max_drift),
+            (
+                "WORD_MATH_SEVERITY_CRITICAL_MAX",
+                &mut cfg.thresholds.severity.critical_max,
+            ),
+            (
+                "WORD_MATH_SEVERITY_SEVERE_MAX",
+                &mut cfg.thresholds.severity.severe_max,
+            ),
+            (
+                "WORD_MATH_SEVERITY_WARN_MAX",
+                &mut cfg.thresholds.severity.warn_max,
+            ),
+            (
+                "WORD_MATH_SEVERITY_NOTICE_MAX",
+                &mut cfg.thresholds.severity.notice_max,
+            ),
+        ];
+        for (name, slot) in float_vars {
+            if let Ok(value_str) = std::env::var(name) {
+                if let Ok(value) = value_str.parse::<f64>() {
+                    *slot = value;
+                }
+            }
+        }
+
         // Optional: normalize if alpha + beta > 1.0
         let sum = cfg.alpha + cfg.beta;
-        if sum > 1.0 && sum > 0.0 {
+        if sum > 1.0 {
             cfg.alpha /= sum;
             cfg.beta /= sum;
         }
@@ -48,40 +280,88 @@ impl WordMathConfig {
     }
 }
 
+/// Errors returned by the fallible analysis entry points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordMathError {
+    /// Input bytes were not valid UTF-8 and the policy is `Utf8Policy::Reject`.
+    InvalidUtf8 {
+        /// Length of the longest valid UTF-8 prefix.
+        valid_up_to: usize,
+    },
+    /// A configuration file could not be read or parsed.
+    Config(String),
+}
+
+impl std::fmt::Display for WordMathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidUtf8 { valid_up_to } => {
+                write!(
+                    f,
+                    "input is not valid UTF-8 (valid up to byte {})",
+                    valid_up_to
+                )
+            }
+            Self::Config(msg) => write!(f, "invalid configuration: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for WordMathError {}
+
 /// Result of analyzing a single message.
 #[derive(Debug, Clone)]
 pub struct WordMathAnalysis {
     pub y_repetition: f64,
     pub z_drift: f64,
+    /// Score after the configured `ScoreTransform`.
     pub score: f64,
+    /// Share of decoded chars that are U+FFFD replacement characters.
+    /// Always 0.0 for `&str` inputs that contain none.
+    pub malformed_ratio: f64,
+    /// Emoji handling used for tokenization.
+    pub emoji_mode: EmojiMode,
+    /// Number of emoji grapheme clusters in the message.
+    pub emoji_count: usize,
+    /// Zero-width / bidi control characters removed by normalization.
+    pub invisible_stripped: usize,
+    /// Share of the message's chars that were invisible padding.
+    pub obfuscation: f64,
+    /// Tokens spelled with homoglyphs (folded to Latin before scoring).
+    pub homoglyphs: usize,
+    /// Tokens in runs of single letters ("f r e e").
+    pub split_letters: usize,
+    /// Combined evasion risk in [0, 1]; see `evasion::risk`.
+    pub evasion_risk: f64,
 }
 
 /// Hex-stamped trace metadata for auditing.
 #[derive(Debug, Clone)]
 pub struct WordMathTrace {
     pub hex_id: String,
+    /// Message length in grapheme clusters.
     pub message_len: usize,
+    /// Topic length in grapheme clusters.
     pub topic_len: usize,
+    /// f(y, z) before the output transform.
+    pub raw_score: f64,
+    /// f(y, z) after the output transform (equals `WordMathAnalysis::score`).
+    pub adjusted_score: f64,
 }
 
 /// Compute repetition density y = max_w c(w) / n for a message.
 pub fn compute_repetition_density(message: &str) -> f64 {
-    let words: Vec<String> = message
-        .unicode_words()
-        .map(|w| w.to_lowercase())
-        .collect();
+    repetition_density_of(&text::tokenize(message, EmojiMode::Strip))
+}
 
-    let n = words.len();
+/// Repetition density y over already tokenized input.
+pub fn repetition_density_of(tokens: &[String]) -> f64 {
+    let n = tokens.len();
     if n == 0 {
         return 0.0;
     }
 
-    let mut counts: HashMap<String, usize> = HashMap::new();
-    for w in words {
-        *counts.entry(w).or_insert(0) += 1;
-    }
-
-    let max_count = counts.values().copied().max().unwrap_or(0);
+    let max_count = count::max_token_count(tokens);
     max_count as f64 / n as f64
 }
 
@@ -90,24 +370,24 @@ pub fn compute_repetition_density(message: &str) -> f64 {
 /// In a future version, you can plug in an embedding-based
 /// distance here and keep this as a baseline for ablation.
 pub fn compute_topic_drift(message: &str, topic: &str) -> f64 {
-    let msg_words: HashSet<String> = message
-        .unicode_words()
-        .map(|w| w.to_lowercase())
-        .collect();
-    let topic_words: HashSet<String> = topic
-        .unicode_words()
-        .map(|w| w.to_lowercase())
-        .collect();
+    topic_drift_of(
+        &text::tokenize(message, EmojiMode::Strip),
+        &text::tokenize(topic, EmojiMode::Strip),
+    )
+}
 
-    if msg_words.is_empty() && topic_words.is_empty() {
+/// Jaccard topic drift z over already tokenized message and topic.
+pub fn topic_drift_of(message: &[String], topic: &[String]) -> f64 {
+    if message.is_empty() && topic.is_empty() {
         return 0.0;
     }
-    if msg_words.is_empty() || topic_words.is_empty() {
+    if message.is_empty() || topic.is_empty() {
         return 1.0;
     }
 
-    let intersection_size = msg_words.intersection(&topic_words).count() as f64;
-    let union_size = msg_words.union(&topic_words).count() as f64;
+    let (common, union) = count::distinct_overlap(message, topic);
+    let intersection_size = common as f64;
+    let union_size = union as f64;
 
     let jaccard_similarity = if union_size > 0.0 {
         intersection_size / union_size
@@ -123,14 +403,8 @@ pub fn compute_topic_drift(message: &str, topic: &str) -> f64 {
 ///
 /// Assumes 0 <= alpha, beta, and alpha + beta <= 1. Returns a value in [0, 1].
 pub fn score_linear(y: f64, z: f64, cfg: WordMathConfig) -> f64 {
-    let mut score = 1.0 - cfg.alpha * y - cfg.beta * z;
-    if score < 0.0 {
-        score = 0.0;
-    }
-    if score > 1.0 {
-        score = 1.0;
-    }
-    score
+    let score = 1.0 - cfg.alpha * y - cfg.beta * z;
+    score.clamp(0.0, 1.0)
 }
 
 /// Generate a simple hex ID for tracing.
@@ -153,25 +427,268 @@ pub fn analyze_message_with_trace(
     topic: &str,
     cfg: WordMathConfig,
 ) -> (WordMathAnalysis, WordMathTrace) {
-    let y = compute_repetition_density(message);
-    let z = compute_topic_drift(message);
-    let score = score_linear(y, z, cfg);
+    let topic = CompiledTopic::compile(topic, cfg.emoji_mode);
+    analyze_with_topic(message, &topic, cfg)
+}
+
+/// Like `analyze_message_with_trace`, against a precompiled topic.
+///
+/// If the topic was compiled with a different emoji mode than `cfg`, it is
+/// recompiled so results never depend on how the topic was prepared.
+pub fn analyze_with_topic(
+    message: &str,
+    topic: &CompiledTopic,
+    cfg: WordMathConfig,
+) -> (WordMathAnalysis, WordMathTrace) {
+    let recompiled;
+    let topic = if topic.emoji_mode() == cfg.emoji_mode {
+        topic
+    } else {
+        recompiled = CompiledTopic::compile(topic.text(), cfg.emoji_mode);
+        &recompiled
+    };
+
+    analyze_against(message, cfg, topic.grapheme_len(), |tokens| {
+        drift_from_topic(tokens, topic, cfg)
+    })
+}
+
+/// Like `analyze_with_topic`, with drift weighted by the words' IDF in a
+/// domain corpus (see `corpus::domain_drift_of`).
+pub fn analyze_with_domain(
+    message: &str,
+    topic: &CompiledTopic,
+    domain: &Corpus,
+    cfg: WordMathConfig,
+) -> (WordMathAnalysis, WordMathTrace) {
+    let recompiled;
+    let topic = if topic.emoji_mode() == cfg.emoji_mode {
+        topic
+    } else {
+        recompiled = CompiledTopic::compile(topic.text(), cfg.emoji_mode);
+        &recompiled
+    };
+
+    analyze_against(message, cfg, topic.grapheme_len(), |tokens| {
+        corpus::domain_drift_with(
+            tokens,
+            topic.tokens(),
+            domain,
+            MatchForm::of(&cfg),
+            Arithmetic::of(&cfg),
+        )
+    })
+}
+
+/// Drift of message tokens from `topic`, in the `MatchForm` of `cfg`.
+fn drift_from_topic(tokens: &[String], topic: &CompiledTopic, cfg: WordMathConfig) -> f64 {
+    match MatchForm::of(&cfg) {
+        MatchForm::Exact => topic_drift_of(tokens, topic.tokens()),
+        form => topic_drift_of(&form.keys(tokens), topic.tokens_for(form)),
+    }
+}
+
+/// The topic a message sits closest to, out of several candidates.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TopicMatch {
+    /// Index of the lowest-drift topic; the first one wins ties.
+    pub best: usize,
+    pub drift: f64,
+    /// Index of the second-lowest-drift topic, if there was one.
+    pub runner_up: Option<usize>,
+    /// Runner-up drift minus `drift`: how clearly the best topic won.
+    pub margin: Option<f64>,
+}
+
+/// Rank `topics` by their drift from `message`, e.g. as an intent signal
+/// for routing. Returns None when `topics` is empty.
+pub fn best_topic(
+    message: &str,
+    topics: &[&CompiledTopic],
+    cfg: WordMathConfig,
+) -> Option<TopicMatch> {
+    let normalized = if cfg.normalize {
+        text::normalize(message).text
+    } else {
+        message.to_string()
+    };
+    let msg_tokens = text::tokenize(&normalized, cfg.emoji_mode);
+    let mut drifts: Vec<(usize, f64)> = topics
+        .iter()
+        .enumerate()
+        .map(|(i, topic)| {
+            let drift = if topic.emoji_mode() == cfg.emoji_mode {
+                drift_from_topic(&msg_tokens, topic, cfg)
+            } else {
+                let recompiled = CompiledTopic::compile(topic.text(), cfg.emoji_mode);
+                drift_from_topic(&msg_tokens, &recompiled, cfg)
+            };
+            (i, drift)
+        })
+        .collect();
+    // Stable, so equal drifts keep their request order.
+    drifts.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let (best, drift) = *drifts.first()?;
+    let runner_up = drifts.get(1);
+    Some(TopicMatch {
+        best,
+        drift,
+        runner_up: runner_up.map(|&(i, _)| i),
+        margin: runner_up.map(|&(_, second)| second - drift),
+    })
+}
+
+/// Like `analyze_with_topic`, with drift measured against a document
+/// corpus (see `corpus::corpus_drift_of`) instead of a topic.
+pub fn analyze_with_corpus(
+    message: &str,
+    corpus: &Corpus,
+    cfg: WordMathConfig,
+) -> (WordMathAnalysis, WordMathTrace) {
+    let rebuilt;
+    let corpus = if corpus.emoji_mode() == cfg.emoji_mode {
+        corpus
+    } else {
+        rebuilt = corpus.with_emoji_mode(cfg.emoji_mode);
+        &rebuilt
+    };
+    analyze_against(message, cfg, corpus.grapheme_len(), |tokens| {
+        corpus::corpus_drift_with(tokens, corpus, Arithmetic::of(&cfg))
+    })
+}
+
+/// Shared analysis pipeline; `drift` maps the message tokens to z.
+fn analyze_against(
+    message: &str,
+    cfg: WordMathConfig,
+    topic_len: usize,
+    drift: impl FnOnce(&[String]) -> f64,
+) -> (WordMathAnalysis, WordMathTrace) {
+    let normalized = if cfg.normalize {
+        text::normalize(message)
+    } else {
+        text::Normalized {
+            text: message.to_string(),
+            invisible_stripped: 0,
+        }
+    };
+    let char_count = message.chars().count();
+    let obfuscation = if char_count == 0 {
+        0.0
+    } else {
+        normalized.invisible_stripped as f64 / char_count as f64
+    };
+
+    #[cfg(feature = "spans")]
+    let _analyze_span = tracing::debug_span!(
+        "word_math.analyze",
+        msg_bytes = message.len(),
+        invisible_stripped = normalized.invisible_stripped
+    )
+    .entered();
+
+    let mut msg_tokens = {
+        #[cfg(feature = "spans")]
+        let _span =
+            tracing::debug_span!("word_math.tokenize", tokens = tracing::field::Empty).entered();
+        let tokens = text::tokenize(&normalized.text, cfg.emoji_mode);
+        #[cfg(feature = "spans")]
+        _span.record("tokens", tokens.len());
+        tokens
+    };
+    let homoglyphs = if cfg.normalize {
+        evasion::fold_tokens(&mut msg_tokens)
+    } else {
+        0
+    };
+    let split_letters = evasion::split_letters(&msg_tokens);
+
+    let y = stage!(
+        "word_math.repetition",
+        y,
+        repetition_density_of(&msg_tokens)
+    );
+    let z = stage!("word_math.drift", z, drift(&msg_tokens));
+    let raw_score = stage!("word_math.score", raw_score, score_linear(y, z, cfg));
+    let score = stage!("word_math.transform", score, cfg.transform.apply(raw_score));
 
     let analysis = WordMathAnalysis {
         y_repetition: y,
         z_drift: z,
         score,
+        malformed_ratio: compute_malformed_ratio(message),
+        emoji_mode: cfg.emoji_mode,
+        emoji_count: text::count_emoji(message),
+        invisible_stripped: normalized.invisible_stripped,
+        obfuscation,
+        homoglyphs,
+        split_letters,
+        evasion_risk: evasion::risk(obfuscation, homoglyphs, split_letters, msg_tokens.len()),
     };
 
     let trace = WordMathTrace {
         hex_id: generate_hex_id(),
-        message_len: message.chars().count(),
-        topic_len: topic.chars().count(),
+        message_len: text::grapheme_len(message),
+        topic_len,
+        raw_score,
+        adjusted_score: score,
     };
 
     (analysis, trace)
 }
 
+/// Score a message with the process-wide `global()` analyzer.
+pub fn analyze(message: &str, topic: &str) -> (WordMathAnalysis, WordMathTrace) {
+    global().analyze(message, topic)
+}
+
+/// Score a message and decide its verdict with the `global()` analyzer.
+pub fn evaluate(message: &str, topic: &str) -> VerdictExplanation {
+    global().evaluate(message, topic)
+}
+
+/// Share of chars in `text` that are U+FFFD replacement characters.
+pub fn compute_malformed_ratio(text: &str) -> f64 {
+    let mut total = 0usize;
+    let mut replaced = 0usize;
+    for c in text.chars() {
+        total += 1;
+        if c == char::REPLACEMENT_CHARACTER {
+            replaced += 1;
+        }
+    }
+
+    if total == 0 {
+        return 0.0;
+    }
+    replaced as f64 / total as f64
+}
+
+/// Analyze raw bytes from transports that do not guarantee UTF-8.
+///
+/// Invalid sequences are either decoded lossily or rejected according to
+/// `cfg.utf8_policy`; replacement characters are reported through
+/// `malformed_ratio`. Never panics on arbitrary input.
+pub fn analyze_bytes(
+    bytes: &[u8],
+    topic: &str,
+    cfg: WordMathConfig,
+) -> Result<(WordMathAnalysis, WordMathTrace), WordMathError> {
+    let message = match std::str::from_utf8(bytes) {
+        Ok(text) => std::borrow::Cow::Borrowed(text),
+        Err(err) => match cfg.utf8_policy {
+            Utf8Policy::Lossy => String::from_utf8_lossy(bytes),
+            Utf8Policy::Reject => {
+                return Err(WordMathError::InvalidUtf8 {
+                    valid_up_to: err.valid_up_to(),
+                })
+            }
+        },
+    };
+
+    Ok(analyze_message_with_trace(&message, topic, cfg))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -200,12 +717,153 @@ mod tests {
         assert!((z - 1.0).abs() < 1e-6);
     }
 
+    #[test]
+    fn test_best_topic_reports_margin() {
+        let cfg = WordMathConfig::default();
+        let billing = CompiledTopic::compile("invoice billing refund payment", cfg.emoji_mode);
+        let shipping = CompiledTopic::compile("shipping delivery tracking parcel", cfg.emoji_mode);
+        let found = best_topic("where is my parcel delivery", &[&billing, &shipping], cfg).unwrap();
+        assert_eq!((found.best, found.runner_up), (1, Some(0)));
+        assert!(found.margin.unwrap() > 0.0);
+
+        let single = best_topic("refund please", &[&billing], cfg).unwrap();
+        assert_eq!((single.best, single.margin), (0, None));
+        assert!(best_topic("anything", &[], cfg).is_none());
+    }
+
     #[test]
     fn test_score_linear_bounds() {
         let cfg = WordMathConfig::default();
         let s1 = score_linear(0.0, 0.0, cfg);
         let s2 = score_linear(1.0, 1.0, cfg);
-        assert!(s1 <= 1.0 && s1 >= 0.0);
-        assert!(s2 <= 1.0 && s2 >= 0.0);
+        assert!((0.0..=1.0).contains(&s1));
+        assert!((0.0..=1.0).contains(&s2));
+    }
+
+    #[test]
+    fn test_analyze_bytes_lossy_reports_malformed() {
+        let cfg = WordMathConfig::default();
+        let (analysis, _) = analyze_bytes(b"ok \xff\xfe ok", "ok", cfg).unwrap();
+        assert!(analysis.malformed_ratio > 0.0);
+        assert!((0.0..=1.0).contains(&analysis.score));
+    }
+
+    #[test]
+    fn test_analyze_bytes_reject() {
+        let cfg = WordMathConfig {
+            utf8_policy: Utf8Policy::Reject,
+            ..WordMathConfig::default()
+        };
+        let err = analyze_bytes(b"ok \xff", "ok", cfg).unwrap_err();
+        assert_eq!(err, WordMathError::InvalidUtf8 { valid_up_to: 3 });
+
+        let (analysis, _) = analyze_bytes(b"valid text", "text", cfg).unwrap();
+        assert_eq!(analysis.malformed_ratio, 0.0);
+    }
+
+    #[test]
+    fn test_emoji_token_mode_counts_repetition() {
+        let message = "ok \u{1F525} \u{1F525} \u{1F525}";
+        let strip = WordMathConfig::default();
+        let token = WordMathConfig {
+            emoji_mode: EmojiMode::Token,
+            ..strip
+        };
+
+        let (a_strip, trace) = analyze_message_with_trace(message, "ok", strip);
+        let (a_token, _) = analyze_message_with_trace(message, "ok", token);
+        assert_eq!(a_strip.y_repetition, 1.0);
+        assert!((a_token.y_repetition - 0.75).abs() < 1e-6);
+        assert_eq!(a_token.emoji_count, 3);
+        assert_eq!(a_token.emoji_mode, EmojiMode::Token);
+        assert_eq!(trace.message_len, 8);
+    }
+
+    #[test]
+    fn test_zero_width_padding_is_caught() {
+        let padded = "b\u{200B}uy b\u{200B}uy b\u{200B}uy";
+        let cfg = WordMathConfig::default();
+        let (analysis, _) = analyze_message_with_trace(padded, "buy", cfg);
+        assert_eq!(analysis.invisible_stripped, 3);
+        assert!(analysis.obfuscation > 0.0);
+        assert_eq!(analysis.y_repetition, 1.0);
+
+        let raw = WordMathConfig {
+            normalize: false,
+            ..cfg
+        };
+        let (analysis, _) = analyze_message_with_trace(padded, "buy", raw);
+        assert_eq!(analysis.invisible_stripped, 0);
+        assert_eq!(analysis.obfuscation, 0.0);
+    }
+
+    #[test]
+    fn test_score_transform_floor_recorded_in_trace() {
+        let cfg = WordMathConfig {
+            transform: ScoreTransform {
+                floor: 0.2,
+                ..ScoreTransform::default()
+            },
+            ..WordMathConfig::default()
+        };
+        let (analysis, trace) = analyze_message_with_trace("spam spam spam", "weather", cfg);
+        assert_eq!(trace.raw_score, 0.0);
+        assert_eq!(trace.adjusted_score, 0.2);
+        assert_eq!(analysis.score, 0.2);
+    }
+
+    #[test]
+    fn test_score_transform_affine_rescale() {
+        let t = ScoreTransform {
+            scale: 0.5,
+            offset: 0.5,
+            ceiling: 0.9,
+            ..ScoreTransform::default()
+        };
+        assert!((t.apply(0.0) - 0.5).abs() < 1e-9);
+        assert!((t.apply(1.0) - 0.9).abs() < 1e-9);
+        assert!(ScoreTransform::default().is_identity());
+    }
+
+    #[cfg(feature = "spans")]
+    #[test]
+    fn test_stage_spans_record_values() {
+        use std::sync::{Arc, Mutex};
+        use tracing_subscriber::fmt::format::FmtSpan;
+
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            analyze_message_with_trace("spam spam spam", "rust", WordMathConfig::default())
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        for stage in [
+            "word_math.tokenize",
+            "word_math.repetition",
+            "word_math.drift",
+        ] {
+            assert!(output.contains(stage), "missing {} in {}", stage, output);
+        }
+        assert!(output.contains("tokens=3"));
+        assert!(output.contains("y=1.0"));
     }
 }
@@ -0,0 +1,79 @@
+//! The token form lexical overlap (drift) is measured on.
+//!
+//! By default drift compares tokens exactly. `WordMathConfig::transliterate`
+//! folds tokens across scripts (see `translit`) and
+//! `WordMathConfig::phonetic` compares their Soundex codes (see
+//! `phonetic`); with both set, folded tokens are romanized and then
+//! coded. Repetition always counts the exact tokens.
+
+use crate::{phonetic, translit, WordMathConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchForm {
+    #[default]
+    Exact,
+    Folded,
+    Phonetic,
+    FoldedPhonetic,
+}
+
+impl MatchForm {
+    pub fn of(cfg: &WordMathConfig) -> Self {
+        match (cfg.transliterate, cfg.phonetic) {
+            (false, false) => Self::Exact,
+            (true, false) => Self::Folded,
+            (false, true) => Self::Phonetic,
+            (true, true) => Self::FoldedPhonetic,
+        }
+    }
+
+    /// The key `token` is compared by.
+    pub fn key(self, token: &str) -> String {
+        match self {
+            Self::Exact => token.to_string(),
+            Self::Folded => translit::fold(token),
+            Self::Phonetic => phonetic::soundex(token),
+            // Soundex drops vowels itself, and needs them to keep
+            // separated consonants of one class apart.
+            Self::FoldedPhonetic => phonetic::soundex(&translit::romanize(token)),
+        }
+    }
+
+    /// Keys of `tokens`, distinct and sorted.
+    pub fn keys(self, tokens: &[String]) -> Vec<String> {
+        let mut keys: Vec<String> = tokens.iter().map(|t| self.key(t)).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze_message_with_trace;
+
+    #[test]
+    fn test_phonetic_form_matches_asr_misspellings() {
+        let topic = "kubernetes cluster upgrade";
+        let message = "the kubernetees clustor upgrayde failed";
+        let exact = WordMathConfig::default();
+        let phonetic = WordMathConfig {
+            phonetic: true,
+            ..exact
+        };
+        let (plain, _) = analyze_message_with_trace(message, topic, exact);
+        let (keyed, _) = analyze_message_with_trace(message, topic, phonetic);
+        assert_eq!(plain.z_drift, 1.0);
+        assert_eq!(keyed.z_drift, 0.4);
+        assert_eq!(keyed.y_repetition, plain.y_repetition);
+        assert_eq!(
+            MatchForm::of(&WordMathConfig {
+                transliterate: true,
+                ..phonetic
+            })
+            .key("कुबेरनेटीज़"),
+            MatchForm::FoldedPhonetic.key("kubernetes")
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! RFC 6962 Merkle hashing behind the server's audit digests.
+//!
+//! Leaves are `SHA-256(0x00 || data)`, interior nodes
+//! `SHA-256(0x01 || left || right)`, and a tree of n > 1 leaves splits at
+//! `split(n)`, the largest power of two below n. The server builds trees
+//! from these; clients use `fold_path` to check an inclusion proof against
+//! a published root, and `verify_consistency` to check that a later root
+//! extends an earlier one, without trusting the server that produced them.
+
+pub type Hash = [u8; 32];
+
+/// SHA-256 (FIPS 180-4). Only the Merkle hashes below are public; this
+/// is not a general-purpose digest.
+pub(crate) fn sha256(data: &[u8]) -> Hash {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (slot, value) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *slot = slot.wrapping_add(value);
+        }
+    }
+
+    let mut out = [0u8; 32];
+    for (chunk, word) in out.chunks_exact_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+pub fn to_hex(hash: &Hash) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Inverse of `to_hex`; None unless `hex` is 64 hex digits.
+pub fn from_hex(hex: &str) -> Option<Hash> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut out = [0u8; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+/// RFC 6962 hash of the empty tree.
+pub fn empty_root() -> Hash {
+    sha256(&[])
+}
+
+pub fn leaf_hash(data: &[u8]) -> Hash {
+    let mut prefixed = Vec::with_capacity(data.len() + 1);
+    prefixed.push(0x00);
+    prefixed.extend_from_slice(data);
+    sha256(&prefixed)
+}
+
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut data = Vec::with_capacity(65);
+    data.push(0x01);
+    data.extend_from_slice(left);
+    data.extend_from_slice(right);
+    sha256(&data)
+}
+
+/// Size of the left subtree of a tree with `n` > 1 leaves: the largest
+/// power of two below `n`.
+pub fn split(n: u64) -> u64 {
+    debug_assert!(n > 1);
+    1 << (63 - (n - 1).leading_zeros())
+}
+
+/// Root implied by a leaf and its proof path of (sibling hex, side) steps,
+/// where side is "left" or "right"; None on malformed input.
+pub fn fold_path<'a>(
+    leaf: &str,
+    path: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Option<String> {
+    let mut acc = from_hex(leaf)?;
+    for (sibling, side) in path {
+        let sibling = from_hex(sibling)?;
+        acc = match side {
+            "left" => node_hash(&sibling, &acc),
+            "right" => node_hash(&acc, &sibling),
+            _ => return None,
+        };
+    }
+    Some(to_hex(&acc))
+}
+
+/// Check a consistency proof (RFC 9162, section 2.1.4.2): that the tree of
+/// `second` leaves with root `second_root` extends the tree of `first`
+/// leaves with root `first_root`, without removing or changing any of them.
+pub fn verify_consistency(
+    first: u64,
+    second: u64,
+    first_root: &Hash,
+    second_root: &Hash,
+    proof: &[Hash],
+) -> bool {
+    if first > second {
+        return false;
+    }
+    if first == second {
+        return proof.is_empty() && first_root == second_root;
+    }
+    if first == 0 {
+        return proof.is_empty();
+    }
+    let mut path = proof.iter();
+    let seed = if first.is_power_of_two() {
+        first_root
+    } else {
+        match path.next() {
+            Some(hash) => hash,
+            None => return false,
+        }
+    };
+    let (mut first_node, mut second_node) = (first - 1, second - 1);
+    while first_node & 1 == 1 {
+        first_node >>= 1;
+        second_node >>= 1;
+    }
+    let (mut first_hash, mut second_hash) = (*seed, *seed);
+    for sibling in path {
+        if second_node == 0 {
+            return false;
+        }
+        if first_node & 1 == 1 || first_node == second_node {
+            first_hash = node_hash(sibling, &first_hash);
+            second_hash = node_hash(sibling, &second_hash);
+            while first_node & 1 == 0 && first_node != 0 {
+                first_node >>= 1;
+                second_node >>= 1;
+            }
+        } else {
+            second_hash = node_hash(&second_hash, sibling);
+        }
+        first_node >>= 1;
+        second_node >>= 1;
+    }
+    second_node == 0 && &first_hash == first_root && &second_hash == second_root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 6962 MTH, straight from the definition.
+    fn root(leaves: &[Hash]) -> Hash {
+        match leaves.len() {
+            0 => sha256(&[]),
+            1 => leaves[0],
+            n => {
+                let k = split(n as u64) as usize;
+                node_hash(&root(&leaves[..k]), &root(&leaves[k..]))
+            }
+        }
+    }
+
+    /// RFC 6962 SUBPROOF(m, D[n], b), straight from the definition.
+    fn subproof(m: usize, leaves: &[Hash], complete: bool) -> Vec<Hash> {
+        let n = leaves.len();
+        if m == n {
+            return if complete {
+                Vec::new()
+            } else {
+                vec![root(leaves)]
+            };
+        }
+        let k = split(n as u64) as usize;
+        if m <= k {
+            let mut proof = subproof(m, &leaves[..k], complete);
+            proof.push(root(&leaves[k..]));
+            proof
+        } else {
+            let mut proof = subproof(m - k, &leaves[k..], false);
+            proof.push(root(&leaves[..k]));
+            proof
+        }
+    }
+
+    #[test]
+    fn test_consistency_proofs_verify_and_catch_rewrites() {
+        let leaves: Vec<Hash> = (0..13u8).map(|i| leaf_hash(&[i])).collect();
+        for second in 1..=leaves.len() {
+            for first in 1..=second {
+                let proof = subproof(first, &leaves[..second], true);
+                let (old, new) = (root(&leaves[..first]), root(&leaves[..second]));
+                assert!(
+                    verify_consistency(first as u64, second as u64, &old, &new, &proof),
+                    "{} -> {}",
+                    first,
+                    second
+                );
+                if first < second {
+                    let mut rewritten = leaves[..second].to_vec();
+                    rewritten[first - 1] = leaf_hash(b"changed");
+                    let forged = root(&rewritten);
+                    assert!(!verify_consistency(
+                        first as u64,
+                        second as u64,
+                        &old,
+                        &forged,
+                        &proof
+                    ));
+                }
+            }
+        }
+        assert!(!verify_consistency(5, 3, &leaves[0], &leaves[1], &[]));
+        assert_eq!((split(2), split(5), split(8), split(9)), (1, 4, 4, 8));
+    }
+
+    #[test]
+    fn test_sha256_vectors() {
+        assert_eq!(
+            to_hex(&sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            to_hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            to_hex(&sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        let long = vec![b'a'; 1000];
+        assert_eq!(
+            to_hex(&sha256(&long)),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    #[test]
+    fn test_fold_path_rebuilds_the_root() {
+        let (a, b, c) = (leaf_hash(b"a"), leaf_hash(b"b"), leaf_hash(b"c"));
+        let root = to_hex(&node_hash(&node_hash(&a, &b), &c));
+        let path = [(to_hex(&a), "left"), (to_hex(&c), "right")];
+        let steps = || path.iter().map(|(h, side)| (h.as_str(), *side));
+        assert_eq!(fold_path(&to_hex(&b), steps()), Some(root));
+        assert_eq!(from_hex(&to_hex(&a)), Some(a));
+        assert!(fold_path("zz", steps()).is_none());
+        assert!(fold_path(&to_hex(&b), [(to_hex(&a).as_str(), "up")]).is_none());
+    }
+}
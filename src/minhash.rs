@@ -0,0 +1,88 @@
+//! MinHash signatures for cheap near-duplicate detection across messages.
+
+use crate::hash::FxHasher;
+use crate::text::{self, EmojiMode};
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+
+/// Default number of hash functions per signature.
+pub const DEFAULT_NUM_HASHES: usize = 64;
+
+/// MinHash signature over a message's word bigram shingles.
+///
+/// The fraction of matching slots between two signatures estimates the
+/// Jaccard similarity of their shingle sets.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MinHashSignature {
+    mins: Vec<u64>,
+}
+
+impl MinHashSignature {
+    /// Signature of already tokenized text. Messages shorter than two
+    /// tokens are shingled by unigrams.
+    pub fn from_tokens(tokens: &[String], num_hashes: usize) -> Self {
+        let mut mins = vec![u64::MAX; num_hashes];
+        let mut add = |shingle: &[String]| {
+            for (seed, slot) in mins.iter_mut().enumerate() {
+                let mut hasher = FxHasher::default();
+                seed.hash(&mut hasher);
+                shingle.hash(&mut hasher);
+                *slot = (*slot).min(hasher.finish());
+            }
+        };
+
+        if tokens.len() < 2 {
+            tokens.chunks(1).for_each(&mut add);
+        } else {
+            tokens.windows(2).for_each(&mut add);
+        }
+        Self { mins }
+    }
+
+    /// Signature of raw text with the default tokenizer settings.
+    pub fn of(text: &str) -> Self {
+        Self::from_tokens(&text::tokenize(text, EmojiMode::Strip), DEFAULT_NUM_HASHES)
+    }
+
+    /// True if the signature was built from no tokens at all.
+    pub fn is_empty(&self) -> bool {
+        self.mins.iter().all(|&m| m == u64::MAX)
+    }
+
+    /// Estimated Jaccard similarity in [0, 1]; 0.0 for mismatched sizes
+    /// or empty signatures.
+    pub fn similarity(&self, other: &Self) -> f64 {
+        if self.mins.len() != other.mins.len() || self.is_empty() || other.is_empty() {
+            return 0.0;
+        }
+        let equal = self
+            .mins
+            .iter()
+            .zip(&other.mins)
+            .filter(|(a, b)| a == b)
+            .count();
+        equal as f64 / self.mins.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical_and_disjoint_similarity() {
+        let a = MinHashSignature::of("please reset my account password today");
+        let b = MinHashSignature::of("Please reset my account password today!");
+        let c = MinHashSignature::of("the weather in lisbon is lovely in spring");
+        assert_eq!(a.similarity(&b), 1.0);
+        assert!(a.similarity(&c) < 0.2);
+    }
+
+    #[test]
+    fn test_empty_signature_never_matches() {
+        let empty = MinHashSignature::of("");
+        assert!(empty.is_empty());
+        assert_eq!(empty.similarity(&empty), 0.0);
+        assert!(!MinHashSignature::of("hi").is_empty());
+    }
+}
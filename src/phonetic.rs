@@ -0,0 +1,73 @@
+//! Phonetic keys for matching noisy speech-recognition transcripts.
+//!
+//! ASR output misspells words the way they sound ("kubernetees",
+//! "postgress"), which lexical overlap counts as drift. With
+//! `WordMathConfig::phonetic` set, overlap compares American Soundex codes
+//! instead, so such misspellings still count toward the topic. Soundex
+//! keeps the first letter and three consonant classes, so unrelated words
+//! collide too ("rust" and "roast", "server" and "serf"); enable it only
+//! for voice surfaces.
+
+/// Soundex class of an ASCII letter; None for vowels and h, w, y.
+fn class(c: char) -> Option<u8> {
+    Some(match c {
+        'b' | 'f' | 'p' | 'v' => b'1',
+        'c' | 'g' | 'j' | 'k' | 'q' | 's' | 'x' | 'z' => b'2',
+        'd' | 't' => b'3',
+        'l' => b'4',
+        'm' | 'n' => b'5',
+        'r' => b'6',
+        _ => return None,
+    })
+}
+
+/// American Soundex code of a lowercase token, e.g. "k165" for
+/// "kubernetes". Tokens not starting with an ASCII letter (numbers, other
+/// scripts) are returned unchanged; other non-letters are skipped.
+pub fn soundex(token: &str) -> String {
+    let mut letters = token.chars().filter(char::is_ascii_alphabetic);
+    let first = match token.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => c.to_ascii_lowercase(),
+        _ => return token.to_string(),
+    };
+    letters.next();
+    let mut code = String::with_capacity(4);
+    code.push(first);
+    let mut last = class(first);
+    for c in letters.map(|c| c.to_ascii_lowercase()) {
+        if code.len() == 4 {
+            break;
+        }
+        match class(c) {
+            Some(digit) if Some(digit) != last => {
+                code.push(digit as char);
+                last = Some(digit);
+            }
+            Some(_) => {}
+            // h and w don't separate equal classes; vowels do.
+            None if c == 'h' || c == 'w' => {}
+            None => last = None,
+        }
+    }
+    while code.len() < 4 {
+        code.push('0');
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_soundex_codes() {
+        assert_eq!(soundex("robert"), "r163");
+        assert_eq!(soundex("rupert"), "r163");
+        assert_eq!(soundex("ashcraft"), "a261");
+        assert_eq!(soundex("tymczak"), "t522");
+        assert_eq!(soundex("pfister"), "p236");
+        assert_eq!(soundex("kubernetees"), soundex("kubernetes"));
+        assert_eq!(soundex("42"), "42");
+        assert_eq!(soundex("привет"), "привет");
+    }
+}
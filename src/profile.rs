@@ -0,0 +1,87 @@
+use crate::{WordMathConfig, WordMathError};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Named scoring profiles, e.g. one per product surface.
+///
+/// Loaded from a JSON object mapping profile names to (partial)
+/// `WordMathConfig` objects; omitted fields take their defaults:
+///
+/// ```json
+/// { "support-bot": { "alpha": 0.6, "beta": 0.4, "transform": { "floor": 0.1 } } }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct ProfileSet {
+    profiles: HashMap<String, WordMathConfig>,
+}
+
+impl ProfileSet {
+    /// Parse a profile set from a JSON string.
+    pub fn from_json(json: &str) -> Result<Self, WordMathError> {
+        serde_json::from_str(json).map_err(|e| WordMathError::Config(e.to_string()))
+    }
+
+    /// Read and parse a profile set from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WordMathError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| WordMathError::Config(format!("{}: {}", path.display(), e)))?;
+        Self::from_json(&json)
+    }
+
+    /// Load the file named by WORD_MATH_PROFILES, or an empty set if unset.
+    pub fn from_env() -> Result<Self, WordMathError> {
+        match std::env::var("WORD_MATH_PROFILES") {
+            Ok(path) => Self::load(path),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<WordMathConfig> {
+        self.profiles.get(name).copied()
+    }
+
+    /// Profile names in sorted order.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    pub fn len(&self) -> usize {
+        self.profiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_from_json_fill_defaults() {
+        let set = ProfileSet::from_json(
+            r#"{ "lenient": { "alpha": 0.2, "transform": { "floor": 0.1 } } }"#,
+        )
+        .unwrap();
+        let cfg = set.get("lenient").unwrap();
+        assert_eq!(cfg.alpha, 0.2);
+        assert_eq!(cfg.beta, WordMathConfig::default().beta);
+        assert_eq!(cfg.transform.floor, 0.1);
+        assert_eq!(cfg.transform.ceiling, 1.0);
+        assert!(set.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_profiles_reject_bad_json() {
+        assert!(matches!(
+            ProfileSet::from_json("{ not json"),
+            Err(WordMathError::Config(_))
+        ));
+    }
+}
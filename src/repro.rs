@@ -0,0 +1,86 @@
+//! Bit-reproducible arithmetic for attested scores.
+//!
+//! Plain scoring only uses IEEE-754 addition, multiplication and division,
+//! which give the same bits everywhere. Two things do not: `f64::ln` comes
+//! from the platform's math library, whose last-bit rounding differs
+//! between systems, and long sums lose precision in ways that depend on
+//! their order. With `WordMathConfig::reproducible` set, logarithms are
+//! computed from basic operations only (see `core::log2`) and sums use
+//! Neumaier compensated summation over a fixed order, so the same input
+//! and config score bit-identically on every platform.
+
+use crate::WordMathConfig;
+
+/// How floating-point reductions and logarithms are computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Arithmetic {
+    /// Platform `ln`, left-to-right summation.
+    #[default]
+    Native,
+    /// Portable `ln`, compensated summation.
+    Reproducible,
+}
+
+impl Arithmetic {
+    pub fn of(cfg: &WordMathConfig) -> Self {
+        if cfg.reproducible {
+            Self::Reproducible
+        } else {
+            Self::Native
+        }
+    }
+
+    /// Sum `values` in iteration order.
+    pub fn sum(self, values: impl IntoIterator<Item = f64>) -> f64 {
+        match self {
+            Self::Native => values.into_iter().sum(),
+            Self::Reproducible => compensated_sum(values),
+        }
+    }
+
+    /// Natural logarithm of a positive, normal `x`.
+    pub fn ln(self, x: f64) -> f64 {
+        match self {
+            Self::Native => x.ln(),
+            Self::Reproducible => crate::core::log2(x) * std::f64::consts::LN_2,
+        }
+    }
+}
+
+/// Neumaier's variant of Kahan summation: the rounding error of every
+/// addition is carried separately and added back at the end.
+pub fn compensated_sum(values: impl IntoIterator<Item = f64>) -> f64 {
+    let mut sum = 0.0;
+    let mut compensation = 0.0;
+    for value in values {
+        let t = sum + value;
+        compensation += if f64::abs(sum) >= f64::abs(value) {
+            (sum - t) + value
+        } else {
+            (value - t) + sum
+        };
+        sum = t;
+    }
+    sum + compensation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compensated_sum_keeps_small_terms() {
+        let values = [1e16, 1.0, -1e16];
+        assert_eq!(values.iter().sum::<f64>(), 0.0);
+        assert_eq!(compensated_sum(values), 1.0);
+        assert_eq!(compensated_sum([0.1; 10]), 1.0);
+    }
+
+    #[test]
+    fn test_portable_ln_tracks_platform_ln() {
+        for x in [1.0001, 1.5, 2.0, 10.0, 123_456.789] {
+            let portable = Arithmetic::Reproducible.ln(x);
+            assert!((portable - x.ln()).abs() < 1e-12, "ln({})", x);
+        }
+    }
+}
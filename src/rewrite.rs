@@ -0,0 +1,367 @@
+//! Trim suggestions for degenerate, repetitive messages.
+//!
+//! Rather than rejecting a message outright, upstream systems can drop the
+//! spans suggested here to bring the repetition metric under
+//! `Thresholds::max_repetition`.
+
+use crate::text::{self, EmojiMode, TokenSpan};
+use crate::{repetition_density_of, WordMathConfig};
+use serde::Serialize;
+
+/// Unit of text a suggestion removes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RemovalKind {
+    /// A whole sentence (or line).
+    Sentence,
+    /// Two adjacent words.
+    Bigram,
+}
+
+/// One suggested removal, as a byte range into `TrimSuggestion::source`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Removal {
+    pub kind: RemovalKind,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Result of `suggest_trims`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrimSuggestion {
+    /// Text the removal ranges refer to: the message, normalized when
+    /// `cfg.normalize` is set.
+    pub source: String,
+    /// Removals in the order they were chosen.
+    pub removals: Vec<Removal>,
+    pub y_before: f64,
+    pub y_after: f64,
+    /// `source` with every removal applied.
+    pub trimmed: String,
+    /// False if no sequence of removals got y under the threshold.
+    pub reaches_threshold: bool,
+}
+
+/// Candidate removal: a byte range and the token indices it covers.
+struct Unit {
+    kind: RemovalKind,
+    start: usize,
+    end: usize,
+    tokens: std::ops::Range<usize>,
+}
+
+/// Split `spans` into sentences ending at `.`, `!`, `?` or a newline.
+fn sentences(source: &str, spans: &[TokenSpan]) -> Vec<Unit> {
+    let mut units = Vec::new();
+    let mut first = 0;
+    for i in 0..spans.len() {
+        let gap_end = spans.get(i + 1).map_or(source.len(), |next| next.start);
+        let gap = &source[spans[i].end..gap_end];
+        if i + 1 == spans.len() || gap.contains(['.', '!', '?', '\n']) {
+            units.push(Unit {
+                kind: RemovalKind::Sentence,
+                start: spans[first].start,
+                end: gap_end,
+                tokens: first..i + 1,
+            });
+            first = i + 1;
+        }
+    }
+    units
+}
+
+fn density(tokens: &[String], alive: &[bool]) -> f64 {
+    let kept: Vec<String> = tokens
+        .iter()
+        .zip(alive)
+        .filter(|(_, &alive)| alive)
+        .map(|(token, _)| token.clone())
+        .collect();
+    repetition_density_of(&kept)
+}
+
+/// The most frequent alive token.
+fn most_repeated<'a>(tokens: &'a [String], alive: &[bool]) -> Option<&'a str> {
+    let mut counts: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+    for (token, _) in tokens.iter().zip(alive).filter(|(_, &alive)| alive) {
+        *counts.entry(token.as_str()).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+        .map(|(token, _)| token)
+}
+
+/// The last adjacent alive pair containing `target`, preferring pairs where
+/// both words are `target`.
+fn tail_bigram(spans: &[TokenSpan], alive: &[bool], target: &str) -> Option<Unit> {
+    let live: Vec<usize> = (0..spans.len()).filter(|&i| alive[i]).collect();
+    let pairs = || live.windows(2).rev().filter(|w| w[1] == w[0] + 1);
+    let hits =
+        |w: &&[usize]| (spans[w[0]].token == target) as u8 + (spans[w[1]].token == target) as u8;
+    let pair = pairs()
+        .find(|w| hits(w) == 2)
+        .or_else(|| pairs().find(|w| hits(w) == 1))?;
+    Some(Unit {
+        kind: RemovalKind::Bigram,
+        start: spans[pair[0]].start,
+        end: spans[pair[1]].end,
+        tokens: pair[0]..pair[1] + 1,
+    })
+}
+
+/// Greedily pick sentence or bigram removals until the repetition density
+/// drops to `cfg.thresholds.max_repetition`, taking at each step the
+/// removal that lowers it most (later spans win ties, since degenerate
+/// output usually loops at the tail). Never removes every word.
+pub fn suggest_trims(message: &str, cfg: &WordMathConfig) -> TrimSuggestion {
+    let source = if cfg.normalize {
+        text::normalize(message).text
+    } else {
+        message.to_string()
+    };
+    let spans = text::token_spans(&source, cfg.emoji_mode);
+    let tokens: Vec<String> = spans.iter().map(|span| span.token.clone()).collect();
+    let limit = cfg.thresholds.max_repetition;
+
+    let mut alive = vec![true; tokens.len()];
+    let y_before = repetition_density_of(&tokens);
+    let mut y = y_before;
+    let mut chosen: Vec<Unit> = Vec::new();
+    let sentence_units = sentences(&source, &spans);
+
+    while y > limit {
+        let remaining = alive.iter().filter(|&&a| a).count();
+        let mut candidates: Vec<Unit> = sentence_units
+            .iter()
+            .filter(|unit| unit.tokens.clone().all(|i| alive[i]))
+            .filter(|unit| unit.tokens.len() < remaining)
+            .map(|unit| Unit {
+                tokens: unit.tokens.clone(),
+                ..*unit
+            })
+            .collect();
+        if remaining > 2 {
+            if let Some(target) = most_repeated(&tokens, &alive) {
+                candidates.extend(tail_bigram(&spans, &alive, target));
+            }
+        }
+
+        let mut best: Option<(f64, Unit)> = None;
+        for unit in candidates {
+            let mut trial = alive.clone();
+            unit.tokens.clone().for_each(|i| trial[i] = false);
+            let trial_y = density(&tokens, &trial);
+            if best.as_ref().is_none_or(|(best_y, _)| trial_y <= *best_y) {
+                best = Some((trial_y, unit));
+            }
+        }
+
+        match best {
+            Some((next_y, unit)) if next_y < y => {
+                unit.tokens.clone().for_each(|i| alive[i] = false);
+                y = next_y;
+                chosen.push(unit);
+            }
+            _ => break,
+        }
+    }
+
+    let mut ranges: Vec<(usize, usize)> = chosen.iter().map(|u| (u.start, u.end)).collect();
+    ranges.sort_unstable();
+    let mut trimmed = String::with_capacity(source.len());
+    let mut cursor = 0;
+    for (start, end) in ranges {
+        trimmed.push_str(&source[cursor..start.max(cursor)]);
+        cursor = cursor.max(end);
+    }
+    trimmed.push_str(&source[cursor..]);
+
+    TrimSuggestion {
+        removals: chosen
+            .iter()
+            .map(|unit| Removal {
+                kind: unit.kind,
+                start: unit.start,
+                end: unit.end,
+                text: source[unit.start..unit.end].to_string(),
+            })
+            .collect(),
+        trimmed: trimmed.trim_end().to_string(),
+        source,
+        y_before,
+        y_after: y,
+        reaches_threshold: y <= limit,
+    }
+}
+
+/// Fewest copies of a repeating unit that count as a loop.
+const MIN_LOOP_REPEATS: usize = 3;
+/// Shortest looping tail, in tokens, that counts as degeneration.
+const MIN_LOOP_TOKENS: usize = 6;
+/// Longest repeating unit considered, in tokens.
+const MAX_LOOP_PERIOD: usize = 32;
+
+/// Byte offset in `message` where a looping tail begins, if any.
+///
+/// Scans back from the end for the longest suffix that repeats with some
+/// period of up to `MAX_LOOP_PERIOD` tokens, at least `MIN_LOOP_REPEATS`
+/// times; a trailing partial copy of the unit (a cut-off generation) still
+/// counts. The earliest such onset wins, so `&message[..onset]` is the
+/// prefix written before the model started looping.
+pub fn find_degeneration_onset(message: &str) -> Option<usize> {
+    let spans = text::token_spans(message, EmojiMode::Strip);
+    looping_tail(&spans).map(|(start, _)| spans[start].start)
+}
+
+/// Token index where the looping tail starts, and its period in tokens.
+fn looping_tail(spans: &[TokenSpan]) -> Option<(usize, usize)> {
+    let n = spans.len();
+    let same = |i: usize, j: usize| spans[i].token == spans[j].token;
+
+    let mut onset: Option<(usize, usize)> = None;
+    for period in 1..=MAX_LOOP_PERIOD.min(n / MIN_LOOP_REPEATS) {
+        let mut start = n - period;
+        while start > 0 && same(start - 1, start - 1 + period) {
+            start -= 1;
+        }
+        let tail = n - start;
+        if tail >= period * MIN_LOOP_REPEATS
+            && tail >= MIN_LOOP_TOKENS
+            && onset.is_none_or(|(best, _)| start < best)
+        {
+            onset = Some((start, period));
+        }
+    }
+    onset
+}
+
+/// Result of `sanitize`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sanitized {
+    /// The repaired message.
+    pub text: String,
+    /// Looping tail cut from the end, if one was found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub removed_tail: Option<String>,
+    /// Repeated sentences dropped after their first occurrence.
+    pub collapsed_sentences: usize,
+}
+
+/// Repair a degenerate message instead of rejecting it: cut a looping tail
+/// down to one copy of its unit (see `find_degeneration_onset`) and collapse sentences that repeat an
+/// earlier one word for word, keeping the first copy. Never returns an
+/// empty message for non-empty input.
+pub fn sanitize(message: &str, cfg: &WordMathConfig) -> Sanitized {
+    let source = if cfg.normalize {
+        text::normalize(message).text
+    } else {
+        message.to_string()
+    };
+    // Keep one copy of the looping unit; it is usually meant to be there.
+    let tail_spans = text::token_spans(&source, EmojiMode::Strip);
+    let (head, removed_tail) = match looping_tail(&tail_spans) {
+        Some((start, period)) => {
+            let cut = tail_spans[start + period].start;
+            (&source[..cut], Some(source[cut..].to_string()))
+        }
+        None => (source.as_str(), None),
+    };
+
+    let spans = text::token_spans(head, cfg.emoji_mode);
+    let mut seen: Vec<&[TokenSpan]> = Vec::new();
+    let mut kept = String::with_capacity(head.len());
+    let mut cursor = 0;
+    let mut collapsed_sentences = 0;
+    for unit in sentences(head, &spans) {
+        let words = &spans[unit.tokens.clone()];
+        let repeat = seen.iter().any(|earlier| {
+            earlier.len() == words.len()
+                && earlier.iter().zip(words).all(|(a, b)| a.token == b.token)
+        });
+        if repeat {
+            kept.push_str(&head[cursor..unit.start]);
+            cursor = unit.end;
+            collapsed_sentences += 1;
+        } else {
+            seen.push(words);
+        }
+    }
+    kept.push_str(&head[cursor..]);
+
+    Sanitized {
+        text: kept.trim_end().to_string(),
+        removed_tail,
+        collapsed_sentences,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trims_looping_tail_sentences() {
+        let cfg = WordMathConfig::default();
+        let message = "Rust has great tooling. Spam spam spam. Spam spam spam. Spam spam spam.";
+        let suggestion = suggest_trims(message, &cfg);
+
+        assert!(suggestion.y_before > cfg.thresholds.max_repetition);
+        assert!(suggestion.reaches_threshold);
+        assert!(suggestion.y_after <= cfg.thresholds.max_repetition);
+        assert!(suggestion
+            .removals
+            .iter()
+            .all(|r| r.kind == RemovalKind::Sentence && r.text.starts_with("Spam spam spam.")));
+        assert!(suggestion.trimmed.starts_with("Rust has great tooling."));
+    }
+
+    #[test]
+    fn test_falls_back_to_bigrams_within_one_sentence() {
+        let cfg = WordMathConfig::default();
+        let suggestion = suggest_trims("the the the the cat sat", &cfg);
+        assert!(suggestion.reaches_threshold);
+        assert_eq!(suggestion.removals[0].kind, RemovalKind::Bigram);
+        assert_eq!(suggestion.removals[0].text, "the the");
+    }
+
+    #[test]
+    fn test_degeneration_onset_keeps_good_prefix() {
+        let message = "Here is the summary you asked for. I hope it helps I hope it helps I hope it helps I hope";
+        let onset = find_degeneration_onset(message).unwrap();
+        assert_eq!(&message[..onset], "Here is the summary you asked for. ");
+
+        assert_eq!(find_degeneration_onset("no no no, not that one"), None);
+        assert_eq!(find_degeneration_onset(""), None);
+    }
+
+    #[test]
+    fn test_sanitize_cuts_loop_and_collapses_boilerplate() {
+        let cfg = WordMathConfig::default();
+        let message = "Thanks for reaching out. Your refund is on its way. Thanks for reaching out. \
+                       Let me know if there is anything else anything else anything else anything else";
+        let sanitized = sanitize(message, &cfg);
+        assert_eq!(sanitized.collapsed_sentences, 1);
+        assert_eq!(
+            sanitized.text,
+            "Thanks for reaching out. Your refund is on its way. Let me know if there is anything else"
+        );
+        assert_eq!(
+            sanitized.removed_tail.as_deref(),
+            Some("anything else anything else anything else")
+        );
+
+        let clean = sanitize("a perfectly ordinary sentence", &cfg);
+        assert_eq!(clean.text, "a perfectly ordinary sentence");
+        assert_eq!((clean.removed_tail, clean.collapsed_sentences), (None, 0));
+    }
+
+    #[test]
+    fn test_clean_message_needs_no_trims() {
+        let cfg = WordMathConfig::default();
+        let suggestion = suggest_trims("a perfectly ordinary sentence", &cfg);
+        assert!(suggestion.removals.is_empty());
+        assert_eq!(suggestion.trimmed, "a perfectly ordinary sentence");
+    }
+}
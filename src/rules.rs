@@ -0,0 +1,488 @@
+//! Policy rules evaluated after the metrics are computed.
+//!
+//! A rule is a boolean condition over the analysis metrics plus the verdict
+//! it forces when the condition holds. Rules can only make a verdict
+//! stricter: the final verdict is the most severe of the threshold verdict
+//! and every matching rule. Rule sets are loaded from a JSON array:
+//!
+//! ```json
+//! [
+//!   { "id": "loop-and-drift", "when": "repetition > 0.5 AND drift > 0.2", "then": "block" },
+//!   { "id": "padding", "when": "obfuscation >= 0.1 OR invisible_stripped > 20", "then": "warn" }
+//! ]
+//! ```
+//!
+//! Conditions compare a metric with a number using `>`, `>=`, `<`, `<=`,
+//! `==` or `!=`, and combine comparisons with `AND`, `OR`, `NOT` (or `&&`,
+//! `||`, `!`) and parentheses. `NOT` binds tightest, then `AND`, then `OR`.
+//! Metrics: `score`, `repetition`, `drift`, `malformed`, `obfuscation`,
+//! `emoji_count`, `invisible_stripped`, `homoglyphs`, `split_letters`,
+//! `evasion_risk`.
+
+use crate::verdict::{RuleHit, Severity, Verdict, VerdictExplanation};
+use crate::{WordMathAnalysis, WordMathError};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Analysis value a condition can test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleMetric {
+    Score,
+    Repetition,
+    Drift,
+    Malformed,
+    Obfuscation,
+    EmojiCount,
+    InvisibleStripped,
+    Homoglyphs,
+    SplitLetters,
+    EvasionRisk,
+}
+
+impl RuleMetric {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "score" => Self::Score,
+            "repetition" | "y" => Self::Repetition,
+            "drift" | "z" => Self::Drift,
+            "malformed" => Self::Malformed,
+            "obfuscation" => Self::Obfuscation,
+            "emoji_count" => Self::EmojiCount,
+            "invisible_stripped" => Self::InvisibleStripped,
+            "homoglyphs" => Self::Homoglyphs,
+            "split_letters" => Self::SplitLetters,
+            "evasion_risk" => Self::EvasionRisk,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Score => "score",
+            Self::Repetition => "repetition",
+            Self::Drift => "drift",
+            Self::Malformed => "malformed",
+            Self::Obfuscation => "obfuscation",
+            Self::EmojiCount => "emoji_count",
+            Self::InvisibleStripped => "invisible_stripped",
+            Self::Homoglyphs => "homoglyphs",
+            Self::SplitLetters => "split_letters",
+            Self::EvasionRisk => "evasion_risk",
+        }
+    }
+
+    fn value(self, analysis: &WordMathAnalysis) -> f64 {
+        match self {
+            Self::Score => analysis.score,
+            Self::Repetition => analysis.y_repetition,
+            Self::Drift => analysis.z_drift,
+            Self::Malformed => analysis.malformed_ratio,
+            Self::Obfuscation => analysis.obfuscation,
+            Self::EmojiCount => analysis.emoji_count as f64,
+            Self::InvisibleStripped => analysis.invisible_stripped as f64,
+            Self::Homoglyphs => analysis.homoglyphs as f64,
+            Self::SplitLetters => analysis.split_letters as f64,
+            Self::EvasionRisk => analysis.evasion_risk,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl Comparison {
+    fn holds(self, left: f64, right: f64) -> bool {
+        match self {
+            Self::Gt => left > right,
+            Self::Ge => left >= right,
+            Self::Lt => left < right,
+            Self::Le => left <= right,
+            Self::Eq => left == right,
+            Self::Ne => left != right,
+        }
+    }
+}
+
+/// Parsed rule condition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Compare {
+        metric: RuleMetric,
+        op: Comparison,
+        value: f64,
+    },
+    Not(Box<Condition>),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, WordMathError> {
+        let tokens = lex(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let condition = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(condition),
+            Some(token) => Err(WordMathError::Config(format!(
+                "unexpected '{}' in rule condition",
+                token
+            ))),
+        }
+    }
+
+    pub fn matches(&self, analysis: &WordMathAnalysis) -> bool {
+        match self {
+            Self::Compare { metric, op, value } => op.holds(metric.value(analysis), *value),
+            Self::Not(inner) => !inner.matches(analysis),
+            Self::And(all) => all.iter().all(|c| c.matches(analysis)),
+            Self::Or(any) => any.iter().any(|c| c.matches(analysis)),
+        }
+    }
+}
+
+fn lex(source: &str) -> Result<Vec<String>, WordMathError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            tokens.push(c.to_string());
+            chars.next();
+        } else if "<>=!&|".contains(c) {
+            let mut op = String::new();
+            while let Some(&c) = chars.peek().filter(|c| "<>=!&|".contains(**c)) {
+                op.push(c);
+                chars.next();
+            }
+            tokens.push(op);
+        } else if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' {
+            let mut word = String::new();
+            while let Some(&c) = chars
+                .peek()
+                .filter(|c| c.is_alphanumeric() || **c == '_' || **c == '.' || **c == '-')
+            {
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(word);
+        } else {
+            return Err(WordMathError::Config(format!(
+                "unexpected character '{}' in rule condition",
+                c
+            )));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_is(&self, words: &[&str]) -> bool {
+        self.tokens
+            .get(self.pos)
+            .is_some_and(|t| words.iter().any(|w| t.eq_ignore_ascii_case(w)))
+    }
+
+    fn next(&mut self) -> Result<String, WordMathError> {
+        let token = self.tokens.get(self.pos).cloned().ok_or_else(|| {
+            WordMathError::Config("rule condition ended unexpectedly".to_string())
+        })?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Condition, WordMathError> {
+        let mut any = vec![self.and()?];
+        while self.peek_is(&["or", "||"]) {
+            self.pos += 1;
+            any.push(self.and()?);
+        }
+        Ok(if any.len() == 1 {
+            any.remove(0)
+        } else {
+            Condition::Or(any)
+        })
+    }
+
+    fn and(&mut self) -> Result<Condition, WordMathError> {
+        let mut all = vec![self.not()?];
+        while self.peek_is(&["and", "&&"]) {
+            self.pos += 1;
+            all.push(self.not()?);
+        }
+        Ok(if all.len() == 1 {
+            all.remove(0)
+        } else {
+            Condition::And(all)
+        })
+    }
+
+    fn not(&mut self) -> Result<Condition, WordMathError> {
+        if self.peek_is(&["not", "!"]) {
+            self.pos += 1;
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        if self.peek_is(&["("]) {
+            self.pos += 1;
+            let inner = self.or()?;
+            return match self.next()?.as_str() {
+                ")" => Ok(inner),
+                other => Err(WordMathError::Config(format!(
+                    "expected ')' in rule condition, found '{}'",
+                    other
+                ))),
+            };
+        }
+        let name = self.next()?;
+        let metric = RuleMetric::parse(&name.to_ascii_lowercase())
+            .ok_or_else(|| WordMathError::Config(format!("unknown rule metric: {}", name)))?;
+        let op = match self.next()?.as_str() {
+            ">" => Comparison::Gt,
+            ">=" => Comparison::Ge,
+            "<" => Comparison::Lt,
+            "<=" => Comparison::Le,
+            "==" | "=" => Comparison::Eq,
+            "!=" => Comparison::Ne,
+            other => {
+                return Err(WordMathError::Config(format!(
+                    "unknown comparison '{}' in rule condition",
+                    other
+                )))
+            }
+        };
+        let number = self.next()?;
+        let value = number.parse::<f64>().map_err(|_| {
+            WordMathError::Config(format!(
+                "expected a number in rule condition, found '{}'",
+                number
+            ))
+        })?;
+        Ok(Condition::Compare { metric, op, value })
+    }
+}
+
+#[derive(Deserialize)]
+struct RawRule {
+    id: String,
+    when: String,
+    then: Verdict,
+}
+
+/// One policy rule: if `condition` holds, the verdict is at least `verdict`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub id: String,
+    pub condition: Condition,
+    pub verdict: Verdict,
+}
+
+/// Ordered list of policy rules.
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn from_json(json: &str) -> Result<Self, WordMathError> {
+        let raw: Vec<RawRule> =
+            serde_json::from_str(json).map_err(|e| WordMathError::Config(e.to_string()))?;
+        let mut rules: Vec<Rule> = Vec::with_capacity(raw.len());
+        for rule in raw {
+            if rules.iter().any(|r| r.id == rule.id) {
+                return Err(WordMathError::Config(format!(
+                    "duplicate rule id: {}",
+                    rule.id
+                )));
+            }
+            let condition = Condition::parse(&rule.when).map_err(|e| match e {
+                WordMathError::Config(msg) => {
+                    WordMathError::Config(format!("rule {}: {}", rule.id, msg))
+                }
+                other => other,
+            })?;
+            rules.push(Rule {
+                id: rule.id,
+                condition,
+                verdict: rule.then,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WordMathError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| WordMathError::Config(format!("{}: {}", path.display(), e)))?;
+        Self::from_json(&json)
+    }
+
+    /// Load the file named by WORD_MATH_RULES, or an empty set if unset.
+    pub fn from_env() -> Result<Self, WordMathError> {
+        match std::env::var("WORD_MATH_RULES") {
+            Ok(path) => Self::load(path),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Rules whose condition holds for `analysis`, in file order.
+    pub fn matching<'a>(
+        &'a self,
+        analysis: &'a WordMathAnalysis,
+    ) -> impl Iterator<Item = &'a Rule> {
+        self.rules
+            .iter()
+            .filter(move |rule| rule.condition.matches(analysis))
+    }
+
+    /// Strictest verdict among the matching rules, if any match.
+    pub fn strictest(&self, analysis: &WordMathAnalysis) -> Option<Verdict> {
+        self.matching(analysis).map(|rule| rule.verdict).max()
+    }
+
+    /// Record the matching rules in `explanation.rule_hits` and raise it to
+    /// the strictest one's verdict (and the matching severity floor).
+    /// Returns the matching rules.
+    pub fn apply<'a>(
+        &'a self,
+        analysis: &'a WordMathAnalysis,
+        explanation: &mut VerdictExplanation,
+    ) -> Vec<&'a Rule> {
+        self.apply_where(analysis, explanation, |_| true)
+    }
+
+    /// Like `apply`, considering only rules accepted by `enabled` (e.g.
+    /// to honour runtime kill switches).
+    pub fn apply_where<'a>(
+        &'a self,
+        analysis: &'a WordMathAnalysis,
+        explanation: &mut VerdictExplanation,
+        enabled: impl Fn(&Rule) -> bool,
+    ) -> Vec<&'a Rule> {
+        let matched: Vec<&Rule> = self.matching(analysis).filter(|r| enabled(r)).collect();
+        explanation
+            .rule_hits
+            .extend(matched.iter().map(|rule| RuleHit {
+                id: rule.id.clone(),
+                verdict: rule.verdict,
+            }));
+        if let Some(strictest) = matched.iter().map(|rule| rule.verdict).max() {
+            if strictest > explanation.verdict {
+                explanation.verdict = strictest;
+                explanation.severity = explanation.severity.max(Severity::floor_for(strictest));
+            }
+        }
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyze_message_with_trace, verdict, WordMathConfig};
+
+    #[test]
+    fn test_parse_precedence_and_errors() {
+        let condition =
+            Condition::parse("repetition > 0.5 and not drift <= 0.2 or score < 0.1").unwrap();
+        let Condition::Or(any) = &condition else {
+            panic!("expected OR at the top: {:?}", condition);
+        };
+        assert!(matches!(&any[0], Condition::And(all) if matches!(all[1], Condition::Not(_))));
+
+        assert!(Condition::parse("(repetition > 0.5 && (drift > 0.1))").is_ok());
+        for bad in [
+            "injection_risk > 0.2",
+            "repetition >",
+            "repetition ~ 0.3",
+            "(drift > 0.1",
+            "drift > 0.1 0.2",
+        ] {
+            assert!(Condition::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_disabled_rules_are_not_reported() {
+        let rules = RuleSet::from_json(
+            r#"[
+                { "id": "loop", "when": "repetition > 0.5", "then": "block" },
+                { "id": "any", "when": "score >= 0", "then": "warn" }
+            ]"#,
+        )
+        .unwrap();
+        let cfg = WordMathConfig::default();
+        let (analysis, _) =
+            analyze_message_with_trace("rust rust rust rust web", "rust web server", cfg);
+        let mut explanation = verdict::evaluate(&analysis, &cfg);
+        rules.apply_where(&analysis, &mut explanation, |rule| rule.id != "loop");
+        assert_eq!(
+            explanation.rule_hits,
+            [RuleHit {
+                id: "any".to_string(),
+                verdict: Verdict::Warn
+            }]
+        );
+        assert_ne!(explanation.verdict, Verdict::Block);
+    }
+
+    #[test]
+    fn test_rules_only_tighten_the_verdict() {
+        let rules = RuleSet::from_json(
+            r#"[
+                { "id": "loop", "when": "repetition > 0.5 AND drift > 0.2", "then": "block" },
+                { "id": "lenient", "when": "score < 2", "then": "allow" }
+            ]"#,
+        )
+        .unwrap();
+        let cfg = WordMathConfig::default();
+
+        let (analysis, _) =
+            analyze_message_with_trace("rust rust rust web tokio", "rust web server", cfg);
+        let mut explanation = verdict::evaluate(&analysis, &cfg);
+        assert_ne!(explanation.verdict, Verdict::Block);
+        let matched = rules.apply(&analysis, &mut explanation);
+        assert_eq!(
+            matched.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(),
+            ["loop", "lenient"]
+        );
+        assert_eq!(explanation.verdict, Verdict::Block);
+        assert!(explanation.severity >= Severity::Severe);
+        assert_eq!(explanation.rule_hits.len(), 2);
+        assert_eq!(explanation.summary(), "blocked by rule loop");
+
+        let (analysis, _) = analyze_message_with_trace("spam spam spam spam", "rust", cfg);
+        let mut explanation = verdict::evaluate(&analysis, &cfg);
+        rules.apply(&analysis, &mut explanation);
+        assert_eq!(explanation.verdict, Verdict::Block);
+
+        assert!(RuleSet::from_json(
+            r#"[{ "id": "a", "when": "score < 1", "then": "warn" },
+                { "id": "a", "when": "score < 1", "then": "warn" }]"#
+        )
+        .is_err());
+    }
+}
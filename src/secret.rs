@@ -0,0 +1,134 @@
+use crate::WordMathError;
+use std::fmt;
+
+/// A secret string that is overwritten with zeros when dropped and never
+/// printed by `Debug`.
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        // The whole allocation is cleared, not just `len` bytes, so text cut
+        // off by `truncate` goes too.
+        // SAFETY: every write stays within the allocation's capacity and
+        // only zero bytes are written, which keeps the string valid UTF-8;
+        // volatile writes keep the compiler from eliding them.
+        unsafe {
+            let bytes = self.0.as_mut_vec();
+            let ptr = bytes.as_mut_ptr();
+            for i in 0..bytes.capacity() {
+                std::ptr::write_volatile(ptr.add(i), 0);
+            }
+        }
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+/// Read the secret setting `name` from the environment.
+///
+/// `<name>_FILE`, when set, names a file holding the value (a trailing
+/// newline is dropped); otherwise `<name>` itself is used. Either way,
+/// `${OTHER}` references in the value are replaced with the contents of
+/// the environment variable `OTHER`. Returns `None` when neither is set.
+pub fn from_env(name: &str) -> Result<Option<Secret>, WordMathError> {
+    let file_var = format!("{}_FILE", name);
+    let raw = match std::env::var(&file_var) {
+        Ok(path) => {
+            let mut contents =
+                Secret::new(std::fs::read_to_string(&path).map_err(|e| {
+                    WordMathError::Config(format!("{}: {}: {}", file_var, path, e))
+                })?);
+            let trimmed = contents.0.trim_end_matches(['\r', '\n']).len();
+            contents.0.truncate(trimmed);
+            contents
+        }
+        Err(_) => match std::env::var(name) {
+            Ok(value) => Secret::new(value),
+            Err(_) => return Ok(None),
+        },
+    };
+    expand(&raw, |var| std::env::var(var).ok())
+        .map(Some)
+        .map_err(|e| WordMathError::Config(format!("{}: {}", name, e)))
+}
+
+/// Replace each `${VAR}` in `value` using `lookup`.
+///
+/// All references are resolved first so the result is allocated once at its
+/// final size: growing it would leave copies behind in freed buffers.
+fn expand(value: &Secret, lookup: impl Fn(&str) -> Option<String>) -> Result<Secret, String> {
+    let mut parts: Vec<(&str, Secret)> = Vec::new();
+    let mut rest = value.expose();
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| "unterminated ${".to_string())?
+            + start;
+        let var = &rest[start + 2..end];
+        let resolved = Secret::new(lookup(var).ok_or_else(|| format!("${{{}}} is not set", var))?);
+        parts.push((&rest[..start], resolved));
+        rest = &rest[end + 1..];
+    }
+    let len = parts
+        .iter()
+        .map(|(literal, resolved)| literal.len() + resolved.0.len())
+        .sum::<usize>()
+        + rest.len();
+    let mut out = Secret::new(String::with_capacity(len));
+    for (literal, resolved) in &parts {
+        out.0.push_str(literal);
+        out.0.push_str(resolved.expose());
+    }
+    out.0.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_indirection() {
+        let lookup = |var: &str| (var == "TOKEN").then(|| "s3cr3t".to_string());
+        let expanded = expand(&Secret::new("k:${TOKEN}:admin".into()), lookup).unwrap();
+        assert_eq!(expanded.expose(), "k:s3cr3t:admin");
+        assert_eq!(expanded.0.capacity(), expanded.0.len());
+        assert_eq!(
+            expand(&Secret::new("plain".into()), lookup)
+                .unwrap()
+                .expose(),
+            "plain"
+        );
+        assert!(expand(&Secret::new("${MISSING}".into()), lookup).is_err());
+        assert!(expand(&Secret::new("${TOKEN".into()), lookup).is_err());
+        assert_eq!(format!("{:?}", expanded), "Secret(***)");
+    }
+
+    #[test]
+    fn test_file_variant() {
+        let path =
+            std::env::temp_dir().join(format!("wordmath-secret-{}", crate::generate_hex_id()));
+        std::fs::write(&path, "from-file\n").unwrap();
+        std::env::set_var("WORD_MATH_TEST_SECRET_FILE", &path);
+        let secret = from_env("WORD_MATH_TEST_SECRET").unwrap().unwrap();
+        assert_eq!(secret.expose(), "from-file");
+        std::env::remove_var("WORD_MATH_TEST_SECRET_FILE");
+        assert!(from_env("WORD_MATH_TEST_SECRET").unwrap().is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+}
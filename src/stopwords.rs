@@ -0,0 +1,172 @@
+//! Optional stopword and boilerplate packs for multilingual deployments.
+//!
+//! Each language group is a Cargo feature, so binaries only carry the
+//! lists they need: `stopwords-europe` (en, de, fr, es, it, pt, nl, tr, fi,
+//! el, ru) and `stopwords-asia` (ar, he, hi, ja, ko, zh), or `stopwords`
+//! for both. Packs are keyed by the ISO 639-1 codes `lang::detect`
+//! returns. A pack's lookup set and tokenized phrases are built on first
+//! use, so enabled but unused languages cost only their static data.
+//!
+//! Keyword extraction (`TopicDefinition::from_documents`) and drift
+//! clusters skip the stopwords of the detected language, and extraction
+//! drops its boilerplate phrases. With no pack for a language both behave
+//! as without the feature. Han and kana are tokenized per character, so
+//! the ja and zh packs list single-character particles.
+
+use crate::text::{self, EmojiMode};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+#[cfg(feature = "stopwords-asia")]
+mod asia;
+#[cfg(feature = "stopwords-europe")]
+mod europe;
+
+/// Stopwords and boilerplate phrases of one language.
+#[derive(Debug)]
+pub struct Pack {
+    language: &'static str,
+    words: &'static [&'static str],
+    boilerplate: &'static [&'static str],
+    set: OnceLock<HashSet<&'static str>>,
+    phrases: OnceLock<Vec<Vec<String>>>,
+}
+
+impl Pack {
+    #[cfg_attr(
+        not(any(feature = "stopwords-europe", feature = "stopwords-asia")),
+        allow(dead_code)
+    )]
+    const fn new(
+        language: &'static str,
+        words: &'static [&'static str],
+        boilerplate: &'static [&'static str],
+    ) -> Self {
+        Self {
+            language,
+            words,
+            boilerplate,
+            set: OnceLock::new(),
+            phrases: OnceLock::new(),
+        }
+    }
+
+    /// ISO 639-1 code.
+    pub fn language(&self) -> &'static str {
+        self.language
+    }
+
+    pub fn words(&self) -> &'static [&'static str] {
+        self.words
+    }
+
+    pub fn boilerplate(&self) -> &'static [&'static str] {
+        self.boilerplate
+    }
+
+    /// Whether the lowercased `token` is a stopword.
+    pub fn is_stopword(&self, token: &str) -> bool {
+        self.set
+            .get_or_init(|| self.words.iter().copied().collect())
+            .contains(token)
+    }
+
+    /// Remove every boilerplate phrase from `tokens`, longest phrase first
+    /// at each position; returns the number of tokens removed.
+    pub fn strip_boilerplate(&self, tokens: &mut Vec<String>) -> usize {
+        let phrases = self.phrases.get_or_init(|| {
+            let mut phrases: Vec<Vec<String>> = self
+                .boilerplate
+                .iter()
+                .map(|phrase| text::tokenize(phrase, EmojiMode::Strip))
+                .filter(|tokens| !tokens.is_empty())
+                .collect();
+            phrases.sort_by_key(|p| std::cmp::Reverse(p.len()));
+            phrases
+        });
+        let before = tokens.len();
+        let mut kept = Vec::with_capacity(before);
+        let mut i = 0;
+        while i < tokens.len() {
+            match phrases.iter().find(|p| tokens[i..].starts_with(p)) {
+                Some(phrase) => i += phrase.len(),
+                None => {
+                    kept.push(std::mem::take(&mut tokens[i]));
+                    i += 1;
+                }
+            }
+        }
+        *tokens = kept;
+        before - tokens.len()
+    }
+}
+
+/// Packs of the enabled language groups.
+static GROUPS: &[&[Pack]] = &[
+    #[cfg(feature = "stopwords-europe")]
+    &europe::PACKS,
+    #[cfg(feature = "stopwords-asia")]
+    &asia::PACKS,
+];
+
+/// The pack for `language` (an ISO 639-1 code), if its group is enabled.
+pub fn pack(language: &str) -> Option<&'static Pack> {
+    GROUPS
+        .iter()
+        .flat_map(|group| group.iter())
+        .find(|pack| pack.language == language)
+}
+
+/// The pack for the language `lang::detect` finds in `text`.
+pub fn for_text(text: &str) -> Option<&'static Pack> {
+    crate::lang::detect(text).and_then(pack)
+}
+
+/// Codes of all enabled packs.
+pub fn languages() -> impl Iterator<Item = &'static str> {
+    GROUPS
+        .iter()
+        .flat_map(|group| group.iter())
+        .map(Pack::language)
+}
+
+#[cfg(all(test, any(feature = "stopwords-europe", feature = "stopwords-asia")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "stopwords-europe")]
+    fn test_packs_match_stopwords_and_strip_boilerplate() {
+        let en = pack("en").unwrap();
+        assert!(en.is_stopword("the"));
+        assert!(!en.is_stopword("server"));
+        assert!(
+            for_text("Der Server ist nicht erreichbar und die Logs sind leer")
+                .is_some_and(|p| p.language() == "de")
+        );
+        assert!(languages().any(|l| l == "fr"));
+
+        let mut tokens = text::tokenize(
+            "I hope this helps! Restart the server. Let me know if you have any questions.",
+            EmojiMode::Strip,
+        );
+        assert_eq!(en.strip_boilerplate(&mut tokens), 12);
+        assert_eq!(tokens, ["restart", "the", "server"]);
+    }
+
+    #[test]
+    fn test_pack_words_are_tokens() {
+        // Entries the tokenizer would split or change could never match.
+        for pack in GROUPS.iter().flat_map(|group| group.iter()) {
+            for word in pack.words() {
+                assert_eq!(
+                    text::tokenize(word, EmojiMode::Strip),
+                    [*word],
+                    "{}: {:?}",
+                    pack.language(),
+                    word
+                );
+            }
+        }
+    }
+}
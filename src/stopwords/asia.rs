@@ -0,0 +1,131 @@
+//! Packs for Middle Eastern, South and East Asian languages (feature
+//! `stopwords-asia`).
+
+use super::Pack;
+
+pub(super) static PACKS: [Pack; 6] = [
+    Pack::new(
+        "ar",
+        &[
+            "أن", "إلى", "التي", "الذي", "أو", "على", "عن", "في", "قد", "كان", "لا", "لم", "لكن",
+            "ما", "مع", "من", "هذا", "هذه", "هو", "هي", "و", "ولا", "يا",
+        ],
+        &[
+            "بصفتي نموذج لغة",
+            "آمل أن يكون هذا مفيدا",
+            "لا تتردد في التواصل",
+            "شكرا لرسالتك",
+            "مع أطيب التحيات",
+        ],
+    ),
+    Pack::new(
+        "he",
+        &[
+            "אבל", "או", "אני", "את", "אתה", "גם", "הוא", "היא", "הם", "זה", "זאת", "יש", "כי",
+            "כל", "לא", "מה", "עם", "על", "של", "שלא", "אם", "רק",
+        ],
+        &[
+            "כמודל שפה",
+            "אני מקווה שזה עוזר",
+            "אל תהסס לפנות",
+            "תודה על פנייתך",
+            "בברכה",
+        ],
+    ),
+    Pack::new(
+        "hi",
+        &[
+            "और",
+            "का",
+            "की",
+            "के",
+            "को",
+            "कि",
+            "है",
+            "हैं",
+            "था",
+            "थे",
+            "थी",
+            "में",
+            "से",
+            "पर",
+            "यह",
+            "वह",
+            "भी",
+            "तो",
+            "ही",
+            "लिए",
+            "नहीं",
+            "एक",
+            "हम",
+            "आप",
+            "मैं",
+        ],
+        &[
+            "एक भाषा मॉडल के रूप में",
+            "आशा है इससे मदद मिलेगी",
+            "बेझिझक पूछें",
+            "आपके संदेश के लिए धन्यवाद",
+            "सादर",
+        ],
+    ),
+    Pack::new(
+        "ja",
+        &[
+            "の", "は", "が", "を", "に", "で", "と", "も", "た", "て", "し", "か", "な", "ね",
+            "よ", "へ", "や", "だ", "す", "ま", "る", "れ", "こ", "そ", "あ", "ど",
+        ],
+        &[
+            "言語モデルとして",
+            "お役に立てれば幸いです",
+            "よろしくお願いします",
+            "ご連絡ありがとうございます",
+        ],
+    ),
+    Pack::new(
+        "ko",
+        &[
+            "그",
+            "그리고",
+            "그러나",
+            "하지만",
+            "또는",
+            "및",
+            "이",
+            "저",
+            "것",
+            "수",
+            "등",
+            "더",
+            "또",
+            "그래서",
+            "그런데",
+            "나",
+            "너",
+            "우리",
+            "저는",
+            "제가",
+        ],
+        &[
+            "언어 모델로서",
+            "도움이 되었으면 좋겠습니다",
+            "언제든지 문의해 주세요",
+            "감사합니다",
+        ],
+    ),
+    Pack::new(
+        "zh",
+        &[
+            "的", "了", "是", "在", "和", "也", "就", "都", "而", "及", "与", "着", "或", "我",
+            "你", "他", "她", "它", "们", "这", "那", "个", "之", "其", "把", "被", "让", "对",
+            "从", "有",
+        ],
+        &[
+            "作为一个语言模型",
+            "希望这对你有帮助",
+            "如有任何问题请随时联系",
+            "感谢您的来信",
+            "此致敬礼",
+        ],
+    ),
+];
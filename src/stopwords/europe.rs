@@ -0,0 +1,211 @@
+//! Packs for European languages (feature `stopwords-europe`).
+
+use super::Pack;
+
+pub(super) static PACKS: [Pack; 11] = [
+    Pack::new(
+        "en",
+        &[
+            "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be",
+            "because", "been", "but", "by", "can", "could", "do", "does", "for", "from", "had",
+            "has", "have", "he", "her", "his", "how", "i", "if", "in", "into", "is", "it", "its",
+            "me", "my", "not", "of", "on", "or", "our", "she", "so", "than", "that", "the",
+            "their", "them", "then", "there", "these", "they", "this", "to", "was", "we", "were",
+            "what", "when", "which", "who", "will", "with", "would", "you", "your",
+        ],
+        &[
+            "as an ai language model",
+            "i hope this helps",
+            "let me know if you have any questions",
+            "feel free to reach out",
+            "thank you for your message",
+            "please do not hesitate to contact us",
+            "best regards",
+        ],
+    ),
+    Pack::new(
+        "de",
+        &[
+            "aber", "als", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis", "das", "dass",
+            "dem", "den", "der", "des", "die", "doch", "du", "ein", "eine", "einem", "einen",
+            "einer", "er", "es", "für", "hat", "ich", "ihr", "im", "in", "ist", "mit", "nach",
+            "nicht", "noch", "nur", "oder", "sich", "sie", "sind", "so", "um", "und", "von", "war",
+            "was", "wie", "wir", "zu", "zum", "zur",
+        ],
+        &[
+            "als ki-sprachmodell",
+            "ich hoffe, das hilft",
+            "bei fragen stehe ich gerne zur verfügung",
+            "vielen dank für ihre nachricht",
+            "mit freundlichen grüßen",
+        ],
+    ),
+    Pack::new(
+        "fr",
+        &[
+            "au", "aux", "avec", "ce", "ces", "cette", "dans", "de", "des", "du", "elle", "en",
+            "est", "et", "il", "ils", "je", "la", "le", "les", "leur", "mais", "me", "mon", "ne",
+            "nous", "on", "ou", "par", "pas", "pour", "qui", "que", "sa", "se", "son", "sont",
+            "sur", "ta", "te", "tu", "un", "une", "vous",
+        ],
+        &[
+            "en tant que modèle de langage",
+            "j'espère que cela vous aide",
+            "n'hésitez pas à me contacter",
+            "merci pour votre message",
+            "cordialement",
+        ],
+    ),
+    Pack::new(
+        "es",
+        &[
+            "a", "al", "como", "con", "de", "del", "el", "ella", "en", "es", "esta", "este", "fue",
+            "ha", "la", "las", "le", "lo", "los", "me", "mi", "muy", "no", "nos", "o", "para",
+            "pero", "por", "que", "se", "si", "sin", "su", "sus", "también", "te", "un", "una",
+            "y", "ya", "yo",
+        ],
+        &[
+            "como modelo de lenguaje",
+            "espero que esto te ayude",
+            "no dudes en preguntar",
+            "gracias por tu mensaje",
+            "saludos cordiales",
+        ],
+    ),
+    Pack::new(
+        "it",
+        &[
+            "a", "al", "alla", "anche", "che", "ci", "come", "con", "da", "del", "della", "di",
+            "e", "è", "gli", "ha", "ho", "i", "il", "in", "io", "la", "le", "lo", "ma", "mi", "ne",
+            "nel", "non", "o", "per", "più", "se", "si", "sono", "su", "tu", "un", "una",
+        ],
+        &[
+            "come modello linguistico",
+            "spero che questo ti sia utile",
+            "non esitare a contattarmi",
+            "grazie per il tuo messaggio",
+            "cordiali saluti",
+        ],
+    ),
+    Pack::new(
+        "pt",
+        &[
+            "a", "ao", "as", "com", "como", "da", "das", "de", "do", "dos", "e", "é", "ela", "ele",
+            "em", "eu", "foi", "isso", "mais", "mas", "me", "na", "não", "no", "nos", "o", "os",
+            "ou", "para", "pela", "pelo", "por", "que", "se", "sem", "seu", "sua", "também", "um",
+            "uma", "você",
+        ],
+        &[
+            "como modelo de linguagem",
+            "espero que isso ajude",
+            "fique à vontade para perguntar",
+            "obrigado pela sua mensagem",
+            "atenciosamente",
+        ],
+    ),
+    Pack::new(
+        "nl",
+        &[
+            "aan", "al", "als", "bij", "dan", "dat", "de", "die", "dit", "door", "een", "en", "er",
+            "had", "heb", "het", "hij", "ik", "in", "is", "je", "maar", "met", "mij", "niet",
+            "nog", "of", "om", "ook", "op", "te", "tot", "uit", "van", "voor", "was", "wat", "we",
+            "wij", "zijn", "ze", "zo",
+        ],
+        &[
+            "als taalmodel",
+            "ik hoop dat dit helpt",
+            "aarzel niet om contact op te nemen",
+            "bedankt voor je bericht",
+            "met vriendelijke groet",
+        ],
+    ),
+    Pack::new(
+        "tr",
+        &[
+            "ama", "bir", "biz", "bu", "da", "daha", "de", "diye", "en", "gibi", "hem", "her",
+            "için", "ile", "ise", "kadar", "ki", "mi", "ne", "o", "olan", "olarak", "sen", "siz",
+            "şu", "ve", "veya", "ya", "çok", "ben",
+        ],
+        &[
+            "bir yapay zeka dil modeli olarak",
+            "umarım bu yardımcı olur",
+            "başka sorunuz varsa çekinmeden sorun",
+            "mesajınız için teşekkürler",
+            "saygılarımla",
+        ],
+    ),
+    Pack::new(
+        "fi",
+        &[
+            "ei", "en", "ennen", "et", "hän", "he", "ja", "jo", "jos", "joka", "kanssa", "kuin",
+            "kun", "me", "mutta", "mikä", "minä", "myös", "niin", "nyt", "ole", "olen", "oli",
+            "on", "ovat", "se", "sen", "sinä", "tai", "tämä", "te", "vain", "vielä",
+        ],
+        &[
+            "kielimallina",
+            "toivottavasti tästä on apua",
+            "ota rohkeasti yhteyttä",
+            "kiitos viestistäsi",
+            "ystävällisin terveisin",
+        ],
+    ),
+    Pack::new(
+        "el",
+        &[
+            "αλλά",
+            "από",
+            "για",
+            "δεν",
+            "είναι",
+            "εγώ",
+            "εσύ",
+            "η",
+            "θα",
+            "και",
+            "με",
+            "μη",
+            "μια",
+            "να",
+            "ο",
+            "οι",
+            "όπως",
+            "ότι",
+            "σε",
+            "στη",
+            "στην",
+            "στο",
+            "στον",
+            "τα",
+            "την",
+            "της",
+            "τη",
+            "το",
+            "τον",
+            "του",
+            "των",
+            "ένα",
+        ],
+        &[
+            "ως γλωσσικό μοντέλο",
+            "ελπίζω να βοηθήσει",
+            "μη διστάσετε να επικοινωνήσετε",
+            "ευχαριστώ για το μήνυμά σας",
+            "με εκτίμηση",
+        ],
+    ),
+    Pack::new(
+        "ru",
+        &[
+            "а", "без", "был", "была", "в", "вы", "да", "для", "до", "его", "ее", "если", "же",
+            "за", "и", "из", "или", "к", "как", "мы", "на", "не", "но", "о", "он", "она", "они",
+            "от", "по", "с", "так", "то", "у", "что", "это", "я",
+        ],
+        &[
+            "как языковая модель",
+            "надеюсь, это поможет",
+            "не стесняйтесь обращаться",
+            "спасибо за ваше сообщение",
+            "с уважением",
+        ],
+    ),
+];
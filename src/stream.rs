@@ -0,0 +1,363 @@
+//! Token-by-token guarding of text while it is being generated.
+//!
+//! `StreamGuard::check` is meant to be called from an inference loop with
+//! each generated token (a subword piece, e.g. "Hel", "lo", " world").
+//! Pieces are buffered until a whitespace boundary completes a word; each
+//! completed word updates running counts, so repetition y and drift z are
+//! maintained in O(1) amortized time per token instead of rescoring the
+//! whole text. The verdict is re-evaluated every `cadence` tokens once
+//! `min_words` words are in (a two-word prefix is all repetition and
+//! drift), and at `finish`; between evaluations `check` returns the last
+//! decision. Once a stream is stopped it stays stopped.
+//!
+//! On the completed text the guard's y, z and score equal those of
+//! `analyze_with_topic`. Scripts written without spaces between words are
+//! only counted at `finish`.
+
+use crate::evasion::{self, SplitCounter};
+use crate::hash::{FxHashMap, FxHashSet};
+use crate::matching::MatchForm;
+use crate::verdict::{self, Verdict, VerdictExplanation};
+use crate::{score_linear, text, CompiledTopic, WordMathAnalysis, WordMathConfig};
+
+/// Tokens between verdict evaluations, by default.
+pub const DEFAULT_CADENCE: usize = 8;
+/// Words needed before the first evaluation, by default.
+pub const DEFAULT_MIN_WORDS: usize = 8;
+
+/// What the generation loop should do after a token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Continue,
+    /// Keep generating, but the text so far would be warned about.
+    Warn,
+    /// Stop generating; the text so far would be blocked.
+    Stop,
+}
+
+/// Incremental guard over one generated stream.
+#[derive(Debug, Clone)]
+pub struct StreamGuard {
+    cfg: WordMathConfig,
+    topic: FxHashSet<String>,
+    cadence: usize,
+    min_words: usize,
+    /// Text after the last whitespace boundary, not yet counted.
+    pending: String,
+    counts: FxHashMap<String, usize>,
+    words: usize,
+    max_count: usize,
+    /// Distinct message words (their keys, unless the match form is
+    /// exact) that also occur in the topic.
+    common: usize,
+    form: MatchForm,
+    /// Distinct keys of message words, unless the match form is exact.
+    keys: FxHashSet<String>,
+    chars: usize,
+    malformed: usize,
+    emoji: usize,
+    invisible_stripped: usize,
+    homoglyphs: usize,
+    split: SplitCounter,
+    since_check: usize,
+    decision: Decision,
+    last: Option<VerdictExplanation>,
+}
+
+impl StreamGuard {
+    pub fn new(topic: &str, cfg: WordMathConfig) -> Self {
+        Self::with_topic(&CompiledTopic::compile(topic, cfg.emoji_mode), cfg)
+    }
+
+    /// Like `new`, against a precompiled topic.
+    pub fn with_topic(topic: &CompiledTopic, cfg: WordMathConfig) -> Self {
+        let tokens = if topic.emoji_mode() == cfg.emoji_mode {
+            topic.tokens().to_vec()
+        } else {
+            CompiledTopic::compile(topic.text(), cfg.emoji_mode)
+                .tokens()
+                .to_vec()
+        };
+        let form = MatchForm::of(&cfg);
+        let topic_set = form.keys(&tokens).into_iter().collect();
+        Self {
+            cfg,
+            topic: topic_set,
+            cadence: DEFAULT_CADENCE,
+            min_words: DEFAULT_MIN_WORDS,
+            pending: String::new(),
+            counts: FxHashMap::default(),
+            words: 0,
+            max_count: 0,
+            common: 0,
+            form,
+            keys: FxHashSet::default(),
+            chars: 0,
+            malformed: 0,
+            emoji: 0,
+            invisible_stripped: 0,
+            homoglyphs: 0,
+            split: SplitCounter::default(),
+            since_check: 0,
+            decision: Decision::Continue,
+            last: None,
+        }
+    }
+
+    /// Evaluate the verdict every `cadence` tokens (at least 1).
+    pub fn with_cadence(mut self, cadence: usize) -> Self {
+        self.cadence = cadence.max(1);
+        self
+    }
+
+    /// Wait for `min_words` words before the first evaluation.
+    pub fn with_min_words(mut self, min_words: usize) -> Self {
+        self.min_words = min_words;
+        self
+    }
+
+    /// Feed one generated token.
+    pub fn check(&mut self, token: &str) -> Decision {
+        if self.decision == Decision::Stop {
+            return Decision::Stop;
+        }
+        // Count every word before the token's last whitespace; a word split
+        // across tokens stays pending until it is complete. Only the new
+        // token is searched, so long unspaced runs stay linear.
+        let boundary = token
+            .rfind(char::is_whitespace)
+            .map(|i| self.pending.len() + i);
+        self.pending.push_str(token);
+        if let Some(boundary) = boundary {
+            let rest = self.pending.split_off(boundary);
+            let complete = std::mem::replace(&mut self.pending, rest);
+            self.count(&complete);
+        }
+        self.since_check += 1;
+        if self.since_check >= self.cadence && self.words >= self.min_words {
+            self.evaluate();
+        }
+        self.decision
+    }
+
+    /// Count the last, unterminated word and evaluate the whole stream.
+    pub fn finish(&mut self) -> Decision {
+        let rest = std::mem::take(&mut self.pending);
+        self.count(&rest);
+        if self.decision != Decision::Stop {
+            self.evaluate();
+        }
+        self.decision
+    }
+
+    fn count(&mut self, chunk: &str) {
+        self.chars += chunk.chars().count();
+        self.malformed += chunk
+            .chars()
+            .filter(|&c| c == char::REPLACEMENT_CHARACTER)
+            .count();
+        self.emoji += text::count_emoji(chunk);
+        let normalized = if self.cfg.normalize {
+            let normalized = text::normalize(chunk);
+            self.invisible_stripped += normalized.invisible_stripped;
+            normalized.text
+        } else {
+            chunk.to_string()
+        };
+        for mut word in text::tokenize(&normalized, self.cfg.emoji_mode) {
+            if self.cfg.normalize {
+                if let Some(plain) = evasion::fold_homoglyphs(&word) {
+                    word = plain;
+                    self.homoglyphs += 1;
+                }
+            }
+            self.split.push(&word);
+            self.words += 1;
+            if self.form != MatchForm::Exact {
+                let key = self.form.key(&word);
+                let on_topic = self.topic.contains(&key);
+                if self.keys.insert(key) && on_topic {
+                    self.common += 1;
+                }
+            }
+            let on_topic = self.form == MatchForm::Exact && self.topic.contains(&word);
+            let count = self.counts.entry(word).or_insert(0);
+            *count += 1;
+            if *count == 1 && on_topic {
+                self.common += 1;
+            }
+            self.max_count = self.max_count.max(*count);
+        }
+    }
+
+    /// Metrics of the text counted so far (pending partial words aside).
+    pub fn analysis(&self) -> WordMathAnalysis {
+        let y = if self.words == 0 {
+            0.0
+        } else {
+            self.max_count as f64 / self.words as f64
+        };
+        // Same cases as `topic_drift_of`, from the running overlap.
+        let z = match (self.words == 0, self.topic.is_empty()) {
+            (true, true) => 0.0,
+            (true, false) | (false, true) => 1.0,
+            (false, false) => {
+                let distinct = if self.form == MatchForm::Exact {
+                    self.counts.len()
+                } else {
+                    self.keys.len()
+                };
+                let union = distinct + self.topic.len() - self.common;
+                1.0 - self.common as f64 / union as f64
+            }
+        };
+        let ratio = |count: usize| {
+            if self.chars == 0 {
+                0.0
+            } else {
+                count as f64 / self.chars as f64
+            }
+        };
+        WordMathAnalysis {
+            y_repetition: y,
+            z_drift: z,
+            score: self.cfg.transform.apply(score_linear(y, z, self.cfg)),
+            malformed_ratio: ratio(self.malformed),
+            emoji_mode: self.cfg.emoji_mode,
+            emoji_count: self.emoji,
+            invisible_stripped: self.invisible_stripped,
+            obfuscation: ratio(self.invisible_stripped),
+            homoglyphs: self.homoglyphs,
+            split_letters: self.split.letters,
+            evasion_risk: evasion::risk(
+                ratio(self.invisible_stripped),
+                self.homoglyphs,
+                self.split.letters,
+                self.words,
+            ),
+        }
+    }
+
+    fn evaluate(&mut self) {
+        self.since_check = 0;
+        let explanation = verdict::evaluate(&self.analysis(), &self.cfg);
+        self.decision = match explanation.verdict {
+            Verdict::Allow => Decision::Continue,
+            Verdict::Warn => Decision::Warn,
+            Verdict::Block => Decision::Stop,
+        };
+        self.last = Some(explanation);
+    }
+
+    /// The explanation behind the latest decision, if one was evaluated.
+    pub fn explanation(&self) -> Option<&VerdictExplanation> {
+        self.last.as_ref()
+    }
+
+    /// Words counted so far.
+    pub fn words(&self) -> usize {
+        self.words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze_message_with_trace;
+
+    #[test]
+    fn test_stream_matches_whole_text_analysis() {
+        let cfg = WordMathConfig::default();
+        let topic = "rust web server deployment";
+        // Subword pieces, as a tokenizer would emit them.
+        let pieces = [
+            "The",
+            " ru",
+            "st",
+            " web",
+            " ser\u{200B}",
+            "ver",
+            " needs",
+            " a",
+            " deploy",
+            "ment",
+            " plan,",
+            " and",
+            " the",
+            " plan",
+            " needs",
+            " tests.",
+        ];
+        let message = pieces.concat();
+        let mut guard = StreamGuard::new(topic, cfg).with_cadence(3);
+        for piece in pieces {
+            guard.check(piece);
+        }
+        guard.finish();
+
+        let (expected, _) = analyze_message_with_trace(&message, topic, cfg);
+        let streamed = guard.analysis();
+        assert_eq!(streamed.y_repetition, expected.y_repetition);
+        assert_eq!(streamed.z_drift, expected.z_drift);
+        assert_eq!(streamed.score, expected.score);
+        assert_eq!(streamed.invisible_stripped, 1);
+    }
+
+    #[test]
+    fn test_stream_matches_whole_text_analysis_when_transliterating() {
+        let cfg = WordMathConfig {
+            transliterate: true,
+            ..WordMathConfig::default()
+        };
+        let topic = "क्या हाल है आप कैसे हैं";
+        let message = "kya haal hai, aap kaise ho? haal theek hai";
+        let mut guard = StreamGuard::new(topic, cfg);
+        for piece in message.split_inclusive(' ') {
+            guard.check(piece);
+        }
+        guard.finish();
+
+        let (expected, _) = analyze_message_with_trace(message, topic, cfg);
+        assert_eq!(guard.analysis().z_drift, expected.z_drift);
+        assert_eq!(guard.analysis().y_repetition, expected.y_repetition);
+        let (plain, _) = analyze_message_with_trace(message, topic, WordMathConfig::default());
+        assert_eq!(plain.z_drift, 1.0);
+        assert!(expected.z_drift < 0.5, "{}", expected.z_drift);
+    }
+
+    #[test]
+    fn test_stream_matches_whole_text_evasion_signals() {
+        let cfg = WordMathConfig::default();
+        let topic = "rust web server";
+        let message = "the rust sеrvеr is f r e e, r u s t web";
+        let mut guard = StreamGuard::new(topic, cfg);
+        for piece in message.split_inclusive(' ') {
+            guard.check(piece);
+        }
+        guard.finish();
+
+        let (expected, _) = analyze_message_with_trace(message, topic, cfg);
+        let streamed = guard.analysis();
+        assert_eq!(streamed.homoglyphs, 1);
+        assert_eq!(streamed.split_letters, 8);
+        assert_eq!(streamed.z_drift, expected.z_drift);
+        assert_eq!(streamed.evasion_risk, expected.evasion_risk);
+    }
+
+    #[test]
+    fn test_stream_stops_on_a_loop_and_stays_stopped() {
+        let cfg = WordMathConfig::default();
+        let mut guard = StreamGuard::new("rust web server", cfg).with_cadence(4);
+        assert_eq!(guard.check("rust"), Decision::Continue);
+        let mut decisions = Vec::new();
+        for _ in 0..40 {
+            decisions.push(guard.check(" again"));
+        }
+        assert_eq!(decisions.last(), Some(&Decision::Stop));
+        // Decisions only change at the cadence.
+        assert!(decisions[..2].iter().all(|d| *d == Decision::Continue));
+        assert_eq!(guard.check(" rust"), Decision::Stop);
+        assert_eq!(guard.finish(), Decision::Stop);
+        assert_eq!(guard.explanation().unwrap().verdict, Verdict::Block);
+    }
+}
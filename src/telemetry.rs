@@ -0,0 +1,114 @@
+//! Metrics emitted by `Analyzer`, for embedders outside the bundled server.
+//!
+//! Updates go through the `metrics` crate facade, so any exporter the
+//! application installs with `metrics::set_global_recorder` (Prometheus,
+//! StatsD, ...) receives them. With no recorder installed the calls are
+//! no-ops. Only compiled with the `metrics` feature.
+//!
+//! Emitted metrics:
+//! - `word_math_analyses_total` (counter)
+//! - `word_math_score` (histogram of transformed scores)
+//! - `word_math_verdicts_total{verdict}` (counter, from `Analyzer::evaluate`)
+
+pub const ANALYSES_TOTAL: &str = "word_math_analyses_total";
+pub const SCORE: &str = "word_math_score";
+pub const VERDICTS_TOTAL: &str = "word_math_verdicts_total";
+
+pub(crate) fn counter(name: &'static str, labels: &[(&'static str, &'static str)], value: u64) {
+    metrics::counter!(name, labels).increment(value);
+}
+
+pub(crate) fn histogram(name: &'static str, labels: &[(&'static str, &'static str)], value: f64) {
+    metrics::histogram!(name, labels).record(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Analyzer, WordMathConfig};
+    use metrics::{
+        Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+        SharedString, Unit,
+    };
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Captured(Arc<Mutex<Vec<String>>>);
+
+    struct Handle {
+        key: Key,
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Handle {
+        fn push(&self, op: &str, value: String) {
+            let labels: Vec<String> = self
+                .key
+                .labels()
+                .map(|label| format!("{}={}", label.key(), label.value()))
+                .collect();
+            self.events.lock().unwrap().push(format!(
+                "{}{:?} {} {}",
+                self.key.name(),
+                labels,
+                op,
+                value
+            ));
+        }
+    }
+
+    impl CounterFn for Handle {
+        fn increment(&self, value: u64) {
+            self.push("+=", value.to_string());
+        }
+        fn absolute(&self, value: u64) {
+            self.push("=", value.to_string());
+        }
+    }
+
+    impl HistogramFn for Handle {
+        fn record(&self, value: f64) {
+            self.push("<-", value.to_string());
+        }
+    }
+
+    impl Captured {
+        fn handle(&self, key: &Key) -> Arc<Handle> {
+            Arc::new(Handle {
+                key: key.clone(),
+                events: Arc::clone(&self.0),
+            })
+        }
+    }
+
+    impl Recorder for Captured {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.handle(key))
+        }
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.handle(key))
+        }
+    }
+
+    #[test]
+    fn test_analyzer_emits_metrics() {
+        // A thread-local recorder, so concurrent tests don't interleave.
+        let captured = Captured::default();
+        metrics::with_local_recorder(&captured, || {
+            Analyzer::new(WordMathConfig::default()).evaluate("spam spam spam spam", "rust");
+        });
+        assert_eq!(
+            *captured.0.lock().unwrap(),
+            [
+                "word_math_analyses_total[] += 1",
+                "word_math_score[] <- 0",
+                "word_math_verdicts_total[\"verdict=block\"] += 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// How emoji sequences (including ZWJ sequences and flags) are tokenized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmojiMode {
+    /// Drop emoji; only word tokens are scored.
+    #[default]
+    Strip,
+    /// Keep each emoji grapheme cluster as its own token.
+    Token,
+}
+
+impl EmojiMode {
+    /// Parse a mode name ("strip" or "token"), case-insensitively.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "strip" => Some(Self::Strip),
+            "token" => Some(Self::Token),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Strip => "strip",
+            Self::Token => "token",
+        }
+    }
+}
+
+/// Number of extended grapheme clusters (user-perceived characters).
+pub fn grapheme_len(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// True if the grapheme cluster renders as an emoji.
+///
+/// Covers the pictographic blocks, regional-indicator flags and
+/// text-default symbols forced to emoji presentation with U+FE0F.
+pub fn is_emoji_grapheme(grapheme: &str) -> bool {
+    let mut has_symbol = false;
+    for c in grapheme.chars() {
+        match c as u32 {
+            0x1F000..=0x1FAFF => return true,
+            0x2300..=0x23FF | 0x2600..=0x27BF | 0x2B00..=0x2BFF => has_symbol = true,
+            0xFE0F if has_symbol => return true,
+            _ => {}
+        }
+    }
+    has_symbol
+}
+
+/// Count emoji grapheme clusters in `text`.
+pub fn count_emoji(text: &str) -> usize {
+    text.graphemes(true)
+        .filter(|g| is_emoji_grapheme(g))
+        .count()
+}
+
+/// Output of the normalization pre-pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Normalized {
+    pub text: String,
+    /// Number of zero-width and bidi control characters removed.
+    pub invisible_stripped: usize,
+}
+
+/// True for zero-width and bidi control characters that render as nothing.
+pub fn is_invisible_control(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{180E}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{2069}'
+            | '\u{061C}'
+            | '\u{FEFF}'
+    )
+}
+
+fn is_emoji_char(c: char) -> bool {
+    let mut buf = [0u8; 4];
+    is_emoji_grapheme(c.encode_utf8(&mut buf))
+}
+
+/// Collapse whitespace runs to a single space, trim the ends and remove
+/// invisible control characters.
+///
+/// A ZWJ between two emoji is kept so that ZWJ sequences still form a
+/// single grapheme; everywhere else it counts as stripped padding.
+pub fn normalize(text: &str) -> Normalized {
+    let mut out = String::with_capacity(text.len());
+    let mut invisible_stripped = 0usize;
+    let mut pending_space = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\u{200D}' {
+            let joins_emoji = out.chars().next_back().is_some_and(is_emoji_char)
+                && chars.peek().copied().is_some_and(is_emoji_char);
+            if joins_emoji {
+                out.push(c);
+            } else {
+                invisible_stripped += 1;
+            }
+            continue;
+        }
+        if is_invisible_control(c) {
+            invisible_stripped += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            pending_space = !out.is_empty();
+            continue;
+        }
+        if pending_space {
+            out.push(' ');
+            pending_space = false;
+        }
+        out.push(c);
+    }
+
+    Normalized {
+        text: out,
+        invisible_stripped,
+    }
+}
+
+/// A token and the byte range it was taken from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenSpan {
+    pub start: usize,
+    pub end: usize,
+    /// Lowercased token text.
+    pub token: String,
+}
+
+/// Tokens of `text` with their byte offsets, in order.
+pub fn token_spans(text: &str, mode: EmojiMode) -> Vec<TokenSpan> {
+    let mut spans = Vec::new();
+    for (start, segment) in text.split_word_bound_indices() {
+        if segment.chars().any(char::is_alphanumeric) {
+            spans.push(TokenSpan {
+                start,
+                end: start + segment.len(),
+                token: segment.to_lowercase(),
+            });
+        } else if mode == EmojiMode::Token {
+            spans.extend(
+                segment
+                    .grapheme_indices(true)
+                    .filter(|(_, g)| is_emoji_grapheme(g))
+                    .map(|(offset, g)| TokenSpan {
+                        start: start + offset,
+                        end: start + offset + g.len(),
+                        token: g.to_string(),
+                    }),
+            );
+        }
+    }
+    spans
+}
+
+/// Lowercased word tokens, plus emoji clusters when `mode` is `Token`.
+pub fn tokenize(text: &str, mode: EmojiMode) -> Vec<String> {
+    token_spans(text, mode)
+        .into_iter()
+        .map(|span| span.token)
+        .collect()
+}
+
+/// Sentences of `text`, split after `.`, `!`, `?` and newlines, trimmed,
+/// with empty pieces dropped.
+pub fn sentences(text: &str) -> Vec<&str> {
+    text.split_inclusive(['.', '!', '?', '\n'])
+        .map(str::trim)
+        .filter(|sentence| sentence.chars().any(char::is_alphanumeric))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grapheme_len_zwj_and_combining() {
+        // Family emoji: four code points joined by ZWJ, one grapheme.
+        assert_eq!(
+            grapheme_len("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}"),
+            1
+        );
+        // "e" + combining acute accent.
+        assert_eq!(grapheme_len("e\u{301}"), 1);
+    }
+
+    #[test]
+    fn test_tokenize_emoji_modes() {
+        let text = "spam \u{1F525}\u{1F525} spam \u{1F1FA}\u{1F1F8}";
+        assert_eq!(tokenize(text, EmojiMode::Strip), vec!["spam", "spam"]);
+        assert_eq!(
+            tokenize(text, EmojiMode::Token),
+            vec![
+                "spam",
+                "\u{1F525}",
+                "\u{1F525}",
+                "spam",
+                "\u{1F1FA}\u{1F1F8}"
+            ]
+        );
+        assert_eq!(count_emoji(text), 3);
+    }
+
+    #[test]
+    fn test_normalize_strips_invisible_and_collapses_whitespace() {
+        let n = normalize("  fr\u{200B}ee \u{202E}\t\n  mo\u{FEFF}ney  ");
+        assert_eq!(n.text, "free money");
+        assert_eq!(n.invisible_stripped, 3);
+    }
+
+    #[test]
+    fn test_normalize_keeps_emoji_zwj_sequences() {
+        let family = "\u{1F468}\u{200D}\u{1F469}";
+        let n = normalize(family);
+        assert_eq!(n.text, family);
+        assert_eq!(n.invisible_stripped, 0);
+    }
+
+    #[test]
+    fn test_sentences_split_and_trim() {
+        assert_eq!(
+            sentences("One. Two!! Three?\n  four"),
+            vec!["One.", "Two!", "Three?", "four"]
+        );
+    }
+
+    #[test]
+    fn test_token_spans_offsets() {
+        let text = "Hi, \u{1F525} there";
+        let spans = token_spans(text, EmojiMode::Token);
+        let slices: Vec<&str> = spans.iter().map(|s| &text[s.start..s.end]).collect();
+        assert_eq!(slices, vec!["Hi", "\u{1F525}", "there"]);
+        assert_eq!(spans[0].token, "hi");
+    }
+}
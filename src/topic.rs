@@ -0,0 +1,392 @@
+//! Precompiled topics.
+//!
+//! Tokenizing the topic is pure per-topic work, so callers that score many
+//! messages against the same few topics compile them once and share the
+//! result behind an `Arc`, leaving only the message side per request.
+
+use crate::hash::FxHashMap;
+use crate::matching::MatchForm;
+use crate::stopwords;
+use crate::text::{self, EmojiMode};
+use crate::WordMathError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Default number of free-text topics kept by a `TopicCache`.
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+/// Default number of keywords kept by `TopicDefinition::from_documents`.
+pub const DEFAULT_TOPIC_KEYWORDS: usize = 40;
+
+/// Function words that never make useful topic keywords.
+const COMMON_WORDS: &[&str] = &[
+    "about", "after", "also", "and", "any", "are", "because", "been", "before", "but", "can",
+    "could", "does", "each", "for", "from", "had", "has", "have", "how", "into", "its", "may",
+    "more", "most", "must", "not", "only", "other", "our", "should", "some", "such", "than",
+    "that", "the", "their", "them", "then", "there", "these", "they", "this", "those", "through",
+    "use", "used", "using", "was", "were", "what", "when", "where", "which", "while", "who",
+    "will", "with", "would", "you", "your",
+];
+
+/// A topic with its token set computed up front.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledTopic {
+    text: String,
+    emoji_mode: EmojiMode,
+    /// Distinct tokens in sorted order.
+    tokens: Vec<String>,
+    grapheme_len: usize,
+    /// Keys of `tokens` per non-exact `MatchForm`, computed on first use.
+    keys: [OnceLock<Vec<String>>; 3],
+}
+
+impl CompiledTopic {
+    pub fn compile(text: &str, emoji_mode: EmojiMode) -> Self {
+        let mut tokens = text::tokenize(text, emoji_mode);
+        tokens.sort_unstable();
+        tokens.dedup();
+        Self {
+            text: text.to_string(),
+            emoji_mode,
+            tokens,
+            grapheme_len: text::grapheme_len(text),
+            keys: Default::default(),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn emoji_mode(&self) -> EmojiMode {
+        self.emoji_mode
+    }
+
+    /// Distinct topic tokens, sorted.
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+
+    /// Distinct topic tokens in `form`, sorted.
+    pub fn tokens_for(&self, form: MatchForm) -> &[String] {
+        let slot = match form {
+            MatchForm::Exact => return &self.tokens,
+            MatchForm::Folded => &self.keys[0],
+            MatchForm::Phonetic => &self.keys[1],
+            MatchForm::FoldedPhonetic => &self.keys[2],
+        };
+        slot.get_or_init(|| form.keys(&self.tokens))
+    }
+
+    pub fn grapheme_len(&self) -> usize {
+        self.grapheme_len
+    }
+}
+
+/// One keyword of a structured topic definition.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeightedKeyword {
+    pub term: String,
+    /// Relative importance in (0, 1]; the strongest keyword has weight 1.
+    pub weight: f64,
+}
+
+/// A topic described by weighted keywords rather than free text, usually
+/// extracted from reference documents with `from_documents`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct TopicDefinition {
+    pub keywords: Vec<WeightedKeyword>,
+}
+
+impl TopicDefinition {
+    /// Extract up to `top_k` keywords from a document corpus.
+    ///
+    /// A term's weight is its mean per-document relative frequency times
+    /// the share of documents it appears in, so terms the whole corpus keeps
+    /// returning to beat terms one document dwells on. Function words,
+    /// numbers and terms under three characters are skipped, as are the
+    /// stopwords and boilerplate of the document's language when its
+    /// `stopwords` pack is enabled.
+    pub fn from_documents(documents: &[&str], top_k: usize, emoji_mode: EmojiMode) -> Self {
+        let mut scores: HashMap<String, (f64, usize)> = HashMap::new();
+        for document in documents {
+            let mut tokens = text::tokenize(document, emoji_mode);
+            let pack = stopwords::for_text(document);
+            if let Some(pack) = pack {
+                pack.strip_boilerplate(&mut tokens);
+            }
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for token in &tokens {
+                let keep = token.chars().count() >= 3
+                    && !token.chars().all(|c| c.is_numeric())
+                    && !COMMON_WORDS.contains(&token.as_str())
+                    && !pack.is_some_and(|p| p.is_stopword(token));
+                if keep {
+                    *counts.entry(token.as_str()).or_default() += 1;
+                }
+            }
+            for (term, count) in counts {
+                let entry = scores.entry(term.to_string()).or_default();
+                entry.0 += count as f64 / tokens.len() as f64;
+                entry.1 += 1;
+            }
+        }
+
+        let n = documents.len().max(1) as f64;
+        let mut ranked: Vec<(String, f64)> = scores
+            .into_iter()
+            .map(|(term, (tf_sum, df))| (term, (tf_sum / n) * (df as f64 / n)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(top_k);
+
+        let max = ranked.first().map_or(1.0, |(_, score)| *score);
+        Self {
+            keywords: ranked
+                .into_iter()
+                .map(|(term, score)| WeightedKeyword {
+                    term,
+                    weight: (score / max * 1000.0).round() / 1000.0,
+                })
+                .collect(),
+        }
+    }
+
+    /// The keywords as topic text, strongest first.
+    pub fn text(&self) -> String {
+        self.keywords
+            .iter()
+            .map(|k| k.term.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// A registry entry: plain topic text or a structured definition.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TopicEntry {
+    Text(String),
+    Definition(TopicDefinition),
+}
+
+/// Named topics compiled once at startup.
+///
+/// Loaded from a JSON object mapping topic IDs to topic text or to a
+/// structured definition (as written by `wordmath topic build`):
+///
+/// ```json
+/// {
+///   "billing": "invoices refunds payment methods",
+///   "k8s": { "keywords": [{ "term": "kubernetes", "weight": 1.0 }] }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TopicRegistry {
+    topics: HashMap<String, Arc<CompiledTopic>>,
+}
+
+impl TopicRegistry {
+    /// Compile every `(id, text)` pair with the given emoji mode.
+    pub fn compile<'a, I>(entries: I, emoji_mode: EmojiMode) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let topics = entries
+            .into_iter()
+            .map(|(id, text)| {
+                (
+                    id.to_string(),
+                    Arc::new(CompiledTopic::compile(text, emoji_mode)),
+                )
+            })
+            .collect();
+        Self { topics }
+    }
+
+    /// Parse and compile a registry from a JSON string.
+    pub fn from_json(json: &str, emoji_mode: EmojiMode) -> Result<Self, WordMathError> {
+        let raw: HashMap<String, TopicEntry> =
+            serde_json::from_str(json).map_err(|e| WordMathError::Config(e.to_string()))?;
+        let texts: Vec<(String, String)> = raw
+            .into_iter()
+            .map(|(id, entry)| match entry {
+                TopicEntry::Text(text) => (id, text),
+                TopicEntry::Definition(definition) => (id, definition.text()),
+            })
+            .collect();
+        Ok(Self::compile(
+            texts.iter().map(|(id, text)| (id.as_str(), text.as_str())),
+            emoji_mode,
+        ))
+    }
+
+    /// Read, parse and compile a registry from a JSON file.
+    pub fn load(path: impl AsRef<Path>, emoji_mode: EmojiMode) -> Result<Self, WordMathError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| WordMathError::Config(format!("{}: {}", path.display(), e)))?;
+        Self::from_json(&json, emoji_mode)
+    }
+
+    /// Load the file named by WORD_MATH_TOPICS, or an empty registry if unset.
+    pub fn from_env(emoji_mode: EmojiMode) -> Result<Self, WordMathError> {
+        match std::env::var("WORD_MATH_TOPICS") {
+            Ok(path) => Self::load(path, emoji_mode),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<CompiledTopic>> {
+        self.topics.get(id).cloned()
+    }
+
+    /// Topic IDs in sorted order.
+    pub fn ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.topics.keys().map(String::as_str).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn len(&self) -> usize {
+        self.topics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.topics.is_empty()
+    }
+}
+
+/// Hit / miss counters of a `TopicCache`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+/// Bounded, thread-safe cache of compiled free-text topics.
+///
+/// When full, the cache is cleared wholesale rather than tracking
+/// recency; topic churn is low and this keeps lookups to one map access.
+#[derive(Debug)]
+pub struct TopicCache {
+    capacity: usize,
+    entries: Mutex<FxHashMap<(String, EmojiMode), Arc<CompiledTopic>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for TopicCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+impl TopicCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: Mutex::new(FxHashMap::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Return the compiled topic, compiling and caching it on a miss.
+    pub fn get_or_compile(&self, text: &str, emoji_mode: EmojiMode) -> Arc<CompiledTopic> {
+        let key = (text.to_string(), emoji_mode);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(topic) = entries.get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Arc::clone(topic);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        if entries.len() >= self.capacity {
+            entries.clear();
+        }
+        let topic = Arc::new(CompiledTopic::compile(text, emoji_mode));
+        entries.insert(key, Arc::clone(&topic));
+        topic
+    }
+
+    /// Count a lookup that was served from elsewhere (e.g. a registry).
+    pub fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().map(|e| e.len()).unwrap_or(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compiled_topic_tokens_sorted_distinct() {
+        let topic = CompiledTopic::compile("Web server, rust web", EmojiMode::Strip);
+        assert_eq!(topic.tokens(), ["rust", "server", "web"]);
+        assert_eq!(topic.grapheme_len(), 20);
+    }
+
+    #[test]
+    fn test_registry_from_json() {
+        let registry =
+            TopicRegistry::from_json(r#"{ "k8s": "kubernetes deployments" }"#, EmojiMode::Strip)
+                .unwrap();
+        assert_eq!(registry.ids(), ["k8s"]);
+        assert_eq!(
+            registry.get("k8s").unwrap().tokens(),
+            ["deployments", "kubernetes"]
+        );
+        assert!(registry.get("nope").is_none());
+    }
+
+    #[test]
+    fn test_definition_from_documents() {
+        let docs = [
+            "Kubernetes deployments roll out pods. Deployments manage replica sets.",
+            "Scale kubernetes deployments with the kubectl tool; pods restart.",
+            "The ingress routes traffic to kubernetes services.",
+        ];
+        let definition = TopicDefinition::from_documents(&docs, 3, EmojiMode::Strip);
+        let terms: Vec<&str> = definition
+            .keywords
+            .iter()
+            .map(|k| k.term.as_str())
+            .collect();
+        assert_eq!(terms, ["kubernetes", "deployments", "pods"]);
+        assert_eq!(definition.keywords[0].weight, 1.0);
+        assert!(!terms.contains(&"the"));
+
+        let json = serde_json::json!({ "k8s": definition, "plain": "billing" }).to_string();
+        let registry = TopicRegistry::from_json(&json, EmojiMode::Strip).unwrap();
+        assert_eq!(
+            registry.get("k8s").unwrap().tokens(),
+            ["deployments", "kubernetes", "pods"]
+        );
+    }
+
+    #[test]
+    fn test_cache_hits_and_capacity() {
+        let cache = TopicCache::new(2);
+        let a1 = cache.get_or_compile("a", EmojiMode::Strip);
+        let a2 = cache.get_or_compile("a", EmojiMode::Strip);
+        assert!(Arc::ptr_eq(&a1, &a2));
+        cache.get_or_compile("a", EmojiMode::Token);
+        cache.get_or_compile("b", EmojiMode::Strip);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 3));
+        assert_eq!(stats.entries, 1);
+    }
+}
@@ -0,0 +1,178 @@
+//! Script folding for matching romanized text against native-script topics.
+//!
+//! "kya haal hai" and "क्या हाल है" share no token, so the message drifts
+//! 100% from the topic. With `WordMathConfig::transliterate` set, drift
+//! compares folded tokens instead: Devanagari and Arabic-script letters
+//! (including the Urdu and Persian additions) are transliterated to Latin,
+//! Latin diacritics are dropped, and the result is reduced to a consonant
+//! skeleton, keeping only a leading vowel. Romanizations disagree mostly on
+//! vowels ("haal", "hal", "हाल"), and Arabic script usually omits them, so
+//! all three fold to "hl". Repetition is still counted on the raw tokens.
+//!
+//! Skeletons are lossy ("rust" and "roast" both fold to "rst"), which is
+//! why folding is opt-in, e.g. per profile for multilingual surfaces.
+
+/// Vowels dropped from a skeleton; y and w are treated as vowels since
+/// romanizations use them where the native script writes a long vowel.
+const VOWELS: &[char] = &['a', 'e', 'i', 'o', 'u', 'y', 'w'];
+
+/// Latin transliteration of a Devanagari char. Consonants carry no
+/// inherent vowel; the skeleton would drop it anyway.
+fn devanagari(c: char) -> Option<&'static str> {
+    Some(match c {
+        'अ' | 'आ' | 'ा' | 'ॅ' | 'ॉ' | 'ऑ' => "a",
+        'इ' | 'ई' | 'ि' | 'ी' => "i",
+        'उ' | 'ऊ' | 'ु' | 'ू' => "u",
+        'ए' | 'ऐ' | 'े' | 'ै' => "e",
+        'ओ' | 'औ' | 'ो' | 'ौ' => "o",
+        'ऋ' | 'ृ' => "ri",
+        'क' => "k",
+        'ख' => "kh",
+        'ग' => "g",
+        'घ' => "gh",
+        'ङ' | 'ञ' | 'ण' | 'न' | 'ं' | 'ँ' => "n",
+        'च' => "ch",
+        'छ' => "chh",
+        'ज' => "j",
+        'झ' => "jh",
+        'ट' | 'त' => "t",
+        'ठ' | 'थ' => "th",
+        'ड' | 'द' => "d",
+        'ढ' | 'ध' => "dh",
+        'प' => "p",
+        'फ' => "ph",
+        'ब' => "b",
+        'भ' => "bh",
+        'म' => "m",
+        'य' => "y",
+        'र' | '\u{095C}' | '\u{095D}' => "r",
+        'ल' => "l",
+        'व' => "w",
+        'श' | 'ष' => "sh",
+        'स' => "s",
+        'ह' | 'ः' => "h",
+        '\u{0958}' => "q",
+        '\u{0959}' => "kh",
+        '\u{095A}' => "gh",
+        '\u{095B}' => "z",
+        '\u{095E}' => "f",
+        // Virama, nukta and accents.
+        '्' | '़' | '॑' | '॒' => "",
+        _ => return None,
+    })
+}
+
+/// Latin transliteration of an Arabic-script char; short-vowel marks are
+/// dropped.
+fn arabic(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ا' | 'أ' | 'آ' | 'ى' | 'ة' => "a",
+        'إ' => "i",
+        'ب' => "b",
+        'پ' => "p",
+        'ت' | 'ط' | 'ٹ' => "t",
+        'ث' => "th",
+        'ج' => "j",
+        'چ' => "ch",
+        'ح' | 'ه' | 'ھ' | 'ۃ' => "h",
+        'خ' => "kh",
+        'د' | 'ض' | 'ڈ' => "d",
+        'ذ' => "dh",
+        'ر' | 'ڑ' => "r",
+        'ز' | 'ظ' => "z",
+        'ژ' => "zh",
+        'س' | 'ص' => "s",
+        'ش' => "sh",
+        'غ' => "gh",
+        'ف' => "f",
+        'ق' => "q",
+        'ك' | 'ک' => "k",
+        'گ' => "g",
+        'ل' => "l",
+        'م' => "m",
+        'ن' | 'ں' => "n",
+        'و' | 'ؤ' => "w",
+        'ي' | 'ی' | 'ئ' => "y",
+        'ے' => "e",
+        'ع' | 'ء' | 'ـ' => "",
+        '\u{064B}'..='\u{0652}' | '\u{0670}' => "",
+        _ => return None,
+    })
+}
+
+/// Base letter of a Latin letter with a diacritic, for the common Western
+/// European and IAST (romanized Sanskrit and Hindi) letters.
+fn latin_base(c: char) -> Option<char> {
+    Some(match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' => 'a',
+        'ç' | 'ć' | 'č' => 'c',
+        'ḍ' | 'ď' => 'd',
+        'è' | 'é' | 'ê' | 'ë' | 'ē' => 'e',
+        'ḥ' => 'h',
+        'ì' | 'í' | 'î' | 'ï' | 'ī' => 'i',
+        'ñ' | 'ṇ' | 'ṅ' | 'ṃ' => 'n',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' => 'o',
+        'ṛ' | 'ř' => 'r',
+        'ś' | 'ṣ' | 'š' => 's',
+        'ṭ' | 'ť' => 't',
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' => 'u',
+        'ý' | 'ÿ' => 'y',
+        'ž' | 'ź' | 'ż' => 'z',
+        _ => return None,
+    })
+}
+
+/// Latin transliteration of `token`, diacritics removed.
+pub fn romanize(token: &str) -> String {
+    let mut out = String::with_capacity(token.len());
+    for c in token.chars() {
+        if let Some(latin) = devanagari(c).or_else(|| arabic(c)) {
+            out.push_str(latin);
+        } else if let Some(base) = latin_base(c) {
+            out.push(base);
+        } else {
+            out.extend(c.to_lowercase());
+        }
+    }
+    out
+}
+
+/// The folded form of `token`: its romanization reduced to a consonant
+/// skeleton with doubled letters collapsed. A token that is all vowels
+/// keeps its first letter.
+pub fn fold(token: &str) -> String {
+    let roman = romanize(token);
+    let mut out = String::with_capacity(roman.len());
+    let mut last = None;
+    for (i, c) in roman.chars().enumerate() {
+        if (i > 0 && VOWELS.contains(&c)) || last == Some(c) {
+            continue;
+        }
+        out.push(c);
+        last = Some(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_romanized_and_native_spellings_fold_together() {
+        for (native, romanized) in [
+            ("क्या", "kya"),
+            ("हाल", "haal"),
+            ("है", "hai"),
+            ("आप", "aap"),
+            ("कैसे", "kaise"),
+            ("مرحبا", "marhaba"),
+            ("شكرا", "shukra"),
+            ("کیسے", "kaise"),
+        ] {
+            assert_eq!(fold(native), fold(romanized), "{} / {}", native, romanized);
+        }
+        assert_eq!(fold("café"), fold("cafe"));
+        assert_ne!(fold("server"), fold("deploy"));
+    }
+}
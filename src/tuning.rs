@@ -0,0 +1,219 @@
+//! Weight and threshold tuning against reviewer labels.
+//!
+//! Given messages whose metrics are known and whose correct verdict a human
+//! reviewer supplied, `tune` runs a coordinate search over alpha, beta and
+//! the four thresholds, minimizing the verdict disagreement. A mistake costs
+//! its distance on the Allow < Warn < Block scale, so blocking a message
+//! that should pass counts twice. Ties are broken by how far scores sit
+//! outside their labeled band, which lets the search cross plateaus where
+//! no single step flips a verdict. Steps start at 0.1 and halve whenever a
+//! full pass finds no improvement.
+
+use crate::verdict::{self, Verdict};
+use crate::{score_linear, EmojiMode, WordMathAnalysis, WordMathConfig};
+use serde::Serialize;
+
+const INITIAL_STEP: f64 = 0.1;
+const MIN_STEP: f64 = 0.005;
+const MAX_PASSES: usize = 200;
+/// alpha, beta, block_max, warn_max, max_repetition, max_drift.
+const COORDINATES: usize = 6;
+
+/// A scored message and the verdict a reviewer says it deserved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabeledSample {
+    pub y_repetition: f64,
+    pub z_drift: f64,
+    pub label: Verdict,
+}
+
+/// Outcome of `tune`: the proposed config and how it compares.
+#[derive(Debug, Clone, Serialize)]
+pub struct TuningReport {
+    pub samples: usize,
+    /// Samples whose verdict differs from the label, before and after.
+    pub disagreements_before: usize,
+    pub disagreements_after: usize,
+    pub cost_before: usize,
+    pub cost_after: usize,
+    pub passes: usize,
+    pub config: WordMathConfig,
+}
+
+fn rank(verdict: Verdict) -> usize {
+    match verdict {
+        Verdict::Allow => 0,
+        Verdict::Warn => 1,
+        Verdict::Block => 2,
+    }
+}
+
+/// The verdict `cfg` gives a message with these metrics, rules aside.
+pub fn verdict_for(y_repetition: f64, z_drift: f64, cfg: &WordMathConfig) -> Verdict {
+    let analysis = WordMathAnalysis {
+        y_repetition,
+        z_drift,
+        score: cfg
+            .transform
+            .apply(score_linear(y_repetition, z_drift, *cfg)),
+        malformed_ratio: 0.0,
+        emoji_mode: EmojiMode::Strip,
+        emoji_count: 0,
+        invisible_stripped: 0,
+        obfuscation: 0.0,
+        homoglyphs: 0,
+        split_letters: 0,
+        evasion_risk: 0.0,
+    };
+    verdict::evaluate(&analysis, cfg).verdict
+}
+
+/// How far a sample's score (and, for Allow, its metrics) lies outside
+/// the band its label calls for.
+fn slack(sample: &LabeledSample, cfg: &WordMathConfig) -> f64 {
+    let t = cfg.thresholds;
+    let score = cfg
+        .transform
+        .apply(score_linear(sample.y_repetition, sample.z_drift, *cfg));
+    match sample.label {
+        Verdict::Allow => {
+            (t.warn_max - score).max(0.0)
+                + (sample.y_repetition - t.max_repetition).max(0.0)
+                + (sample.z_drift - t.max_drift).max(0.0)
+        }
+        Verdict::Warn => (t.block_max - score).max(0.0) + (score - t.warn_max).max(0.0),
+        Verdict::Block => (score - t.block_max).max(0.0),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Fit {
+    disagreements: usize,
+    cost: usize,
+    slack: f64,
+}
+
+impl Fit {
+    fn better_than(&self, other: &Fit) -> bool {
+        self.cost < other.cost || (self.cost == other.cost && self.slack < other.slack - 1e-12)
+    }
+}
+
+fn fit(samples: &[LabeledSample], cfg: &WordMathConfig) -> Fit {
+    let mut fit = Fit {
+        disagreements: 0,
+        cost: 0,
+        slack: 0.0,
+    };
+    for sample in samples {
+        let got = rank(verdict_for(sample.y_repetition, sample.z_drift, cfg));
+        let want = rank(sample.label);
+        if got != want {
+            fit.disagreements += 1;
+            fit.cost += got.abs_diff(want);
+            fit.slack += slack(sample, cfg);
+        }
+    }
+    fit
+}
+
+/// The tunable parameters, each kept in [0, 1].
+fn coordinate(cfg: &mut WordMathConfig, i: usize) -> &mut f64 {
+    match i {
+        0 => &mut cfg.alpha,
+        1 => &mut cfg.beta,
+        2 => &mut cfg.thresholds.block_max,
+        3 => &mut cfg.thresholds.warn_max,
+        4 => &mut cfg.thresholds.max_repetition,
+        _ => &mut cfg.thresholds.max_drift,
+    }
+}
+
+/// Search for the config closest to the reviewers' labels, from `start`.
+pub fn tune(samples: &[LabeledSample], start: WordMathConfig) -> TuningReport {
+    let before = fit(samples, &start);
+    let mut best = start;
+    let mut best_fit = before;
+    let mut step = INITIAL_STEP;
+    let mut passes = 0;
+    while step >= MIN_STEP && best_fit.cost > 0 && passes < MAX_PASSES {
+        passes += 1;
+        let mut improved = false;
+        for i in 0..COORDINATES {
+            for direction in [1.0, -1.0] {
+                let mut candidate = best;
+                let value = coordinate(&mut candidate, i);
+                // Rounded so proposals read as 0.3, not 0.30000000000000004.
+                *value = ((*value + direction * step) * 1e5).round().clamp(0.0, 1e5) / 1e5;
+                if candidate.thresholds.block_max > candidate.thresholds.warn_max {
+                    continue;
+                }
+                let candidate_fit = fit(samples, &candidate);
+                if candidate_fit.better_than(&best_fit) {
+                    best = candidate;
+                    best_fit = candidate_fit;
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            step /= 2.0;
+        }
+    }
+    TuningReport {
+        samples: samples.len(),
+        disagreements_before: before.disagreements,
+        disagreements_after: best_fit.disagreements,
+        cost_before: before.cost,
+        cost_after: best_fit.cost,
+        passes,
+        config: best,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(y: f64, z: f64, label: Verdict) -> LabeledSample {
+        LabeledSample {
+            y_repetition: y,
+            z_drift: z,
+            label,
+        }
+    }
+
+    #[test]
+    fn test_tune_reduces_disagreement() {
+        // Reviewers tolerate drift far more than the defaults do.
+        let samples = [
+            sample(0.1, 0.9, Verdict::Allow),
+            sample(0.1, 0.8, Verdict::Allow),
+            sample(0.2, 0.7, Verdict::Allow),
+            sample(0.7, 0.2, Verdict::Block),
+            sample(0.8, 0.3, Verdict::Block),
+            sample(0.5, 0.1, Verdict::Warn),
+        ];
+        let start = WordMathConfig::default();
+        let report = tune(&samples, start);
+        assert!(report.disagreements_before > 0);
+        assert_eq!(report.disagreements_after, 0);
+        assert!(report.config.beta < start.beta);
+        assert!(report.config.thresholds.block_max <= report.config.thresholds.warn_max);
+        for s in &samples {
+            assert_eq!(
+                verdict_for(s.y_repetition, s.z_drift, &report.config),
+                s.label
+            );
+        }
+    }
+
+    #[test]
+    fn test_tune_keeps_agreeing_config() {
+        let cfg = WordMathConfig::default();
+        let samples = [sample(0.0, 0.0, Verdict::Allow)];
+        let report = tune(&samples, cfg);
+        assert_eq!(report.passes, 0);
+        assert_eq!(report.config.alpha, cfg.alpha);
+    }
+}
@@ -0,0 +1,379 @@
+use crate::{WordMathAnalysis, WordMathConfig};
+use serde::{Deserialize, Serialize};
+
+/// Decision derived from an analysis and the configured thresholds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Allow,
+    Warn,
+    Block,
+}
+
+impl Verdict {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Warn => "warn",
+            Self::Block => "block",
+        }
+    }
+}
+
+/// Graded severity for moderation queues that triage rather than block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Clean,
+    Notice,
+    Warn,
+    Severe,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Clean => "clean",
+            Self::Notice => "notice",
+            Self::Warn => "warn",
+            Self::Severe => "severe",
+            Self::Critical => "critical",
+        }
+    }
+
+    /// Lowest severity consistent with a verdict.
+    pub(crate) fn floor_for(verdict: Verdict) -> Self {
+        match verdict {
+            Verdict::Allow => Self::Clean,
+            Verdict::Warn => Self::Notice,
+            Verdict::Block => Self::Severe,
+        }
+    }
+}
+
+/// Upper score bounds of the severity bands; scores above `notice_max` are
+/// clean. The defaults line up with the default verdict thresholds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeverityBands {
+    pub critical_max: f64,
+    pub severe_max: f64,
+    pub warn_max: f64,
+    pub notice_max: f64,
+}
+
+impl Default for SeverityBands {
+    fn default() -> Self {
+        Self {
+            critical_max: 0.1,
+            severe_max: 0.3,
+            warn_max: 0.5,
+            notice_max: 0.7,
+        }
+    }
+}
+
+impl SeverityBands {
+    /// Band a score falls in.
+    pub fn classify(&self, score: f64) -> Severity {
+        if score <= self.critical_max {
+            Severity::Critical
+        } else if score <= self.severe_max {
+            Severity::Severe
+        } else if score <= self.warn_max {
+            Severity::Warn
+        } else if score <= self.notice_max {
+            Severity::Notice
+        } else {
+            Severity::Clean
+        }
+    }
+}
+
+/// Verdict thresholds. Scores at or below `block_max` block, at or below
+/// `warn_max` warn; raw metrics above their maxima warn regardless of score.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thresholds {
+    pub block_max: f64,
+    pub warn_max: f64,
+    pub max_repetition: f64,
+    pub max_drift: f64,
+    /// Score bands for `VerdictExplanation::severity`.
+    pub severity: SeverityBands,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            block_max: 0.3,
+            warn_max: 0.7,
+            max_repetition: 0.6,
+            max_drift: 0.9,
+            severity: SeverityBands::default(),
+        }
+    }
+}
+
+/// A metric that feeds the score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Score,
+    Repetition,
+    Drift,
+}
+
+impl Metric {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Score => "score",
+            Self::Repetition => "repetition",
+            Self::Drift => "drift",
+        }
+    }
+}
+
+/// Which configured threshold a value crossed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdKind {
+    BlockMax,
+    WarnMax,
+    MaxRepetition,
+    MaxDrift,
+}
+
+/// One threshold that fired, with the margin by which it was crossed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ThresholdHit {
+    pub threshold: ThresholdKind,
+    pub metric: Metric,
+    pub value: f64,
+    pub limit: f64,
+    /// Distance past the limit; always >= 0.
+    pub margin: f64,
+    /// Verdict this threshold alone would produce.
+    pub verdict: Verdict,
+}
+
+/// A policy rule (see `rules`) that matched the analysis.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuleHit {
+    pub id: String,
+    /// Verdict the rule forces.
+    pub verdict: Verdict,
+}
+
+/// Verdict plus the provenance needed to explain it to a user.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VerdictExplanation {
+    pub verdict: Verdict,
+    /// Score band, raised if needed so it never understates the verdict
+    /// (Warn is at least Notice, Block at least Severe).
+    pub severity: Severity,
+    /// Thresholds that fired, most decisive first.
+    pub fired: Vec<ThresholdHit>,
+    /// The metric that removed the most from the score.
+    pub top_contributor: Metric,
+    /// Policy rules that matched, in rule-file order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rule_hits: Vec<RuleHit>,
+}
+
+impl VerdictExplanation {
+    /// The threshold that decided the verdict, if any fired.
+    pub fn primary(&self) -> Option<&ThresholdHit> {
+        self.fired.first()
+    }
+
+    /// Human-readable reason, e.g. "blocked because repetition 0.82 exceeded 0.6".
+    pub fn summary(&self) -> String {
+        let action = match self.verdict {
+            Verdict::Allow => "allowed",
+            Verdict::Warn => "warned",
+            Verdict::Block => "blocked",
+        };
+        // A rule decided the verdict if no threshold reached it.
+        if self.primary().map_or(Verdict::Allow, |hit| hit.verdict) < self.verdict {
+            if let Some(rule) = self.rule_hits.iter().find(|r| r.verdict == self.verdict) {
+                return format!("{} by rule {}", action, rule.id);
+            }
+        }
+        let Some(hit) = self.primary() else {
+            return "allowed".to_string();
+        };
+        let relation = match hit.threshold {
+            ThresholdKind::BlockMax | ThresholdKind::WarnMax => "fell below",
+            ThresholdKind::MaxRepetition | ThresholdKind::MaxDrift => "exceeded",
+        };
+        format!(
+            "{} because {} {:.2} {} {}",
+            action,
+            hit.metric.as_str(),
+            hit.value,
+            relation,
+            hit.limit
+        )
+    }
+}
+
+/// Derive the verdict for an analysis and record which thresholds fired.
+pub fn evaluate(analysis: &WordMathAnalysis, cfg: &WordMathConfig) -> VerdictExplanation {
+    let t = cfg.thresholds;
+    let mut fired = Vec::new();
+
+    if analysis.score <= t.block_max {
+        fired.push(ThresholdHit {
+            threshold: ThresholdKind::BlockMax,
+            metric: Metric::Score,
+            value: analysis.score,
+            limit: t.block_max,
+            margin: t.block_max - analysis.score,
+            verdict: Verdict::Block,
+        });
+    } else if analysis.score <= t.warn_max {
+        fired.push(ThresholdHit {
+            threshold: ThresholdKind::WarnMax,
+            metric: Metric::Score,
+            value: analysis.score,
+            limit: t.warn_max,
+            margin: t.warn_max - analysis.score,
+            verdict: Verdict::Warn,
+        });
+    }
+    if analysis.y_repetition > t.max_repetition {
+        fired.push(ThresholdHit {
+            threshold: ThresholdKind::MaxRepetition,
+            metric: Metric::Repetition,
+            value: analysis.y_repetition,
+            limit: t.max_repetition,
+            margin: analysis.y_repetition - t.max_repetition,
+            verdict: Verdict::Warn,
+        });
+    }
+    if analysis.z_drift > t.max_drift {
+        fired.push(ThresholdHit {
+            threshold: ThresholdKind::MaxDrift,
+            metric: Metric::Drift,
+            value: analysis.z_drift,
+            limit: t.max_drift,
+            margin: analysis.z_drift - t.max_drift,
+            verdict: Verdict::Warn,
+        });
+    }
+
+    // Most severe first, then the largest margin.
+    fired.sort_by(|a, b| {
+        b.verdict
+            .cmp(&a.verdict)
+            .then(b.margin.total_cmp(&a.margin))
+    });
+
+    let verdict = fired.first().map_or(Verdict::Allow, |hit| hit.verdict);
+    let top_contributor = if cfg.alpha * analysis.y_repetition >= cfg.beta * analysis.z_drift {
+        Metric::Repetition
+    } else {
+        Metric::Drift
+    };
+
+    let severity = t
+        .severity
+        .classify(analysis.score)
+        .max(Severity::floor_for(verdict));
+
+    VerdictExplanation {
+        verdict,
+        severity,
+        fired,
+        top_contributor,
+        rule_hits: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze_message_with_trace;
+
+    #[test]
+    fn test_clean_message_is_allowed() {
+        let cfg = WordMathConfig::default();
+        let (analysis, _) =
+            analyze_message_with_trace("rust axum web server", "rust axum web server", cfg);
+        let explanation = evaluate(&analysis, &cfg);
+        assert_eq!(explanation.verdict, Verdict::Allow);
+        assert_eq!(explanation.severity, Severity::Clean);
+        assert!(explanation.fired.is_empty());
+        assert_eq!(explanation.summary(), "allowed");
+    }
+
+    #[test]
+    fn test_block_reports_threshold_and_contributor() {
+        let cfg = WordMathConfig::default();
+        let (analysis, _) =
+            analyze_message_with_trace("buy buy buy buy buy now", "rust web server", cfg);
+        let explanation = evaluate(&analysis, &cfg);
+        assert_eq!(explanation.verdict, Verdict::Block);
+
+        let primary = explanation.primary().unwrap();
+        assert_eq!(primary.threshold, ThresholdKind::BlockMax);
+        assert!(primary.margin > 0.0);
+        assert!(explanation
+            .fired
+            .iter()
+            .any(|hit| hit.threshold == ThresholdKind::MaxRepetition));
+        assert_eq!(explanation.top_contributor, Metric::Drift);
+        assert!(explanation.summary().starts_with("blocked because score"));
+    }
+
+    #[test]
+    fn test_severity_bands_and_verdict_floor() {
+        let bands = SeverityBands::default();
+        assert_eq!(bands.classify(0.05), Severity::Critical);
+        assert_eq!(bands.classify(0.3), Severity::Severe);
+        assert_eq!(bands.classify(0.45), Severity::Warn);
+        assert_eq!(bands.classify(0.6), Severity::Notice);
+        assert_eq!(bands.classify(0.9), Severity::Clean);
+
+        // A drift warning on a high score still reports at least Notice.
+        let cfg = WordMathConfig::default();
+        let (analysis, _) = analyze_message_with_trace("rust", "pizza toppings", cfg);
+        let mut analysis = analysis;
+        analysis.score = 0.95;
+        let explanation = evaluate(&analysis, &cfg);
+        assert_eq!(explanation.verdict, Verdict::Warn);
+        assert_eq!(explanation.severity, Severity::Notice);
+    }
+
+    #[test]
+    fn test_summary_names_a_rule_only_when_it_decided() {
+        let cfg = WordMathConfig::default();
+        let (analysis, _) =
+            analyze_message_with_trace("rust axum web server", "rust axum web server", cfg);
+        let mut explanation = evaluate(&analysis, &cfg);
+        assert!(!serde_json::to_string(&explanation)
+            .unwrap()
+            .contains("rule_hits"));
+        explanation.rule_hits.push(RuleHit {
+            id: "padding".to_string(),
+            verdict: Verdict::Warn,
+        });
+        explanation.verdict = Verdict::Warn;
+        assert_eq!(explanation.summary(), "warned by rule padding");
+        let json = serde_json::to_value(&explanation).unwrap();
+        assert_eq!(json["rule_hits"][0]["id"], "padding");
+        assert_eq!(json["rule_hits"][0]["verdict"], "warn");
+
+        // A threshold that reaches the verdict on its own keeps the credit.
+        let (analysis, _) =
+            analyze_message_with_trace("buy buy buy buy buy now", "rust web server", cfg);
+        let mut explanation = evaluate(&analysis, &cfg);
+        explanation.rule_hits.push(RuleHit {
+            id: "loop".to_string(),
+            verdict: Verdict::Block,
+        });
+        assert!(explanation.summary().starts_with("blocked because score"));
+    }
+}
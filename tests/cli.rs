@@ -0,0 +1,143 @@
+//! The `wordmath` binary's documented exit codes and JSON output.
+//!
+//! CI jobs gate on these codes (0 = Allow, 3 = Warn, 4 = Block,
+//! 5 = divergent eval groups, 2 = usage or runtime error) and parse the
+//! `--format json` records, so both are part of the contract.
+
+use std::process::{Command, Output};
+
+const TOPIC: &str = "rust web server";
+
+fn wordmath(args: &[&str]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_wordmath"));
+    for (name, _) in std::env::vars_os() {
+        if name.to_string_lossy().starts_with("WORD_MATH_") {
+            command.env_remove(name);
+        }
+    }
+    command.args(args).output().expect("running wordmath")
+}
+
+fn analyze_json(message: &str) -> (i32, serde_json::Value) {
+    let out = wordmath(&["analyze", "--topic", TOPIC, "--format", "json", message]);
+    let record = serde_json::from_slice(&out.stdout).unwrap_or_else(|e| {
+        panic!("{}: {}", e, String::from_utf8_lossy(&out.stdout));
+    });
+    (out.status.code().unwrap(), record)
+}
+
+#[test]
+fn analyze_exit_codes_follow_the_verdict() {
+    let cases = [
+        ("rust web server", "allow", 0),
+        (
+            "The rust web server handles requests. My favourite pizza topping is \
+             pineapple and the weather is sunny today at the beach.",
+            "warn",
+            3,
+        ),
+        (
+            "pizza pizza pizza pizza pizza pizza pizza pizza",
+            "block",
+            4,
+        ),
+    ];
+    for (message, verdict, code) in cases {
+        let (status, record) = analyze_json(message);
+        assert_eq!(status, code, "{}", record);
+        assert_eq!(record["verdict"], verdict);
+        assert_eq!(record["exit_code"], code);
+    }
+}
+
+#[test]
+fn analyze_json_record_has_a_stable_shape() {
+    let (_, record) = analyze_json("pizza pizza pizza pizza pizza pizza pizza pizza");
+    let object = record.as_object().unwrap();
+    let mut keys: Vec<&str> = object.keys().map(String::as_str).collect();
+    keys.sort_unstable();
+    assert_eq!(
+        keys,
+        [
+            "evasion_risk",
+            "exit_code",
+            "hex_id",
+            "raw_score",
+            "reason",
+            "score",
+            "verdict",
+            "y_repetition",
+            "z_drift"
+        ]
+    );
+    for metric in [
+        "evasion_risk",
+        "raw_score",
+        "score",
+        "y_repetition",
+        "z_drift",
+    ] {
+        let value = record[metric].as_f64().unwrap();
+        assert!((0.0..=1.0).contains(&value), "{} = {}", metric, value);
+    }
+    assert_eq!(record["hex_id"].as_str().unwrap().len(), 16);
+    assert!(record["reason"].as_str().unwrap().starts_with("blocked"));
+
+    let (_, allowed) = analyze_json("rust web server");
+    assert!(allowed.get("reason").is_none());
+}
+
+#[test]
+fn usage_and_runtime_errors_exit_with_2() {
+    assert_eq!(wordmath(&[]).status.code(), Some(2));
+    assert_eq!(wordmath(&["frobnicate"]).status.code(), Some(2));
+    assert_eq!(wordmath(&["analyze", "message"]).status.code(), Some(2));
+    let missing = wordmath(&["eval", "--set", "/nonexistent/cases.jsonl"]);
+    assert_eq!(missing.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&missing.stderr).starts_with("wordmath: "));
+    let archive = wordmath(&["admin", "backup", "--out", "snapshot.tar.zst"]);
+    assert_eq!(archive.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&archive.stderr).contains("JSON lines"));
+    assert_eq!(wordmath(&["help"]).status.code(), Some(0));
+}
+
+#[test]
+fn eval_exits_with_5_only_when_groups_diverge() {
+    let dir = std::env::temp_dir().join(format!("wordmath-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let case = |group: &str, message: &str| {
+        serde_json::json!({"group": group, "topic": TOPIC, "message": message}).to_string()
+    };
+    let even = dir.join("even.jsonl");
+    let skewed = dir.join("skewed.jsonl");
+    std::fs::write(
+        &even,
+        [case("a", "rust web server"), case("b", "web server, rust")].join("\n"),
+    )
+    .unwrap();
+    std::fs::write(
+        &skewed,
+        [
+            case("a", "rust web server"),
+            case("b", "web server, rust"),
+            case("c", "server: rust web"),
+            case("d", "buy buy buy cheap pills buy buy"),
+        ]
+        .join("\n"),
+    )
+    .unwrap();
+
+    let even = wordmath(&["eval", "--set", even.to_str().unwrap()]);
+    let skewed = wordmath(&[
+        "eval",
+        "--set",
+        skewed.to_str().unwrap(),
+        "--format",
+        "json",
+    ]);
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(even.status.code(), Some(0));
+    assert_eq!(skewed.status.code(), Some(5));
+    let report: serde_json::Value = serde_json::from_slice(&skewed.stdout).unwrap();
+    assert!(!report["divergent"].as_array().unwrap().is_empty());
+}
@@ -0,0 +1,58 @@
+//! Known evasion tricks from `tests/evasion/corpus.json`.
+//!
+//! Every trick must be caught by the metrics (a Warn or Block verdict) or
+//! flagged by the evasion signal (`evasion_risk >= evasion::RISK_FLAG`);
+//! `flagged` records which cases the signal is expected to fire on, so a
+//! detector regression fails here even while the metrics still catch the
+//! case. Cases with trick "none" are benign controls.
+
+use serde::Deserialize;
+use std::path::Path;
+use word_math_guard::{analyze_message_with_trace, evasion, verdict, Verdict, WordMathConfig};
+
+#[derive(Debug, Deserialize)]
+struct Case {
+    id: String,
+    trick: String,
+    topic: String,
+    message: String,
+    flagged: bool,
+}
+
+fn corpus() -> Vec<Case> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/evasion/corpus.json");
+    let raw = std::fs::read_to_string(&path).expect("tests/evasion/corpus.json is missing");
+    serde_json::from_str(&raw).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+#[test]
+fn evasion_tricks_are_caught() {
+    let cfg = WordMathConfig::default();
+    let mut failures = Vec::new();
+    let cases = corpus();
+    assert!(cases.iter().any(|case| case.trick != "none"));
+
+    for case in &cases {
+        let (analysis, _) = analyze_message_with_trace(&case.message, &case.topic, cfg);
+        let verdict = verdict::evaluate(&analysis, &cfg).verdict;
+        let flagged = analysis.evasion_risk >= evasion::RISK_FLAG;
+        if flagged != case.flagged {
+            failures.push(format!(
+                "{} ({}): evasion_risk {:.4}, expected flagged={}",
+                case.id, case.trick, analysis.evasion_risk, case.flagged
+            ));
+        }
+        if case.trick != "none" && !flagged && verdict == Verdict::Allow {
+            failures.push(format!(
+                "{} ({}): allowed with score {:.4}",
+                case.id, case.trick, analysis.score
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "evasion corpus:\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,118 @@
+//! Golden-score regression tests over the curated messages in
+//! `tests/corpus/*.json`.
+//!
+//! Each case records the y / z / score produced with the default config.
+//! Downstream threshold calibrations depend on these values, so any change
+//! that moves a metric by more than `TOLERANCE` fails here. After an
+//! intentional metric change, regenerate the expectations with
+//! `WORD_MATH_BLESS=1 cargo test --test golden_corpus` and review the diff.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use word_math_guard::{analyze_message_with_trace, WordMathConfig};
+
+const TOLERANCE: f64 = 0.01;
+const METRICS: [&str; 3] = ["y_repetition", "z_drift", "score"];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Case {
+    id: String,
+    message: String,
+    topic: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expect: Option<Expected>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Expected {
+    y_repetition: f64,
+    z_drift: f64,
+    score: f64,
+}
+
+impl Expected {
+    fn get(&self, metric: &str) -> f64 {
+        match metric {
+            "y_repetition" => self.y_repetition,
+            "z_drift" => self.z_drift,
+            _ => self.score,
+        }
+    }
+}
+
+fn corpus_files() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("tests/corpus is missing")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    files
+}
+
+fn measure(message: &str, topic: &str) -> Expected {
+    let (analysis, _) = analyze_message_with_trace(message, topic, WordMathConfig::default());
+    let round = |x: f64| (x * 1e6).round() / 1e6;
+    Expected {
+        y_repetition: round(analysis.y_repetition),
+        z_drift: round(analysis.z_drift),
+        score: round(analysis.score),
+    }
+}
+
+#[test]
+fn golden_scores_within_tolerance() {
+    let bless = std::env::var_os("WORD_MATH_BLESS").is_some();
+    let mut failures = Vec::new();
+    let mut checked = 0;
+
+    for path in corpus_files() {
+        let raw = fs::read_to_string(&path).unwrap();
+        let mut cases: Vec<Case> =
+            serde_json::from_str(&raw).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+        for case in &mut cases {
+            let actual = measure(&case.message, &case.topic);
+            if bless {
+                case.expect = Some(actual);
+                continue;
+            }
+
+            let expected = case
+                .expect
+                .unwrap_or_else(|| panic!("{}: {} has no expectations", path.display(), case.id));
+            for metric in METRICS {
+                let expected = expected.get(metric);
+                let got = actual.get(metric);
+                if (got - expected).abs() > TOLERANCE {
+                    failures.push(format!(
+                        "{}::{} {}: expected {:.4}, got {:.4}",
+                        path.file_name().unwrap().to_string_lossy(),
+                        case.id,
+                        metric,
+                        expected,
+                        got
+                    ));
+                }
+            }
+            checked += 1;
+        }
+
+        if bless {
+            let pretty = serde_json::to_string_pretty(&cases).unwrap();
+            fs::write(&path, pretty + "\n").unwrap();
+        }
+    }
+
+    if !bless {
+        assert!(checked > 0, "golden corpus is empty");
+    }
+    assert!(
+        failures.is_empty(),
+        "golden scores drifted beyond {}:\n{}",
+        TOLERANCE,
+        failures.join("\n")
+    );
+}